@@ -8,7 +8,10 @@ use clap::Parser;
 use ipa_core::{
     error::Error,
     ff::Fp32BitPrime,
-    helpers::{query::IpaQueryConfig, GatewayConfig},
+    helpers::{
+        query::{DpConfig, IpaQueryConfig},
+        GatewayConfig,
+    },
     protocol::{step::ProtocolStep::IpaPrf, Gate},
     test_fixture::{
         ipa::{ipa_in_the_clear, test_oprf_ipa, CappingOrder, IpaSecurityModel},
@@ -57,12 +60,10 @@ struct Args {
         help = "The size of the attribution window, in seconds. Pass 0 for an infinite window."
     )]
     attribution_window: u32,
-    /// DP parameters. Will run with DP by default. Can only be run without DP if `with_dp` == 0.
-    /// in which case the value of `epsilon` is ignored.
-    #[arg(short = 'd', long, default_value = "1")]
-    with_dp: u32,
-    #[arg(short = 'e', long, default_value = "1.0")]
-    epsilon: f64,
+    /// DP parameters. Will run with discrete Laplace noise by default, pass
+    /// `--dp-mechanism none` to run without it.
+    #[command(flatten)]
+    dp: DpConfig,
     /// The random seed to use.
     #[arg(short = 's', long)]
     random_seed: Option<u64>,
@@ -93,8 +94,7 @@ impl Args {
             per_user_credit_cap: self.per_user_cap,
             max_breakdown_key: self.breakdown_keys,
             attribution_window_seconds: self.attribution_window(),
-            with_dp: self.with_dp,
-            epsilon: self.epsilon,
+            dp: self.dp,
             plaintext_match_keys: true,
             ..Default::default()
         }
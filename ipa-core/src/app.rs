@@ -1,24 +1,27 @@
-use std::{num::NonZeroUsize, sync::Weak};
+use std::{num::NonZeroUsize, sync::Weak, time::Duration};
 
 use async_trait::async_trait;
 
 use crate::{
     helpers::{
-        query::{PrepareQuery, QueryConfig, QueryInput},
+        query::{PaddingPolicy, PrepareQuery, QueryConfig, QueryInput},
         routing::{Addr, RouteId},
         ApiError, BodyStream, HandlerBox, HandlerRef, HelperIdentity, HelperResponse,
         MpcTransportImpl, RequestHandler, ShardTransportImpl, Transport,
     },
-    hpke::{KeyRegistry, PrivateKeyOnly},
+    hpke::{KeyRegistry, PrivateKeyOnly, ReloadableKeyRegistry},
     protocol::QueryId,
-    query::{NewQueryError, QueryProcessor, QueryStatus},
+    query::{NewQueryError, PrivacyBudget, QueryProcessor, QueryStatus, SeenReports},
     sync::Arc,
 };
 
 #[derive(Default)]
 pub struct AppConfig {
     active_work: Option<NonZeroUsize>,
-    key_registry: Option<KeyRegistry<PrivateKeyOnly>>,
+    key_registry: Option<Arc<ReloadableKeyRegistry<PrivateKeyOnly>>>,
+    seen_reports: Option<Arc<SeenReports>>,
+    privacy_budget: Option<Arc<PrivacyBudget>>,
+    padding_policy: Option<PaddingPolicy>,
 }
 
 impl AppConfig {
@@ -29,10 +32,46 @@ impl AppConfig {
     }
 
     #[must_use]
-    pub fn with_key_registry(mut self, key_registry: KeyRegistry<PrivateKeyOnly>) -> Self {
+    pub fn with_key_registry(self, key_registry: KeyRegistry<PrivateKeyOnly>) -> Self {
+        self.with_reloadable_key_registry(Arc::new(ReloadableKeyRegistry::new(key_registry)))
+    }
+
+    /// Use a key registry that can be replaced while the helper is running, to rotate
+    /// keys without restarting it.
+    #[must_use]
+    pub fn with_reloadable_key_registry(
+        mut self,
+        key_registry: Arc<ReloadableKeyRegistry<PrivateKeyOnly>>,
+    ) -> Self {
         self.key_registry = Some(key_registry);
         self
     }
+
+    /// Use this store to detect reports submitted in earlier queries. By default, duplicate
+    /// reports are only detected within a query.
+    #[must_use]
+    pub fn with_seen_reports(mut self, seen_reports: Arc<SeenReports>) -> Self {
+        self.seen_reports = Some(seen_reports);
+        self
+    }
+
+    /// Refuse queries that would spend more privacy budget than this ledger allows. By default,
+    /// helper accepts any query.
+    #[must_use]
+    pub fn with_privacy_budget(mut self, privacy_budget: Arc<PrivacyBudget>) -> Self {
+        self.privacy_budget = Some(privacy_budget);
+        self
+    }
+
+    /// Refuse queries that pad their inputs less than this policy requires. By default, helper
+    /// accepts the padding of [`DpConfig::default`] or stronger.
+    ///
+    /// [`DpConfig::default`]: crate::helpers::query::DpConfig
+    #[must_use]
+    pub fn with_padding_policy(mut self, padding_policy: PaddingPolicy) -> Self {
+        self.padding_policy = Some(padding_policy);
+        self
+    }
 }
 
 pub struct Setup {
@@ -59,8 +98,22 @@ struct Inner {
 impl Setup {
     #[must_use]
     pub fn new(config: AppConfig) -> (Self, HandlerRef) {
-        let key_registry = config.key_registry.unwrap_or_else(KeyRegistry::empty);
-        let query_processor = QueryProcessor::new(key_registry, config.active_work);
+        let key_registry = config
+            .key_registry
+            .unwrap_or_else(|| Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())));
+        let seen_reports = config
+            .seen_reports
+            .unwrap_or_else(|| Arc::new(SeenReports::in_memory(Duration::ZERO)));
+        let privacy_budget = config
+            .privacy_budget
+            .unwrap_or_else(|| Arc::new(PrivacyBudget::unlimited()));
+        let query_processor = QueryProcessor::new(
+            key_registry,
+            seen_reports,
+            privacy_budget,
+            config.padding_policy.unwrap_or_default(),
+            config.active_work,
+        );
         let handler = HandlerBox::empty();
         let this = Self {
             query_processor,
@@ -184,6 +237,10 @@ impl RequestHandler for Inner {
                 let req = req.into::<PrepareQuery>()?;
                 HelperResponse::from(qp.prepare(&self.mpc_transport, req)?)
             }
+            RouteId::AbortQuery => {
+                let query_id = ext_query_id(&req)?;
+                HelperResponse::from(qp.abort(query_id)?)
+            }
             RouteId::QueryInput => {
                 let query_id = ext_query_id(&req)?;
                 HelperResponse::from(qp.receive_inputs(
@@ -203,6 +260,7 @@ impl RequestHandler for Inner {
                 let query_id = ext_query_id(&req)?;
                 HelperResponse::from(qp.complete(query_id).await?)
             }
+            RouteId::PublicKeys => HelperResponse::from(qp.public_keys()),
         })
     }
 }
@@ -2,7 +2,12 @@ use std::fmt::Debug;
 
 use clap::{Parser, Subcommand};
 use ipa_core::{
-    cli::crypto::{decrypt_and_reconstruct, encrypt, DecryptArgs, EncryptArgs},
+    cli::{
+        crypto::{
+            decrypt_and_reconstruct, encrypt, inspect, DecryptArgs, EncryptArgs, InspectArgs,
+        },
+        Verbosity,
+    },
     error::BoxError,
 };
 
@@ -10,6 +15,9 @@ use ipa_core::{
 #[clap(name = "crypto-util", about = "Crypto Util CLI")]
 #[command(about)]
 struct Args {
+    #[clap(flatten)]
+    logging: Verbosity,
+
     #[command(subcommand)]
     action: CryptoUtilCommand,
 }
@@ -18,14 +26,23 @@ struct Args {
 enum CryptoUtilCommand {
     Encrypt(EncryptArgs),
     Decrypt(DecryptArgs),
+    Inspect(InspectArgs),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), BoxError> {
     let args = Args::parse();
+    let _handle = args.logging.setup_logging();
     match args.action {
         CryptoUtilCommand::Encrypt(encrypt_args) => encrypt(&encrypt_args)?,
         CryptoUtilCommand::Decrypt(decrypt_args) => decrypt_and_reconstruct(decrypt_args).await?,
+        CryptoUtilCommand::Inspect(inspect_args) => {
+            let summary = inspect(&inspect_args).await?;
+            print!("{summary}");
+            if !summary.is_valid() {
+                return Err("input file has malformed or undecryptable reports".into());
+            }
+        }
     }
     Ok(())
 }
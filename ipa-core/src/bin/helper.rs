@@ -6,6 +6,8 @@ use std::{
     os::fd::{FromRawFd, RawFd},
     path::{Path, PathBuf},
     process,
+    sync::Arc,
+    time::Duration,
 };
 
 use clap::{self, Parser, Subcommand};
@@ -14,10 +16,16 @@ use ipa_core::{
     cli::{
         client_config_setup, keygen, test_setup, ConfGenArgs, KeygenArgs, TestSetupArgs, Verbosity,
     },
-    config::{hpke_registry, HpkeServerConfig, NetworkConfig, ServerConfig, TlsConfig},
+    config::{
+        hpke_registry, reload_hpke_keys, HpkeServerConfig, NetworkConfig, ServerConfig,
+        StreamCompression, TlsConfig,
+    },
     error::BoxError,
-    helpers::HelperIdentity,
+    helpers::{query::PaddingPolicy, HelperIdentity},
+    hpke::ReloadableKeyRegistry,
+    key_encryption::{read_key_file, KeyEncryptionKey},
     net::{ClientIdentity, HttpShardTransport, HttpTransport, MpcHelperClient},
+    query::{PrivacyBudget, SeenReports},
     AppConfig, AppSetup,
 };
 use tracing::{error, info};
@@ -91,9 +99,69 @@ struct ServerArgs {
     #[arg(long, requires = "mk_public_key")]
     mk_private_key: Option<PathBuf>,
 
+    /// File with the key that decrypts TLS and match key private key files encrypted at rest. If
+    /// not set, it is read from the `IPA_KEY_ENCRYPTION_KEY` environment variable, if that is set
+    #[arg(long)]
+    key_encryption_key_file: Option<PathBuf>,
+
+    /// Manifest listing private keys for decrypting match keys, with their identifiers and
+    /// validity periods. It can also be a directory containing `manifest.toml`.
+    #[arg(long, conflicts_with_all = ["mk_public_key", "mk_private_key"])]
+    mk_key_manifest: Option<PathBuf>,
+
+    /// How often to reload keys listed in the key manifest, in seconds
+    #[arg(long, default_value = "60")]
+    mk_key_reload_interval: u64,
+
     /// Override the amount of active work processed in parallel
     #[arg(long)]
     active_work: Option<NonZeroUsize>,
+
+    /// Accept record streams from other helpers compressed with this codec
+    #[arg(long, value_enum, default_value_t = StreamCompression::None)]
+    stream_compression: StreamCompression,
+
+    /// File to persist reports this helper has processed, to detect them if they are submitted
+    /// again after a restart
+    #[arg(long)]
+    seen_reports_file: Option<PathBuf>,
+
+    /// For how long to remember processed reports, in seconds
+    #[arg(long, default_value = "604800")]
+    seen_reports_window: u64,
+
+    /// Maximum privacy budget each site can spend per epoch. If set, queries must declare the
+    /// site and epochs of their reports, and are refused once the budget is spent
+    #[arg(long)]
+    privacy_budget: Option<f64>,
+
+    /// Maximum delta each site can spend per epoch, in addition to `privacy_budget` epsilon
+    #[arg(long, requires = "privacy_budget")]
+    privacy_budget_delta: Option<f64>,
+
+    /// File to persist privacy budget spent by queries. Must be set together with `privacy_budget`
+    #[arg(long, requires = "privacy_budget")]
+    privacy_budget_file: Option<PathBuf>,
+
+    /// Refuse queries that pad their inputs with a larger epsilon than this
+    #[arg(long, default_value_t = PaddingPolicy::default().max_epsilon)]
+    max_padding_epsilon: f64,
+
+    /// Refuse queries that pad their inputs with a larger delta than this
+    #[arg(long, default_value_t = PaddingPolicy::default().max_delta)]
+    max_padding_delta: f64,
+
+    /// Refuse queries whose input padding hides fewer records per match key than this
+    #[arg(long, default_value_t = PaddingPolicy::default().min_matchkey_cardinality_cap)]
+    min_matchkey_cardinality_cap: u32,
+
+    /// Refuse queries whose aggregation padding hides fewer records per breakdown than this
+    #[arg(long, default_value_t = PaddingPolicy::default().min_aggregation_padding_sensitivity)]
+    min_aggregation_padding_sensitivity: u32,
+
+    /// Accept queries that don't pad their inputs. This is only meant for benchmarks
+    #[arg(long)]
+    allow_no_padding: bool,
 }
 
 #[derive(Debug, Subcommand)]
@@ -114,12 +182,17 @@ fn read_file(path: &Path) -> Result<BufReader<fs::File>, BoxError> {
 async fn server(args: ServerArgs) -> Result<(), BoxError> {
     let my_identity = HelperIdentity::try_from(args.identity.expect("enforced by clap")).unwrap();
 
+    let key_encryption_key = KeyEncryptionKey::load(args.key_encryption_key_file.as_deref())?;
+
     let (identity, server_tls) = match (args.tls_cert, args.tls_key) {
         (Some(cert_file), Some(key_file)) => {
-            let mut key = read_file(&key_file)?;
+            let key = fs::read(&key_file)
+                .map_err(|e| format!("failed to read file {}: {e:?}", key_file.display()))?;
+            let key = read_key_file(&key, key_encryption_key.as_ref())
+                .map_err(|e| format!("failed to load TLS key {}: {e}", key_file.display()))?;
             let mut certs = read_file(&cert_file)?;
             (
-                ClientIdentity::from_pkcs8(&mut certs, &mut key)?,
+                ClientIdentity::from_pkcs8(&mut certs, &mut key.as_ref())?,
                 Some(TlsConfig::File {
                     certificate_file: cert_file,
                     private_key_file: key_file,
@@ -130,12 +203,58 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         _ => panic!("should have been rejected by clap"),
     };
 
-    let mk_encryption = args.mk_private_key.map(|sk_path| HpkeServerConfig::File {
-        private_key_file: sk_path,
-    });
+    let mk_encryption = match (args.mk_private_key, args.mk_key_manifest) {
+        (Some(sk_path), None) => Some(HpkeServerConfig::File {
+            private_key_file: sk_path,
+        }),
+        (None, Some(manifest_file)) => Some(HpkeServerConfig::Manifest { manifest_file }),
+        (None, None) => None,
+        (Some(_), Some(_)) => panic!("should have been rejected by clap"),
+    };
+
+    let key_registry = Arc::new(ReloadableKeyRegistry::new(
+        hpke_registry(mk_encryption.as_ref(), key_encryption_key.as_ref()).await?,
+    ));
+    if let Some(HpkeServerConfig::Manifest { manifest_file }) = &mk_encryption {
+        tokio::spawn(reload_hpke_keys(
+            manifest_file.clone(),
+            key_encryption_key.clone(),
+            Arc::clone(&key_registry),
+            Duration::from_secs(args.mk_key_reload_interval),
+        ));
+    }
+
+    let seen_reports_window = Duration::from_secs(args.seen_reports_window);
+    let seen_reports = match &args.seen_reports_file {
+        Some(path) => SeenReports::open(path, seen_reports_window)
+            .map_err(|e| format!("failed to open {}: {e}", path.display()))?,
+        None => SeenReports::in_memory(seen_reports_window),
+    };
+
+    let privacy_budget_cap = args.privacy_budget.unwrap_or(f64::INFINITY);
+    let privacy_budget = match &args.privacy_budget_file {
+        Some(path) => PrivacyBudget::open(path, privacy_budget_cap)
+            .map_err(|e| format!("failed to open {}: {e}", path.display()))?,
+        None => PrivacyBudget::in_memory(privacy_budget_cap),
+    };
+    let privacy_budget = match args.privacy_budget_delta {
+        Some(delta_cap) => privacy_budget.with_delta_cap(delta_cap),
+        None => privacy_budget,
+    };
+
+    let padding_policy = PaddingPolicy {
+        max_epsilon: args.max_padding_epsilon,
+        max_delta: args.max_padding_delta,
+        min_matchkey_cardinality_cap: args.min_matchkey_cardinality_cap,
+        min_aggregation_padding_sensitivity: args.min_aggregation_padding_sensitivity,
+        allow_no_padding: args.allow_no_padding,
+    };
 
     let app_config = AppConfig::default()
-        .with_key_registry(hpke_registry(mk_encryption.as_ref()).await?)
+        .with_reloadable_key_registry(key_registry)
+        .with_seen_reports(Arc::new(seen_reports))
+        .with_privacy_budget(Arc::new(privacy_budget))
+        .with_padding_policy(padding_policy)
         .with_active_work(args.active_work);
     let (setup, handler) = AppSetup::new(app_config);
 
@@ -144,6 +263,8 @@ async fn server(args: ServerArgs) -> Result<(), BoxError> {
         disable_https: args.disable_https,
         tls: server_tls,
         hpke_config: mk_encryption,
+        key_encryption_key,
+        stream_compression: args.stream_compression,
     };
 
     let scheme = if args.disable_https {
@@ -14,8 +14,8 @@ use hyper::http::uri::Scheme;
 use ipa_core::{
     cli::{
         playbook::{
-            make_clients, playbook_oprf_ipa, run_query_and_validate, validate, validate_dp,
-            InputSource,
+            log_confidence_intervals, make_clients, playbook_oprf_ipa, run_query_and_validate,
+            validate, validate_dp, InputSource,
         },
         CsvSerializer, IpaQueryResult, Verbosity,
     },
@@ -23,7 +23,8 @@ use ipa_core::{
     ff::{boolean_array::BA32, FieldType},
     helpers::query::{DpMechanism, IpaQueryConfig, QueryConfig, QuerySize, QueryType},
     net::MpcHelperClient,
-    report::{EncryptedOprfReportStreams, DEFAULT_KEY_ID},
+    query::estimate_ipa,
+    report::EncryptedOprfReportStreams,
     test_fixture::{
         ipa::{ipa_in_the_clear, CappingOrder, IpaSecurityModel, TestRawDataRecord},
         EventGenerator, EventGeneratorConfig, HybridEventGenerator, HybridGeneratorConfig,
@@ -58,10 +59,27 @@ struct Args {
     #[arg(long, value_name = "OUTPUT_FILE")]
     output_file: Option<PathBuf>,
 
+    /// Confidence level of the intervals logged for every breakdown of IPA results
+    #[arg(long, default_value_t = 0.95, value_parser = confidence_level)]
+    confidence: f64,
+
+    /// Adjust the IPA results of a query with a breakdown hierarchy so that every breakdown is
+    /// the sum of its children at the next finer level. The adjusted results are reported in
+    /// `consistent_levels`, next to the noisy ones.
+    #[arg(long)]
+    consistent_hierarchy: bool,
+
     #[command(subcommand)]
     action: ReportCollectorCommand,
 }
 
+fn confidence_level(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(v) if (0.0..1.0).contains(&v) => Ok(v),
+        _ => Err(format!("confidence must be in [0, 1), got {s}")),
+    }
+}
+
 #[derive(Debug, Parser)]
 pub struct CommandInput {
     #[arg(
@@ -129,6 +147,16 @@ enum ReportCollectorCommand {
         #[clap(flatten)]
         encrypted_inputs: EncryptedInputs,
 
+        #[clap(flatten)]
+        ipa_query_config: IpaQueryConfig,
+    },
+    /// Estimate the output noise, the padding and the MPC cost of an OPRF IPA query, without
+    /// running it
+    EstimateOprfIpa {
+        /// Expected number of records in the query
+        #[arg(long, short = 'n')]
+        records: usize,
+
         #[clap(flatten)]
         ipa_query_config: IpaQueryConfig,
     },
@@ -169,7 +197,25 @@ async fn main() -> Result<(), Box<dyn Error>> {
         Scheme::HTTPS
     };
 
-    let (clients, network) = make_clients(args.network.as_deref(), scheme, args.wait).await;
+    // reject DP parameters out of range before reaching out to the helpers
+    if let ReportCollectorCommand::SemiHonestOprfIpaTest(config)
+    | ReportCollectorCommand::MaliciousOprfIpaTest(config)
+    | ReportCollectorCommand::SemiHonestOprfIpa {
+        ipa_query_config: config,
+        ..
+    }
+    | ReportCollectorCommand::MaliciousOprfIpa {
+        ipa_query_config: config,
+        ..
+    }
+    | ReportCollectorCommand::EstimateOprfIpa {
+        ipa_query_config: config,
+        ..
+    } = &args.action
+    {
+        config.dp.validate()?;
+    }
+
     match args.action {
         ReportCollectorCommand::GenIpaInputs {
             count,
@@ -181,7 +227,13 @@ async fn main() -> Result<(), Box<dyn Error>> {
             seed,
             gen_args,
         } => gen_hybrid_inputs(count, seed, args.output_file, gen_args)?,
-        ReportCollectorCommand::SemiHonestOprfIpaTest(config) => {
+        // estimates are computed offline, without reaching out to the helpers
+        ReportCollectorCommand::EstimateOprfIpa {
+            records,
+            ref ipa_query_config,
+        } => estimate_oprf_ipa(records, ipa_query_config, args.output_file.as_ref())?,
+        ReportCollectorCommand::SemiHonestOprfIpaTest(ref config) => {
+            let (clients, network) = make_clients(args.network.as_deref(), scheme, args.wait).await;
             ipa_test(
                 &args,
                 &network,
@@ -191,7 +243,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
             )
             .await?
         }
-        ReportCollectorCommand::MaliciousOprfIpaTest(config) => {
+        ReportCollectorCommand::MaliciousOprfIpaTest(ref config) => {
+            let (clients, network) = make_clients(args.network.as_deref(), scheme, args.wait).await;
             ipa_test(
                 &args,
                 &network,
@@ -203,8 +256,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
         ReportCollectorCommand::MaliciousOprfIpa {
             ref encrypted_inputs,
-            ipa_query_config,
+            ref ipa_query_config,
         } => {
+            let (clients, _) = make_clients(args.network.as_deref(), scheme, args.wait).await;
             ipa(
                 &args,
                 IpaSecurityModel::Malicious,
@@ -216,8 +270,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
         ReportCollectorCommand::SemiHonestOprfIpa {
             ref encrypted_inputs,
-            ipa_query_config,
+            ref ipa_query_config,
         } => {
+            let (clients, _) = make_clients(args.network.as_deref(), scheme, args.wait).await;
             ipa(
                 &args,
                 IpaSecurityModel::SemiHonest,
@@ -232,6 +287,31 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn estimate_oprf_ipa(
+    records: usize,
+    ipa_query_config: &IpaQueryConfig,
+    output_file: Option<&PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    let estimate = estimate_ipa(ipa_query_config, records)?;
+    tracing::info!(
+        "Expected noise std per breakdown: {:.2}, padding rows: {:.0} OPRF and {:.0} aggregation, \
+         MPC cost: {:.0} records and {:.3e} boolean multiplications",
+        estimate.noise.output.std,
+        estimate.oprf_padding_rows.mean,
+        estimate.aggregation_padding_rows.mean,
+        estimate.cost.records,
+        estimate.cost.boolean_multiplications,
+    );
+
+    let output = serde_json::to_string_pretty(&estimate)?;
+    if let Some(path) = output_file {
+        std::fs::write(path, output)?;
+    } else {
+        println!("{output}");
+    }
+    Ok(())
+}
+
 fn gen_hybrid_inputs(
     count: u32,
     seed: Option<u64>,
@@ -283,7 +363,11 @@ fn gen_inputs(
     Ok(())
 }
 
-fn get_query_type(security_model: IpaSecurityModel, ipa_query_config: IpaQueryConfig) -> QueryType {
+fn get_query_type(
+    security_model: IpaSecurityModel,
+    ipa_query_config: &IpaQueryConfig,
+) -> QueryType {
+    let ipa_query_config = ipa_query_config.clone();
     match security_model {
         IpaSecurityModel::SemiHonest => QueryType::SemiHonestOprfIpa(ipa_query_config),
         IpaSecurityModel::Malicious => QueryType::MaliciousOprfIpa(ipa_query_config),
@@ -332,7 +416,7 @@ fn write_ipa_output_file(
 async fn ipa(
     args: &Args,
     security_model: IpaSecurityModel,
-    ipa_query_config: IpaQueryConfig,
+    ipa_query_config: &IpaQueryConfig,
     helper_clients: &[MpcHelperClient; 3],
     encrypted_inputs: &EncryptedInputs,
 ) -> Result<(), Box<dyn Error>> {
@@ -361,15 +445,19 @@ async fn ipa(
     // the value for histogram values (BA32) must be kept in sync with the server-side
     // implementation, otherwise a runtime reconstruct error will be generated.
     // see ipa-core/src/query/executor.rs
-    let actual = run_query_and_validate::<BA32>(
+    let mut actual = run_query_and_validate::<BA32>(
         encrypted_oprf_report_streams.streams,
         encrypted_oprf_report_streams.query_size,
         helper_clients,
         query_id,
-        ipa_query_config,
+        ipa_query_config.clone(),
     )
     .await;
+    if args.consistent_hierarchy {
+        actual.estimate_consistent_levels();
+    }
 
+    log_confidence_intervals(&actual, args.confidence);
     if let Some(ref path) = args.output_file {
         write_ipa_output_file(path, &actual)?;
     } else {
@@ -382,7 +470,7 @@ async fn ipa_test(
     args: &Args,
     network: &NetworkConfig,
     security_model: IpaSecurityModel,
-    ipa_query_config: IpaQueryConfig,
+    ipa_query_config: &IpaQueryConfig,
     helper_clients: &[MpcHelperClient; 3],
 ) -> Result<(), Box<dyn Error>> {
     let input = InputSource::from(&args.input);
@@ -424,34 +512,40 @@ async fn ipa_test(
     // the value for histogram values (BA32) must be kept in sync with the server-side
     // implementation, otherwise a runtime reconstruct error will be generated.
     // see ipa-core/src/query/executor.rs
-    let actual = playbook_oprf_ipa::<BA32, _>(
+    let mut actual = playbook_oprf_ipa::<BA32, _>(
         input_rows,
         helper_clients,
         query_id,
-        ipa_query_config,
-        Some((DEFAULT_KEY_ID, key_registries)),
+        ipa_query_config.clone(),
+        Some(key_registries),
     )
     .await;
+    if args.consistent_hierarchy {
+        actual.estimate_consistent_levels();
+    }
 
     if let Some(ref path) = args.output_file {
         write_ipa_output_file(path, &actual)?;
     }
 
     tracing::info!("{m:?}", m = ipa_query_config);
+    log_confidence_intervals(&actual, args.confidence);
 
-    match ipa_query_config.with_dp {
-        0 => {
+    // `breakdowns` has the finest level of a breakdown hierarchy, which gets a share of the budget
+    let noise = match ipa_query_config.breakdown_hierarchy {
+        Some(hierarchy) => hierarchy.level_noise(ipa_query_config.dp.noise()),
+        None => ipa_query_config.dp.noise(),
+    };
+    match noise {
+        DpMechanism::NoDp => {
             validate(&expected, &actual.breakdowns);
         }
-        _ => {
+        dp_mechanism => {
             validate_dp(
                 expected,
                 actual.breakdowns,
-                ipa_query_config.epsilon,
                 ipa_query_config.per_user_credit_cap,
-                DpMechanism::DiscreteLaplace {
-                    epsilon: ipa_query_config.epsilon,
-                },
+                dp_mechanism,
             );
         }
     }
@@ -2,7 +2,6 @@ use std::{fs, fs::File, io::Write, iter::zip, path::PathBuf};
 
 use clap::Args;
 use config::Map;
-use hpke::Serializable as _;
 use toml::{Table, Value};
 
 use crate::{
@@ -215,6 +214,6 @@ fn assert_hpke_config(expected: &Value, actual: Option<&HpkeClientConfig>) {
             .get("public_key")
             .and_then(toml::Value::as_str)
             .map(ToOwned::to_owned),
-        actual.map(|v| hex::encode(v.public_key.to_bytes()))
+        actual.map(|v| v.public_key.encode())
     );
 }
@@ -1,28 +1,55 @@
 use std::{
-    fmt::Debug,
+    collections::BTreeMap,
+    fmt::{Debug, Display, Formatter},
     fs::{read_to_string, File, OpenOptions},
-    io::{BufRead, BufReader, Write},
+    io::{self, BufRead, BufReader, BufWriter, Lines, Read, Write},
     iter::zip,
-    path::PathBuf,
+    num::NonZeroUsize,
+    panic::resume_unwind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
 };
 
-use clap::Parser;
-use rand::thread_rng;
+use clap::{Parser, ValueEnum};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    cli::playbook::{BreakdownKey, InputSource, Timestamp, TriggerValue},
+    cli::{
+        playbook::{BreakdownKey, InputItem, Timestamp, TriggerValue},
+        CsvSerializer,
+    },
     config::{hpke_registry, HpkeServerConfig, KeyRegistries, NetworkConfig},
     error::BoxError,
-    ff::{
-        boolean_array::{BA20, BA3, BA8},
-        U128Conversions,
-    },
-    hpke::{KeyRegistry, PrivateKeyOnly},
-    report::{EncryptedOprfReport, EventType, OprfReport, DEFAULT_KEY_ID},
+    ff::U128Conversions,
+    hpke::{KeyRegistry, PrivateKeyOnly, PublicKeyOnly},
+    key_encryption::KeyEncryptionKey,
+    report::{EncryptedOprfReport, Epoch, EventType, KeyIdentifier, OprfReport},
     secret_sharing::IntoShares,
-    test_fixture::{ipa::TestRawDataRecord, Reconstruct},
+    test_fixture::{hybrid::TestHybridRecord, ipa::TestRawDataRecord, Reconstruct},
 };
 
+/// Number of reports processed by a worker thread at a time. Each batch gets its own random
+/// number generator seeded from the main one, so the output only depends on the seed and not on
+/// the number of threads. Tests use small batches to exercise batching with little input.
+const BATCH_SIZE: usize = if cfg!(test) { 4 } else { 4096 };
+
+/// How often to report progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+type Report = OprfReport<BreakdownKey, TriggerValue, Timestamp>;
+
+/// Kind of records in the plaintext input or output.
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum ReportType {
+    /// IPA test records: `timestamp,match_key,is_trigger,breakdown_key,trigger_value`.
+    #[default]
+    Ipa,
+    /// Hybrid impressions, `i,match_key,breakdown_key`, and conversions,
+    /// `c,match_key,value`.
+    Hybrid,
+}
+
 #[derive(Debug, Parser)]
 #[clap(name = "test_encrypt", about = "Test Encrypt")]
 #[command(about)]
@@ -38,6 +65,16 @@ pub struct EncryptArgs {
     /// Path to helper network configuration file
     #[arg(long)]
     network: PathBuf,
+    /// Kind of records in the input file
+    #[arg(long, value_enum, default_value_t)]
+    report_type: ReportType,
+    /// Seed for secret sharing and encryption. The same seed and input always produce the same
+    /// output
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Number of worker threads. Defaults to the number of available CPUs
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
 }
 
 #[derive(Debug, Parser)]
@@ -71,6 +108,258 @@ pub struct DecryptArgs {
     /// The destination file for decrypted output.
     #[arg(long, value_name = "FILE")]
     output_file: PathBuf,
+
+    /// Kind of records in the encrypted files
+    #[arg(long, value_enum, default_value_t)]
+    report_type: ReportType,
+
+    /// Number of worker threads. Defaults to the number of available CPUs
+    #[arg(long)]
+    threads: Option<NonZeroUsize>,
+}
+
+/// Plaintext record that can be secret shared into an [`OprfReport`] and put back together from
+/// its shares.
+trait Record: InputItem + CsvSerializer + Sized {
+    fn into_shares<R: Rng>(self, rng: &mut R) -> [Report; 3];
+
+    fn from_shares(shares: [Report; 3]) -> Self;
+}
+
+impl Record for TestRawDataRecord {
+    fn into_shares<R: Rng>(self, rng: &mut R) -> [Report; 3] {
+        self.share_with(rng)
+    }
+
+    fn from_shares([r1, r2, r3]: [Report; 3]) -> Self {
+        // event type isn't secret shared, so we explictly make sure it is consistent across all
+        // three files
+        assert_eq!(r1.event_type, r2.event_type);
+        assert_eq!(r2.event_type, r3.event_type);
+
+        TestRawDataRecord {
+            timestamp: [r1.timestamp, r2.timestamp, r3.timestamp]
+                .reconstruct()
+                .as_u128()
+                .try_into()
+                .unwrap(),
+            user_id: [r1.match_key, r2.match_key, r3.match_key]
+                .reconstruct()
+                .as_u128()
+                .try_into()
+                .unwrap(),
+            is_trigger_report: r1.event_type == EventType::Trigger,
+            breakdown_key: [r1.breakdown_key, r2.breakdown_key, r3.breakdown_key]
+                .reconstruct()
+                .as_u128()
+                .try_into()
+                .unwrap(),
+            trigger_value: [r1.trigger_value, r2.trigger_value, r3.trigger_value]
+                .reconstruct()
+                .as_u128()
+                .try_into()
+                .unwrap(),
+        }
+    }
+}
+
+/// Hybrid reports use the same encrypted format as IPA reports: impressions are source events
+/// and conversions are trigger events.
+impl Record for TestHybridRecord {
+    fn into_shares<R: Rng>(self, rng: &mut R) -> [Report; 3] {
+        let record = match self {
+            TestHybridRecord::TestImpression {
+                match_key,
+                breakdown_key,
+            } => TestRawDataRecord {
+                timestamp: 0,
+                user_id: match_key,
+                is_trigger_report: false,
+                breakdown_key,
+                trigger_value: 0,
+            },
+            TestHybridRecord::TestConversion { match_key, value } => TestRawDataRecord {
+                timestamp: 0,
+                user_id: match_key,
+                is_trigger_report: true,
+                breakdown_key: 0,
+                trigger_value: value,
+            },
+        };
+
+        record.share_with(rng)
+    }
+
+    fn from_shares(shares: [Report; 3]) -> Self {
+        let record = TestRawDataRecord::from_shares(shares);
+        if record.is_trigger_report {
+            TestHybridRecord::TestConversion {
+                match_key: record.user_id,
+                value: record.trigger_value,
+            }
+        } else {
+            TestHybridRecord::TestImpression {
+                match_key: record.user_id,
+                breakdown_key: record.breakdown_key,
+            }
+        }
+    }
+}
+
+/// Logs how many reports have been processed so far, at most once per [`PROGRESS_INTERVAL`].
+struct Progress {
+    action: &'static str,
+    total_bytes: Option<u64>,
+    bytes: u64,
+    reports: u64,
+    started: Instant,
+    last_logged: Instant,
+}
+
+impl Progress {
+    fn new(action: &'static str, input: &Path) -> Self {
+        let now = Instant::now();
+        Self {
+            action,
+            total_bytes: input.metadata().ok().map(|m| m.len()),
+            bytes: 0,
+            reports: 0,
+            started: now,
+            last_logged: now,
+        }
+    }
+
+    fn advance(&mut self, reports: usize, bytes: u64) {
+        self.reports += u64::try_from(reports).unwrap();
+        self.bytes += bytes;
+        if self.last_logged.elapsed() >= PROGRESS_INTERVAL {
+            self.last_logged = Instant::now();
+            match self.total_bytes {
+                Some(total) if total > 0 => tracing::info!(
+                    "{} {} reports ({}%)",
+                    self.action,
+                    self.reports,
+                    self.bytes.saturating_mul(100) / total
+                ),
+                _ => tracing::info!("{} {} reports", self.action, self.reports),
+            }
+        }
+    }
+
+    fn finish(&self) {
+        tracing::info!(
+            "{} {} reports in {:.1?}",
+            self.action,
+            self.reports,
+            self.started.elapsed()
+        );
+    }
+}
+
+fn threads(requested: Option<NonZeroUsize>) -> usize {
+    requested
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, NonZeroUsize::get)
+}
+
+/// Reads the next chunk of up to `len` lines, skipping empty ones.
+fn read_chunk<R: BufRead>(lines: &mut Lines<R>, len: usize) -> io::Result<Vec<String>> {
+    let mut chunk = Vec::with_capacity(len);
+    for line in lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            chunk.push(line);
+            if chunk.len() == len {
+                break;
+            }
+        }
+    }
+
+    Ok(chunk)
+}
+
+/// Applies `f` to every batch on up to `threads` worker threads, keeping the order of batches.
+/// A panic on any worker is propagated to the caller.
+fn map_batches<I, O, F>(batches: &[I], threads: usize, f: F) -> Vec<O>
+where
+    I: Sync,
+    O: Send,
+    F: Fn(&I) -> O + Sync,
+{
+    let per_thread = batches.len().div_ceil(threads).max(1);
+    thread::scope(|s| {
+        let workers = batches
+            .chunks(per_thread)
+            .map(|batches| s.spawn(|| batches.iter().map(&f).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| resume_unwind(e)))
+            .collect()
+    })
+}
+
+fn encrypt_batch<T: Record>(
+    lines: &[String],
+    seed: <StdRng as SeedableRng>::Seed,
+    key_registries: [(KeyIdentifier, &KeyRegistry<PublicKeyOnly>); 3],
+) -> [Vec<u8>; 3] {
+    let mut rng = StdRng::from_seed(seed);
+    let mut output = [Vec::new(), Vec::new(), Vec::new()];
+    for line in lines {
+        let shares = T::from_str(line).into_shares(&mut rng);
+        for ((share, (key_id, key_registry)), output) in
+            zip(zip(shares, key_registries), &mut output)
+        {
+            let encrypted = share.encrypt(key_id, key_registry, &mut rng).unwrap();
+            output.extend_from_slice(hex::encode(encrypted).as_bytes());
+            output.push(b'\n');
+        }
+    }
+
+    output
+}
+
+fn encrypt_all<T: Record>(
+    args: &EncryptArgs,
+    key_registries: [(KeyIdentifier, &KeyRegistry<PublicKeyOnly>); 3],
+    mut writers: [BufWriter<File>; 3],
+) -> Result<(), BoxError> {
+    let threads = threads(args.threads);
+    let mut rng = args
+        .seed
+        .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let mut lines = BufReader::new(File::open(&args.input_file)?).lines();
+    let mut progress = Progress::new("encrypted", &args.input_file);
+
+    loop {
+        let chunk = read_chunk(&mut lines, threads * BATCH_SIZE)?;
+        if chunk.is_empty() {
+            break;
+        }
+        let batches = chunk
+            .chunks(BATCH_SIZE)
+            .map(|batch| (batch, rng.gen()))
+            .collect::<Vec<_>>();
+        let encrypted = map_batches(&batches, threads, |(batch, seed)| {
+            encrypt_batch::<T>(batch, *seed, key_registries)
+        });
+        for batch in encrypted {
+            for (writer, output) in zip(&mut writers, batch) {
+                writer.write_all(&output)?;
+            }
+        }
+        progress.advance(
+            chunk.len(),
+            chunk.iter().map(|line| line.len() as u64 + 1).sum(),
+        );
+    }
+    for writer in &mut writers {
+        writer.flush()?;
+    }
+    progress.finish();
+
+    Ok(())
 }
 
 /// # Panics
@@ -78,9 +367,6 @@ pub struct DecryptArgs {
 /// # Errors
 /// if it cannot open the files
 pub fn encrypt(args: &EncryptArgs) -> Result<(), BoxError> {
-    let input = InputSource::from_file(&args.input_file);
-
-    let mut rng = thread_rng();
     let mut key_registries = KeyRegistries::default();
 
     let network = NetworkConfig::from_toml_str(
@@ -97,70 +383,96 @@ pub fn encrypt(args: &EncryptArgs) -> Result<(), BoxError> {
         panic!("could not load network file")
     };
 
-    let shares: [Vec<OprfReport<BreakdownKey, TriggerValue, Timestamp>>; 3] =
-        input.iter::<TestRawDataRecord>().share();
-
-    for (index, (shares, key_registry)) in zip(shares, key_registries).enumerate() {
-        let output_filename = format!("helper{}.enc", index + 1);
-        let mut writer = OpenOptions::new()
+    let writers = [1, 2, 3].map(|helper| {
+        let output_filename = format!("helper{helper}.enc");
+        let file = OpenOptions::new()
             .write(true)
             .create_new(true)
             .open(args.output_dir.join(&output_filename))
             .unwrap_or_else(|e| panic!("unable write to {}. {}", &output_filename, e));
+        BufWriter::new(file)
+    });
 
-        for share in shares {
-            let output = share
-                .encrypt(DEFAULT_KEY_ID, key_registry, &mut rng)
-                .unwrap();
-            let hex_output = hex::encode(&output);
-            writeln!(writer, "{hex_output}")?;
-        }
+    match args.report_type {
+        ReportType::Ipa => encrypt_all::<TestRawDataRecord>(args, key_registries, writers),
+        ReportType::Hybrid => encrypt_all::<TestHybridRecord>(args, key_registries, writers),
     }
-
-    Ok(())
 }
 
+/// Encrypted key files are decrypted with the key-encryption key from the environment.
 async fn build_hpke_registry(
     private_key_file: PathBuf,
 ) -> Result<KeyRegistry<PrivateKeyOnly>, BoxError> {
     let mk_encryption = Some(HpkeServerConfig::File { private_key_file });
-    let key_registry = hpke_registry(mk_encryption.as_ref()).await?;
+    let kek = KeyEncryptionKey::load(None)?;
+    let key_registry = hpke_registry(mk_encryption.as_ref(), kek.as_ref()).await?;
     Ok(key_registry)
 }
 
-struct DecryptedReports {
-    reader: BufReader<File>,
-    key_registry: KeyRegistry<PrivateKeyOnly>,
+fn open_encrypted(filename: &Path) -> Lines<BufReader<File>> {
+    let file = File::open(filename)
+        .unwrap_or_else(|e| panic!("unable to open file {}. {e}", filename.display()));
+    BufReader::new(file).lines()
 }
 
-impl DecryptedReports {
-    fn new(filename: &PathBuf, key_registry: KeyRegistry<PrivateKeyOnly>) -> Self {
-        let file = File::open(filename)
-            .unwrap_or_else(|e| panic!("unable to open file {filename:?}. {e}"));
-        let reader = BufReader::new(file);
-        Self {
-            reader,
-            key_registry,
-        }
-    }
-}
-
-impl Iterator for DecryptedReports {
-    type Item = OprfReport<BA8, BA3, BA20>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut line = String::new();
-        if self.reader.read_line(&mut line).unwrap() > 0 {
+fn decrypt_batch<T: Record>(
+    lines: &[[String; 3]],
+    key_registries: &[KeyRegistry<PrivateKeyOnly>; 3],
+) -> Vec<u8> {
+    let mut output = Vec::new();
+    for helper_lines in lines {
+        let reports = zip(helper_lines, key_registries).map(|(line, key_registry)| {
             let encrypted_report_bytes = hex::decode(line.trim()).unwrap();
             let enc_report =
                 EncryptedOprfReport::from_bytes(encrypted_report_bytes.as_slice()).unwrap();
-            let dec_report: OprfReport<BA8, BA3, BA20> =
-                enc_report.decrypt(&self.key_registry).unwrap();
-            Some(dec_report)
-        } else {
-            None
+            let report: Report = enc_report.decrypt(key_registry).unwrap();
+            report
+        });
+        let reports: [Report; 3] = reports.collect::<Vec<_>>().try_into().unwrap();
+        T::from_shares(reports).to_csv(&mut output).unwrap();
+        output.push(b'\n');
+    }
+
+    output
+}
+
+fn decrypt_all<T: Record>(
+    args: &DecryptArgs,
+    key_registries: &[KeyRegistry<PrivateKeyOnly>; 3],
+    mut writer: BufWriter<File>,
+) -> Result<(), BoxError> {
+    let threads = threads(args.threads);
+    let mut files = [&args.input_file1, &args.input_file2, &args.input_file3]
+        .map(|filename| open_encrypted(filename));
+    let mut progress = Progress::new("decrypted", &args.input_file1);
+
+    loop {
+        let chunk = {
+            let [c1, c2, c3] = files
+                .each_mut()
+                .map(|lines| read_chunk(lines, threads * BATCH_SIZE));
+            zip(c1?, zip(c2?, c3?))
+                .map(|(l1, (l2, l3))| [l1, l2, l3])
+                .collect::<Vec<_>>()
+        };
+        if chunk.is_empty() {
+            break;
         }
+        let batches = chunk.chunks(BATCH_SIZE).collect::<Vec<_>>();
+        for output in map_batches(&batches, threads, |batch| {
+            decrypt_batch::<T>(batch, key_registries)
+        }) {
+            writer.write_all(&output)?;
+        }
+        progress.advance(
+            chunk.len(),
+            chunk.iter().map(|[line, _, _]| line.len() as u64 + 1).sum(),
+        );
     }
+    writer.flush()?;
+    progress.finish();
+
+    Ok(())
 }
 
 /// # Panics
@@ -168,103 +480,336 @@ impl Iterator for DecryptedReports {
 /// # Errors
 /// if it cannot open the files
 pub async fn decrypt_and_reconstruct(args: DecryptArgs) -> Result<(), BoxError> {
-    let key_registry1 = build_hpke_registry(args.mk_private_key1).await?;
-    let key_registry2 = build_hpke_registry(args.mk_private_key2).await?;
-    let key_registry3 = build_hpke_registry(args.mk_private_key3).await?;
-    let decrypted_reports1 = DecryptedReports::new(&args.input_file1, key_registry1);
-    let decrypted_reports2 = DecryptedReports::new(&args.input_file2, key_registry2);
-    let decrypted_reports3 = DecryptedReports::new(&args.input_file3, key_registry3);
-
-    let mut writer = Box::new(
+    let key_registries = [
+        build_hpke_registry(args.mk_private_key1.clone()).await?,
+        build_hpke_registry(args.mk_private_key2.clone()).await?,
+        build_hpke_registry(args.mk_private_key3.clone()).await?,
+    ];
+
+    let writer = BufWriter::new(
         OpenOptions::new()
             .write(true)
             .create_new(true)
-            .open(args.output_file)?,
+            .open(&args.output_file)?,
     );
 
-    for (dec_report1, (dec_report2, dec_report3)) in
-        decrypted_reports1.zip(decrypted_reports2.zip(decrypted_reports3))
-    {
-        let timestamp = [
-            dec_report1.timestamp,
-            dec_report2.timestamp,
-            dec_report3.timestamp,
-        ]
-        .reconstruct()
-        .as_u128();
+    match args.report_type {
+        ReportType::Ipa => decrypt_all::<TestRawDataRecord>(&args, &key_registries, writer),
+        ReportType::Hybrid => decrypt_all::<TestHybridRecord>(&args, &key_registries, writer),
+    }
+}
 
-        let match_key = [
-            dec_report1.match_key,
-            dec_report2.match_key,
-            dec_report3.match_key,
-        ]
-        .reconstruct()
-        .as_u128();
-
-        // these aren't reconstucted, so we explictly make sure
-        // they are consistent across all three files, then set
-        // it to the first one (without loss of generality)
-        assert_eq!(dec_report1.event_type, dec_report2.event_type);
-        assert_eq!(dec_report2.event_type, dec_report3.event_type);
-        let is_trigger_report = dec_report1.event_type == EventType::Trigger;
-
-        let breakdown_key = [
-            dec_report1.breakdown_key,
-            dec_report2.breakdown_key,
-            dec_report3.breakdown_key,
-        ]
-        .reconstruct()
-        .as_u128();
+/// How reports are framed in an encrypted file.
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// Every report is preceded by its length as `u16` LE, like query inputs sent to helpers.
+    #[default]
+    LengthDelimited,
+    /// One hex-encoded report per line, like files written by `encrypt`.
+    Hex,
+}
 
-        let trigger_value = [
-            dec_report1.trigger_value,
-            dec_report2.trigger_value,
-            dec_report3.trigger_value,
-        ]
-        .reconstruct()
-        .as_u128();
+#[derive(Debug, Parser)]
+#[clap(
+    name = "test_inspect",
+    about = "Summarize and validate encrypted reports"
+)]
+#[command(about)]
+pub struct InspectArgs {
+    /// Path to file with encrypted reports
+    #[arg(long)]
+    input_file: PathBuf,
+
+    /// How reports are framed in the input file
+    #[arg(long, value_enum, default_value_t)]
+    format: ReportFormat,
+
+    /// Private key of the helper the file is meant for. If set, a sample of reports is decrypted
+    /// to check that they were encrypted for this helper
+    #[arg(long)]
+    mk_private_key: Option<PathBuf>,
+
+    /// Number of reports to decrypt, starting from the first one
+    #[arg(long, default_value_t = 100)]
+    sample: usize,
+}
+
+/// Number of malformed records and decryption failures listed individually in the summary.
+const MAX_LISTED_ERRORS: usize = 20;
+
+/// Problem with the record at `offset` bytes from the start of the file. Records are numbered
+/// from 1.
+#[derive(Debug)]
+pub struct RecordError {
+    pub record: usize,
+    pub offset: u64,
+    pub reason: String,
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "record {} at offset {}: {}",
+            self.record, self.offset, self.reason
+        )
+    }
+}
+
+/// What [`inspect`] found in an encrypted file.
+#[derive(Debug, Default)]
+pub struct InspectSummary {
+    /// Number of well-formed reports.
+    pub reports: usize,
+    pub sources: usize,
+    pub triggers: usize,
+    /// Number of reports per key id, epoch and site domain.
+    pub key_ids: BTreeMap<KeyIdentifier, usize>,
+    pub epochs: BTreeMap<Epoch, usize>,
+    pub site_domains: BTreeMap<String, usize>,
+    /// Number of records that could not be parsed, and the first few of them.
+    pub malformed: usize,
+    pub malformed_records: Vec<RecordError>,
+    /// Number of reports decrypted successfully with the private key, if one was given.
+    pub decrypted: usize,
+    /// Number of reports that could not be decrypted, and the first few of them.
+    pub undecryptable: usize,
+    pub undecryptable_records: Vec<RecordError>,
+}
+
+impl InspectSummary {
+    /// Whether all records are well-formed and all decrypted reports in the sample decrypted
+    /// successfully.
+    #[must_use]
+    pub fn is_valid(&self) -> bool {
+        self.malformed == 0 && self.undecryptable == 0
+    }
 
+    fn add_malformed(&mut self, record: usize, offset: u64, reason: String) {
+        self.malformed += 1;
+        if self.malformed_records.len() < MAX_LISTED_ERRORS {
+            self.malformed_records.push(RecordError {
+                record,
+                offset,
+                reason,
+            });
+        }
+    }
+
+    fn add_undecryptable(&mut self, record: usize, offset: u64, reason: String) {
+        self.undecryptable += 1;
+        if self.undecryptable_records.len() < MAX_LISTED_ERRORS {
+            self.undecryptable_records.push(RecordError {
+                record,
+                offset,
+                reason,
+            });
+        }
+    }
+}
+
+fn write_counts<K: Display>(
+    f: &mut Formatter<'_>,
+    name: &str,
+    counts: &BTreeMap<K, usize>,
+) -> std::fmt::Result {
+    write!(f, "{name}:")?;
+    for (key, count) in counts {
+        write!(f, " {key} ({count})")?;
+    }
+    writeln!(f)
+}
+
+impl Display for InspectSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         writeln!(
-            writer,
-            "{},{},{},{},{}",
-            timestamp,
-            match_key,
-            u8::from(is_trigger_report),
-            breakdown_key,
-            trigger_value,
+            f,
+            "reports: {} ({} sources, {} triggers)",
+            self.reports, self.sources, self.triggers
         )?;
+        write_counts(f, "key ids", &self.key_ids)?;
+        if let (Some(first), Some(last)) = (self.epochs.keys().next(), self.epochs.keys().last()) {
+            writeln!(f, "epoch range: {first}..={last}")?;
+        }
+        write_counts(f, "epochs", &self.epochs)?;
+        write_counts(f, "site domains", &self.site_domains)?;
+        writeln!(f, "malformed records: {}", self.malformed)?;
+        for error in &self.malformed_records {
+            writeln!(f, "  {error}")?;
+        }
+        if self.decrypted + self.undecryptable > 0 {
+            writeln!(
+                f,
+                "decryption check: {} of {} reports decrypted",
+                self.decrypted,
+                self.decrypted + self.undecryptable
+            )?;
+            for error in &self.undecryptable_records {
+                writeln!(f, "  {error}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads into `buf` until it is full or the reader is exhausted, returning the number of bytes
+/// read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Calls `f` with the offset and contents of every record in the file, or the reason it can't be
+/// read. A truncated record can only be the last one, because the rest of the file can't be
+/// framed after it.
+fn for_each_record<R: BufRead, F: FnMut(u64, Result<Vec<u8>, String>)>(
+    mut reader: R,
+    format: ReportFormat,
+    mut f: F,
+) -> io::Result<()> {
+    let mut offset = 0_u64;
+    match format {
+        ReportFormat::LengthDelimited => loop {
+            let mut len = [0_u8; 2];
+            match read_full(&mut reader, &mut len)? {
+                0 => break,
+                2 => {}
+                n => {
+                    f(
+                        offset,
+                        Err(format!("truncated length prefix: {n} of 2 bytes")),
+                    );
+                    break;
+                }
+            }
+            let len = usize::from(u16::from_le_bytes(len));
+            let mut data = vec![0; len];
+            let read = read_full(&mut reader, &mut data)?;
+            if read < len {
+                f(
+                    offset,
+                    Err(format!("truncated record: {read} of {len} bytes")),
+                );
+                break;
+            }
+            f(offset, Ok(data));
+            offset += 2 + len as u64;
+        },
+        ReportFormat::Hex => {
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let read = reader.read_until(b'\n', &mut line)?;
+                if read == 0 {
+                    break;
+                }
+                let trimmed = line.trim_ascii();
+                if !trimmed.is_empty() {
+                    f(
+                        offset,
+                        hex::decode(trimmed).map_err(|e| format!("invalid hex: {e}")),
+                    );
+                }
+                offset += read as u64;
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Parses encrypted reports without decrypting them and summarizes what is in the file. If a
+/// private key is given, also checks that a sample of reports can be decrypted with it.
+///
+/// # Errors
+/// If the input file or the private key can't be read.
+pub async fn inspect(args: &InspectArgs) -> Result<InspectSummary, BoxError> {
+    let key_registry = match &args.mk_private_key {
+        Some(path) => Some(build_hpke_registry(path.clone()).await?),
+        None => None,
+    };
+    let reader = BufReader::new(File::open(&args.input_file)?);
+    let mut summary = InspectSummary::default();
+    let mut record = 0;
+
+    for_each_record(reader, args.format, |offset, data| {
+        record += 1;
+        let report = data.and_then(|data| {
+            EncryptedOprfReport::<BreakdownKey, TriggerValue, Timestamp, _>::from_bytes(data)
+                .map_err(|e| e.to_string())
+        });
+        let report = match report {
+            Ok(report) => report,
+            Err(reason) => {
+                summary.add_malformed(record, offset, reason);
+                return;
+            }
+        };
+
+        summary.reports += 1;
+        match report.event_type() {
+            EventType::Source => summary.sources += 1,
+            EventType::Trigger => summary.triggers += 1,
+        }
+        *summary.key_ids.entry(report.key_id()).or_default() += 1;
+        *summary.epochs.entry(report.epoch()).or_default() += 1;
+        *summary
+            .site_domains
+            .entry(report.site_domain().to_owned())
+            .or_default() += 1;
+
+        if let Some(key_registry) = &key_registry {
+            if summary.decrypted + summary.undecryptable < args.sample {
+                match report.decrypt(key_registry) {
+                    Ok(_) => summary.decrypted += 1,
+                    Err(e) => summary.add_undecryptable(record, offset, e.to_string()),
+                }
+            }
+        }
+    })?;
+
+    Ok(summary)
+}
+
 #[cfg(all(test, feature = "in-memory-infra"))]
 mod tests {
     use std::{
         fs::File,
         io::{BufRead, BufReader, Write},
+        iter::zip,
         path::Path,
         sync::Arc,
     };
 
     use clap::Parser;
-    use hpke::Deserializable;
     use rand::thread_rng;
     use tempfile::{tempdir, NamedTempFile};
 
     use crate::{
         cli::{
-            crypto::{decrypt_and_reconstruct, encrypt, DecryptArgs, EncryptArgs},
+            crypto::{
+                decrypt_and_reconstruct, encrypt, inspect, DecryptArgs, EncryptArgs, InspectArgs,
+            },
             CsvSerializer,
         },
         ff::{boolean_array::BA16, U128Conversions},
-        helpers::query::{IpaQueryConfig, QuerySize},
+        helpers::query::{DpConfig, IpaQueryConfig, QuerySize},
         hpke::{IpaPrivateKey, KeyRegistry, PrivateKeyOnly},
         query::OprfIpaQuery,
-        report::EncryptedOprfReportStreams,
+        report::{EncryptedOprfReportStreams, DEFAULT_KEY_ID},
         test_fixture::{
-            ipa::TestRawDataRecord, join3v, EventGenerator, EventGeneratorConfig, Reconstruct,
-            TestWorld,
+            hybrid::TestHybridRecord, ipa::TestRawDataRecord, join3v, EventGenerator,
+            EventGeneratorConfig, Reconstruct, TestWorld,
         },
     };
 
@@ -331,18 +876,73 @@ public_key = "b900be35da06106a83ed73c33f733e03e4ea5888b7ea4c912ab270b0b0f8381e"
         output_dir: &Path,
         network_file: &Path,
     ) -> EncryptArgs {
-        EncryptArgs::try_parse_from([
-            "test_encrypt",
-            "--input-file",
-            input_file.to_str().unwrap(),
-            "--output-dir",
-            output_dir.to_str().unwrap(),
-            "--network",
-            network_file.to_str().unwrap(),
-        ])
+        build_encrypt_args_with(input_file, output_dir, network_file, &[])
+    }
+
+    fn build_encrypt_args_with(
+        input_file: &Path,
+        output_dir: &Path,
+        network_file: &Path,
+        extra_args: &[&str],
+    ) -> EncryptArgs {
+        EncryptArgs::try_parse_from(
+            [
+                "test_encrypt",
+                "--input-file",
+                input_file.to_str().unwrap(),
+                "--output-dir",
+                output_dir.to_str().unwrap(),
+                "--network",
+                network_file.to_str().unwrap(),
+            ]
+            .iter()
+            .chain(extra_args),
+        )
         .unwrap()
     }
 
+    /// Decrypt arguments for files written by [`encrypt`] to `output_dir`, using the private keys
+    /// matching [`write_network_file`].
+    fn build_decrypt_args_for(
+        output_dir: &Path,
+        mk_private_keys: &[NamedTempFile; 3],
+        decrypt_output: &Path,
+        extra_args: &[&str],
+    ) -> DecryptArgs {
+        let enc = [1, 2, 3].map(|i| output_dir.join(format!("helper{i}.enc")));
+        DecryptArgs::try_parse_from(
+            [
+                "test_decrypt",
+                "--input-file1",
+                enc[0].to_str().unwrap(),
+                "--input-file2",
+                enc[1].to_str().unwrap(),
+                "--input-file3",
+                enc[2].to_str().unwrap(),
+                "--mk-private-key1",
+                mk_private_keys[0].path().to_str().unwrap(),
+                "--mk-private-key2",
+                mk_private_keys[1].path().to_str().unwrap(),
+                "--mk-private-key3",
+                mk_private_keys[2].path().to_str().unwrap(),
+                "--output-file",
+                decrypt_output.to_str().unwrap(),
+            ]
+            .iter()
+            .chain(extra_args),
+        )
+        .unwrap()
+    }
+
+    fn write_mk_private_keys() -> [NamedTempFile; 3] {
+        [
+            "53d58e022981f2edbf55fec1b45dbabd08a3442cb7b7c598839de5d7a5888bff",
+            "3a0a993a3cfc7e8d381addac586f37de50c2a14b1a6356d71e94ca2afaeb2569",
+            "1fb5c5274bf85fbe6c7935684ef05499f6cfb89ac21640c28330135cc0e8a0f7",
+        ]
+        .map(write_mk_private_key)
+    }
+
     fn build_decrypt_args(
         enc1: &Path,
         enc2: &Path,
@@ -535,6 +1135,241 @@ public_key = "cfdbaaff16b30aa8a4ab07eaad2cdd80458208a1317aefbb807e46dce596617e"
         are_files_equal(input_file.path(), &decrypt_output);
     }
 
+    #[tokio::test]
+    async fn encrypt_deterministic_with_seed() {
+        let input_file = write_input_file();
+        let network_file = write_network_file();
+        let output_dirs = [tempdir().unwrap(), tempdir().unwrap()];
+        for (output_dir, threads) in zip(&output_dirs, ["1", "3"]) {
+            encrypt(&build_encrypt_args_with(
+                input_file.path(),
+                output_dir.path(),
+                network_file.path(),
+                &["--seed", "42", "--threads", threads],
+            ))
+            .unwrap();
+        }
+        for i in 1..=3 {
+            let file = format!("helper{i}.enc");
+            are_files_equal(
+                &output_dirs[0].path().join(&file),
+                &output_dirs[1].path().join(&file),
+            );
+        }
+
+        let decrypt_output = output_dirs[0].path().join("output");
+        decrypt_and_reconstruct(build_decrypt_args_for(
+            output_dirs[0].path(),
+            &write_mk_private_keys(),
+            &decrypt_output,
+            &["--threads", "2"],
+        ))
+        .await
+        .unwrap();
+        are_files_equal(input_file.path(), &decrypt_output);
+    }
+
+    #[tokio::test]
+    async fn encrypt_and_decrypt_hybrid() {
+        let records = [
+            TestHybridRecord::TestImpression {
+                match_key: 12345,
+                breakdown_key: 2,
+            },
+            TestHybridRecord::TestConversion {
+                match_key: 12345,
+                value: 5,
+            },
+            TestHybridRecord::TestImpression {
+                match_key: 68362,
+                breakdown_key: 1,
+            },
+            TestHybridRecord::TestConversion {
+                match_key: 68362,
+                value: 2,
+            },
+            TestHybridRecord::TestConversion {
+                match_key: 11111,
+                value: 7,
+            },
+        ];
+        let mut input_file = NamedTempFile::new().unwrap();
+        for record in records {
+            record.to_csv(input_file.as_file_mut()).unwrap();
+            writeln!(input_file.as_file()).unwrap();
+        }
+        input_file.flush().unwrap();
+        let output_dir = tempdir().unwrap();
+        let network_file = write_network_file();
+        encrypt(&build_encrypt_args_with(
+            input_file.path(),
+            output_dir.path(),
+            network_file.path(),
+            &["--report-type", "hybrid"],
+        ))
+        .unwrap();
+
+        let decrypt_output = output_dir.path().join("output");
+        decrypt_and_reconstruct(build_decrypt_args_for(
+            output_dir.path(),
+            &write_mk_private_keys(),
+            &decrypt_output,
+            &["--report-type", "hybrid"],
+        ))
+        .await
+        .unwrap();
+        are_files_equal(input_file.path(), &decrypt_output);
+    }
+
+    /// Converts a file written by [`encrypt`] into length-delimited reports.
+    fn write_length_delimited(enc_file: &Path) -> (NamedTempFile, Vec<Vec<u8>>) {
+        let reports = BufReader::new(File::open(enc_file).unwrap())
+            .lines()
+            .map(|line| hex::decode(line.unwrap()).unwrap())
+            .collect::<Vec<_>>();
+        let mut file = NamedTempFile::new().unwrap();
+        for report in &reports {
+            write_delimited(file.as_file_mut(), report);
+        }
+        (file, reports)
+    }
+
+    fn write_delimited(file: &mut File, data: &[u8]) {
+        file.write_all(&u16::try_from(data.len()).unwrap().to_le_bytes())
+            .unwrap();
+        file.write_all(data).unwrap();
+    }
+
+    fn build_inspect_args(input_file: &Path, extra_args: &[&str]) -> InspectArgs {
+        InspectArgs::try_parse_from(
+            ["test_inspect", "--input-file", input_file.to_str().unwrap()]
+                .iter()
+                .chain(extra_args),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn inspect_encrypted_file() {
+        let input_file = write_input_file();
+        let output_dir = tempdir().unwrap();
+        let network_file = write_network_file();
+        encrypt(&build_encrypt_args(
+            input_file.path(),
+            output_dir.path(),
+            network_file.path(),
+        ))
+        .unwrap();
+        let enc_file = output_dir.path().join("helper1.enc");
+        let (delimited_file, _) = write_length_delimited(&enc_file);
+
+        for args in [
+            build_inspect_args(delimited_file.path(), &[]),
+            build_inspect_args(&enc_file, &["--format", "hex"]),
+        ] {
+            let summary = inspect(&args).await.unwrap();
+            assert!(summary.is_valid(), "{summary}");
+            assert_eq!(10, summary.reports);
+            assert_eq!(10, summary.sources + summary.triggers);
+            assert_eq!(
+                vec![(DEFAULT_KEY_ID, 10)],
+                summary.key_ids.into_iter().collect::<Vec<_>>()
+            );
+            assert_eq!(10, summary.epochs.values().sum::<usize>());
+            assert_eq!(10, summary.site_domains.values().sum::<usize>());
+            assert_eq!(0, summary.decrypted + summary.undecryptable);
+        }
+    }
+
+    #[tokio::test]
+    async fn inspect_malformed_records() {
+        let input_file = write_input_file();
+        let output_dir = tempdir().unwrap();
+        let network_file = write_network_file();
+        encrypt(&build_encrypt_args(
+            input_file.path(),
+            output_dir.path(),
+            network_file.path(),
+        ))
+        .unwrap();
+        let (_, reports) = write_length_delimited(&output_dir.path().join("helper1.enc"));
+
+        let mut file = NamedTempFile::new().unwrap();
+        write_delimited(file.as_file_mut(), &reports[0]);
+        write_delimited(file.as_file_mut(), &[1, 2, 3]);
+        write_delimited(file.as_file_mut(), &reports[1]);
+        // length prefix promises more bytes than there are left in the file
+        file.write_all(&100_u16.to_le_bytes()).unwrap();
+        file.write_all(&reports[2][..10]).unwrap();
+
+        let summary = inspect(&build_inspect_args(file.path(), &[]))
+            .await
+            .unwrap();
+        assert!(!summary.is_valid());
+        assert_eq!(2, summary.reports);
+        assert_eq!(2, summary.malformed);
+        let second_offset = 2 + reports[0].len() as u64;
+        let fourth_offset = second_offset + 2 + 3 + 2 + reports[1].len() as u64;
+        assert_eq!(
+            vec![(2, second_offset), (4, fourth_offset)],
+            summary
+                .malformed_records
+                .iter()
+                .map(|e| (e.record, e.offset))
+                .collect::<Vec<_>>()
+        );
+        assert!(summary.malformed_records[1]
+            .reason
+            .contains("truncated record: 10 of 100 bytes"));
+    }
+
+    #[tokio::test]
+    async fn inspect_decrypts_sample() {
+        let input_file = write_input_file();
+        let output_dir = tempdir().unwrap();
+        let network_file = write_network_file();
+        encrypt(&build_encrypt_args(
+            input_file.path(),
+            output_dir.path(),
+            network_file.path(),
+        ))
+        .unwrap();
+        let (delimited_file, _) = write_length_delimited(&output_dir.path().join("helper1.enc"));
+        let [key1, key2, _] = write_mk_private_keys();
+
+        let summary = inspect(&build_inspect_args(
+            delimited_file.path(),
+            &[
+                "--mk-private-key",
+                key1.path().to_str().unwrap(),
+                "--sample",
+                "4",
+            ],
+        ))
+        .await
+        .unwrap();
+        assert!(summary.is_valid(), "{summary}");
+        assert_eq!((4, 0), (summary.decrypted, summary.undecryptable));
+
+        // reports encrypted for helper 1 can't be decrypted by helper 2
+        let summary = inspect(&build_inspect_args(
+            delimited_file.path(),
+            &["--mk-private-key", key2.path().to_str().unwrap()],
+        ))
+        .await
+        .unwrap();
+        assert!(!summary.is_valid());
+        assert_eq!((0, 10), (summary.decrypted, summary.undecryptable));
+        assert_eq!(
+            (1..=10).collect::<Vec<_>>(),
+            summary
+                .undecryptable_records
+                .iter()
+                .map(|e| e.record)
+                .collect::<Vec<_>>()
+        );
+    }
+
     #[tokio::test]
     async fn encrypt_and_execute_query() {
         const EXPECTED: &[u128] = &[0, 2, 5];
@@ -609,13 +1444,10 @@ public_key = "cfdbaaff16b30aa8a4ab07eaad2cdd80458208a1317aefbb807e46dce596617e"
                 .zip(world.contexts())
                 .zip(mk_private_keys.into_iter())
                 .map(|((input, ctx), mk_private_key)| {
-                    let mk_private_key = hex::decode(mk_private_key)
-                        .map(|bytes| IpaPrivateKey::from_bytes(&bytes).unwrap())
-                        .unwrap();
+                    let mk_private_key = IpaPrivateKey::decode(mk_private_key).unwrap();
                     let query_config = IpaQueryConfig {
                         max_breakdown_key: 3,
-                        with_dp: 0,
-                        epsilon: 1.0,
+                        dp: DpConfig::no_noise().with_relaxed_padding(),
                         ..Default::default()
                     };
 
@@ -629,7 +1461,7 @@ public_key = "cfdbaaff16b30aa8a4ab07eaad2cdd80458208a1317aefbb807e46dce596617e"
         .await;
 
         assert_eq!(
-            results.reconstruct()[0..3]
+            results.map(|r| r.result).reconstruct()[0..3]
                 .iter()
                 .map(U128Conversions::as_u128)
                 .collect::<Vec<u128>>(),
@@ -0,0 +1,125 @@
+use crate::helpers::query::BreakdownHierarchy;
+
+/// Makes the noisy histograms of the levels of `hierarchy` consistent with each other: every
+/// breakdown of the output is the sum of its children at the next finer level.
+///
+/// This is the tree-based estimator of [Hay et al.], which finds the consistent histograms
+/// closest to `levels` in the least squares sense. It assumes that every level has the same
+/// noise, which is the case when levels split the DP budget evenly. The output is usually more
+/// accurate than `levels`, especially at the coarser levels, and because it only post-processes
+/// the query output, it does not spend any budget.
+///
+/// `levels` has the histograms of every level, from the coarsest to the finest.
+///
+/// ## Panics
+/// If `levels` does not have the shape of `hierarchy`.
+///
+/// [Hay et al.]: https://arxiv.org/abs/0904.0942
+#[must_use]
+pub fn consistent_estimates(hierarchy: &BreakdownHierarchy, levels: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    assert!(
+        hierarchy.level_sizes().eq(levels.iter().map(Vec::len)),
+        "histograms do not match breakdown hierarchy {hierarchy}"
+    );
+    let fanout = |level: usize| levels[level + 1].len() / levels[level].len();
+
+    // Bottom up, the best estimate of every breakdown from its own value and the values of its
+    // descendants, together with the variance of that estimate, relative to the noise variance.
+    let mut subtree = levels.to_vec();
+    let mut variance = levels
+        .iter()
+        .map(|level| vec![1.0; level.len()])
+        .collect::<Vec<_>>();
+    for level in (0..levels.len() - 1).rev() {
+        let fanout = fanout(level);
+        for i in 0..levels[level].len() {
+            let children = i * fanout..(i + 1) * fanout;
+            let sum = subtree[level + 1][children.clone()].iter().sum::<f64>();
+            let sum_variance = variance[level + 1][children].iter().sum::<f64>();
+            let weight = 1.0 / (1.0 + 1.0 / sum_variance);
+            subtree[level][i] = weight * (levels[level][i] + sum / sum_variance);
+            variance[level][i] = weight;
+        }
+    }
+
+    // Top down, every breakdown gets a share of the difference between the estimate of its
+    // parent and the sum of the estimates of its siblings.
+    let mut estimates = subtree.clone();
+    for level in 0..levels.len() - 1 {
+        let fanout = fanout(level);
+        for i in 0..levels[level].len() {
+            let children = i * fanout..(i + 1) * fanout;
+            let sum = subtree[level + 1][children.clone()].iter().sum::<f64>();
+            let sum_variance = variance[level + 1][children.clone()].iter().sum::<f64>();
+            let difference = estimates[level][i] - sum;
+            for child in children {
+                estimates[level + 1][child] = subtree[level + 1][child]
+                    + difference * variance[level + 1][child] / sum_variance;
+            }
+        }
+    }
+
+    estimates
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::consistent_estimates;
+    use crate::helpers::query::BreakdownHierarchy;
+
+    fn assert_close(expected: &[Vec<f64>], actual: &[Vec<f64>]) {
+        for (expected, actual) in expected.iter().flatten().zip(actual.iter().flatten()) {
+            assert!(
+                (expected - actual).abs() < 1e-9,
+                "expected {expected:?}, got {actual:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn two_levels() {
+        let hierarchy = BreakdownHierarchy::new(&[1, 1]).unwrap();
+        let estimates =
+            consistent_estimates(&hierarchy, &[vec![10.0, 0.0], vec![3.0, 5.0, 1.0, -1.0]]);
+        // the parent weighs its own value twice as much as the sum of its two children
+        let parent = (2.0 * 10.0 + 8.0) / 3.0;
+        assert_close(
+            &[
+                vec![parent, 0.0],
+                vec![
+                    3.0 + (parent - 8.0) / 2.0,
+                    5.0 + (parent - 8.0) / 2.0,
+                    1.0,
+                    -1.0,
+                ],
+            ],
+            &estimates,
+        );
+    }
+
+    #[test]
+    fn consistent() {
+        let hierarchy = BreakdownHierarchy::new(&[1, 2, 1]).unwrap();
+        let levels = hierarchy
+            .level_sizes()
+            .map(|size| {
+                (0..size)
+                    .map(|i| f64::from(u32::try_from(i * 7 % 5).unwrap()))
+                    .collect()
+            })
+            .collect::<Vec<Vec<f64>>>();
+        let estimates = consistent_estimates(&hierarchy, &levels);
+        for level in 0..estimates.len() - 1 {
+            let fanout = estimates[level + 1].len() / estimates[level].len();
+            for (parent, children) in estimates[level]
+                .iter()
+                .zip(estimates[level + 1].chunks(fanout))
+            {
+                assert!((parent - children.iter().sum::<f64>()).abs() < 1e-9);
+            }
+        }
+
+        // histograms that are already consistent do not change
+        assert_close(&estimates, &consistent_estimates(&hierarchy, &estimates));
+    }
+}
@@ -2,7 +2,11 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::helpers::query::{IpaQueryConfig, QuerySize};
+use crate::{
+    cli::consistent_estimates,
+    helpers::query::{IpaQueryConfig, QuerySize},
+    query::{NoiseMetadata, QueryMetadata},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct QueryResult {
@@ -14,4 +18,129 @@ pub struct QueryResult {
     )]
     pub latency: Duration,
     pub breakdowns: Vec<u32>,
+    /// If the query has a breakdown hierarchy, the histogram of every level, from the coarsest
+    /// to the finest. `breakdowns` has the finest level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub levels: Option<Vec<Vec<u32>>>,
+    /// Consistent estimates of `levels`, see [`Self::estimate_consistent_levels`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consistent_levels: Option<Vec<Vec<f64>>>,
+    /// Metadata reported by each helper.
+    #[serde(default)]
+    pub metadata: [QueryMetadata; 3],
+}
+
+/// Negative noise makes small breakdowns wrap around, so values in the upper half of the range
+/// are read as negative. This needs to be kept in sync with histogram values being BA32.
+fn noisy_value(v: u32) -> f64 {
+    if v > 1 << 31 {
+        f64::from(v) - 2.0_f64.powi(32)
+    } else {
+        f64::from(v)
+    }
+}
+
+impl QueryResult {
+    /// Noise that the helpers reported adding to the query. Every helper reports it, this takes
+    /// the first report.
+    #[must_use]
+    pub fn noise(&self) -> Option<NoiseMetadata> {
+        self.metadata.iter().find_map(|m| m.noise)
+    }
+
+    /// For every breakdown, the interval that contains its true value with probability at least
+    /// `confidence`. `None` if the helpers did not report the noise they added.
+    #[must_use]
+    pub fn confidence_intervals(&self, confidence: f64) -> Option<Vec<(f64, f64)>> {
+        let noise = self.noise()?.output;
+        Some(
+            self.breakdowns
+                .iter()
+                .map(|&v| noise.confidence_interval(noisy_value(v), confidence))
+                .collect(),
+        )
+    }
+
+    /// Sets `consistent_levels` to the estimates of every level of the breakdown hierarchy that
+    /// are consistent with each other, see [`consistent_estimates`]. Does nothing if the query
+    /// does not have a breakdown hierarchy.
+    pub fn estimate_consistent_levels(&mut self) {
+        let (Some(hierarchy), Some(levels)) = (self.config.breakdown_hierarchy, &self.levels)
+        else {
+            return;
+        };
+        let levels = levels
+            .iter()
+            .map(|level| level.iter().copied().map(noisy_value).collect())
+            .collect::<Vec<_>>();
+        self.consistent_levels = Some(consistent_estimates(&hierarchy, &levels));
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::Duration;
+
+    use super::QueryResult;
+    use crate::{
+        helpers::query::{IpaQueryConfig, NoiseMechanism},
+        protocol::dp::{accountant::PrivacyLoss, OutputNoise},
+        query::{NoiseMetadata, QueryMetadata},
+    };
+
+    #[test]
+    fn confidence_intervals() {
+        let mut result = QueryResult {
+            input_size: 10.try_into().unwrap(),
+            config: IpaQueryConfig::default(),
+            latency: Duration::ZERO,
+            breakdowns: vec![10, u32::MAX - 1],
+            levels: None,
+            consistent_levels: None,
+            metadata: [QueryMetadata::default(); 3],
+        };
+        assert_eq!(None, result.confidence_intervals(0.75));
+
+        result.metadata[1].noise = Some(NoiseMetadata {
+            output: OutputNoise {
+                mechanism: NoiseMechanism::Binomial,
+                epsilon: 1.0,
+                delta: 1e-6,
+                mean: 4.0,
+                std: 2.0,
+            },
+            oprf_padding: None,
+            aggregation_padding: None,
+            total: PrivacyLoss::new(1.0, 1e-6),
+        });
+        assert_eq!(
+            Some(vec![(2.0, 10.0), (-10.0, -2.0)]),
+            result.confidence_intervals(0.75)
+        );
+    }
+
+    #[test]
+    fn consistent_levels() {
+        let mut result = QueryResult {
+            input_size: 10.try_into().unwrap(),
+            config: IpaQueryConfig::default(),
+            latency: Duration::ZERO,
+            breakdowns: vec![4, u32::MAX, 2, 2],
+            levels: Some(vec![vec![3, 4], vec![4, u32::MAX, 2, 2]]),
+            consistent_levels: None,
+            metadata: [QueryMetadata::default(); 3],
+        };
+        result.estimate_consistent_levels();
+        assert_eq!(None, result.consistent_levels);
+
+        result.config.breakdown_hierarchy = Some("1,1".parse().unwrap());
+        result.estimate_consistent_levels();
+        // the levels are already consistent once the wrapped value is read as negative
+        let expected = [3.0, 4.0, 4.0, -1.0, 2.0, 2.0];
+        let actual = result.consistent_levels.unwrap();
+        assert_eq!(6, actual.iter().flatten().count());
+        for (expected, actual) in expected.iter().zip(actual.iter().flatten()) {
+            assert!((expected - actual).abs() < 1e-9, "{expected} != {actual}");
+        }
+    }
 }
@@ -13,7 +13,11 @@ use rcgen::{
 };
 use time::{Duration, OffsetDateTime};
 
-use crate::{error::BoxError, hpke::KeyPair};
+use crate::{
+    error::BoxError,
+    hpke::{CipherSuite, IpaPrivateKey},
+    key_encryption::KeyEncryptionKey,
+};
 
 #[derive(Debug, Args)]
 #[clap(
@@ -46,6 +50,18 @@ pub struct KeygenArgs {
     /// Writes the generated report private key to the file
     #[arg(long)]
     pub(crate) mk_private_key: PathBuf,
+
+    /// Cipher suite of the generated report keys. P-256 keys must be given key ids 128-255
+    #[arg(long, value_enum, default_value_t)]
+    pub(crate) mk_cipher_suite: CipherSuite,
+
+    /// Encrypts the generated private keys with the key-encryption key in this file
+    #[arg(long)]
+    pub(crate) key_encryption_key_file: Option<PathBuf>,
+
+    /// Generates a new key-encryption key and writes it to `key_encryption_key_file`
+    #[arg(long, requires = "key_encryption_key_file")]
+    pub(crate) generate_key_encryption_key: bool,
 }
 
 fn create_new<P: AsRef<Path>>(path: P) -> io::Result<File> {
@@ -56,6 +72,36 @@ fn create_new<P: AsRef<Path>>(path: P) -> io::Result<File> {
         .open(path)
 }
 
+/// Writes a private key, encrypted if `kek` is set.
+fn write_private_key<P: AsRef<Path>, R: Rng + CryptoRng>(
+    path: P,
+    key: &[u8],
+    kek: Option<&KeyEncryptionKey>,
+    rng: &mut R,
+) -> io::Result<()> {
+    match kek {
+        Some(kek) => create_new(path)?.write_all(kek.encrypt(key, rng).as_bytes()),
+        None => create_new(path)?.write_all(key),
+    }
+}
+
+/// Returns the key-encryption key private keys are written with, generating it if asked to.
+fn key_encryption_key<R: Rng + CryptoRng>(
+    args: &KeygenArgs,
+    rng: &mut R,
+) -> Result<Option<KeyEncryptionKey>, BoxError> {
+    let Some(path) = &args.key_encryption_key_file else {
+        return Ok(None);
+    };
+    if args.generate_key_encryption_key {
+        let kek = KeyEncryptionKey::generate(rng);
+        create_new(path)?.write_all(kek.encode().as_bytes())?;
+        Ok(Some(kek))
+    } else {
+        Ok(KeyEncryptionKey::load(Some(path))?)
+    }
+}
+
 /// Generate keys necessary for running a helper service.
 ///
 /// # Errors
@@ -63,7 +109,11 @@ fn create_new<P: AsRef<Path>>(path: P) -> io::Result<File> {
 ///
 /// # Panics
 /// If something that shouldn't happen goes wrong during key generation.
-pub fn keygen_tls<R: Rng + CryptoRng>(args: &KeygenArgs, rng: &mut R) -> Result<(), BoxError> {
+pub fn keygen_tls<R: Rng + CryptoRng>(
+    args: &KeygenArgs,
+    kek: Option<&KeyEncryptionKey>,
+    rng: &mut R,
+) -> Result<(), BoxError> {
     let mut params = CertificateParams::default();
     params.alg = &PKCS_ECDSA_P256_SHA256;
 
@@ -93,18 +143,27 @@ pub fn keygen_tls<R: Rng + CryptoRng>(args: &KeygenArgs, rng: &mut R) -> Result<
 
     create_new(&args.tls_cert)?
         .write_all(gen.serialize_pem().unwrap().replace('\r', "").as_bytes())?;
-    create_new(&args.tls_key)?
-        .write_all(gen.serialize_private_key_pem().replace('\r', "").as_bytes())?;
+    write_private_key(
+        &args.tls_key,
+        gen.serialize_private_key_pem().replace('\r', "").as_bytes(),
+        kek,
+        rng,
+    )?;
 
     Ok(())
 }
 
-/// Generates public and private key used for encrypting and decrypting match keys.
-fn keygen_matchkey<R: Rng + CryptoRng>(args: &KeygenArgs, mut rng: &mut R) -> Result<(), BoxError> {
-    let keypair = KeyPair::gen(&mut rng);
+/// Generates public and private key used for encrypting and decrypting match keys. Keys are
+/// written with their cipher suite, see [`IpaPrivateKey::encode`].
+fn keygen_matchkey<R: Rng + CryptoRng>(
+    args: &KeygenArgs,
+    kek: Option<&KeyEncryptionKey>,
+    rng: &mut R,
+) -> Result<(), BoxError> {
+    let (sk, pk) = IpaPrivateKey::generate(args.mk_cipher_suite, rng);
 
-    create_new(&args.mk_public_key)?.write_all(hex::encode(keypair.pk_bytes()).as_bytes())?;
-    create_new(&args.mk_private_key)?.write_all(hex::encode(keypair.sk_bytes()).as_bytes())?;
+    create_new(&args.mk_public_key)?.write_all(pk.encode().as_bytes())?;
+    write_private_key(&args.mk_private_key, sk.encode().as_bytes(), kek, rng)?;
 
     Ok(())
 }
@@ -118,7 +177,8 @@ fn keygen_matchkey<R: Rng + CryptoRng>(args: &KeygenArgs, mut rng: &mut R) -> Re
 /// If something that shouldn't happen goes wrong during key generation.
 pub fn keygen(args: &KeygenArgs) -> Result<(), BoxError> {
     let mut rng = thread_rng();
-    keygen_tls(args, &mut rng)?;
-    keygen_matchkey(args, &mut rng)?;
+    let kek = key_encryption_key(args, &mut rng)?;
+    keygen_tls(args, kek.as_ref(), &mut rng)?;
+    keygen_matchkey(args, kek.as_ref(), &mut rng)?;
     Ok(())
 }
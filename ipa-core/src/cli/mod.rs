@@ -3,6 +3,7 @@ mod clientconf;
 #[cfg(all(feature = "test-fixture", feature = "web-app", feature = "cli",))]
 pub mod crypto;
 mod csv;
+mod hierarchy;
 mod ipa_output;
 #[cfg(feature = "web-app")]
 mod keygen;
@@ -16,6 +17,7 @@ mod verbosity;
 #[cfg(feature = "web-app")]
 pub use clientconf::{setup as client_config_setup, ConfGenArgs};
 pub use csv::Serializer as CsvSerializer;
+pub use hierarchy::consistent_estimates;
 pub use ipa_output::QueryResult as IpaQueryResult;
 #[cfg(feature = "web-app")]
 pub use keygen::{keygen, KeygenArgs};
@@ -7,8 +7,9 @@ use std::{
 };
 
 use crate::{
-    cli::playbook::generator::U128Generator, ff::U128Conversions,
-    test_fixture::ipa::TestRawDataRecord,
+    cli::playbook::generator::U128Generator,
+    ff::U128Conversions,
+    test_fixture::{hybrid::TestHybridRecord, ipa::TestRawDataRecord},
 };
 
 pub trait InputItem {
@@ -56,6 +57,22 @@ impl InputItem for TestRawDataRecord {
     }
 }
 
+impl InputItem for TestHybridRecord {
+    fn from_str(s: &str) -> Self {
+        match s.splitn(3, ',').collect::<Vec<_>>()[..] {
+            ["i", match_key, breakdown_key] => TestHybridRecord::TestImpression {
+                match_key: match_key.parse().unwrap(),
+                breakdown_key: breakdown_key.parse().unwrap(),
+            },
+            ["c", match_key, value] => TestHybridRecord::TestConversion {
+                match_key: match_key.parse().unwrap(),
+                value: value.parse().unwrap(),
+            },
+            _ => panic!("{s} is not a valid {}", type_name::<Self>()),
+        }
+    }
+}
+
 pub struct InputSource {
     inner: Box<dyn BufRead>,
     sz: Option<u64>,
@@ -189,7 +206,7 @@ mod tests {
         cli::playbook::input::InputItem,
         ff::{Fp31, Fp32BitPrime},
         secret_sharing::IntoShares,
-        test_fixture::Reconstruct,
+        test_fixture::{hybrid::TestHybridRecord, Reconstruct},
     };
 
     #[test]
@@ -214,6 +231,30 @@ mod tests {
         Fp31::from_str("");
     }
 
+    #[test]
+    fn hybrid_record() {
+        assert_eq!(
+            TestHybridRecord::TestImpression {
+                match_key: 12345,
+                breakdown_key: 2,
+            },
+            TestHybridRecord::from_str("i,12345,2")
+        );
+        assert_eq!(
+            TestHybridRecord::TestConversion {
+                match_key: 12345,
+                value: 5,
+            },
+            TestHybridRecord::from_str("c,12345,5")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a valid")]
+    fn hybrid_record_parse_error() {
+        TestHybridRecord::from_str("x,12345,5");
+    }
+
     #[test]
     fn tuple() {
         let input = "20,27";
@@ -19,7 +19,7 @@ use crate::{
     },
     ff::{Serializable, U128Conversions},
     helpers::{
-        query::{IpaQueryConfig, QueryInput, QuerySize},
+        query::{IpaQueryConfig, NoiseMechanism, QueryInput, QuerySize},
         BodyStream,
     },
     hpke::PublicKeyRegistry,
@@ -40,7 +40,7 @@ pub async fn playbook_oprf_ipa<HV, KR>(
     clients: &[MpcHelperClient; 3],
     query_id: QueryId,
     query_config: IpaQueryConfig,
-    encryption: Option<(KeyIdentifier, [&KR; 3])>,
+    encryption: Option<[(KeyIdentifier, &KR); 3]>,
 ) -> IpaQueryResult
 where
     HV: SharedValue + U128Conversions,
@@ -65,7 +65,7 @@ where
                 share.serialize(GenericArray::from_mut_slice(chunk));
             }
         });
-    } else if let Some((key_id, key_registries)) = encryption {
+    } else if let Some(key_registries) = encryption {
         const ESTIMATED_AVERAGE_REPORT_SIZE: usize = 80; // TODO: confirm/adjust
         for buffer in &mut buffers {
             buffer.reserve(query_size * ESTIMATED_AVERAGE_REPORT_SIZE);
@@ -74,15 +74,15 @@ where
         let mut rng = StdRng::from_entropy();
         let shares: [Vec<OprfReport<BreakdownKey, TriggerValue, Timestamp>>; 3] =
             records.iter().cloned().share();
-        zip(&mut buffers, shares)
-            .zip(key_registries)
-            .for_each(|((buf, shares), key_registry)| {
+        zip(&mut buffers, shares).zip(key_registries).for_each(
+            |((buf, shares), (key_id, key_registry))| {
                 for share in shares {
                     share
                         .delimited_encrypt_to(key_id, key_registry, &mut rng, buf)
                         .unwrap();
                 }
-            });
+            },
+        );
     } else {
         panic!(
             "match key encryption was requested, but one or more helpers is missing a public key"
@@ -142,14 +142,28 @@ where
     }
 
     // wait until helpers have processed the query and get the results from them
-    let results: [_; 3] = try_join_all(clients.iter().map(|client| client.query_results(query_id)))
-        .await
-        .unwrap()
-        .try_into()
-        .unwrap();
+    let results: [_; 3] = try_join_all(
+        clients
+            .iter()
+            .map(|client| client.query_results_with_metadata(query_id)),
+    )
+    .await
+    .unwrap()
+    .try_into()
+    .unwrap();
+    let metadata = results.each_ref().map(|(_, metadata)| *metadata);
+    for (i, metadata) in metadata.iter().enumerate() {
+        if metadata.dropped_reports > 0 {
+            tracing::warn!(
+                "helper {} dropped {} reports that it could not decrypt",
+                i + 1,
+                metadata.dropped_reports
+            );
+        }
+    }
 
     let results: Vec<HV> = results
-        .map(|bytes| {
+        .map(|(bytes, _)| {
             AdditiveShare::<HV>::from_byte_slice(&bytes)
                 .collect::<Result<Vec<_>, _>>()
                 .unwrap()
@@ -159,11 +173,29 @@ where
     let lat = mpc_time.elapsed();
 
     tracing::info!("Running IPA for {query_size:?} records took {t:?}", t = lat);
+    // with a breakdown hierarchy, `breakdowns` has the finest level
+    let (levels, results) = match query_config.breakdown_hierarchy {
+        Some(hierarchy) => {
+            let levels = hierarchy.split(results).unwrap();
+            let finest = levels.last().unwrap().clone();
+            let levels = levels
+                .into_iter()
+                .map(|level| {
+                    level
+                        .into_iter()
+                        .map(|v| u32::try_from(v.as_u128()).unwrap())
+                        .collect()
+                })
+                .collect();
+            (Some(levels), finest)
+        }
+        None => (None, results),
+    };
     let mut breakdowns = vec![0; usize::try_from(query_config.max_breakdown_key).unwrap()];
     for (breakdown_key, trigger_value) in results.into_iter().enumerate() {
         // TODO: make the data type used consistent with `ipa_in_the_clear`
         // I think using u32 is wrong, we should move to u128
-        if query_config.with_dp == 0 {
+        if query_config.dp.mechanism == NoiseMechanism::None {
             // otherwise if DP is added trigger_values will not be zero due to noise
             assert!(
                 breakdown_key < query_config.max_breakdown_key.try_into().unwrap()
@@ -182,5 +214,8 @@ where
         config: query_config,
         latency: lat,
         breakdowns,
+        levels,
+        consistent_levels: None,
+        metadata,
     }
 }
@@ -10,15 +10,16 @@ use std::{fs, path::Path, time::Duration};
 pub use add::secure_add;
 use comfy_table::{Cell, Color, Table};
 use hyper::http::uri::Scheme;
-pub use input::InputSource;
+pub use input::{InputItem, InputSource};
 pub use multiply::secure_mul;
 use tokio::time::sleep;
 
 pub use self::ipa::{playbook_oprf_ipa, run_query_and_validate};
 use crate::{
-    config::{ClientConfig, NetworkConfig, PeerConfig},
+    cli::IpaQueryResult,
+    config::{ClientConfig, NetworkConfig, PeerConfig, StreamResumeConfig},
     ff::boolean_array::{BA20, BA3, BA8},
-    helpers::query::DpMechanism,
+    helpers::query::{DpMechanism, NoiseMechanism},
     net::{ClientIdentity, MpcHelperClient},
     protocol::{dp::NoiseParams, ipa_prf::oprf_padding::insecure::OPRFPaddingDp},
 };
@@ -78,6 +79,71 @@ where
     );
 }
 
+/// Logs the noise that the helpers added to the query, and for every breakdown the interval
+/// that contains its true value with probability at least `confidence`.
+///
+/// ## Panics
+/// If `confidence` is not in `[0, 1)`.
+pub fn log_confidence_intervals(result: &IpaQueryResult, confidence: f64) {
+    let Some(noise) = result.noise() else {
+        tracing::warn!("helpers did not report the noise they added");
+        return;
+    };
+    if result.metadata.iter().any(|m| m.noise != Some(noise)) {
+        tracing::warn!("helpers reported different noise: {:?}", result.metadata);
+    }
+    tracing::info!(
+        "padding: OPRF {:?}, aggregation {:?}",
+        noise.oprf_padding,
+        noise.aggregation_padding
+    );
+    tracing::info!(
+        "total privacy loss: epsilon = {}, delta = {}",
+        noise.total.epsilon,
+        noise.total.delta
+    );
+    let output = noise.output;
+    if output.mechanism == NoiseMechanism::None {
+        tracing::info!("no noise was added to the output");
+        return;
+    }
+    tracing::info!(
+        "{} noise with epsilon = {}, delta = {}: mean = {}, standard deviation = {}",
+        output.mechanism,
+        output.epsilon,
+        output.delta,
+        output.mean,
+        output.std
+    );
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "Breakdown".to_string(),
+        "Noisy".to_string(),
+        format!("{}% interval", confidence * 100.0),
+    ]);
+    let intervals = result.confidence_intervals(confidence).unwrap();
+    let threshold = result.config.dp.threshold;
+    for (i, (value, (low, high))) in result.breakdowns.iter().zip(intervals).enumerate() {
+        // helpers report suppressed breakdowns as zero
+        let row = match threshold {
+            Some(threshold) if *value == 0 => vec![
+                Cell::new(i),
+                Cell::new(format!("below {threshold}")),
+                Cell::new("-"),
+            ],
+            _ => vec![
+                Cell::new(i),
+                Cell::new(value),
+                Cell::new(format!("[{low:.1}, {high:.1}]")),
+            ],
+        };
+        table.add_row(row);
+    }
+
+    tracing::info!("\n{table}\n");
+}
+
 /// Validates that the expected result matches the actual.
 ///
 /// ## Panics
@@ -85,10 +151,15 @@ where
 pub fn validate_dp(
     expected: Vec<u32>,
     actual: Vec<u32>,
-    epsilon: f64,
     per_user_credit_cap: u32,
     dp_mechanism: DpMechanism,
 ) {
+    let (epsilon, delta) = match dp_mechanism {
+        DpMechanism::NoDp => (0.0, 0.0),
+        DpMechanism::Binomial { epsilon, delta }
+        | DpMechanism::DiscreteLaplace { epsilon, delta }
+        | DpMechanism::Skellam { epsilon, delta } => (epsilon, delta),
+    };
     let mut expected = expected.into_iter().fuse();
     let mut actual = actual.into_iter().fuse();
     let mut mismatch = Vec::new();
@@ -116,6 +187,7 @@ pub fn validate_dp(
 
         let noise_params = NoiseParams {
             epsilon,
+            delta,
             per_user_credit_cap,
             ell_1_sensitivity: per_user_credit_cap.into(),
             ell_2_sensitivity: per_user_credit_cap.into(),
@@ -124,12 +196,12 @@ pub fn validate_dp(
             ..Default::default()
         };
         let same = match dp_mechanism {
-            DpMechanism::Binomial { epsilon: _ } => {
+            DpMechanism::Binomial { .. } => {
                 let (mean, std) = crate::protocol::dp::binomial_noise_mean_std(&noise_params);
                 next_actual_f64 - mean > next_expected_f64 - 10.0 * std
                     && next_actual_f64 - mean < next_expected_f64 + 10.0 * std
             }
-            DpMechanism::DiscreteLaplace { epsilon: _ } => {
+            DpMechanism::DiscreteLaplace { .. } => {
                 let truncated_discrete_laplace = OPRFPaddingDp::new(
                     noise_params.epsilon,
                     noise_params.delta,
@@ -152,6 +224,18 @@ pub fn validate_dp(
                                              // println!("mean = {mean}, std = {std}, tolerance_factor * std = {}",tolerance_factor * std);
                 (next_actual_f64_shifted - next_expected_f64).abs() < tolerance_factor * 3.0 * std
             }
+            DpMechanism::Skellam { .. } => {
+                // Skellam noise is centered at zero, so it wraps around like Laplace noise
+                let next_actual_f64_shifted = if next_actual_f64 > 2.0_f64.powf(31.0) {
+                    next_actual_f64 - 2.0_f64.powf(32.0)
+                } else {
+                    next_actual_f64
+                };
+                let std = crate::protocol::dp::skellam_noise_std(
+                    crate::protocol::dp::find_smallest_skellam_poisson_mean(&noise_params),
+                );
+                (next_actual_f64_shifted - next_expected_f64).abs() < 10.0 * std
+            }
             DpMechanism::NoDp => next_expected == next_actual,
         };
 
@@ -205,6 +289,7 @@ pub async fn make_clients(
                 PeerConfig::new("localhost:3002".parse().unwrap(), None),
             ],
             client: ClientConfig::default(),
+            stream_resume: StreamResumeConfig::default(),
         }
     };
     let network = network.override_scheme(&scheme);
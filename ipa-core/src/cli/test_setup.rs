@@ -14,6 +14,7 @@ use crate::{
         KeygenArgs,
     },
     error::BoxError,
+    hpke::CipherSuite,
 };
 
 #[derive(Debug, Args)]
@@ -65,6 +66,9 @@ pub fn test_setup(args: TestSetupArgs) -> Result<(), BoxError> {
                 tls_expire_after: 365,
                 mk_public_key: args.output_dir.helper_mk_public_key(id),
                 mk_private_key: args.output_dir.helper_mk_private_key(id),
+                mk_cipher_suite: CipherSuite::default(),
+                key_encryption_key_file: None,
+                generate_key_encryption_key: false,
             };
 
             keygen(&keygen_args)?;
@@ -3,9 +3,10 @@ use std::{
     borrow::{Borrow, Cow},
     fmt::{Debug, Formatter},
     iter::Zip,
-    path::PathBuf,
+    num::NonZeroU64,
+    path::{Path, PathBuf},
     slice,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use hyper::{http::uri::Scheme, Uri};
@@ -19,9 +20,12 @@ use crate::{
     error::BoxError,
     helpers::HelperIdentity,
     hpke::{
-        Deserializable as _, IpaPrivateKey, IpaPublicKey, KeyRegistry, PrivateKeyOnly,
-        PublicKeyOnly, Serializable as _,
+        IpaPrivateKey, IpaPublicKey, KeyRegistry, KeyValidity, PrivateKeyOnly, PublicKeyOnly,
+        ReloadableKeyRegistry,
     },
+    key_encryption::{read_key_file, KeyEncryptionKey},
+    report::{EpochRange, KeyIdentifier},
+    sync::Arc,
 };
 
 pub type OwnedCertificate = CertificateDer<'static>;
@@ -51,6 +55,10 @@ pub struct NetworkConfig {
     /// HTTP client configuration.
     #[serde(default)]
     pub client: ClientConfig,
+
+    /// Controls how helper-to-helper record streams recover from connection loss.
+    #[serde(default)]
+    pub stream_resume: StreamResumeConfig,
 }
 
 impl NetworkConfig {
@@ -71,7 +79,11 @@ impl NetworkConfig {
     }
 
     pub fn new(peers: [PeerConfig; 3], client: ClientConfig) -> Self {
-        Self { peers, client }
+        Self {
+            peers,
+            client,
+            stream_resume: StreamResumeConfig::default(),
+        }
     }
 
     pub fn peers(&self) -> &[PeerConfig; 3] {
@@ -144,6 +156,8 @@ impl PeerConfig {
 /// need to know helper's public key.
 #[derive(Clone, Deserialize)]
 pub struct HpkeClientConfig {
+    /// Hex-encoded public key, prefixed with its cipher suite (e.g. `p256:04ab..`) unless it is
+    /// an X25519 key.
     #[serde(deserialize_with = "pk_from_str")]
     pub public_key: IpaPublicKey,
 }
@@ -161,6 +175,12 @@ impl HpkeClientConfig {
     pub fn new(public_key: IpaPublicKey) -> Self {
         Self { public_key }
     }
+
+    /// Identifier reports encrypted under this key should carry.
+    #[must_use]
+    pub fn key_id(&self) -> KeyIdentifier {
+        self.public_key.suite().default_key_id()
+    }
 }
 
 /// Reads a Certificate in PEM format using Serde Serialization
@@ -185,14 +205,11 @@ where
     D: Deserializer<'de>,
 {
     let s: String = Deserialize::deserialize(deserializer)?;
-    let mut buf = vec![0_u8; 32];
-    hex::decode_to_slice(s, &mut buf).map_err(<D::Error as serde::de::Error>::custom)?;
-
-    IpaPublicKey::from_bytes(&buf).map_err(<D::Error as serde::de::Error>::custom)
+    IpaPublicKey::decode(&s).map_err(<D::Error as serde::de::Error>::custom)
 }
 
 fn pk_to_str(pk: &IpaPublicKey) -> String {
-    hex::encode(pk.to_bytes().as_slice())
+    pk.encode()
 }
 
 #[derive(Clone, Debug)]
@@ -201,7 +218,8 @@ pub enum TlsConfig {
         /// Path to file containing certificate in PEM format
         certificate_file: PathBuf,
 
-        /// Path to file containing private key in PEM format
+        /// Path to file containing private key in PEM format, possibly encrypted with the
+        /// server's key-encryption key
         private_key_file: PathBuf,
     },
     Inline {
@@ -215,38 +233,230 @@ pub enum TlsConfig {
 
 #[derive(Clone, Debug)]
 pub enum HpkeServerConfig {
+    /// A single key, identified by the default key identifier of its cipher suite.
     File {
-        /// Path to file containing private key which decrypts match keys
+        /// Path to file containing private key which decrypts match keys, possibly encrypted with
+        /// the server's key-encryption key
         private_key_file: PathBuf,
     },
     Inline {
-        // Private key in hex format
+        // Private key in hex format, prefixed with its cipher suite unless it is X25519
         private_key: String,
     },
+    /// Multiple keys with explicit identifiers and validity periods, listed in a [`KeyManifest`].
+    Manifest {
+        /// Path to the manifest file, or to a directory containing `manifest.toml`.
+        manifest_file: PathBuf,
+    },
 }
 
+/// Encrypted key files are decrypted with `kek`, see [`crate::key_encryption`].
+///
 /// # Errors
 /// If there is a problem with the HPKE configuration.
 pub async fn hpke_registry(
     config: Option<&HpkeServerConfig>,
+    kek: Option<&KeyEncryptionKey>,
 ) -> Result<KeyRegistry<PrivateKeyOnly>, BoxError> {
     let sk_str = match config {
         None => return Ok(KeyRegistry::<PrivateKeyOnly>::empty()),
-        Some(HpkeServerConfig::Inline { private_key }) => {
-            Cow::Borrowed(private_key.trim().as_bytes())
-        }
+        Some(HpkeServerConfig::Inline { private_key }) => Cow::Borrowed(private_key.as_bytes()),
         Some(HpkeServerConfig::File { private_key_file }) => {
-            Cow::Owned(fs::read_to_string(private_key_file).await?.trim().into())
+            Cow::Owned(fs::read(private_key_file).await?)
+        }
+        Some(HpkeServerConfig::Manifest { manifest_file }) => {
+            return Ok(KeyManifest::from_file(manifest_file, kek)
+                .await?
+                .registry_at(SystemTime::now()));
         }
     };
 
-    let sk = hex::decode(sk_str)?;
-
-    Ok(KeyRegistry::from_keys([PrivateKeyOnly(
-        IpaPrivateKey::from_bytes(&sk)?,
+    let sk = private_key_from_hex(&read_key_file(&sk_str, kek)?)?;
+    Ok(KeyRegistry::from_keys_with_ids([(
+        sk.suite().default_key_id(),
+        PrivateKeyOnly(sk),
     )]))
 }
 
+fn private_key_from_hex(sk_str: &[u8]) -> Result<IpaPrivateKey, BoxError> {
+    Ok(IpaPrivateKey::decode(std::str::from_utf8(sk_str)?)?)
+}
+
+/// Reloads HPKE keys listed in the manifest every `interval`, replacing the contents of
+/// `registry`. This picks up keys added to or removed from the manifest, as well as keys that
+/// became valid or expired since the last reload.
+///
+/// If the manifest cannot be loaded, the keys that are currently in use are kept.
+pub async fn reload_hpke_keys(
+    manifest_file: PathBuf,
+    kek: Option<KeyEncryptionKey>,
+    registry: Arc<ReloadableKeyRegistry<PrivateKeyOnly>>,
+    interval: Duration,
+) {
+    loop {
+        tokio::time::sleep(interval).await;
+        match KeyManifest::from_file(&manifest_file, kek.as_ref()).await {
+            Ok(manifest) => {
+                let keys = manifest.registry_at(SystemTime::now());
+                tracing::debug!(
+                    "reloaded HPKE keys from {}, active key ids: {:?}",
+                    manifest_file.display(),
+                    keys.key_ids().collect::<Vec<_>>()
+                );
+                registry.replace(keys);
+            }
+            Err(e) => tracing::error!(
+                "failed to reload HPKE keys from {}, keeping the current keys: {e}",
+                manifest_file.display()
+            ),
+        }
+    }
+}
+
+/// List of HPKE private keys a helper uses to decrypt reports.
+///
+/// The manifest is a TOML file:
+/// ```toml
+/// # How long keys stay usable for decryption after their validity period ends.
+/// grace_period_secs = 604800
+///
+/// [[keys]]
+/// # Ids 0-127 are for X25519 keys, 128-255 for P-256 keys.
+/// id = 0
+/// # Relative paths are resolved against the directory the manifest is in. The file has the
+/// # key in hex, prefixed with `p256:` for P-256 keys. It can be encrypted with the helper's
+/// # key-encryption key.
+/// private_key_file = "mk-0.key"
+/// # Validity period, in seconds since the Unix epoch. Both ends are optional.
+/// valid_from = 1704067200
+/// valid_until = 1706745600
+/// # Optional. Reports from other epochs encrypted under this key are rejected.
+/// epochs = "4-5"
+/// ```
+///
+/// Reports encrypted under a key are accepted from the start of its validity period until
+/// the grace period after its end, so the keys used by devices can be rotated without
+/// coordinating with the helpers.
+pub struct KeyManifest {
+    grace_period: Duration,
+    keys: Vec<ManifestKey>,
+}
+
+struct ManifestKey {
+    id: KeyIdentifier,
+    validity: KeyValidity,
+    private_key: IpaPrivateKey,
+}
+
+#[derive(Deserialize)]
+struct KeyManifestFile {
+    #[serde(
+        default,
+        rename = "grace_period_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    grace_period: Duration,
+    keys: Vec<KeyManifestFileEntry>,
+}
+
+#[derive(Deserialize)]
+struct KeyManifestFileEntry {
+    id: KeyIdentifier,
+    private_key_file: PathBuf,
+    #[serde(default)]
+    valid_from: Option<u64>,
+    #[serde(default)]
+    valid_until: Option<u64>,
+    #[serde(default)]
+    epochs: Option<EpochRange>,
+}
+
+impl KeyManifest {
+    /// Loads the manifest and all keys listed in it. If `path` is a directory, the manifest is
+    /// read from `manifest.toml` in that directory. Encrypted key files are decrypted with `kek`.
+    ///
+    /// # Errors
+    /// If the manifest or any of the key files cannot be read or parsed, if the same key
+    /// identifier is used more than once, if a key has an identifier reserved for the other
+    /// cipher suite, or if its validity period cannot be represented.
+    pub async fn from_file<P: AsRef<Path>>(
+        path: P,
+        kek: Option<&KeyEncryptionKey>,
+    ) -> Result<Self, BoxError> {
+        let mut path = path.as_ref().to_path_buf();
+        if fs::metadata(&path).await?.is_dir() {
+            path.push("manifest.toml");
+        }
+        let manifest: KeyManifestFile = toml::from_str(&fs::read_to_string(&path).await?)?;
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        let mut keys = Vec::with_capacity(manifest.keys.len());
+        for entry in manifest.keys {
+            if keys.iter().any(|key: &ManifestKey| key.id == entry.id) {
+                return Err(format!("duplicate key id {} in {}", entry.id, path.display()).into());
+            }
+            let key_path = base_dir.join(&entry.private_key_file);
+            let key_file = fs::read(&key_path).await?;
+            let private_key = read_key_file(&key_file, kek)
+                .map_err(BoxError::from)
+                .and_then(|sk| private_key_from_hex(&sk))
+                .and_then(|sk| {
+                    sk.suite().check_key_id(entry.id)?;
+                    Ok(sk)
+                })
+                .map_err(|e| {
+                    format!(
+                        "failed to load key {} from {}: {e}",
+                        entry.id,
+                        key_path.display()
+                    )
+                })?;
+            let to_time = |secs| {
+                SystemTime::UNIX_EPOCH
+                    .checked_add(Duration::from_secs(secs))
+                    .ok_or_else(|| {
+                        format!(
+                            "validity period of key {} in {} is out of range: {secs}",
+                            entry.id,
+                            path.display()
+                        )
+                    })
+            };
+            keys.push(ManifestKey {
+                id: entry.id,
+                validity: KeyValidity {
+                    valid_from: entry.valid_from.map(to_time).transpose()?,
+                    valid_until: entry.valid_until.map(to_time).transpose()?,
+                    epochs: entry.epochs,
+                },
+                private_key,
+            });
+        }
+
+        Ok(Self {
+            grace_period: manifest.grace_period,
+            keys,
+        })
+    }
+
+    /// Builds a registry with the keys that can be used to decrypt reports at `now`.
+    #[must_use]
+    pub fn registry_at(&self, now: SystemTime) -> KeyRegistry<PrivateKeyOnly> {
+        KeyRegistry::from_keys_with_validity(
+            self.keys
+                .iter()
+                .filter(|key| key.validity.is_usable_at(now, self.grace_period))
+                .map(|key| {
+                    (
+                        key.id,
+                        PrivateKeyOnly(key.private_key.clone()),
+                        key.validity,
+                    )
+                }),
+        )
+    }
+}
+
 /// Configuration information for launching an instance of the helper party web service.
 #[derive(Clone, Debug)]
 pub struct ServerConfig {
@@ -261,6 +471,13 @@ pub struct ServerConfig {
 
     /// Configuration needed for decrypting match keys
     pub hpke_config: Option<HpkeServerConfig>,
+
+    /// Decrypts the TLS and HPKE private key files, if they are encrypted at rest
+    pub key_encryption_key: Option<KeyEncryptionKey>,
+
+    /// Compression accepted on helper-to-helper record streams. Uncompressed streams are always
+    /// accepted.
+    pub stream_compression: StreamCompression,
 }
 
 pub trait HyperClientConfigurator {
@@ -270,6 +487,16 @@ pub trait HyperClientConfigurator {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClientConfig {
     pub http_config: HttpClientConfigurator,
+
+    /// Compression to use for record streams sent to other helpers. It is only applied
+    /// once the peer has advertised that it accepts it.
+    #[serde(default)]
+    pub stream_compression: StreamCompression,
+
+    /// Cap on the outbound bandwidth used for record streams, enforced for each peer
+    /// separately. Unlimited if not set.
+    #[serde(default)]
+    pub bandwidth_limit: Option<BandwidthLimit>,
 }
 
 impl Default for ClientConfig {
@@ -288,6 +515,8 @@ impl ClientConfig {
     pub fn configure_http2(conf: Http2Configurator) -> Self {
         Self {
             http_config: HttpClientConfigurator::Http2(conf),
+            stream_compression: StreamCompression::default(),
+            bandwidth_limit: None,
         }
     }
 
@@ -295,10 +524,47 @@ impl ClientConfig {
     pub fn use_http1() -> Self {
         Self {
             http_config: HttpClientConfigurator::http1(),
+            stream_compression: StreamCompression::default(),
+            bandwidth_limit: None,
+        }
+    }
+
+    #[must_use]
+    pub fn with_stream_compression(self, stream_compression: StreamCompression) -> Self {
+        Self {
+            stream_compression,
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub fn with_bandwidth_limit(self, bandwidth_limit: BandwidthLimit) -> Self {
+        Self {
+            bandwidth_limit: Some(bandwidth_limit),
+            ..self
         }
     }
 }
 
+/// Outbound bandwidth cap for traffic sent to a single peer, enforced with a token bucket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthLimit {
+    /// Sustained rate, in bytes per second.
+    pub bytes_per_sec: NonZeroU64,
+
+    /// Number of bytes that can be sent at once after a period of inactivity. Defaults to
+    /// one second worth of traffic.
+    #[serde(default)]
+    pub burst_bytes: Option<NonZeroU64>,
+}
+
+impl BandwidthLimit {
+    #[must_use]
+    pub fn burst_bytes(&self) -> NonZeroU64 {
+        self.burst_bytes.unwrap_or(self.bytes_per_sec)
+    }
+}
+
 impl<B: Borrow<ClientConfig>> HyperClientConfigurator for B {
     fn configure<'a>(&self, client_builder: &'a mut Builder) -> &'a mut Builder {
         self.borrow().http_config.configure(client_builder)
@@ -414,16 +680,94 @@ impl Debug for Http2Configurator {
     }
 }
 
+/// Helper-to-helper record streams carry byte offsets, so when a connection drops in the middle
+/// of a stream, the sending helper can reconnect and resume from the last byte the receiving
+/// helper has acknowledged.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StreamResumeConfig {
+    /// How long the receiving helper waits for the sender to reconnect after connection loss.
+    /// The sending helper gives up if none of its data gets through for the same amount of time.
+    /// Setting it to zero disables stream resumption.
+    #[serde(
+        rename = "grace_period_secs",
+        serialize_with = "crate::serde::duration::to_secs",
+        deserialize_with = "crate::serde::duration::from_secs"
+    )]
+    pub grace_period: Duration,
+
+    /// Maximum number of bytes the sending helper keeps for each stream, to retransmit the ones
+    /// the receiving helper has not acknowledged after reconnecting. If it is missing more data
+    /// than that, the stream fails.
+    pub replay_buffer_bytes: usize,
+}
+
+impl Default for StreamResumeConfig {
+    fn default() -> Self {
+        Self {
+            grace_period: Duration::from_secs(30),
+            replay_buffer_bytes: 1 << 20,
+        }
+    }
+}
+
+impl StreamResumeConfig {
+    /// Configuration that does not allow streams to be resumed. Any connection loss
+    /// terminates the stream.
+    #[must_use]
+    pub fn disabled() -> Self {
+        Self {
+            grace_period: Duration::ZERO,
+            replay_buffer_bytes: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        !self.grace_period.is_zero()
+    }
+}
+
+/// Compression codec for helper-to-helper record streams.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum StreamCompression {
+    /// Records are sent as is.
+    #[default]
+    None,
+    /// Every chunk of records is compressed with DEFLATE ([`RFC 1951`]).
+    ///
+    /// [`RFC 1951`]: https://datatracker.ietf.org/doc/html/rfc1951
+    Deflate,
+}
+
+impl StreamCompression {
+    /// Name of this codec, as it appears in `Content-Encoding` and `Accept-Encoding` headers.
+    #[must_use]
+    pub fn encoding(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Deflate => Some("deflate"),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct KeyRegistries(Vec<KeyRegistry<PublicKeyOnly>>);
 
 impl KeyRegistries {
+    /// Creates a registry with the public key of each helper, from the HPKE configs in the
+    /// network file.
+    ///
+    /// Returns the registries together with the identifier the key has in each of them, or
+    /// `None` if any of the helpers does not have an HPKE config.
+    ///
     /// # Panics
     /// If network file is improperly formatted
     pub fn init_from(
         &mut self,
         network: &NetworkConfig,
-    ) -> Option<[&KeyRegistry<PublicKeyOnly>; 3]> {
+    ) -> Option<[(KeyIdentifier, &KeyRegistry<PublicKeyOnly>); 3]> {
         // Get the configs, if all three peers have one
         let configs = network.peers().iter().try_fold(Vec::new(), |acc, peer| {
             if let (mut vec, Some(hpke_config)) = (acc, peer.hpke_config.as_ref()) {
@@ -437,26 +781,45 @@ impl KeyRegistries {
         // Create key registries
         self.0 = configs
             .into_iter()
-            .map(|hpke| KeyRegistry::from_keys([PublicKeyOnly(hpke.public_key.clone())]))
+            .map(|hpke| {
+                KeyRegistry::from_keys_with_ids([(
+                    hpke.key_id(),
+                    PublicKeyOnly(hpke.public_key.clone()),
+                )])
+            })
             .collect::<Vec<KeyRegistry<PublicKeyOnly>>>();
 
-        Some(self.0.iter().collect::<Vec<_>>().try_into().ok().unwrap())
+        Some(
+            self.0
+                .iter()
+                .map(|registry| (registry.key_ids().next().unwrap(), registry))
+                .collect::<Vec<_>>()
+                .try_into()
+                .ok()
+                .unwrap(),
+        )
     }
 }
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
 
-    use hpke::{kem::X25519HkdfSha256, Kem};
     use hyper::Uri;
     use rand::rngs::StdRng;
     use rand_core::SeedableRng;
 
     use crate::{
-        config::{ClientConfig, HpkeClientConfig, Http2Configurator, HttpClientConfigurator},
+        config::{
+            hpke_registry, BandwidthLimit, ClientConfig, HpkeClientConfig, HpkeServerConfig,
+            Http2Configurator, HttpClientConfigurator, KeyManifest, NetworkConfig,
+            StreamResumeConfig,
+        },
         helpers::HelperIdentity,
+        hpke::{CipherSuite, IpaPrivateKey, KeyPair, KeyValidity, PrivateKeyRegistry},
+        key_encryption::KeyEncryptionKey,
         net::test::TestConfigBuilder,
+        report::EpochRange,
     };
 
     const URI_1: &str = "http://localhost:3000";
@@ -486,7 +849,7 @@ mod tests {
     #[test]
     fn debug_hpke_client_config() {
         let mut rng = StdRng::seed_from_u64(1);
-        let (_, public_key) = X25519HkdfSha256::gen_keypair(&mut rng);
+        let (_, public_key) = IpaPrivateKey::generate(CipherSuite::X25519, &mut rng);
         let config = HpkeClientConfig { public_key };
         assert_eq!(format!("{config:?}"), "HpkeClientConfig { public_key: \"2bd9da78f01d8bc6948bbcbe44ec1e7163d05083e267d110cdb2e75d847e3b6f\" }");
     }
@@ -531,4 +894,273 @@ mod tests {
             }),
         );
     }
+
+    fn write_key_manifest(dir: &std::path::Path, manifest: &str) -> Vec<KeyPair> {
+        let mut rng = StdRng::seed_from_u64(1);
+        let keys = (0..3).map(|_| KeyPair::gen(&mut rng)).collect::<Vec<_>>();
+        for (i, key) in keys.iter().enumerate() {
+            std::fs::write(dir.join(format!("mk-{i}.key")), hex::encode(key.sk_bytes())).unwrap();
+        }
+        std::fs::write(dir.join("manifest.toml"), manifest).unwrap();
+        keys
+    }
+
+    #[tokio::test]
+    async fn key_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let keys = write_key_manifest(
+            dir.path(),
+            r#"
+            grace_period_secs = 100
+
+            [[keys]]
+            id = 3
+            private_key_file = "mk-0.key"
+            valid_until = 1000
+
+            [[keys]]
+            id = 5
+            private_key_file = "mk-1.key"
+            valid_from = 1000
+            valid_until = 2000
+            epochs = "7-8"
+
+            [[keys]]
+            id = 6
+            private_key_file = "mk-2.key"
+            valid_from = 2000
+            "#,
+        );
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let key_ids = |manifest: &KeyManifest, secs| {
+            manifest.registry_at(at(secs)).key_ids().collect::<Vec<_>>()
+        };
+
+        // manifest can be referenced by the directory it is in
+        let manifest = KeyManifest::from_file(dir.path(), None).await.unwrap();
+        assert_eq!(vec![3], key_ids(&manifest, 500));
+        // old key is still accepted during the grace period
+        assert_eq!(vec![3, 5], key_ids(&manifest, 1100));
+        assert_eq!(vec![5], key_ids(&manifest, 1101));
+        assert_eq!(vec![5, 6], key_ids(&manifest, 2000));
+        assert_eq!(vec![6], key_ids(&manifest, 5000));
+
+        let registry = manifest.registry_at(at(1050));
+        assert_eq!(
+            &*keys[1].sk_bytes(),
+            &*registry.private_key(5).unwrap().to_bytes()
+        );
+        assert_eq!(
+            Some(KeyValidity {
+                valid_from: Some(at(1000)),
+                valid_until: Some(at(2000)),
+                epochs: Some(EpochRange::new(7, 8).unwrap()),
+            }),
+            registry.validity(5)
+        );
+        assert!(registry.accepts_epoch(5, 8));
+        assert!(!registry.accepts_epoch(5, 9));
+        assert_eq!(None, registry.validity(3).unwrap().epochs);
+
+        let registry = hpke_registry(
+            Some(&HpkeServerConfig::Manifest {
+                manifest_file: dir.path().join("manifest.toml"),
+            }),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(vec![6], registry.key_ids().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn key_manifest_duplicate_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        write_key_manifest(
+            dir.path(),
+            r#"
+            [[keys]]
+            id = 1
+            private_key_file = "mk-0.key"
+
+            [[keys]]
+            id = 1
+            private_key_file = "mk-1.key"
+            "#,
+        );
+        let err = KeyManifest::from_file(dir.path(), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("duplicate key id 1"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn key_manifest_validity_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        write_key_manifest(
+            dir.path(),
+            &format!(
+                r#"
+                grace_period_secs = 100
+
+                [[keys]]
+                id = 1
+                private_key_file = "mk-0.key"
+                valid_until = {}
+
+                [[keys]]
+                id = 2
+                private_key_file = "mk-1.key"
+                "#,
+                i64::MAX
+            ),
+        );
+        // the end of the grace period cannot be represented, so the key never expires
+        let manifest = KeyManifest::from_file(dir.path(), None).await.unwrap();
+        assert_eq!(
+            vec![1, 2],
+            manifest
+                .registry_at(SystemTime::now())
+                .key_ids()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn p256_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let (sk, pk) = IpaPrivateKey::generate(CipherSuite::P256, &mut rng);
+        std::fs::write(dir.path().join("mk-p256.key"), sk.encode()).unwrap();
+        std::fs::write(
+            dir.path().join("manifest.toml"),
+            r#"
+            [[keys]]
+            id = 130
+            private_key_file = "mk-p256.key"
+            "#,
+        )
+        .unwrap();
+        let registry = KeyManifest::from_file(dir.path(), None)
+            .await
+            .unwrap()
+            .registry_at(SystemTime::now());
+        assert_eq!(pk, registry.private_key(130).unwrap().public_key());
+
+        // key ids below 128 are reserved for X25519 keys
+        std::fs::write(
+            dir.path().join("manifest.toml"),
+            r#"
+            [[keys]]
+            id = 2
+            private_key_file = "mk-p256.key"
+            "#,
+        )
+        .unwrap();
+        let err = KeyManifest::from_file(dir.path(), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("cannot have id 2"), "{err}");
+
+        // single key is registered under the default id of its suite
+        let registry = hpke_registry(
+            Some(&HpkeServerConfig::Inline {
+                private_key: sk.encode(),
+            }),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(vec![128], registry.key_ids().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn encrypted_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        let kek = KeyEncryptionKey::generate(&mut rng);
+        let (sk, pk) = IpaPrivateKey::generate(CipherSuite::X25519, &mut rng);
+        let key_file = dir.path().join("mk-0.key");
+        std::fs::write(&key_file, kek.encrypt(sk.encode().as_bytes(), &mut rng)).unwrap();
+        std::fs::write(
+            dir.path().join("manifest.toml"),
+            r#"
+            [[keys]]
+            id = 4
+            private_key_file = "mk-0.key"
+            "#,
+        )
+        .unwrap();
+
+        let registry = KeyManifest::from_file(dir.path(), Some(&kek))
+            .await
+            .unwrap()
+            .registry_at(SystemTime::now());
+        assert_eq!(pk, registry.private_key(4).unwrap().public_key());
+        let err = KeyManifest::from_file(dir.path(), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("no key-encryption key"), "{err}");
+
+        let config = HpkeServerConfig::File {
+            private_key_file: key_file,
+        };
+        let registry = hpke_registry(Some(&config), Some(&kek)).await.unwrap();
+        assert_eq!(pk, registry.private_key(0).unwrap().public_key());
+        let other = KeyEncryptionKey::generate(&mut rng);
+        let err = hpke_registry(Some(&config), Some(&other))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("failed to decrypt"), "{err}");
+    }
+
+    #[test]
+    fn bandwidth_limit_serde() {
+        let config: ClientConfig = serde_json::from_str(
+            r#"{ "http_config": { "version": "http2" }, "bandwidth_limit": { "bytes_per_sec": 1000 } }"#,
+        )
+        .unwrap();
+        let limit = config.bandwidth_limit.unwrap();
+        assert_eq!(1000, limit.bytes_per_sec.get());
+        assert_eq!(1000, limit.burst_bytes().get());
+
+        let config: ClientConfig =
+            serde_json::from_str(r#"{ "http_config": { "version": "http2" } }"#).unwrap();
+        assert!(config.bandwidth_limit.is_none());
+
+        assert!(serde_json::from_str::<BandwidthLimit>(r#"{ "bytes_per_sec": 0 }"#).is_err());
+    }
+
+    #[test]
+    fn stream_resume_config_serde() {
+        let config: StreamResumeConfig =
+            serde_json::from_str(r#"{ "grace_period_secs": 2.5, "replay_buffer_bytes": 1024 }"#)
+                .unwrap();
+        assert_eq!(
+            StreamResumeConfig {
+                grace_period: Duration::from_millis(2500),
+                replay_buffer_bytes: 1024,
+            },
+            config
+        );
+        assert!(config.is_enabled());
+        assert!(!StreamResumeConfig::disabled().is_enabled());
+
+        let conf = NetworkConfig::from_toml_str(
+            r#"
+            [[peers]]
+            url = "helper1.test"
+            [[peers]]
+            url = "helper2.test"
+            [[peers]]
+            url = "helper3.test"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(StreamResumeConfig::default(), conf.stream_resume);
+    }
 }
@@ -69,6 +69,8 @@ pub enum Error {
     InvalidQueryParameter(BoxError),
     #[error("invalid report: {0}")]
     InvalidReport(#[from] InvalidReportError),
+    #[error("reports used by the query could not be recorded by all helpers")]
+    ReportsNotRecorded,
     #[error("unsupported: {0}")]
     Unsupported(String),
     #[error("Decompressing invalid elliptic curve point: {0}")]
@@ -16,7 +16,7 @@ use typenum::Unsigned;
 use crate::{
     helpers::{
         buffers::OrderingSender, routing::RouteId, ChannelId, Error, GatewayConfig, Message,
-        TotalRecords, Transport, TransportIdentity,
+        TokenBucket, TotalRecords, Transport, TransportIdentity,
     },
     protocol::{QueryId, RecordId},
     sync::Arc,
@@ -43,6 +43,11 @@ pub(super) struct GatewaySender<I> {
     channel_id: ChannelId<I>,
     ordering_tx: OrderingSender,
     total_records: TotalRecords,
+    /// Outbound bandwidth limit for the peer, shared with the transport.
+    bandwidth: Option<Arc<TokenBucket>>,
+    /// How many records fit into one read from [`OrderingSender`]. The bandwidth limit is
+    /// checked once for every batch of this many records.
+    batch_records: NonZeroUsize,
 }
 
 struct GatewaySendStream<I> {
@@ -75,11 +80,19 @@ impl<I: TransportIdentity> Default for GatewaySenders<I> {
 }
 
 impl<I: TransportIdentity> GatewaySender<I> {
-    fn new(channel_id: ChannelId<I>, tx: OrderingSender, total_records: TotalRecords) -> Self {
+    fn new(
+        channel_id: ChannelId<I>,
+        tx: OrderingSender,
+        total_records: TotalRecords,
+        bandwidth: Option<Arc<TokenBucket>>,
+        batch_records: NonZeroUsize,
+    ) -> Self {
         Self {
             channel_id,
             ordering_tx: tx,
             total_records,
+            bandwidth,
+            batch_records,
         }
     }
 
@@ -102,9 +115,18 @@ impl<I: TransportIdentity> GatewaySender<I> {
             }
         }
 
+        // Hold the batch back while the link to the peer is saturated, so the buffer does not
+        // fill up with data that can't be sent yet. Checking once per batch keeps the bucket
+        // lock off the path of every record.
+        let i = usize::from(record_id);
+        if let Some(bucket) = &self.bandwidth {
+            if i % self.batch_records == 0 {
+                bucket.ready().await;
+            }
+        }
+
         // TODO: make OrderingSender::send fallible
         // TODO: test channel close
-        let i = usize::from(record_id);
         self.ordering_tx.send(i, msg).await;
         if self.total_records.is_last(record_id) {
             self.ordering_tx.close(i + 1).await;
@@ -206,6 +228,7 @@ impl<I: TransportIdentity> GatewaySenders<I> {
                 let sender = Self::new_sender(
                     &SendChannelConfig::new::<M>(config, total_records),
                     channel_id.clone(),
+                    transport.bandwidth(channel_id.peer),
                 );
                 entry.insert(Arc::clone(&sender));
 
@@ -229,11 +252,17 @@ impl<I: TransportIdentity> GatewaySenders<I> {
         }
     }
 
-    fn new_sender(config: &SendChannelConfig, channel_id: ChannelId<I>) -> Arc<GatewaySender<I>> {
+    fn new_sender(
+        config: &SendChannelConfig,
+        channel_id: ChannelId<I>,
+        bandwidth: Option<Arc<TokenBucket>>,
+    ) -> Arc<GatewaySender<I>> {
         Arc::new(GatewaySender::new(
             channel_id,
             OrderingSender::new(config.total_capacity, config.record_size, config.read_size),
             config.total_records,
+            bandwidth,
+            NonZeroUsize::new(config.read_size.get() / config.record_size.get()).unwrap(),
         ))
     }
 }
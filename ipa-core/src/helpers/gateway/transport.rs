@@ -4,10 +4,11 @@ use futures::Stream;
 use crate::{
     helpers::{
         transport::routing::RouteId, MpcTransportImpl, NoResourceIdentifier, QueryIdBinding, Role,
-        RoleAssignment, RouteParams, StepBinding, Transport,
+        RoleAssignment, RouteParams, StepBinding, TokenBucket, Transport,
     },
     protocol::{Gate, QueryId},
     sharding::ShardIndex,
+    sync::Arc,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +42,10 @@ impl Transport for RoleResolvingTransport {
         self.roles.role(helper_identity)
     }
 
+    fn bandwidth(&self, dest: Role) -> Option<Arc<TokenBucket>> {
+        self.inner.bandwidth(self.roles.identity(dest))
+    }
+
     async fn send<
         D: Stream<Item = Vec<u8>> + Send + 'static,
         Q: QueryIdBinding,
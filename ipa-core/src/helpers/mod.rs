@@ -62,8 +62,6 @@ pub use gateway::{
 };
 pub use gateway_exports::{Gateway, MpcReceivingEnd, SendingEnd, ShardReceivingEnd};
 pub use prss_protocol::negotiate as negotiate_prss;
-#[cfg(feature = "web-app")]
-pub use transport::WrappedAxumBodyStream;
 #[cfg(feature = "in-memory-infra")]
 pub use transport::{
     config as in_memory_config, InMemoryMpcNetwork, InMemoryShardNetwork, InMemoryTransport,
@@ -72,9 +70,11 @@ pub use transport::{
     make_owned_handler, query, routing, ApiError, BodyStream, BytesStream, HandlerBox, HandlerRef,
     HelperResponse, Identity as TransportIdentity, LengthDelimitedStream, LogErrors, NoQueryId,
     NoResourceIdentifier, NoStep, QueryIdBinding, ReceiveRecords, RecordsStream, RequestHandler,
-    RouteParams, SingleRecordStream, StepBinding, StreamCollection, StreamKey, Transport,
-    WrappedBoxBodyStream,
+    RouteParams, Shaped, SingleRecordStream, StepBinding, StreamCollection, StreamKey, TokenBucket,
+    Transport, WrappedBoxBodyStream,
 };
+#[cfg(feature = "web-app")]
+pub use transport::{ResumableSender, ResumableStream, StreamResumer, WrappedAxumBodyStream};
 use typenum::{Const, ToUInt, Unsigned, U8};
 use x25519_dalek::PublicKey;
 
@@ -10,9 +10,10 @@ use crate::{
         query::PrepareQuery, transport::routing::Addr, BodyStream, HelperIdentity,
         TransportIdentity,
     },
+    hpke::PublicKeySet,
     query::{
-        NewQueryError, PrepareQueryError, ProtocolResult, QueryCompletionError, QueryInputError,
-        QueryStatus, QueryStatusError,
+        NewQueryError, PrepareQueryError, ProtocolResult, QueryAbortError, QueryCompletionError, QueryInputError,
+        QueryMetadata, QueryStatus, QueryStatusError,
     },
     sync::{Arc, Mutex, Weak},
 };
@@ -29,6 +30,8 @@ use crate::{
 ///
 pub struct HelperResponse {
     body: Vec<u8>,
+    /// Set on responses that carry query results.
+    metadata: Option<QueryMetadata>,
 }
 
 /// The lifecycle of request handlers is somewhat complicated. First, to initialize [`Transport`],
@@ -98,7 +101,16 @@ impl HelperResponse {
     /// Returns an empty response that indicates that incoming request has been processed successfully
     #[must_use]
     pub fn ok() -> Self {
-        Self { body: Vec::new() }
+        Self {
+            body: Vec::new(),
+            metadata: None,
+        }
+    }
+
+    /// Returns the metadata of the query, if this response carries query results.
+    #[must_use]
+    pub fn metadata(&self) -> Option<QueryMetadata> {
+        self.metadata
     }
 
     /// Consumes [`Self`] and returns the body of the response.
@@ -118,7 +130,10 @@ impl HelperResponse {
 impl From<PrepareQuery> for HelperResponse {
     fn from(value: PrepareQuery) -> Self {
         let v = serde_json::to_vec(&json!({"query_id": value.query_id})).unwrap();
-        Self { body: v }
+        Self {
+            body: v,
+            metadata: None,
+        }
     }
 }
 
@@ -131,14 +146,29 @@ impl From<()> for HelperResponse {
 impl From<QueryStatus> for HelperResponse {
     fn from(value: QueryStatus) -> Self {
         let v = serde_json::to_vec(&json!({"status": value})).unwrap();
-        Self { body: v }
+        Self {
+            body: v,
+            metadata: None,
+        }
+    }
+}
+
+impl From<PublicKeySet> for HelperResponse {
+    fn from(value: PublicKeySet) -> Self {
+        Self {
+            body: serde_json::to_vec(&value).unwrap(),
+            metadata: None,
+        }
     }
 }
 
 impl<R: AsRef<dyn ProtocolResult>> From<R> for HelperResponse {
     fn from(value: R) -> Self {
         let v = value.as_ref().to_bytes();
-        Self { body: v }
+        Self {
+            body: v,
+            metadata: Some(value.as_ref().metadata()),
+        }
     }
 }
 
@@ -152,6 +182,8 @@ pub enum Error {
     #[error(transparent)]
     QueryPrepare(#[from] PrepareQueryError),
     #[error(transparent)]
+    QueryAbort(#[from] QueryAbortError),
+    #[error(transparent)]
     QueryCompletion(#[from] QueryCompletionError),
     #[error(transparent)]
     QueryStatus(#[from] QueryStatusError),
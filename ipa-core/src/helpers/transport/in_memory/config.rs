@@ -1,7 +1,14 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    helpers::{HelperIdentity, Role, RoleAssignment},
+    helpers::{transport::routing::RouteId, HelperIdentity, Role, RoleAssignment},
     protocol::Gate,
     sharding::ShardIndex,
     sync::Arc,
@@ -142,3 +149,185 @@ impl<F: Fn(&MaliciousHelperContext, &mut Vec<u8>) + Send + Sync> StreamIntercept
         }
     }
 }
+
+/// Faults that can be injected into streams sent over in-memory transport.
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Delays every chunk of the stream by the given duration.
+    Delay(Duration),
+    /// Fails the stream before it delivers any data to the receiver.
+    Drop,
+    /// Closes the stream after delivering the given number of chunks.
+    Truncate { after_chunks: usize },
+    /// Delivers every chunk of the stream twice.
+    Duplicate,
+}
+
+/// Describes a single fault and the streams it applies to. By default, the rule applies
+/// to every stream sent by every helper. Use the filters to narrow it down.
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    fault: Fault,
+    route: Option<RouteId>,
+    gate: Option<String>,
+    origin: Option<HelperIdentity>,
+    probability: f64,
+}
+
+impl FaultRule {
+    #[must_use]
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            route: None,
+            gate: None,
+            origin: None,
+            probability: 1.0,
+        }
+    }
+
+    /// Restricts this rule to streams sent over the given route.
+    #[must_use]
+    pub fn on_route(mut self, route: RouteId) -> Self {
+        self.route = Some(route);
+        self
+    }
+
+    /// Restricts this rule to streams whose gate contains the given string.
+    /// Streams that are not tied to a gate never match this filter.
+    #[must_use]
+    pub fn on_gate<S: Into<String>>(mut self, gate: S) -> Self {
+        self.gate = Some(gate.into());
+        self
+    }
+
+    /// Restricts this rule to streams sent by the given helper.
+    #[must_use]
+    pub fn from_helper(mut self, origin: HelperIdentity) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Applies this rule to each matching stream with the given probability.
+    ///
+    /// ## Panics
+    /// If `probability` is not within `[0, 1]` range.
+    #[must_use]
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&probability),
+            "Probability must be within [0, 1] range, got {probability}"
+        );
+        self.probability = probability;
+        self
+    }
+
+    fn matches(&self, target: &FaultTarget<'_>) -> bool {
+        self.route.map_or(true, |route| route == target.route)
+            && self.origin.map_or(true, |origin| origin == target.identity)
+            && self.gate.as_ref().map_or(true, |gate| {
+                target
+                    .gate
+                    .is_some_and(|target_gate| target_gate.as_ref().contains(gate.as_str()))
+            })
+    }
+}
+
+/// Fault injection layer for in-memory transport. It is used in tests to make sure
+/// protocols fail cleanly when the network misbehaves.
+///
+/// Decisions made by this layer depend only on the seed and the stream being sent
+/// (origin, destination, route and gate), so test runs are deterministic regardless
+/// of the order in which streams are opened.
+#[derive(Debug, Clone, Default)]
+pub struct FaultConfig {
+    seed: u64,
+    rules: Vec<FaultRule>,
+}
+
+impl FaultConfig {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rules: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn with_rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Determines the faults to inject into the given stream. Returns `None` if the
+    /// stream must be delivered as is.
+    pub(super) fn plan(&self, target: &FaultTarget<'_>) -> Option<FaultPlan> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed ^ target.fingerprint());
+        let mut plan = FaultPlan::default();
+        let mut active = false;
+        for rule in &self.rules {
+            // always sample to keep decisions for one rule independent of the others
+            let sampled = rng.gen_bool(rule.probability);
+            if !sampled || !rule.matches(target) {
+                continue;
+            }
+            active = true;
+            match rule.fault {
+                Fault::Delay(delay) => {
+                    plan.delay = Some(plan.delay.unwrap_or_default() + delay);
+                }
+                Fault::Drop => plan.drop = true,
+                Fault::Truncate { after_chunks } => {
+                    plan.truncate_after = Some(
+                        plan.truncate_after
+                            .map_or(after_chunks, |v| v.min(after_chunks)),
+                    );
+                }
+                Fault::Duplicate => plan.duplicate = true,
+            }
+        }
+
+        active.then_some(plan)
+    }
+}
+
+/// Identifies the stream for fault injection.
+pub(super) struct FaultTarget<'a> {
+    pub shard_index: Option<ShardIndex>,
+    pub identity: HelperIdentity,
+    pub dest: &'a str,
+    pub route: RouteId,
+    pub gate: Option<&'a Gate>,
+}
+
+impl FaultTarget<'_> {
+    fn fingerprint(&self) -> u64 {
+        // `DefaultHasher::new` uses fixed keys, so this is stable within a single build.
+        let mut hasher = DefaultHasher::new();
+        self.shard_index.hash(&mut hasher);
+        self.identity.hash(&mut hasher);
+        self.dest.hash(&mut hasher);
+        format!("{:?}", self.route).hash(&mut hasher);
+        self.gate.map(AsRef::<str>::as_ref).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Faults selected for a single stream.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct FaultPlan {
+    pub delay: Option<Duration>,
+    pub drop: bool,
+    pub truncate_after: Option<usize>,
+    pub duplicate: bool,
+}
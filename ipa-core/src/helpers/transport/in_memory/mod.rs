@@ -8,7 +8,8 @@ use transport::TransportConfigBuilder;
 
 use crate::{
     helpers::{
-        in_memory_config::DynStreamInterceptor, transport::in_memory::config::passthrough,
+        in_memory_config::{DynStreamInterceptor, FaultConfig},
+        transport::in_memory::config::passthrough,
         HandlerRef, HelperIdentity,
     },
     sync::{Arc, Weak},
@@ -43,10 +44,23 @@ impl InMemoryMpcNetwork {
     pub fn with_stream_interceptor(
         handlers: [Option<HandlerRef>; 3],
         interceptor: &DynStreamInterceptor,
+    ) -> Self {
+        Self::with_config(handlers, interceptor, &FaultConfig::default())
+    }
+
+    /// Creates the network with all streams going through the given interceptor and
+    /// fault injection layer.
+    #[must_use]
+    pub fn with_config(
+        handlers: [Option<HandlerRef>; 3],
+        interceptor: &DynStreamInterceptor,
+        faults: &FaultConfig,
     ) -> Self {
         let [mut first, mut second, mut third]: [_; 3] = HelperIdentity::make_three().map(|i| {
             let mut config_builder = TransportConfigBuilder::for_helper(i);
-            config_builder.with_interceptor(interceptor);
+            config_builder
+                .with_interceptor(interceptor)
+                .with_faults(faults);
 
             Setup::with_config(i, config_builder.not_sharded())
         });
@@ -1,6 +1,6 @@
 use crate::{
     helpers::{
-        in_memory_config::{passthrough, DynStreamInterceptor},
+        in_memory_config::{passthrough, DynStreamInterceptor, FaultConfig},
         transport::in_memory::transport::{InMemoryTransport, Setup, TransportConfigBuilder},
         HelperIdentity,
     },
@@ -29,11 +29,23 @@ impl InMemoryShardNetwork {
     pub fn with_stream_interceptor<I: Into<ShardIndex>>(
         shard_count: I,
         interceptor: &DynStreamInterceptor,
+    ) -> Self {
+        Self::with_config(shard_count, interceptor, &FaultConfig::default())
+    }
+
+    /// Creates shard-to-shard channels with all streams going through the given interceptor
+    /// and fault injection layer.
+    pub fn with_config<I: Into<ShardIndex>>(
+        shard_count: I,
+        interceptor: &DynStreamInterceptor,
+        faults: &FaultConfig,
     ) -> Self {
         let shard_count = shard_count.into();
         let shard_network: [_; 3] = HelperIdentity::make_three().map(|h| {
             let mut config_builder = TransportConfigBuilder::for_helper(h);
-            config_builder.with_interceptor(interceptor);
+            config_builder
+                .with_interceptor(interceptor)
+                .with_faults(faults);
 
             let mut shard_connections = shard_count
                 .iter()
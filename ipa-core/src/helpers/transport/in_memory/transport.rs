@@ -22,9 +22,9 @@ use crate::{
     error::BoxError,
     helpers::{
         in_memory_config,
-        in_memory_config::DynStreamInterceptor,
+        in_memory_config::{DynStreamInterceptor, FaultConfig},
         transport::{
-            in_memory::config::InspectContext,
+            in_memory::config::{FaultPlan, FaultTarget, InspectContext},
             routing::{Addr, RouteId},
         },
         ApiError, BodyStream, HandlerRef, HelperIdentity, HelperResponse, NoResourceIdentifier,
@@ -117,9 +117,11 @@ impl<I: TransportIdentity> InMemoryTransport<I> {
                             }
                             RouteId::ReceiveQuery
                             | RouteId::PrepareQuery
+                            | RouteId::AbortQuery
                             | RouteId::QueryInput
                             | RouteId::QueryStatus
-                            | RouteId::CompleteQuery => {
+                            | RouteId::CompleteQuery
+                            | RouteId::PublicKeys => {
                                 handler
                                     .as_ref()
                                     .expect("Handler is set")
@@ -183,6 +185,13 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
         let channel = this.get_channel(dest);
         let addr = Addr::from_route(Some(this.identity), route);
         let gate = addr.gate.clone();
+        let faults = this.config.faults.plan(&FaultTarget {
+            shard_index: this.config.shard_index,
+            identity: this.config.identity,
+            dest: dest.as_str().as_ref(),
+            route: addr.route,
+            gate: gate.as_ref(),
+        });
 
         let (ack_tx, ack_rx) = oneshot::channel();
         let context = gate.map(|gate| InspectContext {
@@ -192,23 +201,22 @@ impl<I: TransportIdentity> Transport for Weak<InMemoryTransport<I>> {
             gate,
         });
 
-        channel
-            .send((
-                addr,
-                InMemoryStream::wrap(data.map({
-                    move |mut chunk| {
-                        if let Some(ref context) = context {
-                            this.config.stream_interceptor.peek(context, &mut chunk);
-                        }
-                        Ok(Bytes::from(chunk))
-                    }
-                })),
-                ack_tx,
-            ))
-            .await
-            .map_err(|_e| {
-                io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
-            })?;
+        let data = data.map({
+            move |mut chunk| {
+                if let Some(ref context) = context {
+                    this.config.stream_interceptor.peek(context, &mut chunk);
+                }
+                Ok(Bytes::from(chunk))
+            }
+        });
+        let stream = match faults {
+            Some(plan) => InMemoryStream::with_faults(data, plan),
+            None => InMemoryStream::wrap(data),
+        };
+
+        channel.send((addr, stream, ack_tx)).await.map_err(|_e| {
+            io::Error::new::<String>(io::ErrorKind::ConnectionAborted, "channel closed".into())
+        })?;
 
         ack_rx
             .await
@@ -248,6 +256,60 @@ impl InMemoryStream {
             inner: Box::pin(value),
         }
     }
+
+    /// Forwards `value` to the receiver, injecting the faults from `plan` along the way.
+    ///
+    /// The source stream is always drained to completion, even if the receiver gets a
+    /// dropped or truncated stream, so the sending side never gets stuck waiting for
+    /// the buffer space to free up.
+    fn with_faults<S: Stream<Item = StreamItem> + Send + 'static>(
+        value: S,
+        plan: FaultPlan,
+    ) -> Self {
+        let (tx, rx) = channel(1);
+        tokio::spawn(async move {
+            let mut value = std::pin::pin!(value);
+            let mut tx = Some(tx);
+            if plan.drop {
+                if let Some(tx) = tx.take() {
+                    let _ = tx
+                        .send(Err("stream dropped by fault injection".into()))
+                        .await;
+                }
+            }
+
+            let mut forwarded = 0;
+            while let Some(item) = value.next().await {
+                if plan.truncate_after.is_some_and(|limit| forwarded >= limit) {
+                    tx = None;
+                }
+                let Some(ref sender) = tx else {
+                    continue;
+                };
+
+                #[cfg(not(feature = "shuttle"))]
+                if let Some(delay) = plan.delay {
+                    ::tokio::time::sleep(delay).await;
+                }
+
+                let copies = if plan.duplicate { 2 } else { 1 };
+                for _ in 0..copies {
+                    let item = item
+                        .as_ref()
+                        .map(Clone::clone)
+                        .map_err(|e| e.to_string().into());
+                    if sender.send(item).await.is_err() {
+                        // receiver is gone, keep draining the source
+                        tx = None;
+                        break;
+                    }
+                }
+                forwarded += 1;
+            }
+        });
+
+        Self::from(rx)
+    }
 }
 
 impl From<Receiver<StreamItem>> for InMemoryStream {
@@ -365,7 +427,11 @@ mod tests {
             query::{PrepareQuery, QueryConfig, QueryType::TestMultiply},
             transport::{
                 in_memory::{
-                    transport::{Addr, ConnectionTx, Error, InMemoryStream, InMemoryTransport},
+                    config::{Fault, FaultConfig, FaultRule},
+                    transport::{
+                        Addr, ConnectionTx, Error, InMemoryStream, InMemoryTransport,
+                        TransportConfigBuilder,
+                    },
                     InMemoryMpcNetwork, Setup,
                 },
                 routing::RouteId,
@@ -414,7 +480,7 @@ mod tests {
                     .unwrap()
                     .take()
                     .expect("query callback invoked more than once")
-                    .send(query_config)
+                    .send(query_config.clone())
                     .unwrap();
                 Ok(HelperResponse::from(PrepareQuery {
                     query_id: QueryId,
@@ -429,7 +495,7 @@ mod tests {
 
         send_and_ack(
             &tx,
-            Addr::from_route(Some(HelperIdentity::TWO), expected),
+            Addr::from_route(Some(HelperIdentity::TWO), expected.clone()),
             stream::empty(),
         )
         .await;
@@ -541,6 +607,42 @@ mod tests {
         send_and_verify(HelperIdentity::TWO, HelperIdentity::ONE, &transports).await;
     }
 
+    #[tokio::test]
+    async fn duplicate_fault() {
+        let faults = FaultConfig::new(0)
+            .with_rule(FaultRule::new(Fault::Duplicate).on_route(RouteId::Records));
+        let mut setup1 = Setup::with_config(
+            HelperIdentity::ONE,
+            TransportConfigBuilder::for_helper(HelperIdentity::ONE)
+                .with_faults(&faults)
+                .not_sharded(),
+        );
+        let mut setup2 = Setup::new(HelperIdentity::TWO);
+        setup1.connect(&mut setup2);
+
+        let transport1 = setup1.start(None);
+        let transport2 = setup2.start(None);
+        let gate = Gate::from(STEP);
+
+        Arc::downgrade(&transport1)
+            .send(
+                HelperIdentity::TWO,
+                (RouteId::Records, QueryId, gate.clone()),
+                stream::iter(vec![vec![1, 2, 3], vec![4, 5, 6]]),
+            )
+            .await
+            .unwrap();
+        let recv = Arc::downgrade(&transport2)
+            .receive(HelperIdentity::ONE, (QueryId, gate))
+            .into_bytes_stream();
+
+        // the receiver sees every chunk twice, in order
+        assert_eq!(
+            vec![vec![1, 2, 3], vec![1, 2, 3], vec![4, 5, 6], vec![4, 5, 6]],
+            recv.collect::<Vec<_>>().await
+        );
+    }
+
     #[tokio::test]
     async fn panic_if_stream_received_twice() {
         let (tx, owned_transport) = Setup::new(HelperIdentity::ONE).into_active_conn(None);
@@ -628,11 +730,13 @@ pub struct TransportConfig {
     pub shard_index: Option<ShardIndex>,
     pub identity: HelperIdentity,
     pub stream_interceptor: DynStreamInterceptor,
+    pub faults: FaultConfig,
 }
 
 pub struct TransportConfigBuilder {
     identity: HelperIdentity,
     stream_interceptor: DynStreamInterceptor,
+    faults: FaultConfig,
 }
 
 impl TransportConfigBuilder {
@@ -640,6 +744,7 @@ impl TransportConfigBuilder {
         Self {
             identity,
             stream_interceptor: in_memory_config::passthrough(),
+            faults: FaultConfig::default(),
         }
     }
 
@@ -649,11 +754,18 @@ impl TransportConfigBuilder {
         self
     }
 
+    pub fn with_faults(&mut self, faults: &FaultConfig) -> &mut Self {
+        self.faults = faults.clone();
+
+        self
+    }
+
     pub fn bind_to_shard(&self, shard_index: ShardIndex) -> TransportConfig {
         TransportConfig {
             shard_index: Some(shard_index),
             identity: self.identity,
             stream_interceptor: Arc::clone(&self.stream_interceptor),
+            faults: self.faults.clone(),
         }
     }
 
@@ -662,6 +774,7 @@ impl TransportConfigBuilder {
             shard_index: None,
             identity: self.identity,
             stream_interceptor: Arc::clone(&self.stream_interceptor),
+            faults: self.faults.clone(),
         }
     }
 }
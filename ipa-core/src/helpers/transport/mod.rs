@@ -10,6 +10,7 @@ use futures::Stream;
 use crate::{
     helpers::HelperIdentity,
     protocol::{Gate, QueryId},
+    sync::Arc,
 };
 
 mod handler;
@@ -18,6 +19,7 @@ mod in_memory;
 pub mod query;
 mod receive;
 pub mod routing;
+mod shaping;
 mod stream;
 
 pub use handler::{
@@ -26,12 +28,13 @@ pub use handler::{
 #[cfg(feature = "in-memory-infra")]
 pub use in_memory::{config, InMemoryMpcNetwork, InMemoryShardNetwork, InMemoryTransport};
 pub use receive::{LogErrors, ReceiveRecords};
-#[cfg(feature = "web-app")]
-pub use stream::WrappedAxumBodyStream;
+pub use shaping::{Shaped, TokenBucket};
 pub use stream::{
     BodyStream, BytesStream, LengthDelimitedStream, RecordsStream, SingleRecordStream,
     StreamCollection, StreamKey, WrappedBoxBodyStream,
 };
+#[cfg(feature = "web-app")]
+pub use stream::{ResumableSender, ResumableStream, StreamResumer, WrappedAxumBodyStream};
 
 use crate::{
     helpers::{transport::routing::RouteId, Role, TransportIdentity},
@@ -218,6 +221,12 @@ pub trait Transport: Clone + Send + Sync + 'static {
         route: R,
     ) -> Self::RecordsStream;
 
+    /// Returns the token bucket that limits outbound bandwidth to the given peer, if there is one.
+    /// Gateway uses it to hold back senders while the link to that peer is saturated.
+    fn bandwidth(&self, _dest: Self::Identity) -> Option<Arc<TokenBucket>> {
+        None
+    }
+
     /// Alias for `Clone::clone`.
     ///
     /// `Transport` is implemented for `Weak<InMemoryTranport>` and `Arc<HttpTransport>`. Clippy won't
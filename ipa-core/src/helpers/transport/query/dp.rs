@@ -0,0 +1,577 @@
+use std::{
+    fmt::{Display, Formatter},
+    num::NonZeroU32,
+    str::FromStr,
+};
+
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+
+use super::DpMechanism;
+use crate::protocol::dp::MAX_EPSILON;
+
+/// Mechanism that adds noise to the query output.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum NoiseMechanism {
+    /// No noise. The output is not differentially private, this is only meant for testing.
+    None,
+    /// Binomial noise.
+    Binomial,
+    /// Truncated discrete Laplace noise.
+    #[default]
+    DiscreteLaplace,
+    /// Skellam noise, the difference of two Poisson samples, that every pair of helpers adds.
+    /// It is centered at zero and, unlike the other mechanisms, is zCDP, so the privacy loss of
+    /// several queries or histograms that use it adds up more slowly.
+    Skellam,
+}
+
+impl Display for NoiseMechanism {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Binomial => "binomial",
+            Self::DiscreteLaplace => "discrete-laplace",
+            Self::Skellam => "skellam",
+        })
+    }
+}
+
+/// Dummy records added to the query inputs and to the aggregation, to hide how many records
+/// each match key has and how many records reach each breakdown.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum PaddingMode {
+    /// No padding. The inputs are not differentially private, this is only meant for benchmarks.
+    None,
+    /// Padding sampled from truncated discrete Laplace distributions by every pair of helpers.
+    #[default]
+    Dp,
+    /// Padding whose counts are sampled by the three helpers together, so that no helper knows
+    /// them. It generates a candidate dummy for every coin of a binomial distribution and
+    /// shuffles them, which makes it more expensive than `dp`.
+    JointDp,
+}
+
+impl Display for PaddingMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Dp => "dp",
+            Self::JointDp => "joint-dp",
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DpConfigError {
+    #[error("dp epsilon must be in (0, {MAX_EPSILON}], got {0}")]
+    Epsilon(f64),
+    #[error("dp delta must be in (0, 1), got {0}")]
+    Delta(f64),
+    #[error("dp threshold can't be used with binomial noise, which is not centered at zero")]
+    ThresholdWithBinomial,
+    #[error("padding epsilon must be positive, got {0}")]
+    PaddingEpsilon(f64),
+    #[error("padding delta must be in (0, 1), got {0}")]
+    PaddingDelta(f64),
+    #[error("padding epsilon {0} exceeds the maximum {1} allowed by the helper policy")]
+    PolicyEpsilon(f64, f64),
+    #[error("padding delta {0} exceeds the maximum {1} allowed by the helper policy")]
+    PolicyDelta(f64, f64),
+    #[error(
+        "match key cardinality cap {0} is below the minimum {1} required by the helper policy"
+    )]
+    PolicyCardinalityCap(NonZeroU32, u32),
+    #[error(
+        "aggregation padding sensitivity {0} is below the minimum {1} required by the helper policy"
+    )]
+    PolicySensitivity(NonZeroU32, u32),
+    #[error("helper policy does not allow queries without padding")]
+    PolicyNoPadding,
+}
+
+/// Weakest padding a helper accepts. Queries that ask for less padding are rejected, so a report
+/// collector can't weaken the privacy of the inputs below what the helper operator agreed to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaddingPolicy {
+    /// Largest padding epsilon a query can use.
+    pub max_epsilon: f64,
+    /// Largest padding delta a query can use.
+    pub max_delta: f64,
+    /// Smallest match key cardinality cap a query can use.
+    pub min_matchkey_cardinality_cap: u32,
+    /// Smallest aggregation padding sensitivity a query can use.
+    pub min_aggregation_padding_sensitivity: u32,
+    /// Whether queries can run without padding.
+    pub allow_no_padding: bool,
+}
+
+impl Default for PaddingPolicy {
+    /// Accepts the padding of [`DpConfig::default`] or stronger.
+    fn default() -> Self {
+        let config = DpConfig::default();
+        Self {
+            max_epsilon: config.padding_epsilon,
+            max_delta: config.padding_delta,
+            min_matchkey_cardinality_cap: config.matchkey_cardinality_cap.get(),
+            min_aggregation_padding_sensitivity: config.aggregation_padding_sensitivity.get(),
+            allow_no_padding: false,
+        }
+    }
+}
+
+impl PaddingPolicy {
+    /// Accepts any padding, including none. Only meant for tests and benchmarks.
+    #[must_use]
+    pub fn permissive() -> Self {
+        Self {
+            max_epsilon: f64::INFINITY,
+            max_delta: 1.0,
+            min_matchkey_cardinality_cap: 1,
+            min_aggregation_padding_sensitivity: 1,
+            allow_no_padding: true,
+        }
+    }
+}
+
+/// Differential privacy parameters of a query: the noise added to its output, and the dummy
+/// records that are padded to its inputs and to the aggregation.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+#[serde(default)]
+pub struct DpConfig {
+    /// Mechanism that adds noise to the query output
+    #[cfg_attr(
+        feature = "clap",
+        arg(long = "dp-mechanism", value_enum, default_value_t)
+    )]
+    #[serde(rename = "dp_mechanism")]
+    pub mechanism: NoiseMechanism,
+
+    /// Epsilon of the output noise. Ignored if there is no noise
+    #[cfg_attr(
+        feature = "clap",
+        arg(short = 'e', long = "dp-epsilon", default_value = "5.0")
+    )]
+    #[serde(rename = "dp_epsilon", deserialize_with = "number_from_str")]
+    pub epsilon: f64,
+
+    /// Delta of the output noise. Ignored if there is no noise
+    #[cfg_attr(feature = "clap", arg(long = "dp-delta", default_value = "1e-6"))]
+    #[serde(rename = "dp_delta", deserialize_with = "number_from_str")]
+    pub delta: f64,
+
+    /// Epsilon of the padding, spent once on the inputs, and once on the aggregation if it
+    /// reveals breakdown keys
+    #[cfg_attr(feature = "clap", arg(long, default_value = "5.0"))]
+    #[serde(deserialize_with = "number_from_str")]
+    pub padding_epsilon: f64,
+
+    /// Delta of the padding, spent once on the inputs, and once on the aggregation if it
+    /// reveals breakdown keys
+    #[cfg_attr(feature = "clap", arg(long, default_value = "1e-6"))]
+    #[serde(deserialize_with = "number_from_str")]
+    pub padding_delta: f64,
+
+    /// Whether to pad the inputs and the aggregation with dummy records
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t))]
+    pub padding: PaddingMode,
+
+    /// Largest number of records per match key that the input padding hides
+    #[cfg_attr(feature = "clap", arg(long, default_value = "10"))]
+    #[serde(deserialize_with = "number_from_str")]
+    pub matchkey_cardinality_cap: NonZeroU32,
+
+    /// Largest number of records a single user contributes to any breakdown, that the
+    /// aggregation padding hides
+    #[cfg_attr(feature = "clap", arg(long, default_value = "10"))]
+    #[serde(deserialize_with = "number_from_str")]
+    pub aggregation_padding_sensitivity: NonZeroU32,
+
+    /// Breakdowns whose noisy value is below this threshold are reported as zero. Suppression
+    /// happens in MPC, before the output is revealed. Binomial noise is not centered at zero and
+    /// would keep breakdowns below the threshold, so it can't be combined with a threshold
+    #[cfg_attr(feature = "clap", arg(long = "dp-threshold"))]
+    #[serde(rename = "dp_threshold", deserialize_with = "threshold_from_str")]
+    pub threshold: Option<NonZeroU32>,
+}
+
+impl Default for DpConfig {
+    fn default() -> Self {
+        Self {
+            mechanism: NoiseMechanism::DiscreteLaplace,
+            epsilon: 0.10,
+            delta: 1e-6,
+            padding_epsilon: 5.0,
+            padding_delta: 1e-6,
+            padding: PaddingMode::Dp,
+            matchkey_cardinality_cap: NonZeroU32::new(10).unwrap(),
+            aggregation_padding_sensitivity: NonZeroU32::new(10).unwrap(),
+            threshold: None,
+        }
+    }
+}
+
+impl DpConfig {
+    /// No output noise, and the default padding.
+    #[must_use]
+    pub fn no_noise() -> Self {
+        Self {
+            mechanism: NoiseMechanism::None,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn discrete_laplace(epsilon: f64) -> Self {
+        Self {
+            mechanism: NoiseMechanism::DiscreteLaplace,
+            epsilon,
+            ..Self::default()
+        }
+    }
+
+    /// Padding that hides fewer records per match key and per breakdown than the default. It
+    /// adds fewer dummy records, which keeps tests fast.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn with_relaxed_padding(self) -> Self {
+        Self {
+            matchkey_cardinality_cap: NonZeroU32::new(3).unwrap(),
+            aggregation_padding_sensitivity: NonZeroU32::new(3).unwrap(),
+            ..self
+        }
+    }
+
+    /// Checks that the parameters are in range, so that a query does not fail after it has
+    /// started because of them.
+    ///
+    /// ## Errors
+    /// If any of the parameters is out of range. Epsilon and delta of the output noise are not
+    /// checked if there is no noise, and those of the padding if there is no padding.
+    pub fn validate(&self) -> Result<(), DpConfigError> {
+        let is_probability = |v: f64| v > 0.0 && v < 1.0;
+        if self.mechanism != NoiseMechanism::None {
+            if !(self.epsilon > 0.0 && self.epsilon <= MAX_EPSILON) {
+                return Err(DpConfigError::Epsilon(self.epsilon));
+            }
+            if !is_probability(self.delta) {
+                return Err(DpConfigError::Delta(self.delta));
+            }
+        }
+        if self.mechanism == NoiseMechanism::Binomial && self.threshold.is_some() {
+            return Err(DpConfigError::ThresholdWithBinomial);
+        }
+        if self.padding != PaddingMode::None {
+            if !(self.padding_epsilon > 0.0 && self.padding_epsilon.is_finite()) {
+                return Err(DpConfigError::PaddingEpsilon(self.padding_epsilon));
+            }
+            if !is_probability(self.padding_delta) {
+                return Err(DpConfigError::PaddingDelta(self.padding_delta));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the padding is at least as strong as the helper policy requires.
+    ///
+    /// ## Errors
+    /// If the query asks for less padding than `policy` allows.
+    pub fn check_policy(&self, policy: &PaddingPolicy) -> Result<(), DpConfigError> {
+        if self.padding == PaddingMode::None {
+            return if policy.allow_no_padding {
+                Ok(())
+            } else {
+                Err(DpConfigError::PolicyNoPadding)
+            };
+        }
+        if self.padding_epsilon > policy.max_epsilon {
+            return Err(DpConfigError::PolicyEpsilon(
+                self.padding_epsilon,
+                policy.max_epsilon,
+            ));
+        }
+        if self.padding_delta > policy.max_delta {
+            return Err(DpConfigError::PolicyDelta(
+                self.padding_delta,
+                policy.max_delta,
+            ));
+        }
+        if self.matchkey_cardinality_cap.get() < policy.min_matchkey_cardinality_cap {
+            return Err(DpConfigError::PolicyCardinalityCap(
+                self.matchkey_cardinality_cap,
+                policy.min_matchkey_cardinality_cap,
+            ));
+        }
+        if self.aggregation_padding_sensitivity.get() < policy.min_aggregation_padding_sensitivity {
+            return Err(DpConfigError::PolicySensitivity(
+                self.aggregation_padding_sensitivity,
+                policy.min_aggregation_padding_sensitivity,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Parameters of the mechanism that adds noise to the query output.
+    #[must_use]
+    pub fn noise(&self) -> DpMechanism {
+        let Self { epsilon, delta, .. } = *self;
+        match self.mechanism {
+            NoiseMechanism::None => DpMechanism::NoDp,
+            NoiseMechanism::Binomial => DpMechanism::Binomial { epsilon, delta },
+            NoiseMechanism::DiscreteLaplace => DpMechanism::DiscreteLaplace { epsilon, delta },
+            NoiseMechanism::Skellam => DpMechanism::Skellam { epsilon, delta },
+        }
+    }
+
+    /// Epsilon of the output noise, or 0 if there is none.
+    #[must_use]
+    pub fn noise_epsilon(&self) -> f64 {
+        match self.mechanism {
+            NoiseMechanism::None => 0.0,
+            NoiseMechanism::Binomial
+            | NoiseMechanism::DiscreteLaplace
+            | NoiseMechanism::Skellam => self.epsilon,
+        }
+    }
+}
+
+impl Display for DpConfig {
+    /// Formats the parameters as they appear in a query string.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "dp_mechanism={}&dp_epsilon={}&dp_delta={}&padding_epsilon={}&padding_delta={}",
+            self.mechanism, self.epsilon, self.delta, self.padding_epsilon, self.padding_delta
+        )?;
+        write!(
+            f,
+            "&padding={}&matchkey_cardinality_cap={}&aggregation_padding_sensitivity={}",
+            self.padding, self.matchkey_cardinality_cap, self.aggregation_padding_sensitivity
+        )?;
+        if let Some(threshold) = self.threshold {
+            write!(f, "&dp_threshold={threshold}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The DP section is flattened into query configs, and values of flattened fields in a query
+/// string reach the deserializer as strings.
+fn number_from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr<T> {
+        Number(T),
+        Str(String),
+    }
+
+    match Repr::deserialize(deserializer)? {
+        Repr::Number(v) => Ok(v),
+        Repr::Str(s) => s.parse().map_err(D::Error::custom),
+    }
+}
+
+/// Same as [`number_from_str`], for the optional threshold.
+fn threshold_from_str<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NonZeroU32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Number(NonZeroU32),
+        Str(String),
+    }
+
+    match Option::<Repr>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Repr::Number(v)) => Ok(Some(v)),
+        Some(Repr::Str(s)) => s.parse().map(Some).map_err(D::Error::custom),
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::{DpConfig, DpConfigError, NoiseMechanism, PaddingMode, PaddingPolicy};
+    use crate::helpers::query::DpMechanism;
+
+    #[test]
+    fn validate() {
+        DpConfig::default().validate().unwrap();
+        let config = DpConfig {
+            mechanism: NoiseMechanism::None,
+            epsilon: -1.0,
+            ..DpConfig::default()
+        };
+        config.validate().unwrap();
+        assert_eq!(DpMechanism::NoDp, config.noise());
+        assert!(config.noise_epsilon().abs() < f64::EPSILON);
+
+        let invalid = [
+            (DpConfig::discrete_laplace(0.0), "dp epsilon"),
+            (DpConfig::discrete_laplace(21.0), "dp epsilon"),
+            (DpConfig::discrete_laplace(f64::NAN), "dp epsilon"),
+            (
+                DpConfig {
+                    delta: 1.0,
+                    ..DpConfig::default()
+                },
+                "dp delta",
+            ),
+            (
+                DpConfig {
+                    padding_epsilon: 0.0,
+                    ..DpConfig::no_noise()
+                },
+                "padding epsilon",
+            ),
+            (
+                DpConfig {
+                    padding_delta: 0.0,
+                    ..DpConfig::no_noise()
+                },
+                "padding delta",
+            ),
+            (
+                DpConfig {
+                    mechanism: NoiseMechanism::Binomial,
+                    threshold: NonZeroU32::new(10),
+                    ..DpConfig::default()
+                },
+                "dp threshold",
+            ),
+        ];
+        DpConfig {
+            padding: PaddingMode::None,
+            padding_epsilon: 0.0,
+            ..DpConfig::no_noise()
+        }
+        .validate()
+        .unwrap();
+        for (config, message) in invalid {
+            let err: DpConfigError = config.validate().unwrap_err();
+            assert!(err.to_string().starts_with(message), "{err}");
+        }
+    }
+
+    #[test]
+    fn noise() {
+        let config = DpConfig {
+            mechanism: NoiseMechanism::Binomial,
+            epsilon: 2.0,
+            delta: 1e-8,
+            ..DpConfig::default()
+        };
+        assert_eq!(
+            DpMechanism::Binomial {
+                epsilon: 2.0,
+                delta: 1e-8
+            },
+            config.noise()
+        );
+        assert!((config.noise_epsilon() - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn threshold() {
+        let config: DpConfig = serde_json::from_str(r#"{"dp_threshold":"25"}"#).unwrap();
+        assert_eq!(NonZeroU32::new(25), config.threshold);
+        assert!(config.to_string().ends_with("&dp_threshold=25"));
+
+        let config: DpConfig = serde_json::from_str(r#"{"dp_threshold":25}"#).unwrap();
+        assert_eq!(NonZeroU32::new(25), config.threshold);
+
+        let config: DpConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(None, config.threshold);
+        assert!(!config.to_string().contains("dp_threshold"));
+
+        serde_json::from_str::<DpConfig>(r#"{"dp_threshold":"0"}"#).unwrap_err();
+    }
+
+    #[test]
+    fn padding() {
+        let config: DpConfig = serde_json::from_str(
+            r#"{"padding":"dp","matchkey_cardinality_cap":"3","aggregation_padding_sensitivity":4}"#,
+        )
+        .unwrap();
+        assert_eq!(PaddingMode::Dp, config.padding);
+        assert_eq!(3, config.matchkey_cardinality_cap.get());
+        assert_eq!(4, config.aggregation_padding_sensitivity.get());
+        assert!(config
+            .to_string()
+            .contains("&padding=dp&matchkey_cardinality_cap=3&aggregation_padding_sensitivity=4"));
+
+        let config: DpConfig = serde_json::from_str(r#"{"padding":"joint-dp"}"#).unwrap();
+        assert_eq!(PaddingMode::JointDp, config.padding);
+        assert!(config.to_string().contains("&padding=joint-dp&"));
+
+        let config: DpConfig = serde_json::from_str(r#"{"padding":"none"}"#).unwrap();
+        assert_eq!(PaddingMode::None, config.padding);
+        assert_eq!(
+            DpConfig::default().matchkey_cardinality_cap,
+            config.matchkey_cardinality_cap
+        );
+
+        serde_json::from_str::<DpConfig>(r#"{"matchkey_cardinality_cap":"0"}"#).unwrap_err();
+    }
+
+    #[test]
+    fn padding_policy() {
+        let policy = PaddingPolicy {
+            max_epsilon: 5.0,
+            max_delta: 1e-6,
+            min_matchkey_cardinality_cap: 10,
+            min_aggregation_padding_sensitivity: 10,
+            allow_no_padding: false,
+        };
+        DpConfig::default().check_policy(&policy).unwrap();
+        DpConfig {
+            padding_epsilon: 1.0,
+            matchkey_cardinality_cap: 20.try_into().unwrap(),
+            ..DpConfig::default()
+        }
+        .check_policy(&policy)
+        .unwrap();
+
+        let weaker = [
+            DpConfig {
+                padding_epsilon: 10.0,
+                ..DpConfig::default()
+            },
+            DpConfig {
+                padding_delta: 1e-4,
+                ..DpConfig::default()
+            },
+            DpConfig {
+                matchkey_cardinality_cap: 3.try_into().unwrap(),
+                ..DpConfig::default()
+            },
+            DpConfig {
+                aggregation_padding_sensitivity: 3.try_into().unwrap(),
+                ..DpConfig::default()
+            },
+            DpConfig {
+                padding: PaddingMode::None,
+                ..DpConfig::default()
+            },
+        ];
+        for config in weaker {
+            let err = config.check_policy(&policy).unwrap_err();
+            assert!(err.to_string().contains("helper policy"), "{err}");
+            config.check_policy(&PaddingPolicy::permissive()).unwrap();
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::DpMechanism;
+
+/// Levels of a hierarchical breakdown, such as campaign → ad set → ad. In text form, it is
+/// written as the number of breakdown key bits that every level adds, from the coarsest level to
+/// the finest, for example `2,3,3`.
+///
+/// Breakdown keys are bit prefixes of each other: with `2,3,3`, the campaign of breakdown key
+/// `k` is `k >> 6` and its ad set is `k >> 3`. Breakdown keys that do not fit in the bits of all
+/// levels do not contribute to any level.
+///
+/// An IPA query with a hierarchy outputs one histogram per level, concatenated from the coarsest
+/// level to the finest, and splits its DP budget evenly across the levels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct BreakdownHierarchy {
+    /// Breakdown key bits of every level, counting the bits of all coarser levels.
+    prefix_bits: [u32; Self::MAX_LEVELS],
+    levels: usize,
+}
+
+impl BreakdownHierarchy {
+    /// This must be kept in sync with the level steps in `IpaPrfStep`.
+    pub const MAX_LEVELS: usize = 4;
+    /// Breakdown key bits of all levels together.
+    pub const MAX_BITS: u32 = 8;
+
+    /// ## Errors
+    /// If there are no levels or more than [`Self::MAX_LEVELS`], if a level does not add any
+    /// bits, or if all levels together need more than [`Self::MAX_BITS`] bits.
+    pub fn new(level_bits: &[u32]) -> Result<Self, String> {
+        if level_bits.is_empty() || level_bits.len() > Self::MAX_LEVELS {
+            return Err(format!(
+                "breakdown hierarchy must have between 1 and {} levels, got {}",
+                Self::MAX_LEVELS,
+                level_bits.len()
+            ));
+        }
+        let mut prefix_bits = [0; Self::MAX_LEVELS];
+        let mut total = 0_u32;
+        for (prefix, &bits) in prefix_bits.iter_mut().zip(level_bits) {
+            if bits == 0 {
+                return Err("every level of a breakdown hierarchy needs at least one bit".into());
+            }
+            total = total.saturating_add(bits);
+            *prefix = total;
+        }
+        if total > Self::MAX_BITS {
+            return Err(format!(
+                "breakdown hierarchy needs {total} bits, at most {} are supported",
+                Self::MAX_BITS
+            ));
+        }
+
+        Ok(Self {
+            prefix_bits,
+            levels: level_bits.len(),
+        })
+    }
+
+    #[must_use]
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+
+    /// Breakdown key bits of every level, counting the bits of all coarser levels.
+    #[must_use]
+    pub fn prefix_bits(&self) -> &[u32] {
+        &self.prefix_bits[..self.levels]
+    }
+
+    /// Breakdown key bits of the finest level.
+    #[must_use]
+    pub fn key_bits(&self) -> u32 {
+        self.prefix_bits[self.levels - 1]
+    }
+
+    /// Number of breakdowns of every level.
+    pub fn level_sizes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.prefix_bits().iter().map(|&bits| 1 << bits)
+    }
+
+    /// Number of values in the output of a query, for all levels together.
+    #[must_use]
+    pub fn output_len(&self) -> usize {
+        self.level_sizes().sum()
+    }
+
+    /// Splits the output of a query into the histograms of every level.
+    ///
+    /// ## Errors
+    /// If `values` does not have [`Self::output_len`] elements.
+    pub fn split<T>(&self, mut values: Vec<T>) -> Result<Vec<Vec<T>>, String> {
+        if values.len() != self.output_len() {
+            return Err(format!(
+                "expected {} values for breakdown hierarchy {self}, got {}",
+                self.output_len(),
+                values.len()
+            ));
+        }
+        let mut levels = Vec::with_capacity(self.levels);
+        for size in self.level_sizes() {
+            let rest = values.split_off(size);
+            levels.push(values);
+            values = rest;
+        }
+
+        Ok(levels)
+    }
+
+    /// Noise added to every level, so that all levels together spend the budget of `dp`.
+    #[must_use]
+    pub fn level_noise(&self, dp: DpMechanism) -> DpMechanism {
+        #[allow(clippy::cast_precision_loss)] // there are only a few levels
+        let levels = self.levels as f64;
+        match dp {
+            DpMechanism::NoDp => DpMechanism::NoDp,
+            DpMechanism::Binomial { epsilon, delta } => DpMechanism::Binomial {
+                epsilon: epsilon / levels,
+                delta: delta / levels,
+            },
+            DpMechanism::DiscreteLaplace { epsilon, delta } => DpMechanism::DiscreteLaplace {
+                epsilon: epsilon / levels,
+                delta: delta / levels,
+            },
+            DpMechanism::Skellam { epsilon, delta } => DpMechanism::Skellam {
+                epsilon: epsilon / levels,
+                delta: delta / levels,
+            },
+        }
+    }
+}
+
+impl Display for BreakdownHierarchy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut coarser = 0;
+        for (i, &bits) in self.prefix_bits().iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", bits - coarser)?;
+            coarser = bits;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for BreakdownHierarchy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level_bits = s
+            .split(',')
+            .map(|v| {
+                v.trim()
+                    .parse::<u32>()
+                    .map_err(|e| format!("invalid breakdown hierarchy level {v}: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(&level_bits)
+    }
+}
+
+impl TryFrom<String> for BreakdownHierarchy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<BreakdownHierarchy> for String {
+    fn from(value: BreakdownHierarchy) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::BreakdownHierarchy;
+    use crate::helpers::query::DpMechanism;
+
+    #[test]
+    fn parse() {
+        let hierarchy = "2, 3,3".parse::<BreakdownHierarchy>().unwrap();
+        assert_eq!(3, hierarchy.levels());
+        assert_eq!(&[2, 5, 8], hierarchy.prefix_bits());
+        assert_eq!(8, hierarchy.key_bits());
+        assert_eq!(
+            vec![4, 32, 256],
+            hierarchy.level_sizes().collect::<Vec<_>>()
+        );
+        assert_eq!(292, hierarchy.output_len());
+        assert_eq!("2,3,3", hierarchy.to_string());
+        assert_eq!(
+            hierarchy,
+            serde_json::from_str(&serde_json::to_string(&hierarchy).unwrap()).unwrap()
+        );
+
+        for invalid in ["", "2,,3", "0,3", "3,3,3", "1,1,1,1,1", "two"] {
+            assert!(
+                invalid.parse::<BreakdownHierarchy>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn split() {
+        let hierarchy = BreakdownHierarchy::new(&[1, 1]).unwrap();
+        assert_eq!(
+            vec![vec![0, 1], vec![2, 3, 4, 5]],
+            hierarchy.split((0..6).collect()).unwrap()
+        );
+        assert!(hierarchy.split(vec![0; 5]).is_err());
+    }
+
+    #[test]
+    fn level_noise() {
+        let hierarchy = BreakdownHierarchy::new(&[1, 2, 3, 2]).unwrap();
+        assert_eq!(
+            DpMechanism::DiscreteLaplace {
+                epsilon: 0.25,
+                delta: 0.25e-6,
+            },
+            hierarchy.level_noise(DpMechanism::DiscreteLaplace {
+                epsilon: 1.0,
+                delta: 1e-6,
+            })
+        );
+        assert_eq!(DpMechanism::NoDp, hierarchy.level_noise(DpMechanism::NoDp));
+    }
+}
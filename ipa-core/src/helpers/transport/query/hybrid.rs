@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::DpConfig;
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct HybridQueryParams {
@@ -7,10 +9,9 @@ pub struct HybridQueryParams {
     pub per_user_credit_cap: u32,
     #[cfg_attr(feature = "clap", arg(long, default_value = "5"))]
     pub max_breakdown_key: u32,
-    #[cfg_attr(feature = "clap", arg(short = 'd', long, default_value = "1"))]
-    pub with_dp: u32,
-    #[cfg_attr(feature = "clap", arg(short = 'e', long, default_value = "5.0"))]
-    pub epsilon: f64,
+    #[cfg_attr(feature = "clap", command(flatten))]
+    #[serde(flatten)]
+    pub dp: DpConfig,
     #[cfg_attr(feature = "clap", arg(long))]
     #[serde(default)]
     pub plaintext_match_keys: bool,
@@ -24,8 +25,7 @@ impl Default for HybridQueryParams {
         Self {
             per_user_credit_cap: 8,
             max_breakdown_key: 20,
-            with_dp: 1,
-            epsilon: 0.10,
+            dp: DpConfig::default(),
             plaintext_match_keys: false,
         }
     }
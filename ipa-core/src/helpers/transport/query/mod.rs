@@ -1,3 +1,5 @@
+mod dp;
+mod hierarchy;
 mod hybrid;
 
 use std::{
@@ -5,6 +7,8 @@ use std::{
     num::NonZeroU32,
 };
 
+pub use dp::{DpConfig, DpConfigError, NoiseMechanism, PaddingMode, PaddingPolicy};
+pub use hierarchy::BreakdownHierarchy;
 pub use hybrid::HybridQueryParams;
 use serde::{Deserialize, Deserializer, Serialize};
 
@@ -15,6 +19,7 @@ use crate::{
         RoleAssignment, RouteParams,
     },
     protocol::QueryId,
+    report::EpochRange,
 };
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Serialize)]
@@ -80,7 +85,7 @@ impl From<QuerySize> for usize {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct QueryConfig {
     pub size: QuerySize,
@@ -194,7 +199,7 @@ impl Debug for QueryInput {
     }
 }
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub enum QueryType {
     #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
@@ -216,6 +221,34 @@ impl QueryType {
     pub const SEMI_HONEST_OPRF_IPA_STR: &'static str = "semi-honest-oprf-ipa";
     pub const MALICIOUS_OPRF_IPA_STR: &'static str = "malicious-oprf-ipa";
     pub const SEMI_HONEST_HYBRID_STR: &'static str = "semi-honest-hybrid";
+
+    /// DP parameters of the query, if it has any.
+    #[must_use]
+    pub fn dp(&self) -> Option<&DpConfig> {
+        match self {
+            #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
+            QueryType::TestMultiply
+            | QueryType::TestAddInPrimeField
+            | QueryType::TestShardedShuffle => None,
+            QueryType::SemiHonestOprfIpa(config) | QueryType::MaliciousOprfIpa(config) => {
+                Some(&config.dp)
+            }
+            QueryType::SemiHonestHybrid(params) => Some(&params.dp),
+        }
+    }
+
+    /// Checks the DP parameters of the query, if it has any, against the padding policy of
+    /// this helper.
+    ///
+    /// ## Errors
+    /// If they are out of range, see [`DpConfig::validate`], or weaker than the policy allows,
+    /// see [`DpConfig::check_policy`].
+    pub fn validate_dp(&self, padding_policy: &PaddingPolicy) -> Result<(), DpConfigError> {
+        self.dp().map_or(Ok(()), |dp| {
+            dp.validate()?;
+            dp.check_policy(padding_policy)
+        })
+    }
 }
 
 /// TODO: should this `AsRef` impl (used for `Substep`) take into account config of IPA?
@@ -238,14 +271,61 @@ impl AsRef<str> for QueryType {
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum DpMechanism {
     NoDp,
-    Binomial { epsilon: f64 },
-    DiscreteLaplace { epsilon: f64 },
+    Binomial { epsilon: f64, delta: f64 },
+    DiscreteLaplace { epsilon: f64, delta: f64 },
+    Skellam { epsilon: f64, delta: f64 },
+}
+
+/// What a query does with input reports it cannot use.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidReportPolicy {
+    /// The query fails.
+    #[default]
+    Fail,
+    /// The report is replaced with a dummy source event that does not contribute to the output,
+    /// so all helpers keep the same number of records. The number of reports replaced this way
+    /// is returned with the query results.
+    Drop,
+}
+
+impl Display for InvalidReportPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fail => "fail",
+            Self::Drop => "drop",
+        })
+    }
+}
+
+/// What a query does with input reports that were already submitted. Helpers agree on the set of
+/// duplicates, so a report seen by any of them is treated as a duplicate by all.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateReportPolicy {
+    /// The query fails.
+    #[default]
+    Fail,
+    /// The report is replaced with a dummy source event that does not contribute to the output.
+    /// The number of reports replaced this way is returned with the query results.
+    Drop,
+}
+
+impl Display for DuplicateReportPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fail => "fail",
+            Self::Drop => "drop",
+        })
+    }
 }
 
 #[cfg(test)]
 impl Eq for IpaQueryConfig {}
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct IpaQueryConfig {
     #[cfg_attr(feature = "clap", arg(long, default_value = "8"))]
@@ -254,10 +334,11 @@ pub struct IpaQueryConfig {
     pub max_breakdown_key: u32,
     #[cfg_attr(feature = "clap", arg(long))]
     pub attribution_window_seconds: Option<NonZeroU32>,
-    #[arg(short = 'd', long, default_value = "1")]
-    pub with_dp: u32,
-    #[arg(short = 'e', long, default_value = "5.0")]
-    pub epsilon: f64,
+
+    /// Noise added to the output and padding added to the inputs.
+    #[cfg_attr(feature = "clap", command(flatten))]
+    #[serde(flatten)]
+    pub dp: DpConfig,
 
     /// If false, IPA decrypts match key shares in the input reports. If true, IPA uses match key
     /// shares from input reports directly. Setting this to true also activates an alternate
@@ -266,6 +347,37 @@ pub struct IpaQueryConfig {
     #[cfg_attr(feature = "clap", arg(long))]
     #[serde(default)]
     pub plaintext_match_keys: bool,
+
+    /// Epochs of the reports this query is allowed to process, for example `4` or `4-6`. If set,
+    /// reports from any other epoch are handled according to `invalid_reports`.
+    #[cfg_attr(feature = "clap", arg(long))]
+    #[serde(default)]
+    pub epochs: Option<EpochRange>,
+
+    /// Site whose reports this query processes. If set, reports from any other site are handled
+    /// according to `invalid_reports`. Helpers that enforce a privacy budget require it, together
+    /// with `epochs`, to know whose budget the query spends.
+    #[cfg_attr(feature = "clap", arg(long))]
+    #[serde(default)]
+    pub site_domain: Option<String>,
+
+    /// What to do with reports that cannot be decrypted, or that are outside of the epochs or
+    /// site covered by the query.
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t))]
+    #[serde(default)]
+    pub invalid_reports: InvalidReportPolicy,
+
+    /// What to do with reports that were already submitted, in this query or in an earlier one.
+    #[cfg_attr(feature = "clap", arg(long, value_enum, default_value_t))]
+    #[serde(default)]
+    pub duplicate_reports: DuplicateReportPolicy,
+
+    /// Levels of a hierarchical breakdown, for example `2,3,3`. If set, the query outputs a
+    /// histogram for every level instead of one histogram of all breakdowns, see
+    /// [`BreakdownHierarchy`].
+    #[cfg_attr(feature = "clap", arg(long))]
+    #[serde(default)]
+    pub breakdown_hierarchy: Option<BreakdownHierarchy>,
 }
 
 impl Default for IpaQueryConfig {
@@ -274,9 +386,13 @@ impl Default for IpaQueryConfig {
             per_user_credit_cap: 8,
             max_breakdown_key: 20,
             attribution_window_seconds: None,
-            with_dp: 1,
-            epsilon: 0.10,
+            dp: DpConfig::default(),
             plaintext_match_keys: false,
+            epochs: None,
+            site_domain: None,
+            invalid_reports: InvalidReportPolicy::Fail,
+            duplicate_reports: DuplicateReportPolicy::Fail,
+            breakdown_hierarchy: None,
         }
     }
 }
@@ -289,8 +405,7 @@ impl IpaQueryConfig {
         per_user_credit_cap: u32,
         max_breakdown_key: u32,
         attribution_window_seconds: u32,
-        with_dp: u32,
-        epsilon: f64,
+        dp: DpConfig,
     ) -> Self {
         Self {
             per_user_credit_cap,
@@ -299,10 +414,13 @@ impl IpaQueryConfig {
                 NonZeroU32::new(attribution_window_seconds)
                     .expect("attribution window must be a positive value > 0"),
             ),
-            with_dp,
-            epsilon,
-            // dp_params,
+            dp,
             plaintext_match_keys: false,
+            epochs: None,
+            site_domain: None,
+            invalid_reports: InvalidReportPolicy::Fail,
+            duplicate_reports: DuplicateReportPolicy::Fail,
+            breakdown_hierarchy: None,
         }
     }
 
@@ -311,19 +429,18 @@ impl IpaQueryConfig {
     /// means is that any trigger event can be attributed if there is at least one preceding source event
     /// from the same user in the input.
     #[must_use]
-    pub fn no_window(
-        per_user_credit_cap: u32,
-        max_breakdown_key: u32,
-        with_dp: u32,
-        epsilon: f64,
-    ) -> Self {
+    pub fn no_window(per_user_credit_cap: u32, max_breakdown_key: u32, dp: DpConfig) -> Self {
         Self {
             per_user_credit_cap,
             max_breakdown_key,
             attribution_window_seconds: None,
-            with_dp,
-            epsilon,
+            dp,
             plaintext_match_keys: false,
+            epochs: None,
+            site_domain: None,
+            invalid_reports: InvalidReportPolicy::Fail,
+            duplicate_reports: DuplicateReportPolicy::Fail,
+            breakdown_hierarchy: None,
         }
     }
 }
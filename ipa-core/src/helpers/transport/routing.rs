@@ -8,14 +8,16 @@ use crate::{
 };
 
 // The type of request made to an MPC helper.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RouteId {
     Records,
     ReceiveQuery,
     PrepareQuery,
+    AbortQuery,
     QueryInput,
     QueryStatus,
     CompleteQuery,
+    PublicKeys,
 }
 
 /// The header/metadata of the incoming request.
@@ -0,0 +1,235 @@
+//! Outbound bandwidth shaping for helper-to-helper record streams.
+//!
+//! Every peer gets its own [`TokenBucket`], shared by all record streams sent to that peer. It is
+//! enforced in two places:
+//! * the transport takes tokens for every chunk it puts on the wire and holds the chunk back
+//!   until the bucket has enough tokens to cover it, using [`Shaped`]. For HTTP this is done by
+//!   the client, after compression, so the limit applies to the bytes actually sent.
+//! * the gateway makes senders wait while the bucket is in debt, before their records are
+//!   written into the channel buffer. Once the link to a peer is saturated, MPC circuits that
+//!   talk to that peer are slowed down instead of filling up the send buffers.
+//!
+//! The gateway only waits for tokens, it does not take any, so every byte is counted once.
+//! Other requests, such as query preparation, are not shaped, and neither are streams sent over
+//! in-memory transport.
+
+use std::{
+    num::NonZeroU64,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{FutureExt, Stream};
+use pin_project::pin_project;
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::sync::{Arc, Mutex};
+
+/// Token bucket with one token per byte.
+///
+/// Tokens are reserved before they become available: a caller that takes more than there is in
+/// the bucket drives the balance negative and is told how long to wait before sending. This lets
+/// chunks larger than the burst size through, and makes callers that come later wait for
+/// earlier reservations to be paid off, so the average rate stays within the limit.
+#[derive(Debug)]
+pub struct TokenBucket {
+    bytes_per_sec: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    #[must_use]
+    pub fn new(bytes_per_sec: NonZeroU64, burst_bytes: NonZeroU64) -> Self {
+        #[allow(clippy::cast_precision_loss)] // rates are nowhere close to 2^53 bytes per second
+        let (bytes_per_sec, burst) = (bytes_per_sec.get() as f64, burst_bytes.get() as f64);
+        Self {
+            bytes_per_sec,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` tokens from the bucket and returns how long the caller must wait before
+    /// sending them.
+    ///
+    /// ## Panics
+    /// If the bucket mutex is poisoned.
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        self.reserve_at(Instant::now(), bytes)
+    }
+
+    /// Waits until the bucket is out of debt, without taking any tokens from it.
+    ///
+    /// ## Panics
+    /// If the bucket mutex is poisoned.
+    pub async fn ready(&self) {
+        loop {
+            let wait = self.reserve_at(Instant::now(), 0);
+            if wait.is_zero() {
+                break;
+            }
+            sleep(wait).await;
+        }
+    }
+
+    fn reserve_at(&self, now: Instant, bytes: usize) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let elapsed = now.saturating_duration_since(state.updated).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.bytes_per_sec).min(self.burst);
+        state.updated = now;
+        #[allow(clippy::cast_precision_loss)]
+        {
+            state.tokens -= bytes as f64;
+        }
+
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.bytes_per_sec)
+        }
+    }
+}
+
+/// Stream adapter that paces chunks of `S` according to the given [`TokenBucket`].
+#[pin_project]
+pub struct Shaped<S> {
+    #[pin]
+    inner: S,
+    bucket: Arc<TokenBucket>,
+    held: Option<(Bytes, Pin<Box<Sleep>>)>,
+}
+
+impl<S> Shaped<S> {
+    pub fn new(inner: S, bucket: Arc<TokenBucket>) -> Self {
+        Self {
+            inner,
+            bucket,
+            held: None,
+        }
+    }
+}
+
+impl<S: Stream<Item = Bytes>> Stream for Shaped<S> {
+    type Item = Bytes;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some((_, delay)) = this.held {
+            ready!(delay.poll_unpin(cx));
+            return Poll::Ready(this.held.take().map(|(chunk, _)| chunk));
+        }
+
+        let Some(chunk) = ready!(this.inner.poll_next(cx)) else {
+            return Poll::Ready(None);
+        };
+        let wait = this.bucket.reserve(chunk.len());
+        if wait.is_zero() {
+            return Poll::Ready(Some(chunk));
+        }
+        let mut delay = Box::pin(sleep(wait));
+        if delay.poll_unpin(cx).is_ready() {
+            Poll::Ready(Some(chunk))
+        } else {
+            *this.held = Some((chunk, delay));
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::{num::NonZeroU64, task::Poll, time::Duration};
+
+    use bytes::Bytes;
+    use futures::{
+        stream::{self, poll_immediate},
+        FutureExt, StreamExt,
+    };
+    use tokio::time::Instant;
+
+    use super::{Shaped, TokenBucket};
+    use crate::sync::Arc;
+
+    fn bucket(bytes_per_sec: u64, burst_bytes: u64) -> TokenBucket {
+        TokenBucket::new(
+            NonZeroU64::new(bytes_per_sec).unwrap(),
+            NonZeroU64::new(burst_bytes).unwrap(),
+        )
+    }
+
+    #[test]
+    fn burst_is_free() {
+        let bucket = bucket(1000, 500);
+        let now = Instant::now();
+        assert_eq!(Duration::ZERO, bucket.reserve_at(now, 200));
+        assert_eq!(Duration::ZERO, bucket.reserve_at(now, 300));
+        assert_eq!(Duration::from_millis(100), bucket.reserve_at(now, 100));
+    }
+
+    #[test]
+    fn refills_at_rate() {
+        let bucket = bucket(1000, 500);
+        let now = Instant::now();
+        assert_eq!(Duration::from_millis(500), bucket.reserve_at(now, 1000));
+        // debt is paid off after half a second, and the bucket refills up to the burst size
+        let later = now + Duration::from_secs(10);
+        assert_eq!(Duration::ZERO, bucket.reserve_at(later, 500));
+        assert_eq!(Duration::from_millis(1), bucket.reserve_at(later, 1));
+    }
+
+    #[test]
+    fn later_callers_wait_for_earlier_reservations() {
+        let bucket = bucket(1000, 100);
+        let now = Instant::now();
+        assert_eq!(Duration::from_millis(100), bucket.reserve_at(now, 200));
+        assert_eq!(Duration::from_millis(200), bucket.reserve_at(now, 100));
+    }
+
+    #[tokio::test]
+    async fn ready_waits_out_debt() {
+        let bucket = bucket(100_000, 1000);
+        assert_eq!(Duration::ZERO, bucket.reserve(1000));
+        // an empty bucket is not in debt, and waiting for it does not take tokens
+        assert!(bucket.ready().now_or_never().is_some());
+        assert!(bucket.ready().now_or_never().is_some());
+
+        assert!(!bucket.reserve(500).is_zero());
+        assert!(bucket.ready().now_or_never().is_none());
+        bucket.ready().await;
+        assert_eq!(Duration::ZERO, bucket.reserve(0));
+    }
+
+    #[tokio::test]
+    async fn shaped_stream_holds_chunks() {
+        const CHUNK: usize = 1000;
+        // first chunk fits in the burst, the second one waits 10ms for the bucket to refill
+        let bucket = Arc::new(bucket(100_000, 1000));
+        let mut data = Shaped::new(
+            stream::iter((0..2).map(|_| Bytes::from(vec![0; CHUNK]))),
+            bucket,
+        );
+
+        assert!(matches!(
+            poll_immediate(&mut data).next().await,
+            Some(Poll::Ready(_))
+        ));
+        assert!(matches!(
+            poll_immediate(&mut data).next().await,
+            Some(Poll::Pending)
+        ));
+        assert_eq!(Some(CHUNK), data.next().await.map(|chunk| chunk.len()));
+        assert!(data.next().await.is_none());
+    }
+}
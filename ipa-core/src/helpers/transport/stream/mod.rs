@@ -3,6 +3,8 @@ mod axum_body;
 mod box_body;
 mod collection;
 mod input;
+#[cfg(feature = "web-app")]
+mod resumable;
 
 use std::{
     pin::Pin,
@@ -17,6 +19,8 @@ pub use collection::{StreamCollection, StreamKey};
 use futures::{stream::iter, Stream};
 use futures_util::StreamExt;
 pub use input::{LengthDelimitedStream, RecordsStream, SingleRecordStream};
+#[cfg(feature = "web-app")]
+pub use resumable::{ResumableSender, ResumableStream, StreamResumer};
 
 use crate::{const_assert, error::BoxError};
 
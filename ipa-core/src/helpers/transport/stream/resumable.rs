@@ -0,0 +1,536 @@
+//! Record streams that survive connection loss between helpers.
+//!
+//! Every connection carrying a record stream starts at a byte offset within that stream. The
+//! receiving side knows how many bytes it has acknowledged (delivered to the consumer). The
+//! sending side keeps a bounded buffer of the most recent bytes, so when a connection drops, it
+//! can ask the receiver for the acknowledged offset, reconnect and retransmit from there. If
+//! those bytes are no longer in the buffer, the stream cannot be resumed. Bytes that reach the
+//! receiver through the old connection after it reported its offset are skipped.
+//!
+//! [`ResumableStream`] implements the receiving side and [`ResumableSender`] implements the
+//! sending side.
+
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Formatter},
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::{stream::Fuse, Future, FutureExt, Stream, StreamExt};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Sleep,
+};
+
+use crate::{
+    error::BoxError,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Receiving end of a resumable stream.
+///
+/// It yields data from the most recent connection, discarding bytes that have been received
+/// already. If the connection fails, this stream waits up to the grace period for the sender to
+/// reconnect, and reports the connection error only if that does not happen.
+///
+/// Use [`StreamResumer`] to attach new connections to this stream.
+pub struct ResumableStream<S> {
+    current: Option<S>,
+    /// Number of bytes delivered to the consumer of this stream.
+    acknowledged: Arc<AtomicU64>,
+    /// Number of bytes to discard from the current connection, because they were
+    /// delivered through the previous one.
+    skip: u64,
+    connections: mpsc::UnboundedReceiver<(u64, S)>,
+    grace_period: Duration,
+    /// Set when the current connection failed, while waiting for the sender to reconnect.
+    lost: Option<(BoxError, Pin<Box<Sleep>>)>,
+    finished: bool,
+}
+
+/// Attaches new connections to a [`ResumableStream`].
+pub struct StreamResumer<S> {
+    tx: mpsc::UnboundedSender<(u64, S)>,
+    acknowledged: Arc<AtomicU64>,
+}
+
+impl<S> Debug for StreamResumer<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StreamResumer")
+    }
+}
+
+impl<S> StreamResumer<S> {
+    /// Attaches a connection that carries the stream data starting at `offset`.
+    ///
+    /// ## Errors
+    /// If the receiving end of the stream has been dropped. The connection is returned back
+    /// to the caller.
+    pub fn resume(&self, offset: u64, stream: S) -> Result<(), S> {
+        self.tx
+            .send((offset, stream))
+            .map_err(|mpsc::error::SendError((_, stream))| stream)
+    }
+
+    /// Number of bytes delivered to the consumer of the stream so far. The sender resumes
+    /// the stream from this offset after losing the connection.
+    #[must_use]
+    pub fn acknowledged(&self) -> u64 {
+        self.acknowledged.load(Ordering::Acquire)
+    }
+}
+
+impl<S> ResumableStream<S> {
+    /// Creates a new stream without any connections attached to it. The first connection
+    /// must be provided through the returned [`StreamResumer`].
+    #[must_use]
+    pub fn new(grace_period: Duration) -> (Self, StreamResumer<S>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let acknowledged = Arc::new(AtomicU64::new(0));
+        (
+            Self {
+                current: None,
+                acknowledged: Arc::clone(&acknowledged),
+                skip: 0,
+                connections: rx,
+                grace_period,
+                lost: None,
+                finished: false,
+            },
+            StreamResumer { tx, acknowledged },
+        )
+    }
+
+    /// Number of bytes delivered to the consumer of this stream so far.
+    #[must_use]
+    pub fn acknowledged(&self) -> u64 {
+        self.acknowledged.load(Ordering::Acquire)
+    }
+
+    fn attach(&mut self, offset: u64, stream: S) -> Result<(), BoxError> {
+        let acknowledged = self.acknowledged();
+        if offset > acknowledged {
+            return Err(format!(
+                "cannot resume the stream from offset {offset}: only {acknowledged} bytes have been received"
+            )
+            .into());
+        }
+        if self.current.is_some() || self.lost.is_some() {
+            tracing::info!(offset, acknowledged, "record stream resumed");
+        }
+
+        self.current = Some(stream);
+        self.skip = acknowledged - offset;
+        self.lost = None;
+
+        Ok(())
+    }
+}
+
+impl<S: Stream<Item = Result<Bytes, BoxError>> + Unpin> Stream for ResumableStream<S> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if this.finished {
+                return Poll::Ready(None);
+            }
+
+            // A new connection always takes over, even if the current one has not failed yet:
+            // the sender reconnects only when it considers the old one gone.
+            while let Poll::Ready(Some((offset, stream))) = this.connections.poll_recv(cx) {
+                if let Err(e) = this.attach(offset, stream) {
+                    this.finished = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+
+            if let Some(stream) = this.current.as_mut() {
+                match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(mut bytes))) => {
+                        let len = bytes.len() as u64;
+                        if this.skip >= len {
+                            this.skip -= len;
+                            continue;
+                        }
+                        // `skip` is less than the chunk size, so it fits into `usize`
+                        bytes = bytes.slice(usize::try_from(this.skip).unwrap()..);
+                        this.skip = 0;
+                        this.acknowledged
+                            .fetch_add(bytes.len() as u64, Ordering::AcqRel);
+
+                        return Poll::Ready(Some(Ok(bytes)));
+                    }
+                    Poll::Ready(Some(Err(e))) => {
+                        this.current = None;
+                        if this.grace_period.is_zero() {
+                            this.finished = true;
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                        tracing::warn!(
+                            acknowledged = this.acknowledged(),
+                            "connection lost, waiting {:?} for the sender to reconnect: {e}",
+                            this.grace_period
+                        );
+                        this.lost = Some((e, Box::pin(tokio::time::sleep(this.grace_period))));
+                    }
+                    Poll::Ready(None) => {
+                        this.finished = true;
+                        return Poll::Ready(None);
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            } else if let Some((_, deadline)) = this.lost.as_mut() {
+                return match deadline.as_mut().poll(cx) {
+                    Poll::Ready(()) => {
+                        let (e, _) = this.lost.take().unwrap();
+                        this.finished = true;
+                        Poll::Ready(Some(Err(format!(
+                            "sender did not reconnect within {:?}: {e}",
+                            this.grace_period
+                        )
+                        .into())))
+                    }
+                    Poll::Pending => Poll::Pending,
+                };
+            } else {
+                // no connections attached yet
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<S> Debug for ResumableStream<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResumableStream")
+            .field("acknowledged", &self.acknowledged())
+            .field("connected", &self.current.is_some())
+            .field("finished", &self.finished)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Sending end of a resumable stream.
+///
+/// Each call to [`connect`] creates a [`Connection`] body that starts at the given offset, with
+/// the bytes retained for retransmission, and continues with the data pulled from the source
+/// stream. The
+/// [`ConnectionOutcome`] returned alongside tells whether that body was sent completely or was
+/// dropped halfway, in which case the caller should connect again.
+///
+/// [`connect`]: ResumableSender::connect
+pub struct ResumableSender<S> {
+    state: Arc<Mutex<SenderState<S>>>,
+}
+
+struct SenderState<S> {
+    source: Pin<Box<Fuse<S>>>,
+    /// Most recent chunks pulled from the source, kept for retransmission.
+    replay: VecDeque<Bytes>,
+    /// Total size of chunks inside `replay`.
+    replay_len: usize,
+    /// Offset of the first byte inside `replay`.
+    replay_start: u64,
+    capacity: usize,
+}
+
+impl<S> SenderState<S> {
+    /// Offsets a connection can start at.
+    fn retained(&self) -> Range<u64> {
+        self.replay_start..self.replay_start + self.replay_len as u64
+    }
+
+    fn retain(&mut self, chunk: Bytes) {
+        self.replay_len += chunk.len();
+        self.replay.push_back(chunk);
+        while self.replay_len > self.capacity {
+            let evicted = self.replay.pop_front().unwrap();
+            self.replay_len -= evicted.len();
+            self.replay_start += evicted.len() as u64;
+        }
+    }
+
+    /// Returns the retained bytes starting at `offset`, up to the end of the chunk that
+    /// contains it.
+    fn replay_from(&self, offset: u64) -> Option<Bytes> {
+        let mut start = self.replay_start;
+        for chunk in &self.replay {
+            let end = start + chunk.len() as u64;
+            if offset < end {
+                return Some(chunk.slice(usize::try_from(offset - start).unwrap()..));
+            }
+            start = end;
+        }
+
+        None
+    }
+}
+
+impl<S: Stream<Item = Vec<u8>> + Send> ResumableSender<S> {
+    /// Creates a sender that keeps up to `replay_capacity` most recent bytes pulled
+    /// from `source` for retransmission.
+    pub fn new(source: S, replay_capacity: usize) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SenderState {
+                source: Box::pin(source.fuse()),
+                replay: VecDeque::new(),
+                replay_len: 0,
+                replay_start: 0,
+                capacity: replay_capacity,
+            })),
+        }
+    }
+
+    /// Opens a new connection that starts at `offset`, which is normally the number of bytes
+    /// the receiver has acknowledged.
+    ///
+    /// ## Errors
+    /// If the bytes starting at `offset` are no longer retained for retransmission, or have not
+    /// been sent yet. The error carries the range of offsets retained.
+    ///
+    /// ## Panics
+    /// If the mutex is poisoned.
+    pub fn connect(&self, offset: u64) -> Result<(Connection<S>, ConnectionOutcome), Range<u64>> {
+        let retained = self.state.lock().unwrap().retained();
+        if offset < retained.start || offset > retained.end {
+            return Err(retained);
+        }
+        let (tx, rx) = oneshot::channel();
+        Ok((
+            Connection {
+                state: Arc::clone(&self.state),
+                offset,
+                position: offset,
+                completed: false,
+                outcome: Some(tx),
+            },
+            ConnectionOutcome(rx),
+        ))
+    }
+}
+
+/// Data sent over a single connection of a [`ResumableSender`].
+pub struct Connection<S> {
+    state: Arc<Mutex<SenderState<S>>>,
+    offset: u64,
+    /// Offset of the next byte to send.
+    position: u64,
+    completed: bool,
+    outcome: Option<oneshot::Sender<bool>>,
+}
+
+impl<S> Connection<S> {
+    /// Offset of the first byte sent over this connection.
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<S: Stream<Item = Vec<u8>> + Send> Stream for Connection<S> {
+    type Item = Bytes;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut state = this.state.lock().unwrap();
+        if let Some(chunk) = state.replay_from(this.position) {
+            this.position += chunk.len() as u64;
+            return Poll::Ready(Some(chunk));
+        }
+
+        match state.source.poll_next_unpin(cx) {
+            Poll::Ready(Some(chunk)) => {
+                let chunk = Bytes::from(chunk);
+                this.position += chunk.len() as u64;
+                state.retain(chunk.clone());
+                Poll::Ready(Some(chunk))
+            }
+            Poll::Ready(None) => {
+                this.completed = true;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S> Drop for Connection<S> {
+    fn drop(&mut self) {
+        if let Some(tx) = self.outcome.take() {
+            let _ = tx.send(self.completed);
+        }
+    }
+}
+
+/// Resolves to `true` once the corresponding [`Connection`] has sent all the data, and to
+/// `false` if it was dropped before that.
+pub struct ConnectionOutcome(oneshot::Receiver<bool>);
+
+impl Future for ConnectionOutcome {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.poll_unpin(cx).map(|r| r.unwrap_or(false))
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use futures::{stream, FutureExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+
+    use super::{ResumableSender, ResumableStream};
+    use crate::error::BoxError;
+
+    type Item = Result<Bytes, BoxError>;
+
+    fn connection() -> (mpsc::UnboundedSender<Item>, UnboundedReceiverStream<Item>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, UnboundedReceiverStream::new(rx))
+    }
+
+    fn chunk(data: &[u8]) -> Bytes {
+        Bytes::copy_from_slice(data)
+    }
+
+    #[tokio::test]
+    async fn resumes_from_acknowledged_byte() {
+        let (mut stream, resumer) = ResumableStream::new(Duration::from_secs(5));
+        let (tx, rx) = connection();
+        resumer.resume(0, rx).unwrap();
+        tx.send(Ok(chunk(&[1, 2, 3]))).unwrap();
+        tx.send(Err("connection reset".into())).unwrap();
+
+        assert_eq!(&[1, 2, 3], stream.next().await.unwrap().unwrap().as_ref());
+        assert!(stream.next().now_or_never().is_none());
+
+        // sender retransmits starting from byte 1
+        let (tx, rx) = connection();
+        resumer.resume(1, rx).unwrap();
+        tx.send(Ok(chunk(&[2]))).unwrap();
+        tx.send(Ok(chunk(&[3, 4, 5]))).unwrap();
+        drop(tx);
+
+        assert_eq!(&[4, 5], stream.next().await.unwrap().unwrap().as_ref());
+        assert!(stream.next().await.is_none());
+        assert_eq!(5, stream.acknowledged());
+    }
+
+    #[tokio::test]
+    async fn gap_is_an_error() {
+        let (mut stream, resumer) = ResumableStream::new(Duration::from_secs(5));
+        let (tx, rx) = connection();
+        resumer.resume(0, rx).unwrap();
+        tx.send(Ok(chunk(&[1, 2]))).unwrap();
+        assert_eq!(&[1, 2], stream.next().await.unwrap().unwrap().as_ref());
+
+        let (_tx, rx) = connection();
+        resumer.resume(3, rx).unwrap();
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("offset 3"), "{err}");
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_after_grace_period() {
+        let (mut stream, resumer) = ResumableStream::new(Duration::from_millis(10));
+        let (tx, rx) = connection();
+        resumer.resume(0, rx).unwrap();
+        tx.send(Err("connection reset".into())).unwrap();
+
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("did not reconnect"), "{err}");
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_immediately_without_grace_period() {
+        let (mut stream, resumer) = ResumableStream::new(Duration::ZERO);
+        let (tx, rx) = connection();
+        resumer.resume(0, rx).unwrap();
+        tx.send(Err("connection reset".into())).unwrap();
+
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!("connection reset", err.to_string());
+    }
+
+    #[tokio::test]
+    async fn sender_retransmits_retained_bytes() {
+        let sender = ResumableSender::new(stream::iter(vec![vec![1, 2], vec![3], vec![4, 5]]), 3);
+
+        let (mut conn, outcome) = sender.connect(0).unwrap();
+        assert_eq!(0, conn.offset());
+        assert_eq!(&[1, 2], conn.next().await.unwrap().as_ref());
+        assert_eq!(&[3], conn.next().await.unwrap().as_ref());
+        drop(conn);
+        assert!(!outcome.await);
+
+        // receiver got the first byte only, both chunks fit into the replay buffer
+        let (mut conn, _) = sender.connect(1).unwrap();
+        assert_eq!(1, conn.offset());
+        assert_eq!(&[2], conn.next().await.unwrap().as_ref());
+        assert_eq!(&[3], conn.next().await.unwrap().as_ref());
+        assert_eq!(&[4, 5], conn.next().await.unwrap().as_ref());
+        drop(conn);
+
+        // [1, 2] got evicted and nothing has been sent past byte 5
+        assert_eq!(Some(2..5), sender.connect(1).err());
+        assert_eq!(Some(2..5), sender.connect(6).err());
+
+        let (conn, outcome) = sender.connect(3).unwrap();
+        assert_eq!(vec![4, 5], conn.map(|b| b.to_vec()).concat().await);
+        assert!(outcome.await);
+    }
+
+    #[tokio::test]
+    async fn end_to_end() {
+        let sender = ResumableSender::new(stream::iter((0..10_u8).map(|i| vec![i; 4])), 16);
+        let (mut receiver, resumer) = ResumableStream::new(Duration::from_secs(5));
+
+        // first connection delivers 3 chunks and fails
+        let (mut conn, _) = sender.connect(0).unwrap();
+        let (tx, rx) = connection();
+        assert!(resumer.resume(conn.offset(), rx.boxed()).is_ok());
+        for _ in 0..3 {
+            tx.send(Ok(conn.next().await.unwrap())).unwrap();
+        }
+        tx.send(Err("connection reset".into())).unwrap();
+        drop(conn);
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.extend(receiver.next().await.unwrap().unwrap());
+        }
+
+        // sender resumes from the last byte the receiver has acknowledged
+        let (conn, outcome) = sender.connect(resumer.acknowledged()).unwrap();
+        assert_eq!(12, conn.offset());
+        assert!(resumer.resume(conn.offset(), conn.map(Ok).boxed()).is_ok());
+
+        received.extend(
+            receiver
+                .map(Result::unwrap)
+                .map(|b| b.to_vec())
+                .concat()
+                .await,
+        );
+        assert_eq!(
+            (0..10_u8).flat_map(|i| vec![i; 4]).collect::<Vec<_>>(),
+            received
+        );
+        assert!(outcome.await);
+    }
+}
@@ -6,19 +6,25 @@ use std::{fmt::Debug, io, ops::Add};
 
 use generic_array::ArrayLength;
 use hpke::{
-    aead::AeadTag, single_shot_open_in_place_detached, single_shot_seal_in_place_detached, OpModeR,
-    OpModeS,
+    aead::AeadTag, single_shot_open_in_place_detached, single_shot_seal_in_place_detached, Kem,
+    OpModeR, OpModeS,
 };
+use p256::elliptic_curve::sec1::ToEncodedPoint;
 use rand_core::{CryptoRng, RngCore};
 use typenum::U16;
 
 mod info;
+mod publication;
 mod registry;
+mod suite;
 
 pub use info::Info;
+pub use publication::{PublicKeySet, PublishedKey, PUBLIC_KEY_SET_VERSION};
 pub use registry::{
-    KeyPair, KeyRegistry, PrivateKeyOnly, PrivateKeyRegistry, PublicKeyOnly, PublicKeyRegistry,
+    KeyPair, KeyRegistry, KeyValidity, PrivateKeyOnly, PrivateKeyRegistry, PublicKeyOnly,
+    PublicKeyRegistry, ReloadableKeyRegistry,
 };
+pub use suite::{CipherSuite, IpaPrivateKey, IpaPublicKey, KeyError};
 
 use crate::{
     ff::{GaloisField, Serializable as IpaSerializable},
@@ -26,18 +32,17 @@ use crate::{
     secret_sharing::replicated::semi_honest::AdditiveShare,
 };
 
-/// IPA ciphersuite
-type IpaKem = hpke::kem::X25519HkdfSha256;
+/// IPA ciphersuites, see [`CipherSuite`]
+type X25519Kem = hpke::kem::X25519HkdfSha256;
+type P256Kem = hpke::kem::DhP256HkdfSha256;
 type IpaAead = hpke::aead::AesGcm128;
 type IpaKdf = hpke::kdf::HkdfSha256;
 
-pub type EncapsulationSize = <<IpaKem as hpke::Kem>::EncappedKey as Serializable>::OutputSize;
+/// Size of the encapsulated key slots in reports. X25519 encapsulated keys fill them entirely,
+/// P-256 ones are compressed and only the x coordinate is stored there.
+pub type EncapsulationSize = <<X25519Kem as hpke::Kem>::EncappedKey as Serializable>::OutputSize;
 pub type TagSize = <AeadTag<IpaAead> as Serializable>::OutputSize;
 
-pub type IpaPublicKey = <IpaKem as hpke::kem::Kem>::PublicKey;
-pub type IpaPrivateKey = <IpaKem as hpke::kem::Kem>::PrivateKey;
-pub type IpaEncapsulatedKey = <IpaKem as hpke::kem::Kem>::EncappedKey;
-
 pub use hpke::{Deserializable, Serializable};
 
 pub trait FieldShareCrypt: GaloisField + IpaSerializable {
@@ -58,7 +63,7 @@ where
     <AdditiveShare<F> as IpaSerializable>::Size: Add<U16>,
     <<AdditiveShare<F> as IpaSerializable>::Size as Add<U16>>::Output: ArrayLength,
 {
-    type EncapKeySize = <<IpaKem as hpke::Kem>::EncappedKey as Serializable>::OutputSize;
+    type EncapKeySize = EncapsulationSize;
     type CiphertextSize = <<AdditiveShare<F> as IpaSerializable>::Size as Add<U16>>::Output;
     type SemiHonestShares = AdditiveShare<F>;
 }
@@ -67,6 +72,11 @@ where
 pub enum CryptError {
     #[error("Unknown key {0}")]
     NoSuchKey(KeyIdentifier),
+    #[error("Key {key_id} is a {suite} key, but its id is reserved for the other suite")]
+    WrongSuite {
+        key_id: KeyIdentifier,
+        suite: CipherSuite,
+    },
     #[error("Failed to open ciphertext")]
     Other,
 }
@@ -83,9 +93,35 @@ impl From<io::Error> for CryptError {
     }
 }
 
+/// Checks that the key with the given identifier belongs to the suite its identifier is reserved
+/// for. Reports are parsed according to the suite of the key identifier, so they could not be
+/// decrypted otherwise.
+fn check_suite(key_id: KeyIdentifier, suite: CipherSuite) -> Result<(), CryptError> {
+    if suite == CipherSuite::of_key(key_id) {
+        Ok(())
+    } else {
+        Err(CryptError::WrongSuite { key_id, suite })
+    }
+}
+
+/// Converts a P-256 encapsulated key from the compressed SEC1 form reports carry it in to the
+/// uncompressed form hpke expects.
+fn decompress_p256(enc: &[u8]) -> Result<<P256Kem as Kem>::EncappedKey, CryptError> {
+    let point = p256::PublicKey::from_sec1_bytes(enc).map_err(|_| CryptError::Other)?;
+    Ok(<P256Kem as Kem>::EncappedKey::from_bytes(
+        point.to_encoded_point(false).as_bytes(),
+    )?)
+}
+
+fn compress_p256(enc: &<P256Kem as Kem>::EncappedKey) -> Result<Vec<u8>, CryptError> {
+    let point = p256::EncodedPoint::from_bytes(enc.to_bytes()).map_err(|_| CryptError::Other)?;
+    Ok(point.compress().as_bytes().to_vec())
+}
+
 /// Opens the given ciphertext in place by first obtaining the secret key from `key_registry`
 /// using epoch and key from the `info` parameter and then applying [`HPKE decryption`]
-/// to the provided ciphertext.
+/// to the provided ciphertext. The cipher suite is determined by the key, and `enc` is the
+/// encapsulated key as returned by [`seal_in_place`].
 ///
 /// This function mutates the provided ciphertext slice and replaces it with the plaintext obtained
 /// after opening the ciphertext. The result will contain a pointer to the plaintext slice.
@@ -104,22 +140,39 @@ pub fn open_in_place<'a, R: PrivateKeyRegistry>(
 ) -> Result<&'a [u8], CryptError> {
     let key_id = info.key_id;
     let info = info.to_bytes();
-    let encap_key = <IpaKem as hpke::Kem>::EncappedKey::from_bytes(enc)?;
     let (ct, tag) = ciphertext.split_at_mut(ciphertext.len() - AeadTag::<IpaAead>::size());
     let tag = AeadTag::<IpaAead>::from_bytes(tag)?;
     let sk = key_registry
         .private_key(key_id)
         .ok_or(CryptError::NoSuchKey(key_id))?;
-
-    single_shot_open_in_place_detached::<_, IpaKdf, IpaKem>(
-        &OpModeR::Base,
-        sk,
-        &encap_key,
-        &info,
-        ct,
-        &[],
-        &tag,
-    )?;
+    check_suite(key_id, sk.suite())?;
+
+    match sk {
+        IpaPrivateKey::X25519(sk) => {
+            let encap_key = <X25519Kem as Kem>::EncappedKey::from_bytes(enc)?;
+            single_shot_open_in_place_detached::<_, IpaKdf, X25519Kem>(
+                &OpModeR::Base,
+                sk,
+                &encap_key,
+                &info,
+                ct,
+                &[],
+                &tag,
+            )?;
+        }
+        IpaPrivateKey::P256(sk) => {
+            let encap_key = decompress_p256(enc)?;
+            single_shot_open_in_place_detached::<_, IpaKdf, P256Kem>(
+                &OpModeR::Base,
+                sk,
+                &encap_key,
+                &info,
+                ct,
+                &[],
+                &tag,
+            )?;
+        }
+    }
 
     // at this point ct is no longer a pointer to the ciphertext.
     let pt = ct;
@@ -128,12 +181,12 @@ pub fn open_in_place<'a, R: PrivateKeyRegistry>(
 
 // Avoids a clippy "complex type" warning on the return type from `seal_in_place`.
 // Not intended to be widely used.
-pub(crate) type Ciphertext<'a> = (
-    <IpaKem as hpke::Kem>::EncappedKey,
-    &'a [u8],
-    AeadTag<IpaAead>,
-);
+pub(crate) type Ciphertext<'a> = (Vec<u8>, &'a [u8], AeadTag<IpaAead>);
 
+/// Seals the plaintext in place under the public key `info` refers to. The encapsulated key is
+/// returned in the form reports carry it: 32 bytes for X25519, and compressed SEC1 (33 bytes)
+/// for P-256.
+///
 /// ## Errors
 /// If the match key cannot be sealed for any reason.
 pub(crate) fn seal_in_place<'a, R: CryptoRng + RngCore, K: PublicKeyRegistry>(
@@ -147,15 +200,30 @@ pub(crate) fn seal_in_place<'a, R: CryptoRng + RngCore, K: PublicKeyRegistry>(
     let pk_r = key_registry
         .public_key(key_id)
         .ok_or(CryptError::NoSuchKey(key_id))?;
-
-    let (encap_key, tag) = single_shot_seal_in_place_detached::<IpaAead, IpaKdf, IpaKem, _>(
-        &OpModeS::Base,
-        pk_r,
-        &info,
-        plaintext,
-        &[],
-        rng,
-    )?;
+    check_suite(key_id, pk_r.suite())?;
+
+    let (encap_key, tag) = match pk_r {
+        IpaPublicKey::X25519(pk_r) => {
+            let (encap_key, tag) = single_shot_seal_in_place_detached::<
+                IpaAead,
+                IpaKdf,
+                X25519Kem,
+                _,
+            >(&OpModeS::Base, pk_r, &info, plaintext, &[], rng)?;
+            (encap_key.to_bytes().to_vec(), tag)
+        }
+        IpaPublicKey::P256(pk_r) => {
+            let (encap_key, tag) = single_shot_seal_in_place_detached::<IpaAead, IpaKdf, P256Kem, _>(
+                &OpModeS::Base,
+                pk_r,
+                &info,
+                plaintext,
+                &[],
+                rng,
+            )?;
+            (compress_p256(&encap_key)?, tag)
+        }
+    };
 
     // at this point `plaintext` is no longer a pointer to the plaintext.
     Ok((encap_key, plaintext, tag))
@@ -193,7 +261,7 @@ mod tests {
         ///
         /// [`url`]: https://datatracker.ietf.org/doc/html/rfc9180#section-4
         /// [`aead`]: IpaAead
-        enc: [u8; 32],
+        enc: Vec<u8>,
 
         /// Ciphertext + tag
         ct: [u8; MATCHKEY_CT_LEN],
@@ -243,7 +311,7 @@ mod tests {
             ct_and_tag[ciphertext.len()..].copy_from_slice(&Serializable::to_bytes(&tag));
 
             MatchKeyEncryption {
-                enc: <[u8; 32]>::from(Serializable::to_bytes(&encap_key)),
+                enc: encap_key,
                 ct: ct_and_tag,
                 info,
             }
@@ -384,7 +452,7 @@ mod tests {
                 let mut suite = EncryptionSuite::new(1, rng);
                 let mut encryption = suite.seal(0, EventType::Source, &new_share(0, 0));
 
-                encryption.enc.as_mut()[bad_byte] ^= 1 << bad_bit;
+                encryption.enc[bad_byte] ^= 1 << bad_bit;
                 suite.open(0, EventType::Source, encryption).unwrap_err();
             }
         }
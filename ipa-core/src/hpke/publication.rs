@@ -0,0 +1,235 @@
+//! Document helpers publish to let report encryptors discover the public keys they should use.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use super::{IpaPublicKey, KeyRegistry, KeyValidity, PrivateKeyOnly, PublicKeyOnly};
+use crate::{
+    error::BoxError,
+    report::{EpochRange, KeyIdentifier},
+};
+
+/// Version of the [`PublicKeySet`] format produced by this helper. Encryptors reject documents
+/// with a version they do not know.
+pub const PUBLIC_KEY_SET_VERSION: u32 = 1;
+
+/// HPKE public keys a helper accepts reports for.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicKeySet {
+    pub version: u32,
+    /// Time this document was produced, in seconds since the Unix epoch.
+    pub issued_at: u64,
+    pub keys: Vec<PublishedKey>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedKey {
+    pub id: KeyIdentifier,
+    /// Public key, hex-encoded and prefixed with its cipher suite, like `p256:04ab..`. X25519
+    /// keys have no prefix.
+    pub public_key: String,
+    /// Start of the validity period, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_from: Option<u64>,
+    /// End of the validity period, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub valid_until: Option<u64>,
+    /// Epochs of the reports that must be encrypted under this key. Reports from other epochs
+    /// encrypted under it are rejected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epochs: Option<EpochRange>,
+}
+
+fn to_unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn from_unix_secs(secs: u64) -> Result<SystemTime, BoxError> {
+    SystemTime::UNIX_EPOCH
+        .checked_add(Duration::from_secs(secs))
+        .ok_or_else(|| format!("time {secs} in public key set is out of range").into())
+}
+
+impl PublicKeySet {
+    /// Lists public keys for all private keys in `registry`.
+    #[must_use]
+    pub fn from_registry(registry: &KeyRegistry<PrivateKeyOnly>, now: SystemTime) -> Self {
+        Self {
+            version: PUBLIC_KEY_SET_VERSION,
+            issued_at: to_unix_secs(now),
+            keys: registry
+                .public_keys()
+                .map(|(id, pk, validity)| PublishedKey {
+                    id,
+                    public_key: pk.encode(),
+                    valid_from: validity.valid_from.map(to_unix_secs),
+                    valid_until: validity.valid_until.map(to_unix_secs),
+                    epochs: validity.epochs,
+                })
+                .collect(),
+        }
+    }
+
+    /// Parses a JSON-encoded document.
+    ///
+    /// ## Errors
+    /// If the document is malformed or has a version other than [`PUBLIC_KEY_SET_VERSION`].
+    pub fn from_json(bytes: &[u8]) -> Result<Self, BoxError> {
+        let set: Self = serde_json::from_slice(bytes)?;
+        if set.version != PUBLIC_KEY_SET_VERSION {
+            return Err(format!("unsupported public key set version {}", set.version).into());
+        }
+        Ok(set)
+    }
+
+    /// Whether this document was produced before `time`.
+    #[must_use]
+    pub fn is_issued_before(&self, time: SystemTime) -> bool {
+        self.issued_at < to_unix_secs(time)
+    }
+
+    /// Builds a registry with the keys that should be used to encrypt reports at `now`. Keys
+    /// that are not valid yet, or no longer valid, are left out.
+    ///
+    /// ## Errors
+    /// If any of the public keys cannot be decoded or has an identifier reserved for the other
+    /// cipher suite, if the same key identifier is listed more than once, or if a validity
+    /// period cannot be represented.
+    pub fn registry_at(&self, now: SystemTime) -> Result<KeyRegistry<PublicKeyOnly>, BoxError> {
+        let mut keys = Vec::with_capacity(self.keys.len());
+        for (i, key) in self.keys.iter().enumerate() {
+            if self.keys[..i].iter().any(|other| other.id == key.id) {
+                return Err(format!("duplicate key id {} in public key set", key.id).into());
+            }
+            let validity = KeyValidity {
+                valid_from: key.valid_from.map(from_unix_secs).transpose()?,
+                valid_until: key.valid_until.map(from_unix_secs).transpose()?,
+                epochs: key.epochs,
+            };
+            if !validity.is_usable_at(now, Duration::ZERO) {
+                continue;
+            }
+            let pk = IpaPublicKey::decode(&key.public_key)
+                .and_then(|pk| pk.suite().check_key_id(key.id).map(|()| pk))
+                .map_err(|e| format!("invalid public key {}: {e}", key.id))?;
+            keys.push((key.id, PublicKeyOnly(pk), validity));
+        }
+
+        Ok(KeyRegistry::from_keys_with_validity(keys))
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use rand::rngs::StdRng;
+    use rand_core::SeedableRng;
+
+    use super::{PublicKeySet, PUBLIC_KEY_SET_VERSION};
+    use crate::{
+        hpke::{
+            CipherSuite, IpaPrivateKey, KeyRegistry, KeyValidity, PrivateKeyOnly, PublicKeyRegistry,
+        },
+        report::EpochRange,
+    };
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let keys = [CipherSuite::X25519, CipherSuite::P256]
+            .map(|suite| IpaPrivateKey::generate(suite, &mut rng));
+        let registry = KeyRegistry::from_keys_with_validity([
+            (
+                4,
+                PrivateKeyOnly(keys[0].0.clone()),
+                KeyValidity {
+                    valid_from: None,
+                    valid_until: Some(at(1000)),
+                    epochs: None,
+                },
+            ),
+            (
+                135,
+                PrivateKeyOnly(keys[1].0.clone()),
+                KeyValidity {
+                    valid_from: Some(at(900)),
+                    valid_until: None,
+                    epochs: Some(EpochRange::single(2)),
+                },
+            ),
+        ]);
+
+        let set = PublicKeySet::from_registry(&registry, at(950));
+        assert_eq!(PUBLIC_KEY_SET_VERSION, set.version);
+        assert_eq!(950, set.issued_at);
+        let set = PublicKeySet::from_json(&serde_json::to_vec(&set).unwrap()).unwrap();
+
+        assert_eq!(Some(EpochRange::single(2)), set.keys[1].epochs);
+        let current = set.registry_at(at(950)).unwrap();
+        assert_eq!(vec![4, 135], current.key_ids().collect::<Vec<_>>());
+        assert_eq!(
+            Some(EpochRange::single(2)),
+            current.validity(135).unwrap().epochs
+        );
+        assert_eq!(&keys[0].1, current.public_key(4).unwrap());
+        assert_eq!(&keys[1].1, current.public_key(135).unwrap());
+
+        // expired keys must not be used for encryption, even if helpers still accept them
+        let current = set.registry_at(at(1001)).unwrap();
+        assert_eq!(vec![135], current.key_ids().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let err = PublicKeySet::from_json(br#"{"version":2,"issued_at":0,"keys":[]}"#).unwrap_err();
+        assert!(err.to_string().contains("unsupported"), "{err}");
+    }
+
+    #[test]
+    fn duplicate_key_ids() {
+        let key = hex::encode([1; 32]);
+        let set = PublicKeySet::from_json(
+            format!(
+                r#"{{"version":1,"issued_at":0,"keys":[{{"id":1,"public_key":"{key}"}},{{"id":1,"public_key":"{key}"}}]}}"#
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let err = set.registry_at(at(0)).err().unwrap();
+        assert!(err.to_string().contains("duplicate key id 1"), "{err}");
+    }
+
+    #[test]
+    fn key_id_of_other_suite() {
+        let key = hex::encode([1; 32]);
+        let set = PublicKeySet::from_json(
+            format!(r#"{{"version":1,"issued_at":0,"keys":[{{"id":130,"public_key":"{key}"}}]}}"#)
+                .as_bytes(),
+        )
+        .unwrap();
+        let err = set.registry_at(at(0)).err().unwrap();
+        assert!(err.to_string().contains("cannot have id 130"), "{err}");
+    }
+
+    #[test]
+    fn validity_out_of_range() {
+        let key = hex::encode([1; 32]);
+        let set = PublicKeySet::from_json(
+            format!(
+                r#"{{"version":1,"issued_at":0,"keys":[{{"id":1,"public_key":"{key}","valid_until":{}}}]}}"#,
+                u64::MAX
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let err = set.registry_at(at(0)).err().unwrap();
+        assert!(err.to_string().contains("out of range"), "{err}");
+    }
+}
@@ -1,8 +1,13 @@
-use std::ops::Deref;
+use std::{
+    ops::Deref,
+    time::{Duration, SystemTime},
+};
 
-use hpke::Serializable;
-
-use super::{IpaPrivateKey, IpaPublicKey, KeyIdentifier};
+use super::{CipherSuite, IpaPrivateKey, IpaPublicKey, KeyIdentifier};
+use crate::{
+    report::{Epoch, EpochRange},
+    sync::{Arc, Mutex},
+};
 
 /// A pair of secret key and public key. Public keys used by UA to encrypt the data towards helpers
 /// secret keys used by helpers to open the ciphertexts. Each helper needs access to both
@@ -21,16 +26,20 @@ impl From<(IpaPrivateKey, IpaPublicKey)> for KeyPair {
 }
 
 impl KeyPair {
-    pub fn gen<R: rand::RngCore + rand::CryptoRng>(mut r: &mut R) -> Self {
-        <super::IpaKem as hpke::Kem>::gen_keypair(&mut r).into()
+    /// Generates an X25519 key pair.
+    pub fn gen<R: rand::RngCore + rand::CryptoRng>(r: &mut R) -> Self {
+        Self::gen_in(CipherSuite::X25519, r)
+    }
+
+    pub fn gen_in<R: rand::RngCore + rand::CryptoRng>(suite: CipherSuite, r: &mut R) -> Self {
+        IpaPrivateKey::generate(suite, r).into()
     }
 
     /// Returns the public key bytes. With X25519 crate it is possible to borrow those bytes, but
     /// hpke crate wraps those types and does not offer `as_bytes`.
     #[must_use]
     pub fn pk_bytes(&self) -> Box<[u8]> {
-        let pk_bytes: [u8; 32] = self.pk.to_bytes().into();
-        Box::new(pk_bytes)
+        self.pk.to_bytes().into_boxed_slice()
     }
 
     /// Returns the secret key bytes, for the same reason as [`pk_bytes`] it returns an owned slice,
@@ -39,8 +48,7 @@ impl KeyPair {
     /// [`pk_bytes`]: Self::pk_bytes
     #[must_use]
     pub fn sk_bytes(&self) -> Box<[u8]> {
-        let sk_bytes: [u8; 32] = self.sk.to_bytes().into();
-        Box::new(sk_bytes)
+        self.sk.to_bytes().into_boxed_slice()
     }
 }
 
@@ -78,11 +86,48 @@ pub trait PublicKeyRegistry {
 
 pub trait PrivateKeyRegistry: Send + Sync + 'static {
     fn private_key(&self, key_id: KeyIdentifier) -> Option<&IpaPrivateKey>;
+
+    /// Whether reports from `epoch` are accepted when encrypted under the given key.
+    fn accepts_epoch(&self, _key_id: KeyIdentifier, _epoch: Epoch) -> bool {
+        true
+    }
+}
+
+/// Period of time during which a key should be used to encrypt reports, and epochs of the reports
+/// encrypted under it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyValidity {
+    pub valid_from: Option<SystemTime>,
+    pub valid_until: Option<SystemTime>,
+    /// If set, reports from other epochs are rejected when encrypted under this key. Assigning
+    /// separate keys to epochs ties every report to the privacy budget of a single epoch.
+    pub epochs: Option<EpochRange>,
+}
+
+impl KeyValidity {
+    /// Whether reports encrypted under this key can be decrypted at `now`, allowing for
+    /// `grace_period` after the key has expired. If the end of the grace period cannot be
+    /// represented, the key does not expire.
+    #[must_use]
+    pub fn is_usable_at(&self, now: SystemTime, grace_period: Duration) -> bool {
+        self.valid_from.map_or(true, |from| from <= now)
+            && self.valid_until.map_or(true, |until| {
+                until
+                    .checked_add(grace_period)
+                    .map_or(true, |end| now <= end)
+            })
+    }
+
+    /// Whether reports from `epoch` can be encrypted under this key.
+    #[must_use]
+    pub fn covers_epoch(&self, epoch: Epoch) -> bool {
+        self.epochs.map_or(true, |epochs| epochs.contains(epoch))
+    }
 }
 
 /// A registry that holds all the keys available for helper/UA to use.
 pub struct KeyRegistry<K> {
-    keys: Box<[K]>,
+    keys: Box<[(KeyIdentifier, K, KeyValidity)]>,
 }
 
 impl<K> KeyRegistry<K> {
@@ -93,29 +138,132 @@ impl<K> KeyRegistry<K> {
         Self { keys: Box::new([]) }
     }
 
+    /// Creates a registry where keys are identified by their position in `pairs`.
+    ///
+    /// ## Panics
+    /// If there are more keys than [`KeyIdentifier`] can address.
     pub fn from_keys<const N: usize>(pairs: [K; N]) -> Self {
+        Self::from_keys_with_ids(
+            pairs
+                .into_iter()
+                .enumerate()
+                .map(|(i, key)| (KeyIdentifier::try_from(i).unwrap(), key)),
+        )
+    }
+
+    /// Creates a registry from keys with explicitly assigned identifiers. Keys do not have
+    /// a limited validity period.
+    ///
+    /// ## Panics
+    /// If the same identifier is assigned to more than one key.
+    pub fn from_keys_with_ids<I: IntoIterator<Item = (KeyIdentifier, K)>>(keys: I) -> Self {
+        Self::from_keys_with_validity(
+            keys.into_iter()
+                .map(|(key_id, key)| (key_id, key, KeyValidity::default())),
+        )
+    }
+
+    /// Creates a registry from keys with explicitly assigned identifiers and validity periods.
+    ///
+    /// ## Panics
+    /// If the same identifier is assigned to more than one key.
+    pub fn from_keys_with_validity<I: IntoIterator<Item = (KeyIdentifier, K, KeyValidity)>>(
+        keys: I,
+    ) -> Self {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        for (i, (key_id, _, _)) in keys.iter().enumerate() {
+            assert!(
+                keys[..i].iter().all(|(other, _, _)| other != key_id),
+                "duplicate key identifier {key_id}"
+            );
+        }
+
         Self {
-            keys: pairs.into_iter().collect::<Vec<_>>().into_boxed_slice(),
+            keys: keys.into_boxed_slice(),
         }
     }
 
+    /// Identifiers of all keys in this registry.
+    pub fn key_ids(&self) -> impl Iterator<Item = KeyIdentifier> + '_ {
+        self.keys.iter().map(|(key_id, _, _)| *key_id)
+    }
+
+    /// Validity period of the key with the given identifier.
+    #[must_use]
+    pub fn validity(&self, key_id: KeyIdentifier) -> Option<KeyValidity> {
+        self.keys
+            .iter()
+            .find_map(|(id, _, validity)| (*id == key_id).then_some(*validity))
+    }
+
+    fn key_accepts_epoch(&self, key_id: KeyIdentifier, epoch: Epoch) -> bool {
+        self.validity(key_id)
+            .map_or(true, |validity| validity.covers_epoch(epoch))
+    }
+
     fn key(&self, key_id: KeyIdentifier) -> Option<&K> {
-        match key_id as usize {
-            key_id if key_id < self.keys.len() => Some(&self.keys[key_id]),
-            _ => None,
-        }
+        self.keys
+            .iter()
+            .find_map(|(id, key, _)| (*id == key_id).then_some(key))
+    }
+}
+
+impl KeyRegistry<PrivateKeyOnly> {
+    /// Public keys matching the private keys in this registry, with their identifiers and
+    /// validity periods.
+    pub fn public_keys(
+        &self,
+    ) -> impl Iterator<Item = (KeyIdentifier, IpaPublicKey, KeyValidity)> + '_ {
+        self.keys
+            .iter()
+            .map(|(key_id, sk, validity)| (*key_id, sk.public_key(), *validity))
     }
 }
 
 impl KeyRegistry<KeyPair> {
+    /// ## Panics
+    /// If `keys_count` exceeds the number of available key identifiers.
     #[cfg(any(test, feature = "test-fixture"))]
     pub fn random<R: rand::RngCore + rand::CryptoRng>(keys_count: usize, r: &mut R) -> Self {
-        let keys = (0..keys_count).map(|_| KeyPair::gen(r)).collect::<Vec<_>>();
+        Self::from_keys_with_ids(
+            (0..keys_count).map(|i| (KeyIdentifier::try_from(i).unwrap(), KeyPair::gen(r))),
+        )
+    }
+}
+
+/// Holds the key registry a helper currently uses, allowing it to be replaced while the helper
+/// is running, for example when keys are rotated.
+///
+/// Queries take a snapshot of the registry when they start, so replacing it does not affect
+/// queries that are already running.
+pub struct ReloadableKeyRegistry<K> {
+    current: Mutex<Arc<KeyRegistry<K>>>,
+}
 
+impl<K> ReloadableKeyRegistry<K> {
+    #[must_use]
+    pub fn new(registry: KeyRegistry<K>) -> Self {
         Self {
-            keys: keys.into_boxed_slice(),
+            current: Mutex::new(Arc::new(registry)),
         }
     }
+
+    /// Returns the registry currently in use.
+    ///
+    /// ## Panics
+    /// If the lock is poisoned.
+    #[must_use]
+    pub fn current(&self) -> Arc<KeyRegistry<K>> {
+        Arc::clone(&self.current.lock().unwrap())
+    }
+
+    /// Replaces the registry for all queries that start after this call.
+    ///
+    /// ## Panics
+    /// If the lock is poisoned.
+    pub fn replace(&self, registry: KeyRegistry<K>) {
+        *self.current.lock().unwrap() = Arc::new(registry);
+    }
 }
 
 impl PrivateKeyRegistry for KeyRegistry<KeyPair> {
@@ -123,6 +271,10 @@ impl PrivateKeyRegistry for KeyRegistry<KeyPair> {
     fn private_key(&self, key_id: KeyIdentifier) -> Option<&IpaPrivateKey> {
         self.key(key_id).map(|v| &v.sk)
     }
+
+    fn accepts_epoch(&self, key_id: KeyIdentifier, epoch: Epoch) -> bool {
+        self.key_accepts_epoch(key_id, epoch)
+    }
 }
 
 impl PrivateKeyRegistry for KeyRegistry<PrivateKeyOnly> {
@@ -130,6 +282,10 @@ impl PrivateKeyRegistry for KeyRegistry<PrivateKeyOnly> {
     fn private_key(&self, key_id: KeyIdentifier) -> Option<&IpaPrivateKey> {
         self.key(key_id).map(|sk| &**sk)
     }
+
+    fn accepts_epoch(&self, key_id: KeyIdentifier, epoch: Epoch) -> bool {
+        self.key_accepts_epoch(key_id, epoch)
+    }
 }
 
 impl PublicKeyRegistry for KeyRegistry<KeyPair> {
@@ -146,47 +302,44 @@ impl PublicKeyRegistry for KeyRegistry<PublicKeyOnly> {
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use hpke::{HpkeError, OpModeR, OpModeS};
     use rand::rngs::StdRng;
     use rand_core::{CryptoRng, RngCore, SeedableRng};
 
     use super::*;
-    use crate::hpke::{IpaAead, IpaEncapsulatedKey, IpaKdf, IpaKem};
+    use crate::{
+        hpke::{open_in_place, seal_in_place, CryptError, Info, Serializable},
+        report::EventType,
+    };
+
+    const HELPER_ORIGIN: &str = "foo";
+    const SITE_DOMAIN: &str = "bar";
 
-    const INFO_STR: &[u8] = b"This is an INFO string.";
-    const AAD: &[u8] = b"This is AAD.";
+    fn info(key_id: KeyIdentifier) -> Info<'static> {
+        Info::new(key_id, 0, EventType::Source, HELPER_ORIGIN, SITE_DOMAIN).unwrap()
+    }
 
-    fn encrypt<R: RngCore + CryptoRng>(
-        pk: &IpaPublicKey,
+    fn encrypt<R: RngCore + CryptoRng, K: PublicKeyRegistry>(
+        registry: &K,
+        key_id: KeyIdentifier,
         pt: &[u8],
         r: &mut R,
-    ) -> (IpaEncapsulatedKey, Vec<u8>) {
-        let (encapsulated_key, mut encryption_context) =
-            hpke::setup_sender::<IpaAead, IpaKdf, IpaKem, _>(&OpModeS::Base, pk, INFO_STR, r)
-                .expect("Can setup the sender.");
-
-        (
-            encapsulated_key,
-            encryption_context
-                .seal(pt, AAD)
-                .expect("Encryption failed."),
-        )
+    ) -> (Vec<u8>, Vec<u8>) {
+        let info = info(key_id);
+        let mut buf = pt.to_vec();
+        let (encap_key, ct, tag) =
+            seal_in_place(registry, &mut buf, &info, r).expect("Encryption failed.");
+
+        (encap_key, [ct, &tag.to_bytes()].concat())
     }
 
-    fn decrypt<I: AsRef<[u8]>>(
-        sk: &IpaPrivateKey,
-        payload: &(IpaEncapsulatedKey, I),
-    ) -> Result<Vec<u8>, HpkeError> {
+    fn decrypt<K: PrivateKeyRegistry>(
+        registry: &K,
+        key_id: KeyIdentifier,
+        payload: &(Vec<u8>, Vec<u8>),
+    ) -> Result<Vec<u8>, CryptError> {
         let (encap_key, ct) = payload;
-        let mut decryption_context = hpke::setup_receiver::<IpaAead, IpaKdf, IpaKem>(
-            &OpModeR::Base,
-            sk,
-            encap_key,
-            INFO_STR,
-        )
-        .expect("Can setup the receiver.");
-
-        decryption_context.open(ct.as_ref(), AAD)
+        let mut ct = ct.clone();
+        open_in_place(registry, encap_key, &mut ct, &info(key_id)).map(<[u8]>::to_vec)
     }
 
     #[test]
@@ -197,24 +350,115 @@ mod tests {
 
         let registry = KeyRegistry::<KeyPair>::from_keys([keypair1, keypair2]);
         let pt = b"This is a plaintext.";
-        let ct_payload = encrypt(registry.public_key(0).unwrap(), pt, &mut rng);
-        assert_eq!(
-            Ok(pt.to_vec()),
-            decrypt(registry.private_key(0).unwrap(), &ct_payload)
-        );
+        let ct_payload = encrypt(&registry, 0, pt, &mut rng);
+        assert_eq!(pt.to_vec(), decrypt(&registry, 0, &ct_payload).unwrap());
 
-        assert_eq!(
-            HpkeError::OpenError,
-            decrypt(registry.private_key(1).unwrap(), &ct_payload).unwrap_err()
-        );
+        assert!(matches!(
+            decrypt(&registry, 1, &ct_payload),
+            Err(CryptError::Other)
+        ));
 
         let keypair3 = KeyPair::gen(&mut rng);
         let private_registry =
             KeyRegistry::<PrivateKeyOnly>::from_keys([PrivateKeyOnly(keypair3.sk)]);
 
+        assert!(matches!(
+            decrypt(&private_registry, 0, &ct_payload),
+            Err(CryptError::Other)
+        ));
+    }
+
+    #[test]
+    fn explicit_key_ids() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let keypair = KeyPair::gen(&mut rng);
+        let pk = keypair.pk.clone();
+        let registry = KeyRegistry::from_keys_with_ids([(7, keypair)]);
+
+        assert_eq!(vec![7], registry.key_ids().collect::<Vec<_>>());
+        assert!(registry.private_key(0).is_none());
+        let pt = b"This is a plaintext.";
+        let ct_payload = encrypt(
+            &KeyRegistry::from_keys_with_ids([(7, PublicKeyOnly(pk))]),
+            7,
+            pt,
+            &mut rng,
+        );
+        assert_eq!(pt.to_vec(), decrypt(&registry, 7, &ct_payload).unwrap());
+    }
+
+    #[test]
+    fn mixed_suites() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let registry = KeyRegistry::from_keys_with_ids([
+            (0, KeyPair::gen_in(CipherSuite::X25519, &mut rng)),
+            (128, KeyPair::gen_in(CipherSuite::P256, &mut rng)),
+        ]);
+        let pt = b"This is a plaintext.";
+
+        for (key_id, encap_len) in [(0, 32), (128, 33)] {
+            let ct_payload = encrypt(&registry, key_id, pt, &mut rng);
+            assert_eq!(encap_len, ct_payload.0.len());
+            assert_eq!(
+                pt.to_vec(),
+                decrypt(&registry, key_id, &ct_payload).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn key_id_of_other_suite() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let registry = KeyRegistry::from_keys([KeyPair::gen_in(CipherSuite::P256, &mut rng)]);
+
+        let mut buf = b"This is a plaintext.".to_vec();
+        assert!(matches!(
+            seal_in_place(&registry, &mut buf, &info(0), &mut rng),
+            Err(CryptError::WrongSuite {
+                key_id: 0,
+                suite: CipherSuite::P256
+            })
+        ));
+    }
+
+    #[test]
+    fn grace_period_overflow() {
+        let validity = KeyValidity {
+            valid_until: Some(SystemTime::UNIX_EPOCH),
+            ..KeyValidity::default()
+        };
+        let later = SystemTime::UNIX_EPOCH + Duration::from_secs(2);
+        assert!(!validity.is_usable_at(later, Duration::from_secs(1)));
+        assert!(validity.is_usable_at(later, Duration::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate key identifier 1")]
+    fn duplicate_key_ids() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let _ = KeyRegistry::from_keys_with_ids([
+            (1, KeyPair::gen(&mut rng)),
+            (1, KeyPair::gen(&mut rng)),
+        ]);
+    }
+
+    #[test]
+    fn reload() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let reloadable =
+            ReloadableKeyRegistry::new(KeyRegistry::from_keys([KeyPair::gen(&mut rng)]));
+        let before = reloadable.current();
+
+        reloadable.replace(KeyRegistry::from_keys_with_ids([
+            (0, KeyPair::gen(&mut rng)),
+            (1, KeyPair::gen(&mut rng)),
+        ]));
+
+        // snapshot taken before reload is not affected
+        assert_eq!(vec![0], before.key_ids().collect::<Vec<_>>());
         assert_eq!(
-            HpkeError::OpenError,
-            decrypt(private_registry.private_key(0).unwrap(), &ct_payload).unwrap_err()
+            vec![0, 1],
+            reloadable.current().key_ids().collect::<Vec<_>>()
         );
     }
 }
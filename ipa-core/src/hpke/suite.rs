@@ -0,0 +1,311 @@
+use std::{
+    fmt::{Debug, Display, Formatter},
+    str::FromStr,
+};
+
+use hpke::{Deserializable, HpkeError, Kem, Serializable};
+use rand_core::{CryptoRng, RngCore};
+
+use super::{P256Kem, X25519Kem};
+use crate::report::KeyIdentifier;
+
+/// Identifiers of P-256 keys have this bit set, identifiers of X25519 keys don't. Reports carry
+/// the identifier of the key they are encrypted under, so this tells how to parse a report
+/// before the key is looked up.
+const P256_KEY_ID_FLAG: KeyIdentifier = 0x80;
+
+/// HPKE cipher suite reports are encrypted with. All suites use HKDF-SHA256 and AES-128-GCM, and
+/// differ in the KEM.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum CipherSuite {
+    /// DHKEM(X25519, HKDF-SHA256)
+    #[default]
+    X25519,
+    /// DHKEM(P-256, HKDF-SHA256), for devices that only offer hardware-backed P-256 keys.
+    P256,
+}
+
+impl CipherSuite {
+    /// Suite of the key with the given identifier.
+    #[must_use]
+    pub fn of_key(key_id: KeyIdentifier) -> Self {
+        if key_id & P256_KEY_ID_FLAG == 0 {
+            Self::X25519
+        } else {
+            Self::P256
+        }
+    }
+
+    /// Identifier of the key, if a helper has a single key in this suite.
+    #[must_use]
+    pub fn default_key_id(self) -> KeyIdentifier {
+        match self {
+            Self::X25519 => 0,
+            Self::P256 => P256_KEY_ID_FLAG,
+        }
+    }
+
+    /// Checks that keys of this suite can be assigned `key_id`.
+    ///
+    /// ## Errors
+    /// If `key_id` is reserved for keys of the other suite.
+    pub fn check_key_id(self, key_id: KeyIdentifier) -> Result<(), KeyError> {
+        if Self::of_key(key_id) == self {
+            Ok(())
+        } else {
+            Err(KeyError::WrongKeyId {
+                key_id,
+                suite: self,
+            })
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::X25519 => "x25519",
+            Self::P256 => "p256",
+        }
+    }
+}
+
+impl Display for CipherSuite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for CipherSuite {
+    type Err = KeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::X25519, Self::P256]
+            .into_iter()
+            .find(|suite| suite.name() == s)
+            .ok_or_else(|| KeyError::UnknownSuite(s.to_owned()))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeyError {
+    #[error("unknown cipher suite {0}")]
+    UnknownSuite(String),
+    #[error("key is not hex-encoded: {0}")]
+    NotHex(#[from] hex::FromHexError),
+    #[error("invalid {0} key: {1}")]
+    Invalid(CipherSuite, HpkeError),
+    #[error(
+        "{suite} key cannot have id {key_id}: ids 0-127 are for x25519 keys, and 128-255 for \
+         p256 keys"
+    )]
+    WrongKeyId {
+        key_id: KeyIdentifier,
+        suite: CipherSuite,
+    },
+}
+
+/// Splits the text encoding of a key, `[<suite>:]<hex>`. Keys without a suite are X25519 keys,
+/// so that key files written before other suites were supported stay valid.
+fn decode(s: &str) -> Result<(CipherSuite, Vec<u8>), KeyError> {
+    let s = s.trim();
+    let (suite, hex) = match s.split_once(':') {
+        Some((suite, hex)) => (suite.parse()?, hex),
+        None => (CipherSuite::X25519, s),
+    };
+
+    Ok((suite, hex::decode(hex)?))
+}
+
+fn encode(suite: CipherSuite, bytes: &[u8]) -> String {
+    match suite {
+        CipherSuite::X25519 => hex::encode(bytes),
+        CipherSuite::P256 => format!("{suite}:{}", hex::encode(bytes)),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum IpaPublicKey {
+    X25519(<X25519Kem as Kem>::PublicKey),
+    P256(<P256Kem as Kem>::PublicKey),
+}
+
+impl IpaPublicKey {
+    #[must_use]
+    pub fn suite(&self) -> CipherSuite {
+        match self {
+            Self::X25519(_) => CipherSuite::X25519,
+            Self::P256(_) => CipherSuite::P256,
+        }
+    }
+
+    /// Parses a public key of the given suite. P-256 keys are in uncompressed SEC1 form.
+    ///
+    /// ## Errors
+    /// If `bytes` is not a valid key of this suite.
+    pub fn from_bytes(suite: CipherSuite, bytes: &[u8]) -> Result<Self, KeyError> {
+        match suite {
+            CipherSuite::X25519 => Deserializable::from_bytes(bytes).map(Self::X25519),
+            CipherSuite::P256 => Deserializable::from_bytes(bytes).map(Self::P256),
+        }
+        .map_err(|e| KeyError::Invalid(suite, e))
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::X25519(pk) => pk.to_bytes().to_vec(),
+            Self::P256(pk) => pk.to_bytes().to_vec(),
+        }
+    }
+
+    /// Parses the text encoding of a key, as written by [`Self::encode`].
+    ///
+    /// ## Errors
+    /// If the suite is unknown, or the key is not a valid hex-encoded key of that suite.
+    pub fn decode(s: &str) -> Result<Self, KeyError> {
+        let (suite, bytes) = decode(s)?;
+        Self::from_bytes(suite, &bytes)
+    }
+
+    /// Hex-encodes the key, prefixed with its suite unless it is X25519.
+    #[must_use]
+    pub fn encode(&self) -> String {
+        encode(self.suite(), &self.to_bytes())
+    }
+}
+
+impl Debug for IpaPublicKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encode())
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum IpaPrivateKey {
+    X25519(<X25519Kem as Kem>::PrivateKey),
+    P256(<P256Kem as Kem>::PrivateKey),
+}
+
+impl IpaPrivateKey {
+    /// Generates a new key pair of the given suite.
+    pub fn generate<R: RngCore + CryptoRng>(
+        suite: CipherSuite,
+        rng: &mut R,
+    ) -> (Self, IpaPublicKey) {
+        match suite {
+            CipherSuite::X25519 => {
+                let (sk, pk) = X25519Kem::gen_keypair(rng);
+                (Self::X25519(sk), IpaPublicKey::X25519(pk))
+            }
+            CipherSuite::P256 => {
+                let (sk, pk) = P256Kem::gen_keypair(rng);
+                (Self::P256(sk), IpaPublicKey::P256(pk))
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn suite(&self) -> CipherSuite {
+        match self {
+            Self::X25519(_) => CipherSuite::X25519,
+            Self::P256(_) => CipherSuite::P256,
+        }
+    }
+
+    #[must_use]
+    pub fn public_key(&self) -> IpaPublicKey {
+        match self {
+            Self::X25519(sk) => IpaPublicKey::X25519(X25519Kem::sk_to_pk(sk)),
+            Self::P256(sk) => IpaPublicKey::P256(P256Kem::sk_to_pk(sk)),
+        }
+    }
+
+    /// Parses a private key of the given suite. Keys of both suites are 32 bytes long, so the
+    /// suite can't be told from the key itself.
+    ///
+    /// ## Errors
+    /// If `bytes` is not a valid key of this suite.
+    pub fn from_bytes(suite: CipherSuite, bytes: &[u8]) -> Result<Self, KeyError> {
+        match suite {
+            CipherSuite::X25519 => Deserializable::from_bytes(bytes).map(Self::X25519),
+            CipherSuite::P256 => Deserializable::from_bytes(bytes).map(Self::P256),
+        }
+        .map_err(|e| KeyError::Invalid(suite, e))
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::X25519(sk) => sk.to_bytes().to_vec(),
+            Self::P256(sk) => sk.to_bytes().to_vec(),
+        }
+    }
+
+    /// Parses the text encoding of a key, as written by [`Self::encode`].
+    ///
+    /// ## Errors
+    /// If the suite is unknown, or the key is not a valid hex-encoded key of that suite.
+    pub fn decode(s: &str) -> Result<Self, KeyError> {
+        let (suite, bytes) = decode(s)?;
+        Self::from_bytes(suite, &bytes)
+    }
+
+    /// Hex-encodes the key, prefixed with its suite unless it is X25519.
+    #[must_use]
+    pub fn encode(&self) -> String {
+        encode(self.suite(), &self.to_bytes())
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand_core::SeedableRng;
+
+    use super::{CipherSuite, IpaPrivateKey, IpaPublicKey, KeyError};
+
+    #[test]
+    fn key_ids() {
+        assert_eq!(CipherSuite::X25519, CipherSuite::of_key(0));
+        assert_eq!(CipherSuite::X25519, CipherSuite::of_key(127));
+        assert_eq!(CipherSuite::P256, CipherSuite::of_key(128));
+        assert_eq!(CipherSuite::P256, CipherSuite::of_key(255));
+        for suite in [CipherSuite::X25519, CipherSuite::P256] {
+            assert_eq!(suite, CipherSuite::of_key(suite.default_key_id()));
+        }
+        assert!(matches!(
+            CipherSuite::P256.check_key_id(1),
+            Err(KeyError::WrongKeyId { key_id: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn encoding() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for suite in [CipherSuite::X25519, CipherSuite::P256] {
+            let (sk, pk) = IpaPrivateKey::generate(suite, &mut rng);
+            assert_eq!(pk, sk.public_key());
+
+            let decoded_sk = IpaPrivateKey::decode(&sk.encode()).unwrap();
+            let decoded_pk = IpaPublicKey::decode(&pk.encode()).unwrap();
+            assert_eq!(suite, decoded_sk.suite());
+            assert_eq!(pk, decoded_pk);
+            assert_eq!(pk, decoded_sk.public_key());
+        }
+    }
+
+    #[test]
+    fn x25519_keys_without_suite() {
+        let sk = "53d58e022981f2edbf55fec1b45dbabd08a3442cb7b7c598839de5d7a5888bff";
+        let sk = IpaPrivateKey::decode(sk).unwrap();
+        assert_eq!(CipherSuite::X25519, sk.suite());
+        assert_eq!(
+            "92a6fb666c37c008defd74abf3204ebea685742eab8347b08e2f7c759893947a",
+            sk.public_key().encode()
+        );
+        assert!(matches!(
+            IpaPrivateKey::decode("p384:00"),
+            Err(KeyError::UnknownSuite(_))
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+//! Encryption of helper private key files at rest.
+//!
+//! TLS and HPKE private key files can be encrypted with a key-encryption key (KEK): a random
+//! AES-256-GCM key that is kept apart from the key files, in its own file or in the
+//! [`KEY_ENCRYPTION_KEY_ENV`] environment variable. An encrypted key file looks like
+//! ```text
+//! -----BEGIN IPA ENCRYPTED KEY-----
+//! <hex-encoded nonce and ciphertext>
+//! -----END IPA ENCRYPTED KEY-----
+//! ```
+//! and holds the contents of the original file, so PEM-encoded TLS keys and hex-encoded HPKE keys
+//! are encrypted the same way. Key files that are not encrypted are loaded as before.
+
+use std::{
+    borrow::Cow,
+    fmt::{Debug, Formatter},
+    fs, io,
+    path::Path,
+};
+
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use rand_core::{CryptoRng, RngCore};
+
+/// Environment variable with the hex-encoded key-encryption key, used if no key file is given.
+pub const KEY_ENCRYPTION_KEY_ENV: &str = "IPA_KEY_ENCRYPTION_KEY";
+
+const BEGIN: &str = "-----BEGIN IPA ENCRYPTED KEY-----";
+const END: &str = "-----END IPA ENCRYPTED KEY-----";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("key-encryption key must be {KEY_LEN} hex-encoded bytes")]
+    InvalidKey,
+    #[error("failed to read key-encryption key: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed encrypted key file")]
+    Malformed,
+    #[error("key file is encrypted, but no key-encryption key is configured")]
+    MissingKey,
+    #[error("failed to decrypt key file, it is corrupted or encrypted with another key")]
+    Decrypt,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyEncryptionKey([u8; KEY_LEN]);
+
+impl Debug for KeyEncryptionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("KeyEncryptionKey(..)")
+    }
+}
+
+impl KeyEncryptionKey {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut key = [0; KEY_LEN];
+        rng.fill_bytes(&mut key);
+        Self(key)
+    }
+
+    /// Parses a hex-encoded key, as written by [`Self::encode`].
+    ///
+    /// ## Errors
+    /// If `s` is not a hex-encoded key of the right length.
+    pub fn decode(s: &str) -> Result<Self, Error> {
+        let mut key = [0; KEY_LEN];
+        hex::decode_to_slice(s.trim(), &mut key).map_err(|_| Error::InvalidKey)?;
+        Ok(Self(key))
+    }
+
+    #[must_use]
+    pub fn encode(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// Loads the key from `file`, or from the [`KEY_ENCRYPTION_KEY_ENV`] environment variable if
+    /// no file is given. Returns `None` if neither is set.
+    ///
+    /// ## Errors
+    /// If the file cannot be read, or does not contain a valid key.
+    pub fn load(file: Option<&Path>) -> Result<Option<Self>, Error> {
+        let encoded = match file {
+            Some(path) => fs::read_to_string(path)?,
+            None => match std::env::var(KEY_ENCRYPTION_KEY_ENV) {
+                Ok(encoded) => encoded,
+                Err(_) => return Ok(None),
+            },
+        };
+
+        Self::decode(&encoded).map(Some)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+
+    /// Encrypts the contents of a key file.
+    ///
+    /// ## Panics
+    /// If encryption fails, which AES-GCM only does for inputs far larger than any key file.
+    pub fn encrypt<R: RngCore + CryptoRng>(&self, contents: &[u8], rng: &mut R) -> String {
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: contents,
+                    aad: BEGIN.as_bytes(),
+                },
+            )
+            .unwrap();
+
+        format!(
+            "{BEGIN}\n{}{}\n{END}\n",
+            hex::encode(nonce),
+            hex::encode(ciphertext)
+        )
+    }
+
+    /// Decrypts a key file written by [`Self::encrypt`].
+    ///
+    /// ## Errors
+    /// If the file is malformed, or was not encrypted with this key.
+    pub fn decrypt(&self, file: &str) -> Result<Vec<u8>, Error> {
+        let body = file
+            .trim()
+            .strip_prefix(BEGIN)
+            .and_then(|s| s.strip_suffix(END))
+            .ok_or(Error::Malformed)?;
+        let bytes = hex::decode(body.split_whitespace().collect::<String>())
+            .map_err(|_| Error::Malformed)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(Error::Malformed);
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+        self.cipher()
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: BEGIN.as_bytes(),
+                },
+            )
+            .map_err(|_| Error::Decrypt)
+    }
+}
+
+/// Returns the contents of a key file, decrypting them with `kek` if the file is encrypted.
+///
+/// ## Errors
+/// If the file is encrypted and cannot be decrypted with `kek`, or `kek` is not set.
+pub fn read_key_file<'a>(
+    contents: &'a [u8],
+    kek: Option<&KeyEncryptionKey>,
+) -> Result<Cow<'a, [u8]>, Error> {
+    match std::str::from_utf8(contents) {
+        Ok(s) if s.trim_start().starts_with(BEGIN) => {
+            kek.ok_or(Error::MissingKey)?.decrypt(s).map(Cow::Owned)
+        }
+        _ => Ok(Cow::Borrowed(contents)),
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use rand::{rngs::StdRng, thread_rng};
+    use rand_core::SeedableRng;
+
+    use super::{read_key_file, Error, KeyEncryptionKey};
+
+    const KEY: &[u8] = b"53d58e022981f2edbf55fec1b45dbabd08a3442cb7b7c598839de5d7a5888bff\n";
+
+    #[test]
+    fn encrypt_decrypt() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let kek = KeyEncryptionKey::generate(&mut rng);
+        assert_eq!(kek, KeyEncryptionKey::decode(&kek.encode()).unwrap());
+
+        let file = kek.encrypt(KEY, &mut rng);
+        assert!(!file.contains(std::str::from_utf8(KEY).unwrap().trim()));
+        assert_eq!(KEY, &*read_key_file(file.as_bytes(), Some(&kek)).unwrap());
+    }
+
+    #[test]
+    fn plaintext_key_files() {
+        assert_eq!(KEY, &*read_key_file(KEY, None).unwrap());
+        let kek = KeyEncryptionKey::generate(&mut thread_rng());
+        assert_eq!(KEY, &*read_key_file(KEY, Some(&kek)).unwrap());
+    }
+
+    #[test]
+    fn wrong_or_missing_key() {
+        let mut rng = thread_rng();
+        let kek = KeyEncryptionKey::generate(&mut rng);
+        let file = kek.encrypt(KEY, &mut rng);
+
+        assert!(matches!(
+            read_key_file(file.as_bytes(), None),
+            Err(Error::MissingKey)
+        ));
+        let other = KeyEncryptionKey::generate(&mut rng);
+        assert!(matches!(
+            read_key_file(file.as_bytes(), Some(&other)),
+            Err(Error::Decrypt)
+        ));
+        let truncated = file.replacen(&file[34..40], "", 1);
+        assert!(matches!(
+            read_key_file(truncated.as_bytes(), Some(&kek)),
+            Err(Error::Decrypt)
+        ));
+        assert!(matches!(
+            KeyEncryptionKey::decode("abcd"),
+            Err(Error::InvalidKey)
+        ));
+    }
+}
@@ -15,6 +15,8 @@ pub mod error;
 pub mod ff;
 pub mod helpers;
 pub mod hpke;
+#[cfg(feature = "web-app")]
+pub mod key_encryption;
 
 #[cfg(feature = "web-app")]
 pub mod net;
@@ -43,7 +45,7 @@ extern crate shuttle_crate as shuttle;
 pub(crate) mod sync {
     pub use shuttle::sync::{Arc, Mutex, MutexGuard, Weak};
     pub mod atomic {
-        pub use shuttle::sync::atomic::{AtomicUsize, Ordering};
+        pub use shuttle::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
     }
 }
 
@@ -51,7 +53,7 @@ pub(crate) mod sync {
 pub(crate) mod sync {
     pub use std::sync::{Arc, Mutex, MutexGuard, Weak};
     pub mod atomic {
-        pub use std::sync::atomic::{AtomicUsize, Ordering};
+        pub use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
     }
 }
 
@@ -3,8 +3,12 @@ use std::{
     future::Future,
     io::{self, BufRead},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{ready, Context, Poll},
+    time::SystemTime,
 };
 
 use axum::{
@@ -12,9 +16,16 @@ use axum::{
     http::uri::{self, Parts, Scheme},
 };
 use bytes::Bytes;
-use futures::{stream::StreamExt, Stream};
+use futures::{
+    stream::{BoxStream, StreamExt},
+    Stream,
+};
 use http_body_util::BodyExt;
-use hyper::{header::HeaderName, http::HeaderValue, Request, Response, StatusCode, Uri};
+use hyper::{
+    header::{HeaderName, ACCEPT_ENCODING, CONTENT_ENCODING},
+    http::HeaderValue,
+    HeaderMap, Request, Response, StatusCode, Uri,
+};
 use hyper_rustls::{ConfigBuilderExt, HttpsConnector, HttpsConnectorBuilder};
 use hyper_util::{
     client::legacy::{connect::HttpConnector, Client},
@@ -27,13 +38,14 @@ use tracing::error;
 use crate::{
     config::{
         ClientConfig, HyperClientConfigurator, NetworkConfig, OwnedCertificate, OwnedPrivateKey,
-        PeerConfig,
+        PeerConfig, StreamCompression,
     },
     helpers::{
         query::{PrepareQuery, QueryConfig, QueryInput},
-        HelperIdentity,
+        HelperIdentity, Shaped, TokenBucket,
     },
-    net::{http_serde, server::HTTP_CLIENT_ID_HEADER, Error, CRYPTO_PROVIDER},
+    hpke::PublicKeySet,
+    net::{compression, http_serde, server::HTTP_CLIENT_ID_HEADER, Error, CRYPTO_PROVIDER},
     protocol::{Gate, QueryId},
 };
 
@@ -93,6 +105,7 @@ impl ClientIdentity {
 #[pin_project]
 pub struct ResponseFuture<'a> {
     authority: &'a uri::Authority,
+    compression: &'a CompressionNegotiation,
     #[pin]
     inner: hyper_util::client::legacy::ResponseFuture,
 }
@@ -113,6 +126,10 @@ impl<'a> ResponseFromEndpoint<'a> {
         self.inner.status()
     }
 
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
     pub fn into_body(self) -> Body {
         self.inner.into_body()
     }
@@ -129,6 +146,7 @@ impl<'a> Future for ResponseFuture<'a> {
         let this = self.project();
         match ready!(this.inner.poll(cx)) {
             Ok(resp) => {
+                this.compression.update(resp.headers());
                 let (http_parts, http_body) = resp.into_parts();
                 let axum_resp = Response::from_parts(http_parts, Body::new(http_body));
                 Poll::Ready(Ok(ResponseFromEndpoint {
@@ -144,6 +162,47 @@ impl<'a> Future for ResponseFuture<'a> {
     }
 }
 
+/// Tracks whether the peer accepts record streams compressed with the codec configured in
+/// [`ClientConfig`]. Helpers advertise the codecs they accept in the `Accept-Encoding` header of
+/// their responses, as described in [`RFC 7694`].
+///
+/// [`RFC 7694`]: https://datatracker.ietf.org/doc/html/rfc7694
+#[derive(Debug, Default)]
+struct CompressionNegotiation {
+    codec: StreamCompression,
+    accepted: AtomicBool,
+}
+
+impl CompressionNegotiation {
+    fn new(codec: StreamCompression) -> Self {
+        Self {
+            codec,
+            accepted: AtomicBool::new(false),
+        }
+    }
+
+    fn update(&self, headers: &HeaderMap) {
+        let (Some(encoding), Some(accept_encoding)) =
+            (self.codec.encoding(), headers.get(ACCEPT_ENCODING))
+        else {
+            return;
+        };
+        let accepted = accept_encoding.to_str().is_ok_and(|v| {
+            v.split(',')
+                .any(|coding| coding.trim().eq_ignore_ascii_case(encoding))
+        });
+        self.accepted.store(accepted, Ordering::Relaxed);
+    }
+
+    fn current(&self) -> StreamCompression {
+        if self.accepted.load(Ordering::Relaxed) {
+            self.codec
+        } else {
+            StreamCompression::None
+        }
+    }
+}
+
 /// TODO: we need a client that can be used by any system that is not aware of the internals
 ///       of the helper network. That means that create query and send inputs API need to be
 ///       separated from prepare/step data etc.
@@ -155,6 +214,11 @@ pub struct MpcHelperClient {
     scheme: uri::Scheme,
     authority: uri::Authority,
     auth_header: Option<(HeaderName, HeaderValue)>,
+    compression: Arc<CompressionNegotiation>,
+    /// Enforces [`ClientConfig::bandwidth_limit`] on record streams sent to this peer.
+    bandwidth: Option<crate::sync::Arc<TokenBucket>>,
+    /// Peer's TLS certificate, used to verify documents it signs.
+    certificate: Option<OwnedCertificate>,
 }
 
 impl MpcHelperClient {
@@ -187,6 +251,7 @@ impl MpcHelperClient {
         peer_config: PeerConfig,
         identity: ClientIdentity,
     ) -> Self {
+        let certificate = peer_config.certificate.clone();
         let (connector, auth_header) = if peer_config.url.scheme() == Some(&Scheme::HTTP) {
             // This connector works for both http and https. A regular HttpConnector would suffice,
             // but would make the type of `self.client` variable.
@@ -247,15 +312,22 @@ impl MpcHelperClient {
                 None,
             )
         };
-        Self::new_internal(peer_config.url, connector, auth_header, client_config)
+        Self::new_internal(
+            peer_config.url,
+            connector,
+            auth_header,
+            certificate,
+            client_config,
+        )
     }
 
     #[must_use]
-    fn new_internal<C: HyperClientConfigurator>(
+    fn new_internal(
         addr: Uri,
         connector: HttpsConnector<HttpConnector>,
         auth_header: Option<(HeaderName, HeaderValue)>,
-        conf: &C,
+        certificate: Option<OwnedCertificate>,
+        conf: &ClientConfig,
     ) -> Self {
         let mut builder = Client::builder(TokioExecutor::new());
         // the following timer is necessary for http2, in particular for any timeouts
@@ -275,15 +347,37 @@ impl MpcHelperClient {
             scheme,
             authority,
             auth_header,
+            compression: Arc::new(CompressionNegotiation::new(conf.stream_compression)),
+            bandwidth: conf
+                .bandwidth_limit
+                .map(|limit| {
+                    crate::sync::Arc::new(TokenBucket::new(limit.bytes_per_sec, limit.burst_bytes()))
+                }),
+            certificate,
         }
     }
 
+    /// Compression applied to record streams sent to this peer. It remains
+    /// [`StreamCompression::None`] until the peer advertises support for the codec set in
+    /// [`ClientConfig`].
+    #[must_use]
+    pub fn stream_compression(&self) -> StreamCompression {
+        self.compression.current()
+    }
+
+    /// Token bucket that enforces [`ClientConfig::bandwidth_limit`] for this peer.
+    #[must_use]
+    pub fn bandwidth(&self) -> Option<&crate::sync::Arc<TokenBucket>> {
+        self.bandwidth.as_ref()
+    }
+
     pub fn request(&self, mut req: Request<Body>) -> ResponseFuture<'_> {
         if let Some((k, v)) = self.auth_header.clone() {
             req.headers_mut().insert(k, v);
         }
         ResponseFuture {
             authority: &self.authority,
+            compression: &self.compression,
             inner: self.client.request(req),
         }
     }
@@ -335,6 +429,49 @@ impl MpcHelperClient {
         }
     }
 
+    /// Fetches HPKE public keys this helper currently accepts reports for. The document is
+    /// verified against the helper's certificate from the network configuration.
+    ///
+    /// Documents issued before `not_before` are rejected, so that a stale document, cached or
+    /// replayed on the way, can't make encryptors use keys the helper no longer publishes.
+    ///
+    /// Encryptors should call [`PublicKeySet::registry_at`] on the result to select keys that
+    /// are valid at the time reports are encrypted.
+    /// # Errors
+    /// If the helper has no certificate in the network configuration, if the request fails to
+    /// deliver to helper, if the document cannot be verified, or if it was issued before
+    /// `not_before`.
+    pub async fn public_keys(&self, not_before: SystemTime) -> Result<PublicKeySet, Error> {
+        let Some(certificate) = self.certificate.as_ref() else {
+            return Err(Error::InvalidPublicKeySet {
+                dest: self.authority.to_string(),
+                inner: "helper certificate is required to verify public keys".into(),
+            });
+        };
+        let req = http_serde::keys::Request;
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let resp = self.request(req).await?;
+        if resp.status().is_success() {
+            let bytes = Self::response_to_bytes(resp).await?;
+            let signed: http_serde::keys::SignedPublicKeySet = serde_json::from_slice(&bytes)?;
+            signed
+                .verify(certificate)
+                .and_then(|keys| {
+                    if keys.is_issued_before(not_before) {
+                        Err(format!("public key set issued at {} is stale", keys.issued_at).into())
+                    } else {
+                        Ok(keys)
+                    }
+                })
+                .map_err(|inner| Error::InvalidPublicKeySet {
+                    dest: self.authority.to_string(),
+                    inner,
+                })
+        } else {
+            Err(Error::from_failed_resp(resp).await)
+        }
+    }
+
     /// Intended to be called externally, by the report collector. Informs the MPC ring that
     /// the external party wants to start a new query.
     /// # Errors
@@ -365,6 +502,17 @@ impl MpcHelperClient {
         Self::resp_ok(resp).await
     }
 
+    /// Used to communicate from one helper to another. Specifically, the helper that coordinates a
+    /// query aborts it on a follower that accepted it, when the other follower rejected it.
+    /// # Errors
+    /// If the request has illegal arguments, or fails to deliver to helper
+    pub async fn abort_query(&self, query_id: QueryId) -> Result<(), Error> {
+        let req = http_serde::query::abort::Request::new(query_id);
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let resp = self.request(req).await?;
+        Self::resp_ok(resp).await
+    }
+
     /// Intended to be called externally, e.g. by the report collector. After the report collector
     /// calls "create query", it must then send the data for the query to each of the clients. This
     /// query input contains the data intended for a helper.
@@ -379,24 +527,62 @@ impl MpcHelperClient {
 
     /// Sends a batch of messages associated with a query's step to another helper. Messages are a
     /// contiguous block of records. Also includes [`crate::protocol::RecordId`] information and
-    /// [`crate::helpers::network::ChannelId`].
+    /// [`crate::helpers::network::ChannelId`]. `offset` is the position of the first byte of `data`
+    /// within the records stream; it is non-zero when resuming the stream after connection loss.
+    ///
+    /// The stream is compressed if this peer has advertised support for the codec set in
+    /// [`ClientConfig`], and paced to stay within its bandwidth limit, if there is one.
     /// # Errors
     /// If the request has illegal arguments, or fails to deliver to helper
     /// # Panics
     /// If messages size > max u32 (unlikely)
-    pub fn step<S: Stream<Item = Vec<u8>> + Send + 'static>(
+    pub fn step<B: Into<Bytes>, S: Stream<Item = B> + Send + 'static>(
         &self,
         query_id: QueryId,
         gate: &Gate,
+        offset: u64,
         data: S,
     ) -> Result<ResponseFuture, Error> {
-        let data = data.map(|v| Ok::<bytes::Bytes, Error>(Bytes::from(v)));
-        let body = axum::body::Body::from_stream(data);
-        let req = http_serde::query::step::Request::new(query_id, gate.clone(), body);
-        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        let codec = self.stream_compression();
+        let data = data.map(|v| -> Bytes { v.into() });
+        let data: BoxStream<'static, Bytes> = if codec == StreamCompression::None {
+            data.boxed()
+        } else {
+            compression::compress(codec, gate.as_ref().to_string(), data).boxed()
+        };
+        let data = match &self.bandwidth {
+            Some(bucket) => Shaped::new(data, crate::sync::Arc::clone(bucket)).boxed(),
+            None => data,
+        };
+        let body = axum::body::Body::from_stream(data.map(Ok::<bytes::Bytes, Error>));
+        let req = http_serde::query::step::Request::new(query_id, gate.clone(), offset, body);
+        let mut req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+        if let Some(encoding) = codec.encoding() {
+            req.headers_mut()
+                .insert(CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
         Ok(self.request(req))
     }
 
+    /// Asks the peer how many bytes of the records stream for `gate` it has acknowledged. After
+    /// losing the connection, the stream is resumed from that offset.
+    /// # Errors
+    /// If the request fails to deliver to helper or the response is malformed.
+    pub async fn step_offset(&self, query_id: QueryId, gate: &Gate) -> Result<u64, Error> {
+        let req = http_serde::query::step::OffsetRequest::new(query_id, gate.clone());
+        let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
+
+        let resp = self.request(req).await?;
+        if resp.status().is_success() {
+            let bytes = Self::response_to_bytes(resp).await?;
+            let http_serde::query::step::OffsetResponseBody { offset } =
+                serde_json::from_slice(&bytes)?;
+            Ok(offset)
+        } else {
+            Err(Error::from_failed_resp(resp).await)
+        }
+    }
+
     /// Retrieve the status of a query.
     ///
     /// ## Errors
@@ -427,12 +613,37 @@ impl MpcHelperClient {
     /// If the request has illegal arguments, or fails to deliver to helper
     #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
     pub async fn query_results(&self, query_id: QueryId) -> Result<bytes::Bytes, Error> {
+        Ok(self.query_results_with_metadata(query_id).await?.0)
+    }
+
+    /// Same as [`Self::query_results`], but also returns metadata this helper reported for
+    /// the query.
+    ///
+    /// ## Errors
+    /// If the request has illegal arguments, fails to deliver to helper, or if the metadata
+    /// sent by helper is malformed.
+    #[cfg(any(all(test, not(feature = "shuttle")), feature = "cli"))]
+    pub async fn query_results_with_metadata(
+        &self,
+        query_id: QueryId,
+    ) -> Result<(bytes::Bytes, crate::query::QueryMetadata), Error> {
         let req = http_serde::query::results::Request::new(query_id);
         let req = req.try_into_http_request(self.scheme.clone(), self.authority.clone())?;
         let resp = self.request(req).await?;
         if resp.status().is_success() {
+            let metadata = match resp
+                .headers()
+                .get(&http_serde::query::results::METADATA_HEADER)
+            {
+                Some(value) => serde_json::from_slice(value.as_bytes()).map_err(|e| {
+                    Error::InvalidHeader(
+                        format!("{}: {e}", http_serde::query::results::METADATA_HEADER).into(),
+                    )
+                })?,
+                None => crate::query::QueryMetadata::default(),
+            };
             let body = resp.into_body().collect().await?.to_bytes();
-            Ok(body)
+            Ok((body, metadata))
         } else {
             Err(Error::from_failed_resp(resp).await)
         }
@@ -455,6 +666,7 @@ pub(crate) mod tests {
         future::{ready, Future},
         iter::zip,
         task::Poll,
+        time::{Duration, SystemTime},
     };
 
     use futures::stream::{once, poll_immediate};
@@ -462,14 +674,16 @@ pub(crate) mod tests {
 
     use super::*;
     use crate::{
+        config::BandwidthLimit,
         ff::{FieldType, Fp31},
         helpers::{
-            make_owned_handler, query::QueryType::TestMultiply, BytesStream, HelperResponse,
-            RequestHandler, RoleAssignment, Transport, MESSAGE_PAYLOAD_SIZE_BYTES,
+            make_owned_handler, query::QueryType::TestMultiply, routing::RouteId, BytesStream,
+            HelperResponse, RequestHandler, RoleAssignment, Transport, MESSAGE_PAYLOAD_SIZE_BYTES,
         },
+        hpke::{PublishedKey, PUBLIC_KEY_SET_VERSION},
         net::test::TestServer,
         protocol::step::TestExecutionStep,
-        query::ProtocolResult,
+        query::{ProtocolResult, QueryMetadata, WithMetadata},
         secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
         sync::Arc,
     };
@@ -510,7 +724,7 @@ pub(crate) mod tests {
         server_handler: HandlerF,
     ) -> ClientOut
     where
-        ClientOut: Eq + Debug,
+        ClientOut: PartialEq + Debug,
         ClientFut: Future<Output = ClientOut>,
         ClientF: Fn(MpcHelperClient) -> ClientFut,
         HandlerF: Fn() -> Arc<dyn RequestHandler<Identity = HelperIdentity>>,
@@ -555,15 +769,81 @@ pub(crate) mod tests {
         assert_eq!(expected_output, &output);
     }
 
+    #[tokio::test]
+    async fn public_keys() {
+        let issued_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let expected = PublicKeySet {
+            version: PUBLIC_KEY_SET_VERSION,
+            issued_at: 1_700_000_000,
+            keys: vec![PublishedKey {
+                id: 3,
+                public_key: hex::encode([7; 32]),
+                valid_from: Some(1_600_000_000),
+                valid_until: None,
+                epochs: None,
+            }],
+        };
+        let handler = || {
+            let expected = expected.clone();
+            make_owned_handler(move |addr, _| {
+                let expected = expected.clone();
+                async move {
+                    assert_eq!(RouteId::PublicKeys, addr.route);
+                    Ok(HelperResponse::from(expected))
+                }
+            })
+        };
+
+        // keys are signed with the TLS key of the helper
+        for use_http1 in [true, false] {
+            let mut builder = TestServer::builder().with_request_handler(handler());
+            if use_http1 {
+                builder = builder.use_http1();
+            }
+            let keys = builder
+                .build()
+                .await
+                .client
+                .public_keys(issued_at)
+                .await
+                .unwrap();
+            assert_eq!(expected, keys);
+        }
+
+        // a document issued before the time requested by the caller is stale
+        let err = TestServer::builder()
+            .with_request_handler(handler())
+            .build()
+            .await
+            .client
+            .public_keys(issued_at + Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidPublicKeySet { .. }), "{err}");
+
+        // without TLS, there is nothing to verify the keys against
+        let err = TestServer::builder()
+            .disable_https()
+            .with_request_handler(handler())
+            .build()
+            .await
+            .client
+            .public_keys(issued_at)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidPublicKeySet { .. }), "{err}");
+    }
+
     #[tokio::test]
     async fn create() {
         let expected_query_id = QueryId;
-        let expected_query_config = QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap();
+        // query config is not `Copy`, so each closure builds its own
+        let expected_query_config = || QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap();
 
         let handler = || {
             make_owned_handler(move |addr, _| async move {
                 let query_config = addr.into::<QueryConfig>().unwrap();
-                assert_eq!(query_config, expected_query_config);
+                assert_eq!(query_config, expected_query_config());
 
                 Ok(HelperResponse::from(PrepareQuery {
                     query_id: expected_query_id,
@@ -573,7 +853,7 @@ pub(crate) mod tests {
             })
         };
         let query_id = test_query_command(
-            |client| async move { client.create_query(expected_query_config).await.unwrap() },
+            |client| async move { client.create_query(expected_query_config()).await.unwrap() },
             handler,
         )
         .await;
@@ -582,12 +862,12 @@ pub(crate) mod tests {
 
     #[tokio::test]
     async fn prepare() {
-        let config = QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap();
+        let config = || QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap();
         let handler = move || {
             make_owned_handler(move |addr, _| async move {
                 let input = PrepareQuery {
                     query_id: QueryId,
-                    config,
+                    config: config(),
                     roles: RoleAssignment::new(HelperIdentity::make_three()),
                 };
                 let prepare_query = addr.into::<PrepareQuery>().unwrap();
@@ -601,7 +881,7 @@ pub(crate) mod tests {
             |client| {
                 let req = PrepareQuery {
                     query_id: QueryId,
-                    config,
+                    config: config(),
                     roles: RoleAssignment::new(HelperIdentity::make_three()),
                 };
                 async move { client.prepare_query(req).await.unwrap() }
@@ -649,6 +929,7 @@ pub(crate) mod tests {
             .step(
                 expected_query_id,
                 &expected_step,
+                0,
                 once(ready(expected_payload.clone())),
             )
             .unwrap()
@@ -667,6 +948,65 @@ pub(crate) mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn step_compression_negotiated() {
+        let TestServer {
+            client, transport, ..
+        } = TestServer::builder()
+            .with_stream_compression(StreamCompression::Deflate)
+            .build()
+            .await;
+        // compression is off until the server advertises it
+        assert_eq!(StreamCompression::None, client.stream_compression());
+
+        for i in 0..2 {
+            let step = Gate::default().narrow(&TestExecutionStep::Iter(i));
+            let payload = vec![u8::try_from(i).unwrap(); 4 * MESSAGE_PAYLOAD_SIZE_BYTES];
+            let resp = client
+                .step(QueryId, &step, 0, once(ready(payload.clone())))
+                .unwrap()
+                .await
+                .unwrap();
+            MpcHelperClient::resp_ok(resp).await.unwrap();
+            assert_eq!(StreamCompression::Deflate, client.stream_compression());
+
+            let stream = Arc::clone(&transport)
+                .receive(HelperIdentity::ONE, (QueryId, step))
+                .into_bytes_stream();
+            assert_eq!(payload, stream.concat().await);
+        }
+    }
+
+    #[tokio::test]
+    async fn step_bandwidth_limit() {
+        const BYTES_PER_SEC: u64 = 20_000;
+        let TestServer {
+            client, transport, ..
+        } = TestServer::builder()
+            .with_bandwidth_limit(BandwidthLimit {
+                bytes_per_sec: BYTES_PER_SEC.try_into().unwrap(),
+                burst_bytes: Some(1000.try_into().unwrap()),
+            })
+            .build()
+            .await;
+        let step = Gate::default().narrow(&TestExecutionStep::Iter(0));
+        let chunks = (0..5_u8).map(|i| vec![i; 1000]).collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        let resp = client
+            .step(QueryId, &step, 0, futures::stream::iter(chunks.clone()))
+            .unwrap()
+            .await
+            .unwrap();
+        MpcHelperClient::resp_ok(resp).await.unwrap();
+        let stream = Arc::clone(&transport)
+            .receive(HelperIdentity::ONE, (QueryId, step))
+            .into_bytes_stream();
+        assert_eq!(chunks.concat(), stream.concat().await);
+        // everything except the initial burst is paced
+        assert!(start.elapsed() >= std::time::Duration::from_millis(4000 * 1000 / BYTES_PER_SEC));
+    }
+
     #[tokio::test]
     async fn results() {
         let expected_results = [
@@ -695,4 +1035,27 @@ pub(crate) mod tests {
                 .to_bytes()
         );
     }
+
+    #[tokio::test]
+    async fn results_with_metadata() {
+        let expected = QueryMetadata {
+            dropped_reports: 2,
+            ..Default::default()
+        };
+        let handler = move || {
+            make_owned_handler(move |_, _| async move {
+                let results: Box<dyn ProtocolResult> = Box::new(WithMetadata {
+                    result: vec![Replicated::<Fp31>::ZERO],
+                    metadata: expected,
+                });
+                Ok(HelperResponse::from(results))
+            })
+        };
+        let (_, metadata) = test_query_command(
+            |client| async move { client.query_results_with_metadata(QueryId).await.unwrap() },
+            handler,
+        )
+        .await;
+        assert_eq!(expected, metadata);
+    }
 }
@@ -0,0 +1,297 @@
+//! Compression of helper-to-helper record streams.
+//!
+//! Every chunk produced by the sender is compressed independently and framed as
+//! `compressed_len: u32 LE | uncompressed_len: u32 LE | compressed bytes`. Keeping chunks
+//! independent lets the receiver decompress data as it arrives, and keeps stream offsets
+//! used to resume a stream after connection loss in the uncompressed domain: a new connection
+//! always starts at a frame boundary.
+//!
+//! Compression is negotiated as described in [`RFC 7694`]: a helper that accepts compressed
+//! streams lists the codec in the `Accept-Encoding` header of its responses, and the sender
+//! switches to it, setting `Content-Encoding` on the requests it makes after that.
+//!
+//! [`RFC 7694`]: https://datatracker.ietf.org/doc/html/rfc7694
+
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::{stream, Stream, StreamExt};
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
+use pin_project::pin_project;
+
+use crate::{
+    config::StreamCompression,
+    error::BoxError,
+    telemetry::{
+        labels::STEP,
+        metrics::{STREAM_BYTES_COMPRESSED, STREAM_BYTES_UNCOMPRESSED},
+    },
+};
+
+/// Level 1 gives most of the size reduction on the highly redundant data MPC protocols send,
+/// for a fraction of the CPU cost of higher levels.
+const COMPRESSION_LEVEL: u8 = 1;
+
+const FRAME_HEADER_LEN: usize = 2 * size_of::<u32>();
+
+/// Larger chunks are split before compression. This also bounds the amount of memory the
+/// receiver allocates for a single frame.
+const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+/// Deflate may expand incompressible data a little, by 5 bytes for every 64 KiB block.
+/// Larger frames are rejected before the receiver buffers them.
+const MAX_COMPRESSED_FRAME_LEN: usize = MAX_FRAME_LEN + MAX_FRAME_LEN / 1024;
+
+/// Compresses every chunk of `data` with the given codec.
+///
+/// Size of the stream before and after compression is recorded in
+/// [`STREAM_BYTES_UNCOMPRESSED`] and [`STREAM_BYTES_COMPRESSED`] metrics, labelled with `step`.
+pub fn compress<S: Stream<Item = Bytes> + Send + 'static>(
+    codec: StreamCompression,
+    step: String,
+    data: S,
+) -> impl Stream<Item = Bytes> + Send {
+    data.flat_map(move |chunk| {
+        let frames = chunk
+            .chunks(MAX_FRAME_LEN)
+            .map(|raw| encode_frame(codec, raw))
+            .collect::<Vec<_>>();
+        stream::iter(frames)
+    })
+    .inspect(move |frame| {
+        let uncompressed = (&frame[size_of::<u32>()..]).get_u32_le();
+        metrics::counter!(STREAM_BYTES_UNCOMPRESSED, u64::from(uncompressed), STEP => step.clone());
+        metrics::counter!(STREAM_BYTES_COMPRESSED, frame.len() as u64, STEP => step.clone());
+    })
+}
+
+fn encode_frame(codec: StreamCompression, raw: &[u8]) -> Bytes {
+    let compressed = match codec {
+        StreamCompression::Deflate => compress_to_vec(raw, COMPRESSION_LEVEL),
+        StreamCompression::None => raw.to_vec(),
+    };
+    let mut frame = BytesMut::with_capacity(FRAME_HEADER_LEN + compressed.len());
+    // both fit in u32 because raw chunks are bounded by `MAX_FRAME_LEN` and deflate
+    // overhead is small
+    frame.put_u32_le(u32::try_from(compressed.len()).unwrap());
+    frame.put_u32_le(u32::try_from(raw.len()).unwrap());
+    frame.put_slice(&compressed);
+    frame.freeze()
+}
+
+/// Reverses [`compress`], producing uncompressed chunks from a stream of frames.
+#[pin_project]
+pub struct Decompress<S> {
+    codec: StreamCompression,
+    #[pin]
+    inner: S,
+    buf: BytesMut,
+}
+
+impl<S> Decompress<S> {
+    pub fn new(codec: StreamCompression, inner: S) -> Self {
+        Self {
+            codec,
+            inner,
+            buf: BytesMut::new(),
+        }
+    }
+
+    /// Decodes the next frame from the buffer, if it has been received completely.
+    fn next_frame(codec: StreamCompression, buf: &mut BytesMut) -> Option<Result<Bytes, BoxError>> {
+        if buf.len() < FRAME_HEADER_LEN {
+            return None;
+        }
+        let mut header = &buf[..FRAME_HEADER_LEN];
+        let compressed_len = header.get_u32_le() as usize;
+        let uncompressed_len = header.get_u32_le() as usize;
+        if uncompressed_len > MAX_FRAME_LEN {
+            return Some(Err(format!(
+                "frame of {uncompressed_len} uncompressed bytes exceeds the limit of {MAX_FRAME_LEN}"
+            )
+            .into()));
+        }
+        if compressed_len > MAX_COMPRESSED_FRAME_LEN {
+            return Some(Err(format!(
+                "frame of {compressed_len} compressed bytes exceeds the limit of {MAX_COMPRESSED_FRAME_LEN}"
+            )
+            .into()));
+        }
+        if buf.len() < FRAME_HEADER_LEN + compressed_len {
+            return None;
+        }
+        buf.advance(FRAME_HEADER_LEN);
+        let payload = buf.split_to(compressed_len);
+        let raw = match codec {
+            StreamCompression::Deflate => {
+                match decompress_to_vec_with_limit(&payload, uncompressed_len) {
+                    Ok(raw) => Bytes::from(raw),
+                    Err(e) => return Some(Err(format!("failed to decompress frame: {e}").into())),
+                }
+            }
+            StreamCompression::None => payload.freeze(),
+        };
+
+        Some(if raw.len() == uncompressed_len {
+            Ok(raw)
+        } else {
+            Err(format!(
+                "frame decompressed to {} bytes, expected {uncompressed_len}",
+                raw.len()
+            )
+            .into())
+        })
+    }
+}
+
+impl<S: Stream<Item = Result<Bytes, BoxError>>> Stream for Decompress<S> {
+    type Item = Result<Bytes, BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(frame) = Self::next_frame(*this.codec, this.buf) {
+                return Poll::Ready(Some(frame));
+            }
+            match ready!(this.inner.as_mut().poll_next(cx)) {
+                Some(Ok(bytes)) => this.buf.extend_from_slice(&bytes),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None if this.buf.is_empty() => return Poll::Ready(None),
+                None => {
+                    return Poll::Ready(Some(Err(format!(
+                        "records stream ended in the middle of a compressed frame, {} bytes left",
+                        this.buf.len()
+                    )
+                    .into())))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use bytes::Bytes;
+    use futures::{stream, StreamExt, TryStreamExt};
+
+    use super::{compress, Decompress, MAX_COMPRESSED_FRAME_LEN, MAX_FRAME_LEN};
+    use crate::{config::StreamCompression, error::BoxError};
+
+    async fn deflate(chunks: Vec<Vec<u8>>) -> Vec<u8> {
+        compress(
+            StreamCompression::Deflate,
+            "test".to_string(),
+            stream::iter(chunks.into_iter().map(Bytes::from)),
+        )
+        .map(Vec::from)
+        .concat()
+        .await
+    }
+
+    async fn inflate(data: &[u8], split_at: usize) -> Result<Vec<u8>, BoxError> {
+        // deliver compressed stream in arbitrary pieces
+        let pieces = data
+            .chunks(split_at)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect::<Vec<_>>();
+        Decompress::new(StreamCompression::Deflate, stream::iter(pieces))
+            .map_ok(Vec::from)
+            .try_concat()
+            .await
+    }
+
+    #[tokio::test]
+    async fn compressible_data_shrinks() {
+        let data = vec![0_u8; 4096];
+        let compressed = deflate(vec![data.clone()]).await;
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(data, inflate(&compressed, 7).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn round_trip_arbitrary_chunks() {
+        let chunks = (0..10_u8)
+            .map(|i| (0..u16::from(i) * 100).map(|v| (v % 7) as u8 ^ i).collect())
+            .collect::<Vec<Vec<u8>>>();
+        let expected = chunks.concat();
+        let compressed = deflate(chunks).await;
+        for split_at in [1, 3, 64, 100_000] {
+            assert_eq!(expected, inflate(&compressed, split_at).await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn large_chunks_are_split() {
+        let data = (0..MAX_FRAME_LEN * 2 + 5)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect::<Vec<_>>();
+        let frames = compress(
+            StreamCompression::Deflate,
+            "test".to_string(),
+            stream::iter([Bytes::from(data.clone())]),
+        )
+        .count()
+        .await;
+        assert_eq!(3, frames);
+        let compressed = deflate(vec![data.clone()]).await;
+        assert_eq!(data, inflate(&compressed, 4096).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn truncated_frame() {
+        let compressed = deflate(vec![b"hello".to_vec()]).await;
+        let err = inflate(&compressed[..compressed.len() - 1], 3)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("middle of a compressed frame"),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_frame() {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&4_u32.to_le_bytes());
+        frame.extend_from_slice(&u32::MAX.to_le_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        let err = inflate(&frame, 16).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn oversized_compressed_frame() {
+        let mut header = Vec::new();
+        header.extend_from_slice(
+            &u32::try_from(MAX_COMPRESSED_FRAME_LEN + 1)
+                .unwrap()
+                .to_le_bytes(),
+        );
+        header.extend_from_slice(&4_u32.to_le_bytes());
+        // the frame is rejected as soon as its header arrives, without waiting for the payload
+        let mut stream = Decompress::new(
+            StreamCompression::Deflate,
+            stream::iter([Ok(Bytes::from(header))]).chain(stream::pending()),
+        );
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn incompressible_frame_fits() {
+        let mut state = 1_u32;
+        let data = (0..MAX_FRAME_LEN)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state.to_le_bytes()[0]
+            })
+            .collect::<Vec<_>>();
+        let compressed = deflate(vec![data.clone()]).await;
+        assert_eq!(data, inflate(&compressed, 100_000).await.unwrap());
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::Range;
+
 use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
@@ -59,6 +61,20 @@ pub enum Error {
     },
     #[error("{error}")]
     Application { code: StatusCode, error: BoxError },
+    #[error("Lost connection to {dest} while sending records stream from offset {offset}")]
+    ConnectionLost { dest: String, offset: u64 },
+    #[error("Cannot resume records stream to {dest} from offset {offset}: only bytes {retained:?} are kept for retransmission")]
+    StreamNotResumable {
+        dest: String,
+        offset: u64,
+        retained: Range<u64>,
+    },
+    #[error("invalid public key set received from {dest}: {inner}")]
+    InvalidPublicKeySet {
+        dest: String,
+        #[source]
+        inner: BoxError,
+    },
 }
 
 impl Error {
@@ -152,7 +168,10 @@ impl IntoResponse for Error {
             | Self::HyperHttpPassthrough(_)
             | Self::FailedHttpRequest { .. }
             | Self::InvalidUri(_)
-            | Self::MissingExtension(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | Self::MissingExtension(_)
+            | Self::ConnectionLost { .. }
+            | Self::StreamNotResumable { .. }
+            | Self::InvalidPublicKeySet { .. } => StatusCode::INTERNAL_SERVER_ERROR,
 
             Self::Application { code, .. } => code,
         };
@@ -11,7 +11,7 @@
 //! [`crate::net::server::handlers`]. This module provides functions to accept
 //! requests for each of the server APIs.
 //!
-//! This module is organized into the submodules "echo" and "query" for their
+//! This module is organized into the submodules "echo", "keys" and "query" for their
 //! respective APIs. Each module might have a Request struct used by the client
 //! to provide request parameters using [`crate::transport`] types.
 
@@ -68,6 +68,117 @@ pub mod echo {
     pub const AXUM_PATH: &str = "/echo";
 }
 
+pub mod keys {
+    use axum::body::Body;
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+    use hyper::http::uri;
+    use rustls::SignatureScheme;
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        config::OwnedCertificate,
+        error::BoxError,
+        helpers::{routing::RouteId, NoQueryId, NoStep, RouteParams},
+        hpke::PublicKeySet,
+        net::signing::{self, DocumentSigner},
+    };
+
+    #[derive(Debug, Clone, Default)]
+    pub struct Request;
+
+    impl RouteParams<RouteId, NoQueryId, NoStep> for Request {
+        type Params = &'static str;
+
+        fn resource_identifier(&self) -> RouteId {
+            RouteId::PublicKeys
+        }
+
+        fn query_id(&self) -> NoQueryId {
+            NoQueryId
+        }
+
+        fn gate(&self) -> NoStep {
+            NoStep
+        }
+
+        fn extra(&self) -> Self::Params {
+            ""
+        }
+    }
+
+    impl Request {
+        #[allow(clippy::unused_self)] // same interface as other requests
+        pub fn try_into_http_request(
+            self,
+            scheme: uri::Scheme,
+            authority: uri::Authority,
+        ) -> crate::net::http_serde::OutgoingRequest {
+            let uri = uri::Uri::builder()
+                .scheme(scheme)
+                .authority(authority)
+                .path_and_query(AXUM_PATH)
+                .build()?;
+            Ok(hyper::Request::get(uri).body(Body::empty())?)
+        }
+    }
+
+    /// [`PublicKeySet`] as published by a helper, signed with its TLS private key.
+    ///
+    /// The payload is kept in the exact form it was signed in, so verification does not depend
+    /// on how JSON is serialized. Helpers that run without TLS do not sign the payload, and
+    /// clients do not accept keys from them.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct SignedPublicKeySet {
+        /// JSON-encoded [`PublicKeySet`], in base64.
+        pub payload: String,
+        /// TLS `SignatureScheme` code point identifying the signature algorithm.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub signature_scheme: Option<u16>,
+        /// Signature over the decoded payload, in base64.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub signature: Option<String>,
+    }
+
+    impl SignedPublicKeySet {
+        /// ## Errors
+        /// If signing fails.
+        pub fn sign(payload: &[u8], signer: Option<&DocumentSigner>) -> Result<Self, BoxError> {
+            let (signature_scheme, signature) = match signer {
+                Some(signer) => {
+                    let (scheme, signature) = signer.sign(payload)?;
+                    (Some(u16::from(scheme)), Some(BASE64.encode(signature)))
+                }
+                None => (None, None),
+            };
+            Ok(Self {
+                payload: BASE64.encode(payload),
+                signature_scheme,
+                signature,
+            })
+        }
+
+        /// Verifies the signature against the helper's `certificate` and decodes the payload.
+        ///
+        /// ## Errors
+        /// If the signature is missing or invalid, or if the payload cannot be decoded.
+        pub fn verify(&self, certificate: &OwnedCertificate) -> Result<PublicKeySet, BoxError> {
+            let (Some(scheme), Some(signature)) = (self.signature_scheme, &self.signature) else {
+                return Err("public key set is not signed".into());
+            };
+            let payload = BASE64.decode(&self.payload)?;
+            signing::verify(
+                certificate,
+                SignatureScheme::from(scheme),
+                &payload,
+                &BASE64.decode(signature)?,
+            )?;
+            PublicKeySet::from_json(&payload)
+        }
+    }
+
+    pub const AXUM_PATH: &str = "/keys";
+}
+
 pub mod query {
     use std::fmt::{Display, Formatter};
 
@@ -81,7 +192,9 @@ pub mod query {
 
     use crate::{
         ff::FieldType,
-        helpers::query::{QueryConfig, QuerySize, QueryType},
+        helpers::query::{
+            DuplicateReportPolicy, InvalidReportPolicy, QueryConfig, QuerySize, QueryType,
+        },
         net::Error,
     };
 
@@ -149,7 +262,7 @@ pub mod query {
                 f = self.field_type,
                 size = self.size
             )?;
-            match self.query_type {
+            match &self.query_type {
                 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
                 QueryType::TestMultiply | QueryType::TestAddInPrimeField => Ok(()),
                 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
@@ -157,11 +270,8 @@ pub mod query {
                 QueryType::SemiHonestOprfIpa(config) | QueryType::MaliciousOprfIpa(config) => {
                     write!(
                         f,
-                        "&per_user_credit_cap={}&max_breakdown_key={}&with_dp={}&epsilon={}",
-                        config.per_user_credit_cap,
-                        config.max_breakdown_key,
-                        config.with_dp,
-                        config.epsilon,
+                        "&per_user_credit_cap={}&max_breakdown_key={}&{}",
+                        config.per_user_credit_cap, config.max_breakdown_key, config.dp,
                     )?;
 
                     if config.plaintext_match_keys {
@@ -172,16 +282,33 @@ pub mod query {
                         write!(f, "&attribution_window_seconds={}", window.get())?;
                     }
 
+                    if let Some(epochs) = config.epochs {
+                        write!(f, "&epochs={epochs}")?;
+                    }
+
+                    if let Some(site_domain) = &config.site_domain {
+                        write!(f, "&site_domain={site_domain}")?;
+                    }
+
+                    if config.invalid_reports != InvalidReportPolicy::default() {
+                        write!(f, "&invalid_reports={}", config.invalid_reports)?;
+                    }
+
+                    if config.duplicate_reports != DuplicateReportPolicy::default() {
+                        write!(f, "&duplicate_reports={}", config.duplicate_reports)?;
+                    }
+
+                    if let Some(hierarchy) = config.breakdown_hierarchy {
+                        write!(f, "&breakdown_hierarchy={hierarchy}")?;
+                    }
+
                     Ok(())
                 }
                 QueryType::SemiHonestHybrid(config) => {
                     write!(
                         f,
-                        "&per_user_credit_cap={}&max_breakdown_key={}&with_dp={}&epsilon={}",
-                        config.per_user_credit_cap,
-                        config.max_breakdown_key,
-                        config.with_dp,
-                        config.epsilon,
+                        "&per_user_credit_cap={}&max_breakdown_key={}&{}",
+                        config.per_user_credit_cap, config.max_breakdown_key, config.dp,
                     )?;
 
                     if config.plaintext_match_keys {
@@ -308,6 +435,42 @@ pub mod query {
         pub const AXUM_PATH: &str = "/:query_id";
     }
 
+    pub mod abort {
+        use axum::{body::Body, http::uri};
+
+        use crate::{net::http_serde::query::BASE_AXUM_PATH, protocol::QueryId};
+
+        #[derive(Debug, Clone)]
+        pub struct Request {
+            pub query_id: QueryId,
+        }
+
+        impl Request {
+            pub fn new(query_id: QueryId) -> Self {
+                Self { query_id }
+            }
+
+            pub fn try_into_http_request(
+                self,
+                scheme: uri::Scheme,
+                authority: uri::Authority,
+            ) -> crate::net::http_serde::OutgoingRequest {
+                let uri = uri::Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(format!(
+                        "{}/{}/abort",
+                        BASE_AXUM_PATH,
+                        self.query_id.as_ref(),
+                    ))
+                    .build()?;
+                Ok(hyper::Request::post(uri).body(Body::empty())?)
+            }
+        }
+
+        pub const AXUM_PATH: &str = "/:query_id/abort";
+    }
+
     pub mod input {
         use axum::{body::Body, http::uri};
         use hyper::header::CONTENT_TYPE;
@@ -353,6 +516,7 @@ pub mod query {
 
     pub mod step {
         use axum::{body::Body, http::uri};
+        use serde::{Deserialize, Serialize};
 
         use crate::{
             net::{http_serde::query::BASE_AXUM_PATH, Error},
@@ -365,19 +529,30 @@ pub mod query {
         pub struct Request<B> {
             pub query_id: QueryId,
             pub gate: Gate,
+            /// Offset of the first byte of `body` within the records stream. It is non-zero
+            /// when the sender reconnects after losing the connection.
+            pub offset: u64,
             pub body: B,
         }
 
         impl<B> Request<B> {
-            pub fn new(query_id: QueryId, gate: Gate, body: B) -> Self {
+            pub fn new(query_id: QueryId, gate: Gate, offset: u64, body: B) -> Self {
                 Self {
                     query_id,
                     gate,
+                    offset,
                     body,
                 }
             }
         }
 
+        #[derive(Debug, Deserialize)]
+        pub struct QueryParams {
+            /// Requests sent by older helpers do not carry the offset.
+            #[serde(default)]
+            pub offset: u64,
+        }
+
         /// Convert to hyper request. Used on client side.
         impl Request<Body> {
             pub fn try_into_http_request(
@@ -389,16 +564,56 @@ pub mod query {
                     .scheme(scheme)
                     .authority(authority)
                     .path_and_query(format!(
-                        "{}/{}/step/{}",
+                        "{}/{}/step/{}?offset={}",
                         BASE_AXUM_PATH,
                         self.query_id.as_ref(),
-                        self.gate.as_ref()
+                        self.gate.as_ref(),
+                        self.offset,
                     ))
                     .build()?;
                 Ok(hyper::Request::post(uri).body(self.body)?)
             }
         }
 
+        /// Asks the receiving helper how many bytes of the records stream it has acknowledged,
+        /// to resume the stream from there after losing the connection.
+        #[derive(Debug)]
+        pub struct OffsetRequest {
+            pub query_id: QueryId,
+            pub gate: Gate,
+        }
+
+        impl OffsetRequest {
+            pub fn new(query_id: QueryId, gate: Gate) -> Self {
+                Self { query_id, gate }
+            }
+
+            pub fn try_into_http_request(
+                self,
+                scheme: uri::Scheme,
+                authority: uri::Authority,
+            ) -> Result<hyper::Request<Body>, Error> {
+                let uri = uri::Uri::builder()
+                    .scheme(scheme)
+                    .authority(authority)
+                    .path_and_query(format!(
+                        "{}/{}/step/{}",
+                        BASE_AXUM_PATH,
+                        self.query_id.as_ref(),
+                        self.gate.as_ref(),
+                    ))
+                    .build()?;
+                Ok(hyper::Request::get(uri).body(Body::empty())?)
+            }
+        }
+
+        #[derive(Debug, Serialize, Deserialize)]
+        pub struct OffsetResponseBody {
+            /// Number of bytes of the records stream the receiving helper has acknowledged.
+            /// It is zero if the stream has not been received yet.
+            pub offset: u64,
+        }
+
         pub const AXUM_PATH: &str = "/:query_id/step/*step";
     }
 
@@ -532,5 +747,9 @@ pub mod query {
         }
 
         pub const AXUM_PATH: &str = "/:query_id/complete";
+
+        /// Response header with JSON-encoded [`crate::query::QueryMetadata`].
+        pub static METADATA_HEADER: hyper::header::HeaderName =
+            hyper::header::HeaderName::from_static("x-query-metadata");
     }
 }
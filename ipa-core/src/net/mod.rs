@@ -10,9 +10,11 @@ use rustls_pki_types::CertificateDer;
 use crate::config::{OwnedCertificate, OwnedPrivateKey};
 
 mod client;
+mod compression;
 mod error;
 mod http_serde;
 mod server;
+mod signing;
 #[cfg(all(test, not(feature = "shuttle")))]
 pub mod test;
 mod transport;
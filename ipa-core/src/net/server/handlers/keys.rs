@@ -0,0 +1,107 @@
+use axum::{routing::get, Extension, Json, Router};
+use hyper::StatusCode;
+
+use crate::{
+    helpers::{BodyStream, Transport},
+    net::{
+        http_serde::keys::{self, Request, SignedPublicKeySet},
+        server::Error,
+        signing::DocumentSigner,
+        HttpTransport,
+    },
+    sync::Arc,
+};
+
+async fn handler(
+    transport: Extension<Arc<HttpTransport>>,
+    Extension(signer): Extension<Option<Arc<DocumentSigner>>>,
+) -> Result<Json<SignedPublicKeySet>, Error> {
+    let transport = Transport::clone_ref(&*transport);
+    let payload = transport
+        .dispatch(Request, BodyStream::empty())
+        .await
+        .map_err(|e| Error::application(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_body();
+    SignedPublicKeySet::sign(&payload, signer.as_deref())
+        .map(Json)
+        .map_err(|e| Error::application(StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+pub fn router(transport: Arc<HttpTransport>, signer: Option<Arc<DocumentSigner>>) -> Router {
+    Router::new()
+        .route(keys::AXUM_PATH, get(handler))
+        .layer(Extension(transport))
+        .layer(Extension(signer))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::SystemTime;
+
+    use axum::http::uri::{Authority, Scheme};
+    use http_body_util::BodyExt;
+    use hyper::StatusCode;
+    use rand::thread_rng;
+
+    use crate::{
+        helpers::{
+            make_owned_handler,
+            routing::{Addr, RouteId},
+            BodyStream, HelperIdentity, HelperResponse,
+        },
+        hpke::{CipherSuite, IpaPrivateKey, KeyRegistry, PrivateKeyOnly, PublicKeySet},
+        net::{
+            http_serde::keys::{Request, SignedPublicKeySet},
+            test::{TestServer, TEST_CERTS_DER},
+        },
+    };
+
+    fn public_key_set() -> PublicKeySet {
+        let (sk, _) = IpaPrivateKey::generate(CipherSuite::X25519, &mut thread_rng());
+        PublicKeySet::from_registry(
+            &KeyRegistry::from_keys([PrivateKeyOnly(sk)]),
+            SystemTime::now(),
+        )
+    }
+
+    #[tokio::test]
+    async fn signed_public_keys() {
+        let expected = public_key_set();
+        let handler = make_owned_handler({
+            let expected = expected.clone();
+            move |addr: Addr<HelperIdentity>, _data: BodyStream| {
+                let expected = expected.clone();
+                async move {
+                    let RouteId::PublicKeys = addr.route else {
+                        panic!("unexpected call");
+                    };
+                    Ok(HelperResponse::from(expected))
+                }
+            }
+        });
+        let test_server = TestServer::builder()
+            .with_request_handler(handler)
+            .build()
+            .await;
+
+        let req = Request
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap();
+        let resp = test_server.server.handle_req(req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let signed: SignedPublicKeySet = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(expected, signed.verify(&TEST_CERTS_DER[0]).unwrap());
+        let err = signed.verify(&TEST_CERTS_DER[1]).unwrap_err();
+        assert!(err.to_string().contains("verification failed"), "{err}");
+    }
+
+    #[test]
+    fn unsigned_public_keys() {
+        let payload = serde_json::to_vec(&public_key_set()).unwrap();
+        let unsigned = SignedPublicKeySet::sign(&payload, None).unwrap();
+        let err = unsigned.verify(&TEST_CERTS_DER[0]).unwrap_err();
+        assert!(err.to_string().contains("not signed"), "{err}");
+    }
+}
@@ -1,18 +1,21 @@
 mod echo;
+mod keys;
 mod query;
 
 use axum::Router;
 
 use crate::{
-    net::{http_serde, HttpTransport},
+    net::{http_serde, signing::DocumentSigner, HttpTransport},
     sync::Arc,
 };
 
-pub fn router(transport: Arc<HttpTransport>) -> Router {
-    echo::router().nest(
-        http_serde::query::BASE_AXUM_PATH,
-        Router::new()
-            .merge(query::query_router(Arc::clone(&transport)))
-            .merge(query::h2h_router(transport)),
-    )
+pub fn router(transport: Arc<HttpTransport>, signer: Option<Arc<DocumentSigner>>) -> Router {
+    echo::router()
+        .merge(keys::router(Arc::clone(&transport), signer))
+        .nest(
+            http_serde::query::BASE_AXUM_PATH,
+            Router::new()
+                .merge(query::query_router(Arc::clone(&transport)))
+                .merge(query::h2h_router(transport)),
+        )
 }
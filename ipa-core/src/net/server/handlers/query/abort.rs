@@ -0,0 +1,79 @@
+use axum::{extract::Path, routing::post, Extension, Router};
+use hyper::StatusCode;
+
+use crate::{
+    helpers::{routing::RouteId, BodyStream, Transport},
+    net::{http_serde, server::ClientIdentity, Error, HttpTransport},
+    protocol::QueryId,
+    sync::Arc,
+};
+
+/// Called by the peer helper that coordinates a query, to abort it on this helper after the other
+/// follower rejected it.
+async fn handler(
+    transport: Extension<Arc<HttpTransport>>,
+    _: Extension<ClientIdentity>, // require that client is an authenticated helper
+    Path(query_id): Path<QueryId>,
+) -> Result<(), Error> {
+    let transport = Transport::clone_ref(&*transport);
+    let _ = transport
+        .dispatch((RouteId::AbortQuery, query_id), BodyStream::empty())
+        .await
+        .map_err(|e| Error::application(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(())
+}
+
+pub fn router(transport: Arc<HttpTransport>) -> Router {
+    Router::new()
+        .route(http_serde::query::abort::AXUM_PATH, post(handler))
+        .layer(Extension(transport))
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use axum::body::Body;
+    use hyper::StatusCode;
+
+    use crate::{
+        helpers::{make_owned_handler, routing::RouteId, HelperIdentity, HelperResponse},
+        net::{
+            http_serde,
+            server::{
+                handlers::query::test_helpers::{
+                    assert_fails_with, assert_success_with, MaybeExtensionExt,
+                },
+                ClientIdentity,
+            },
+        },
+        protocol::QueryId,
+    };
+
+    fn request(client_id: Option<ClientIdentity>) -> hyper::Request<Body> {
+        hyper::Request::post(format!(
+            "http://localhost{}/{}/abort",
+            http_serde::query::BASE_AXUM_PATH,
+            QueryId.as_ref(),
+        ))
+        .maybe_extension(client_id)
+        .body(Body::empty())
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn abort_test() {
+        let handler = make_owned_handler(move |addr, _| async move {
+            let RouteId::AbortQuery = addr.route else {
+                panic!("unexpected call");
+            };
+            assert_eq!(Some(QueryId), addr.query_id);
+            Ok(HelperResponse::ok())
+        });
+        assert_success_with(request(Some(ClientIdentity(HelperIdentity::TWO))), handler).await;
+    }
+
+    #[tokio::test]
+    async fn auth_required() {
+        assert_fails_with(request(None), StatusCode::UNAUTHORIZED).await;
+    }
+}
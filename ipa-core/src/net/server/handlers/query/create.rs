@@ -23,6 +23,12 @@ async fn handler(
         Err(err @ ApiError::NewQuery(NewQueryError::State { .. })) => {
             Err(Error::application(StatusCode::CONFLICT, err))
         }
+        Err(err @ ApiError::NewQuery(NewQueryError::Budget { .. })) => {
+            Err(Error::application(StatusCode::FORBIDDEN, err))
+        }
+        Err(err @ ApiError::NewQuery(NewQueryError::InvalidDp(_))) => {
+            Err(Error::application(StatusCode::BAD_REQUEST, err))
+        }
         Err(err) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, err)),
     }
 }
@@ -35,7 +41,7 @@ pub fn router(transport: Arc<HttpTransport>) -> Router {
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::num::NonZeroU32;
+    use std::{fmt::Write, num::NonZeroU32};
 
     use axum::body::Body;
     use hyper::{
@@ -47,7 +53,10 @@ mod tests {
         ff::FieldType,
         helpers::{
             make_owned_handler,
-            query::{IpaQueryConfig, PrepareQuery, QueryConfig, QueryType},
+            query::{
+                BreakdownHierarchy, DpConfig, DuplicateReportPolicy, InvalidReportPolicy,
+                IpaQueryConfig, PrepareQuery, QueryConfig, QueryType,
+            },
             routing::RouteId,
             HelperResponse, Role, RoleAssignment,
         },
@@ -56,24 +65,28 @@ mod tests {
             server::handlers::query::test_helpers::{assert_fails_with, assert_success_with},
         },
         protocol::QueryId,
+        report::EpochRange,
     };
 
     async fn create_test(expected_query_config: QueryConfig) {
-        let req = http_serde::query::create::Request::new(expected_query_config)
+        let req = http_serde::query::create::Request::new(expected_query_config.clone())
             .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
             .unwrap();
-        let handler = make_owned_handler(move |addr, _| async move {
-            let RouteId::ReceiveQuery = addr.route else {
-                panic!("unexpected call");
-            };
-
-            let query_config = addr.into().unwrap();
-            assert_eq!(query_config, expected_query_config);
-            Ok(HelperResponse::from(PrepareQuery {
-                query_id: QueryId,
-                config: query_config,
-                roles: RoleAssignment::try_from([Role::H1, Role::H2, Role::H3]).unwrap(),
-            }))
+        let handler = make_owned_handler(move |addr, _| {
+            let expected_query_config = expected_query_config.clone();
+            async move {
+                let RouteId::ReceiveQuery = addr.route else {
+                    panic!("unexpected call");
+                };
+
+                let query_config = addr.into().unwrap();
+                assert_eq!(query_config, expected_query_config);
+                Ok(HelperResponse::from(PrepareQuery {
+                    query_id: QueryId,
+                    config: query_config,
+                    roles: RoleAssignment::try_from([Role::H1, Role::H2, Role::H3]).unwrap(),
+                }))
+            }
         });
         let resp = assert_success_with(req, handler).await;
         let http_serde::query::create::ResponseBody { query_id } =
@@ -94,9 +107,13 @@ mod tests {
                     per_user_credit_cap: 1,
                     max_breakdown_key: 1,
                     attribution_window_seconds: None,
-                    with_dp: 0,
-                    epsilon: 5.0,
+                    dp: DpConfig::no_noise(),
                     plaintext_match_keys: true,
+                    epochs: None,
+                    site_domain: None,
+                    invalid_reports: InvalidReportPolicy::Fail,
+                    duplicate_reports: DuplicateReportPolicy::Fail,
+                    breakdown_hierarchy: None,
                 }),
                 FieldType::Fp32BitPrime,
                 1,
@@ -114,9 +131,13 @@ mod tests {
                     per_user_credit_cap: 8,
                     max_breakdown_key: 20,
                     attribution_window_seconds: None,
-                    with_dp: 1,
-                    epsilon: 5.0,
+                    dp: DpConfig::discrete_laplace(5.0),
                     plaintext_match_keys: true,
+                    epochs: None,
+                    site_domain: None,
+                    invalid_reports: InvalidReportPolicy::Fail,
+                    duplicate_reports: DuplicateReportPolicy::Fail,
+                    breakdown_hierarchy: None,
                 }),
                 FieldType::Fp32BitPrime,
                 1,
@@ -134,9 +155,13 @@ mod tests {
                     per_user_credit_cap: 8,
                     max_breakdown_key: 20,
                     attribution_window_seconds: None,
-                    with_dp: 1,
-                    epsilon: 5.0,
+                    dp: DpConfig::discrete_laplace(5.0),
                     plaintext_match_keys: true,
+                    epochs: None,
+                    site_domain: None,
+                    invalid_reports: InvalidReportPolicy::Fail,
+                    duplicate_reports: DuplicateReportPolicy::Fail,
+                    breakdown_hierarchy: None,
                 }),
                 FieldType::Fp32BitPrime,
                 1,
@@ -155,14 +180,86 @@ mod tests {
                 per_user_credit_cap: 1,
                 max_breakdown_key: 1,
                 attribution_window_seconds: NonZeroU32::new(86_400),
-                with_dp: 0,
-                epsilon: 5.0,
+                dp: DpConfig::no_noise(),
                 plaintext_match_keys: true,
+                epochs: None,
+                site_domain: None,
+                invalid_reports: InvalidReportPolicy::Fail,
+                duplicate_reports: DuplicateReportPolicy::Fail,
+                breakdown_hierarchy: None,
             }),
         })
         .await;
     }
 
+    #[tokio::test]
+    async fn create_test_ipa_with_epochs_and_site_domain() {
+        create_test(
+            QueryConfig::new(
+                QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+                    epochs: Some(EpochRange::new(3, 5).unwrap()),
+                    site_domain: Some("example.com".to_string()),
+                    ..Default::default()
+                }),
+                FieldType::Fp32BitPrime,
+                1,
+            )
+            .unwrap(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn create_test_ipa_with_dp_threshold() {
+        create_test(
+            QueryConfig::new(
+                QueryType::MaliciousOprfIpa(IpaQueryConfig {
+                    dp: DpConfig {
+                        threshold: NonZeroU32::new(100),
+                        ..DpConfig::default()
+                    },
+                    ..Default::default()
+                }),
+                FieldType::Fp32BitPrime,
+                1,
+            )
+            .unwrap(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn create_test_ipa_drop_invalid_reports() {
+        create_test(
+            QueryConfig::new(
+                QueryType::MaliciousOprfIpa(IpaQueryConfig {
+                    invalid_reports: InvalidReportPolicy::Drop,
+                    ..Default::default()
+                }),
+                FieldType::Fp32BitPrime,
+                1,
+            )
+            .unwrap(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn create_test_ipa_with_breakdown_hierarchy() {
+        create_test(
+            QueryConfig::new(
+                QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+                    breakdown_hierarchy: Some("2,3,3".parse::<BreakdownHierarchy>().unwrap()),
+                    ..Default::default()
+                }),
+                FieldType::Fp32BitPrime,
+                1,
+            )
+            .unwrap(),
+        )
+        .await;
+    }
+
     struct OverrideReq {
         field_type: String,
         query_type_params: String,
@@ -228,23 +325,27 @@ mod tests {
         per_user_credit_cap: String,
         max_breakdown_key: String,
         attribution_window_seconds: Option<String>,
-        with_dp: String,
-        epsilon: String,
+        dp_mechanism: String,
+        dp_epsilon: String,
+        epochs: Option<String>,
     }
 
     impl From<OverrideIPAReq> for hyper::Request<Body> {
         fn from(val: OverrideIPAReq) -> Self {
             let mut query = format!(
-                "query_type={}&per_user_credit_cap={}&max_breakdown_key={}&with_dp={}&epsilon={}",
+                "query_type={}&per_user_credit_cap={}&max_breakdown_key={}&dp_mechanism={}&dp_epsilon={}",
                 val.query_type,
                 val.per_user_credit_cap,
                 val.max_breakdown_key,
-                val.with_dp,
-                val.epsilon,
+                val.dp_mechanism,
+                val.dp_epsilon,
             );
 
             if let Some(window) = val.attribution_window_seconds {
-                query.push_str(&format!("&attribution_window_seconds={window}"));
+                write!(query, "&attribution_window_seconds={window}").unwrap();
+            }
+            if let Some(epochs) = val.epochs {
+                write!(query, "&epochs={epochs}").unwrap();
             }
             OverrideReq {
                 field_type: val.field_type,
@@ -262,8 +363,9 @@ mod tests {
                 per_user_credit_cap: "1".into(),
                 max_breakdown_key: "1".into(),
                 attribution_window_seconds: None,
-                with_dp: "1".into(),
-                epsilon: "3.0".into(),
+                dp_mechanism: "discrete-laplace".into(),
+                dp_epsilon: "3.0".into(),
+                epochs: None,
             }
         }
     }
@@ -312,4 +414,31 @@ mod tests {
         };
         assert_fails_with(req.into(), StatusCode::UNPROCESSABLE_ENTITY).await;
     }
+
+    #[tokio::test]
+    async fn malformed_dp_mechanism_ipa() {
+        let req = OverrideIPAReq {
+            dp_mechanism: "gaussian".into(),
+            ..Default::default()
+        };
+        assert_fails_with(req.into(), StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    #[tokio::test]
+    async fn malformed_dp_epsilon_ipa() {
+        let req = OverrideIPAReq {
+            dp_epsilon: "large".into(),
+            ..Default::default()
+        };
+        assert_fails_with(req.into(), StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    #[tokio::test]
+    async fn malformed_epochs_ipa() {
+        let req = OverrideIPAReq {
+            epochs: Some("5-3".to_string()),
+            ..Default::default()
+        };
+        assert_fails_with(req.into(), StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
 }
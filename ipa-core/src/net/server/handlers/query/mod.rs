@@ -1,3 +1,4 @@
+mod abort;
 mod create;
 mod input;
 mod prepare;
@@ -44,6 +45,7 @@ pub fn query_router(transport: Arc<HttpTransport>) -> Router {
 pub fn h2h_router(transport: Arc<HttpTransport>) -> Router {
     Router::new()
         .merge(prepare::router(Arc::clone(&transport)))
+        .merge(abort::router(Arc::clone(&transport)))
         .merge(step::router(transport))
         .layer(layer_fn(HelperAuthentication::new))
 }
@@ -1,10 +1,16 @@
 use axum::{extract::Path, routing::get, Extension, Router};
-use hyper::StatusCode;
+use hyper::{
+    header::{HeaderMap, HeaderValue},
+    StatusCode,
+};
 
 use crate::{
     helpers::{BodyStream, Transport},
     net::{
-        http_serde::{self, query::results::Request},
+        http_serde::{
+            self,
+            query::results::{Request, METADATA_HEADER},
+        },
         server::Error,
         HttpTransport,
     },
@@ -13,15 +19,27 @@ use crate::{
 };
 
 /// Handles the completion of the query by blocking the sender until query is completed.
+/// Query metadata is returned in the [`METADATA_HEADER`] header.
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
     Path(query_id): Path<QueryId>,
-) -> Result<Vec<u8>, Error> {
+) -> Result<(HeaderMap, Vec<u8>), Error> {
     let req = Request { query_id };
     // TODO: we may be able to stream the response
     let transport = Transport::clone_ref(&*transport);
     match transport.dispatch(req, BodyStream::empty()).await {
-        Ok(resp) => Ok(resp.into_body()),
+        Ok(resp) => {
+            let mut headers = HeaderMap::new();
+            if let Some(metadata) = resp.metadata() {
+                // serialized metadata is plain ASCII, so it is always a valid header value
+                let value = serde_json::to_string(&metadata).unwrap();
+                headers.insert(
+                    METADATA_HEADER.clone(),
+                    HeaderValue::try_from(value).unwrap(),
+                );
+            }
+            Ok((headers, resp.into_body()))
+        }
         Err(e) => Err(Error::application(StatusCode::INTERNAL_SERVER_ERROR, e)),
     }
 }
@@ -45,15 +63,21 @@ mod tests {
         ff::Fp31,
         helpers::{
             make_owned_handler,
+            query::NoiseMechanism,
             routing::{Addr, RouteId},
             BodyStream, HelperIdentity, HelperResponse,
         },
         net::{
             http_serde,
             server::handlers::query::test_helpers::{assert_fails_with, assert_success_with},
+            test::TestServer,
         },
-        protocol::QueryId,
-        query::ProtocolResult,
+        protocol::{
+            dp::{accountant::PrivacyLoss, OutputNoise},
+            ipa_prf::oprf_padding::{PaddingNoise, PaddingSampling},
+            QueryId,
+        },
+        query::{NoiseMetadata, ProtocolResult, QueryMetadata, WithMetadata},
         secret_sharing::replicated::semi_honest::AdditiveShare as Replicated,
     };
 
@@ -84,6 +108,58 @@ mod tests {
         assert_eq!(resp_body, expected_results.to_bytes());
     }
 
+    #[tokio::test]
+    async fn results_metadata() {
+        // JSON does not round trip every float exactly, so this uses ones that it does
+        let metadata = QueryMetadata {
+            dropped_reports: 3,
+            duplicate_reports: 1,
+            noise: Some(NoiseMetadata {
+                output: OutputNoise {
+                    mechanism: NoiseMechanism::DiscreteLaplace,
+                    epsilon: 1.0,
+                    delta: 0.5,
+                    mean: 0.0,
+                    std: 2.25,
+                },
+                oprf_padding: None,
+                aggregation_padding: Some(PaddingNoise {
+                    epsilon: 10.0,
+                    delta: 0.25,
+                    sensitivity: 3,
+                    sampling: PaddingSampling::Joint,
+                    mean: 3.0,
+                    std: 0.125,
+                }),
+                total: PrivacyLoss::new(11.0, 0.75),
+            }),
+        };
+        let req_handler = make_owned_handler(move |_addr, _| async move {
+            let results = Box::new(WithMetadata {
+                result: vec![Replicated::<Fp31>::ZERO],
+                metadata,
+            }) as Box<dyn ProtocolResult>;
+            Ok(HelperResponse::from(results))
+        });
+        let test_server = TestServer::builder()
+            .with_request_handler(req_handler)
+            .build()
+            .await;
+        let req = http_serde::query::results::Request::new(QueryId)
+            .try_into_http_request(Scheme::HTTP, Authority::from_static("localhost"))
+            .unwrap();
+        let resp = test_server.server.handle_req(req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        let header = resp
+            .headers()
+            .get(&http_serde::query::results::METADATA_HEADER)
+            .unwrap();
+        assert_eq!(
+            metadata,
+            serde_json::from_slice::<QueryMetadata>(header.as_bytes()).unwrap()
+        );
+    }
+
     struct OverrideReq {
         query_id: String,
     }
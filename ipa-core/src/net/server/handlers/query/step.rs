@@ -1,8 +1,19 @@
-use axum::{extract::Path, routing::post, Extension, Router};
+use axum::{
+    extract::{Path, Query},
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING},
+        HeaderMap, StatusCode,
+    },
+    response::IntoResponse,
+    routing::post,
+    Extension, Json, Router,
+};
 
 use crate::{
+    config::StreamCompression,
     helpers::{BodyStream, Transport},
     net::{
+        compression::Decompress,
         http_serde,
         server::{ClientIdentity, Error},
         HttpTransport,
@@ -12,21 +23,76 @@ use crate::{
 };
 
 #[allow(clippy::unused_async)] // axum doesn't like synchronous handler
-#[tracing::instrument(level = "trace", "step", skip_all, fields(from = ?**from, gate = ?gate))]
+#[tracing::instrument(level = "trace", "step", skip_all, fields(from = ?**from, gate = ?gate, offset = params.offset))]
 async fn handler(
     transport: Extension<Arc<HttpTransport>>,
     from: Extension<ClientIdentity>,
     Path((query_id, gate)): Path<(QueryId, Gate)>,
+    Query(params): Query<http_serde::query::step::QueryParams>,
+    headers: HeaderMap,
     body: BodyStream,
-) -> Result<(), Error> {
-    let transport = Transport::clone_ref(&*transport);
-    transport.receive_stream(query_id, gate, **from, body);
-    Ok(())
+) -> impl IntoResponse {
+    let accepted = transport.stream_compression();
+    // Let the sender know which compression it can use for the subsequent streams (RFC 7694)
+    let accept_encoding = [(ACCEPT_ENCODING, accepted.encoding().unwrap_or("identity"))];
+
+    let result = content_codec(accepted, &headers).map(|codec| {
+        let body = if codec == StreamCompression::None {
+            body
+        } else {
+            BodyStream::from_bytes_stream(Decompress::new(codec, body))
+        };
+        let transport = Transport::clone_ref(&*transport);
+        transport.receive_stream(query_id, gate, **from, params.offset, body);
+    });
+
+    (accept_encoding, result)
+}
+
+/// Tells the sender how much of the records stream has been acknowledged, so it can resume
+/// the stream from there after losing the connection.
+#[allow(clippy::unused_async)] // axum doesn't like synchronous handler
+async fn offset_handler(
+    transport: Extension<Arc<HttpTransport>>,
+    from: Extension<ClientIdentity>,
+    Path((query_id, gate)): Path<(QueryId, Gate)>,
+) -> Json<http_serde::query::step::OffsetResponseBody> {
+    Json(http_serde::query::step::OffsetResponseBody {
+        offset: transport.stream_offset(query_id, gate, **from),
+    })
+}
+
+/// Determines the compression used for the request body, rejecting codecs this helper does
+/// not accept.
+fn content_codec(
+    accepted: StreamCompression,
+    headers: &HeaderMap,
+) -> Result<StreamCompression, Error> {
+    let Some(content_encoding) = headers.get(CONTENT_ENCODING) else {
+        return Ok(StreamCompression::None);
+    };
+    let content_encoding = content_encoding.to_str()?.trim();
+    if content_encoding.eq_ignore_ascii_case("identity") {
+        Ok(StreamCompression::None)
+    } else if accepted
+        .encoding()
+        .is_some_and(|encoding| content_encoding.eq_ignore_ascii_case(encoding))
+    {
+        Ok(accepted)
+    } else {
+        Err(Error::application(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("records stream compression {content_encoding:?} is not supported"),
+        ))
+    }
 }
 
 pub fn router(transport: Arc<HttpTransport>) -> Router {
     Router::new()
-        .route(http_serde::query::step::AXUM_PATH, post(handler))
+        .route(
+            http_serde::query::step::AXUM_PATH,
+            post(handler).get(offset_handler),
+        )
         .layer(Extension(transport))
 }
 
@@ -35,14 +101,20 @@ mod tests {
     use std::task::Poll;
 
     use axum::body::Body;
-    use futures::{stream::poll_immediate, StreamExt};
-    use hyper::StatusCode;
+    use bytes::Bytes;
+    use futures::{
+        stream::{self, poll_immediate},
+        StreamExt,
+    };
+    use hyper::http::HeaderValue;
     use ipa_step::StepNarrow;
 
     use super::*;
     use crate::{
+        error::BoxError,
         helpers::{HelperIdentity, MESSAGE_PAYLOAD_SIZE_BYTES},
         net::{
+            compression::compress,
             server::handlers::query::test_helpers::{assert_fails_with, MaybeExtensionExt},
             test::TestServer,
         },
@@ -75,6 +147,110 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn resume_after_connection_loss() {
+        fn request(offset: u64, body: Body) -> hyper::Request<Body> {
+            let uri = format!(
+                "http://localhost{}/{}/step/{}?offset={offset}",
+                http_serde::query::BASE_AXUM_PATH,
+                QueryId.as_ref(),
+                Gate::default().narrow("test").as_ref()
+            );
+            hyper::Request::post(uri)
+                .maybe_extension(Some(ClientIdentity(HelperIdentity::TWO)))
+                .body(body)
+                .unwrap()
+        }
+
+        let payload =
+            (0..u8::try_from(DATA_LEN * MESSAGE_PAYLOAD_SIZE_BYTES).unwrap()).collect::<Vec<_>>();
+        let (first, second) = payload.split_at(payload.len() / 2);
+        let test_server = TestServer::builder().build().await;
+
+        // first connection delivers half of the payload and fails
+        Arc::clone(&test_server.transport).receive_stream(
+            QueryId,
+            Gate::default().narrow("test"),
+            HelperIdentity::TWO,
+            0,
+            BodyStream::from_bytes_stream(stream::iter(vec![
+                Ok(Bytes::copy_from_slice(first)),
+                Err(BoxError::from("connection reset")),
+            ])),
+        );
+
+        let mut stream = Arc::clone(&test_server.transport)
+            .receive(
+                HelperIdentity::TWO,
+                (QueryId, Gate::default().narrow("test")),
+            )
+            .into_bytes_stream();
+        assert_eq!(stream.next().await, Some(first.to_vec()));
+
+        // sender reconnects through the step endpoint and retransmits a few bytes
+        // the receiver already has
+        let offset = first.len() - 2;
+        test_server
+            .server
+            .handle_req(request(
+                u64::try_from(offset).unwrap(),
+                Body::from(payload[offset..].to_vec()),
+            ))
+            .await;
+
+        assert_eq!(stream.next().await, Some(second.to_vec()));
+        assert_eq!(stream.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn compressed() {
+        let payload = vec![0; DATA_LEN * MESSAGE_PAYLOAD_SIZE_BYTES];
+        let body = compress(
+            StreamCompression::Deflate,
+            String::new(),
+            stream::iter([Bytes::from(payload.clone())]),
+        )
+        .map(Vec::from)
+        .concat()
+        .await;
+        let mut req: hyper::Request<Body> = OverrideReq {
+            payload: body.clone(),
+            client_id: Some(ClientIdentity(HelperIdentity::TWO)),
+            ..Default::default()
+        }
+        .into();
+        req.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("deflate"));
+        assert!(body.len() < payload.len());
+
+        let test_server = TestServer::builder()
+            .with_stream_compression(StreamCompression::Deflate)
+            .build()
+            .await;
+        let resp = test_server.server.handle_req(req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!("deflate", resp.headers()[ACCEPT_ENCODING]);
+
+        let stream = Arc::clone(&test_server.transport)
+            .receive(
+                HelperIdentity::TWO,
+                (QueryId, Gate::default().narrow("test")),
+            )
+            .into_bytes_stream();
+        assert_eq!(payload, stream.concat().await);
+    }
+
+    #[tokio::test]
+    async fn unsupported_compression() {
+        let mut req: hyper::Request<Body> = OverrideReq::default().into();
+        req.headers_mut()
+            .insert(CONTENT_ENCODING, HeaderValue::from_static("deflate"));
+        let test_server = TestServer::builder().build().await;
+        let resp = test_server.server.handle_req(req).await;
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, resp.status());
+        assert_eq!("identity", resp.headers()[ACCEPT_ENCODING]);
+    }
+
     struct OverrideReq {
         client_id: Option<ClientIdentity>,
         query_id: String,
@@ -45,9 +45,10 @@ use crate::{
     config::{NetworkConfig, OwnedCertificate, OwnedPrivateKey, ServerConfig, TlsConfig},
     error::BoxError,
     helpers::HelperIdentity,
+    key_encryption::read_key_file,
     net::{
-        parse_certificate_and_private_key_bytes, server::config::HttpServerConfig, Error,
-        HttpTransport, CRYPTO_PROVIDER,
+        parse_certificate_and_private_key_bytes, server::config::HttpServerConfig,
+        signing::DocumentSigner, Error, HttpTransport, CRYPTO_PROVIDER,
     },
     sync::Arc,
     task::JoinHandle,
@@ -96,14 +97,28 @@ impl MpcHelperServer {
         }
     }
 
-    fn router(&self) -> Router {
-        handlers::router(Arc::clone(&self.transport))
+    /// ## Panics
+    /// If HTTPS is enabled and the TLS private key cannot be loaded.
+    async fn router(&self) -> Router {
+        // Documents this helper publishes are signed with its TLS key, so they can be verified
+        // against the certificate listed in the network configuration.
+        let signer = if self.config.disable_https {
+            None
+        } else {
+            let (_, key) = certificate_and_key(&self.config)
+                .await
+                .expect("invalid TLS configuration");
+            Some(Arc::new(
+                DocumentSigner::new(key).expect("TLS private key cannot be used for signing"),
+            ))
+        };
+        handlers::router(Arc::clone(&self.transport), signer)
     }
 
     #[cfg(all(test, unit_test))]
     async fn handle_req(&self, req: hyper::Request<axum::body::Body>) -> axum::response::Response {
         use tower::ServiceExt;
-        self.router().oneshot(req).await.unwrap()
+        self.router().await.oneshot(req).await.unwrap()
     }
 
     /// Starts the MPC helper service.
@@ -132,7 +147,7 @@ impl MpcHelperServer {
         #[cfg(not(test))]
         const BIND_ADDRESS: Ipv4Addr = Ipv4Addr::UNSPECIFIED;
 
-        let svc = self.router().layer(
+        let svc = self.router().await.layer(
             TraceLayer::new_for_http()
                 .make_span_with(move |_request: &hyper::Request<_>| tracing.make_span())
                 .on_request(|request: &hyper::Request<_>, _: &Span| {
@@ -259,6 +274,7 @@ async fn certificate_and_key(
             (Cow::Owned(cert), Cow::Owned(key))
         }
     };
+    let key = read_key_file(&key, config.key_encryption_key.as_ref())?;
     parse_certificate_and_private_key_bytes(&mut cert.as_ref(), &mut key.as_ref())
         .map_err(BoxError::from)
 }
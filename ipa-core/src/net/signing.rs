@@ -0,0 +1,117 @@
+//! Signatures over documents helpers publish, such as their public keys.
+//!
+//! Documents are signed with the helper's TLS private key, so anyone who has the helper's
+//! certificate from the network configuration can verify them, without an additional key
+//! distribution mechanism.
+
+use std::{
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use rustls::{sign::SigningKey, SignatureScheme};
+use webpki::EndEntityCert;
+
+use crate::{
+    config::{OwnedCertificate, OwnedPrivateKey},
+    error::BoxError,
+    net::CRYPTO_PROVIDER,
+};
+
+/// Signs documents with the helper's TLS private key.
+pub struct DocumentSigner {
+    key: Arc<dyn SigningKey>,
+}
+
+impl Debug for DocumentSigner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DocumentSigner({:?})", self.key.algorithm())
+    }
+}
+
+impl DocumentSigner {
+    /// ## Errors
+    /// If the key is not supported by the crypto provider.
+    pub fn new(key: OwnedPrivateKey) -> Result<Self, BoxError> {
+        Ok(Self {
+            key: CRYPTO_PROVIDER.key_provider.load_private_key(key)?,
+        })
+    }
+
+    /// Signs `message`, returning the signature and the scheme that was used to produce it.
+    ///
+    /// ## Errors
+    /// If the key does not support any of the schemes available for verification, or if
+    /// signing fails.
+    pub fn sign(&self, message: &[u8]) -> Result<(SignatureScheme, Vec<u8>), BoxError> {
+        let signer = self
+            .key
+            .choose_scheme(
+                &CRYPTO_PROVIDER
+                    .signature_verification_algorithms
+                    .supported_schemes(),
+            )
+            .ok_or("no signature scheme is available for this key")?;
+        Ok((signer.scheme(), signer.sign(message)?))
+    }
+}
+
+/// Verifies that `signature` over `message` was produced with the private key of `certificate`.
+///
+/// ## Errors
+/// If the signature is not valid, or `scheme` is not supported.
+pub fn verify(
+    certificate: &OwnedCertificate,
+    scheme: SignatureScheme,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<(), BoxError> {
+    let cert = EndEntityCert::try_from(certificate)?;
+    let algorithms = CRYPTO_PROVIDER
+        .signature_verification_algorithms
+        .mapping
+        .iter()
+        .find_map(|(s, algorithms)| (*s == scheme).then_some(*algorithms))
+        .ok_or_else(|| format!("unsupported signature scheme {scheme:?}"))?;
+    if algorithms
+        .iter()
+        .any(|alg| cert.verify_signature(*alg, message, signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err("signature verification failed".into())
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{verify, DocumentSigner};
+    use crate::net::{
+        parse_certificate_and_private_key_bytes,
+        test::{TEST_CERTS, TEST_CERTS_DER, TEST_KEYS},
+    };
+
+    fn signer(i: usize) -> DocumentSigner {
+        let (mut cert, mut key) = (TEST_CERTS[i], TEST_KEYS[i]);
+        let (_, key) = parse_certificate_and_private_key_bytes(&mut cert, &mut key).unwrap();
+        DocumentSigner::new(key).unwrap()
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let (scheme, signature) = signer(0).sign(b"hello").unwrap();
+        verify(&TEST_CERTS_DER[0], scheme, b"hello", &signature).unwrap();
+    }
+
+    #[test]
+    fn wrong_message() {
+        let (scheme, signature) = signer(0).sign(b"hello").unwrap();
+        verify(&TEST_CERTS_DER[0], scheme, b"goodbye", &signature).unwrap_err();
+    }
+
+    #[test]
+    fn wrong_certificate() {
+        let (scheme, signature) = signer(0).sign(b"hello").unwrap();
+        verify(&TEST_CERTS_DER[1], scheme, b"hello", &signature).unwrap_err();
+    }
+}
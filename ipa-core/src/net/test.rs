@@ -19,11 +19,11 @@ use tokio::task::JoinHandle;
 
 use crate::{
     config::{
-        ClientConfig, HpkeClientConfig, HpkeServerConfig, NetworkConfig, PeerConfig, ServerConfig,
-        TlsConfig,
+        BandwidthLimit, ClientConfig, HpkeClientConfig, HpkeServerConfig, NetworkConfig,
+        PeerConfig, ServerConfig, StreamCompression, StreamResumeConfig, TlsConfig,
     },
     helpers::{HandlerBox, HelperIdentity, RequestHandler},
-    hpke::{Deserializable as _, IpaPublicKey},
+    hpke::IpaPublicKey,
     net::{ClientIdentity, HttpTransport, MpcHelperClient, MpcHelperServer},
     sync::Arc,
     test_fixture::metrics::MetricsHandle,
@@ -63,12 +63,18 @@ fn get_dummy_matchkey_encryption_info(matchkey_encryption: bool) -> Option<HpkeS
 }
 
 #[must_use]
-fn server_config_insecure_http(port: u16, matchkey_encryption: bool) -> ServerConfig {
+fn server_config_insecure_http(
+    port: u16,
+    matchkey_encryption: bool,
+    stream_compression: StreamCompression,
+) -> ServerConfig {
     ServerConfig {
         port: Some(port),
         disable_https: true,
         tls: None,
         hpke_config: get_dummy_matchkey_encryption_info(matchkey_encryption),
+        key_encryption_key: None,
+        stream_compression,
     }
 }
 
@@ -77,6 +83,7 @@ pub fn server_config_https(
     id: HelperIdentity,
     port: u16,
     matchkey_encryption: bool,
+    stream_compression: StreamCompression,
 ) -> ServerConfig {
     let (certificate, private_key) = get_test_certificate_and_key(id);
     ServerConfig {
@@ -87,6 +94,8 @@ pub fn server_config_https(
             private_key: String::from_utf8(private_key.to_owned()).unwrap(),
         }),
         hpke_config: get_dummy_matchkey_encryption_info(matchkey_encryption),
+        key_encryption_key: None,
+        stream_compression,
     }
 }
 
@@ -96,6 +105,8 @@ pub struct TestConfigBuilder {
     disable_https: bool,
     use_http1: bool,
     disable_matchkey_encryption: bool,
+    stream_compression: StreamCompression,
+    bandwidth_limit: Option<BandwidthLimit>,
 }
 
 impl TestConfigBuilder {
@@ -106,6 +117,8 @@ impl TestConfigBuilder {
             disable_https: true,
             use_http1: false,
             disable_matchkey_encryption: false,
+            stream_compression: StreamCompression::None,
+            bandwidth_limit: None,
         }
     }
 
@@ -116,6 +129,8 @@ impl TestConfigBuilder {
             disable_https: false,
             use_http1: false,
             disable_matchkey_encryption: false,
+            stream_compression: StreamCompression::None,
+            bandwidth_limit: None,
         }
     }
 
@@ -131,6 +146,19 @@ impl TestConfigBuilder {
         self
     }
 
+    /// Enables record stream compression on both clients and servers.
+    #[must_use]
+    pub fn with_stream_compression(mut self, value: StreamCompression) -> Self {
+        self.stream_compression = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_bandwidth_limit(mut self, value: Option<BandwidthLimit>) -> Self {
+        self.bandwidth_limit = value;
+        self
+    }
+
     #[allow(dead_code)]
     #[must_use]
     // TODO(richaj) Add tests for checking the handling of this. At present the code to decrypt does not exist.
@@ -167,28 +195,43 @@ impl TestConfigBuilder {
                     None
                 } else {
                     Some(HpkeClientConfig::new(
-                        IpaPublicKey::from_bytes(
-                            &hex::decode(TEST_HPKE_PUBLIC_KEY.trim()).unwrap(),
-                        )
-                        .unwrap(),
+                        IpaPublicKey::decode(TEST_HPKE_PUBLIC_KEY).unwrap(),
                     ))
                 },
             })
             .collect::<Vec<_>>()
             .try_into()
             .unwrap();
+        let client = self
+            .use_http1
+            .then(ClientConfig::use_http1)
+            .unwrap_or_default()
+            .with_stream_compression(self.stream_compression);
         let network = NetworkConfig {
             peers,
-            client: self
-                .use_http1
-                .then(ClientConfig::use_http1)
-                .unwrap_or_default(),
+            client: match self.bandwidth_limit {
+                Some(limit) => client.with_bandwidth_limit(limit),
+                None => client,
+            },
+            stream_resume: StreamResumeConfig::default(),
         };
         let servers = if self.disable_https {
-            ports.map(|ports| server_config_insecure_http(ports, !self.disable_matchkey_encryption))
+            ports.map(|ports| {
+                server_config_insecure_http(
+                    ports,
+                    !self.disable_matchkey_encryption,
+                    self.stream_compression,
+                )
+            })
         } else {
-            HelperIdentity::make_three()
-                .map(|id| server_config_https(id, ports[id], !self.disable_matchkey_encryption))
+            HelperIdentity::make_three().map(|id| {
+                server_config_https(
+                    id,
+                    ports[id],
+                    !self.disable_matchkey_encryption,
+                    self.stream_compression,
+                )
+            })
         };
         TestConfig {
             network,
@@ -231,6 +274,8 @@ pub struct TestServerBuilder {
     disable_https: bool,
     use_http1: bool,
     disable_matchkey_encryption: bool,
+    stream_compression: StreamCompression,
+    bandwidth_limit: Option<BandwidthLimit>,
 }
 
 impl TestServerBuilder {
@@ -271,6 +316,18 @@ impl TestServerBuilder {
         self
     }
 
+    #[must_use]
+    pub fn with_stream_compression(mut self, value: StreamCompression) -> Self {
+        self.stream_compression = value;
+        self
+    }
+
+    #[must_use]
+    pub fn with_bandwidth_limit(mut self, value: BandwidthLimit) -> Self {
+        self.bandwidth_limit = Some(value);
+        self
+    }
+
     pub async fn build(self) -> TestServer {
         let identity = if self.disable_https {
             ClientIdentity::Helper(HelperIdentity::ONE)
@@ -280,6 +337,8 @@ impl TestServerBuilder {
         let test_config = TestConfig::builder()
             .with_disable_https_option(self.disable_https)
             .with_use_http1_option(self.use_http1)
+            .with_stream_compression(self.stream_compression)
+            .with_bandwidth_limit(self.bandwidth_limit)
             // TODO: add disble_matchkey here
             .build();
         let TestConfig {
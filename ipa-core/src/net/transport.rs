@@ -1,8 +1,10 @@
 use std::{
     borrow::Borrow,
+    collections::HashMap,
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
@@ -10,20 +12,24 @@ use futures::{Stream, TryFutureExt};
 use pin_project::{pin_project, pinned_drop};
 
 use crate::{
-    config::{NetworkConfig, ServerConfig},
+    config::{NetworkConfig, ServerConfig, StreamCompression, StreamResumeConfig},
     helpers::{
         query::QueryConfig,
         routing::{Addr, RouteId},
         ApiError, BodyStream, HandlerRef, HelperIdentity, HelperResponse, NoQueryId,
-        NoResourceIdentifier, NoStep, QueryIdBinding, ReceiveRecords, RequestHandler, RouteParams,
-        StepBinding, StreamCollection, Transport,
+        NoResourceIdentifier, NoStep, QueryIdBinding, ReceiveRecords, RequestHandler,
+        ResumableSender, ResumableStream, RouteParams, StepBinding, StreamCollection, StreamKey,
+        StreamResumer, TokenBucket, Transport,
     },
     net::{client::MpcHelperClient, error::Error, MpcHelperServer},
     protocol::{Gate, QueryId},
     sharding::ShardIndex,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
+/// How long to wait before reconnecting after losing a connection with another helper.
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
 /// HTTP transport for IPA helper service.
 /// TODO: rename to MPC
 pub struct HttpTransport {
@@ -31,7 +37,12 @@ pub struct HttpTransport {
     clients: [MpcHelperClient; 3],
     // TODO(615): supporting multiple queries likely require a hashmap here. It will be ok if we
     // only allow one query at a time.
-    record_streams: StreamCollection<HelperIdentity, BodyStream>,
+    record_streams: StreamCollection<HelperIdentity, ResumableStream<BodyStream>>,
+    /// Allows peers to reconnect to record streams they started sending earlier.
+    stream_resumers: Mutex<HashMap<StreamKey<HelperIdentity>, StreamResumer<BodyStream>>>,
+    stream_resume: StreamResumeConfig,
+    /// Compression accepted on incoming record streams, in addition to uncompressed ones.
+    stream_compression: StreamCompression,
     handler: Option<HandlerRef>,
 }
 
@@ -68,7 +79,13 @@ impl HttpTransport {
         clients: [MpcHelperClient; 3],
         handler: Option<HandlerRef>,
     ) -> (Arc<Self>, MpcHelperServer) {
-        let transport = Self::new_internal(identity, clients, handler);
+        let transport = Self::new_internal(
+            identity,
+            clients,
+            handler,
+            network_config.stream_resume.clone(),
+            server_config.stream_compression,
+        );
         let server = MpcHelperServer::new(Arc::clone(&transport), server_config, network_config);
         (transport, server)
     }
@@ -77,15 +94,25 @@ impl HttpTransport {
         identity: HelperIdentity,
         clients: [MpcHelperClient; 3],
         handler: Option<HandlerRef>,
+        stream_resume: StreamResumeConfig,
+        stream_compression: StreamCompression,
     ) -> Arc<Self> {
         Arc::new(Self {
             identity,
             clients,
             handler,
             record_streams: StreamCollection::default(),
+            stream_resumers: Mutex::default(),
+            stream_resume,
+            stream_compression,
         })
     }
 
+    /// Compression this helper accepts on record streams sent by other helpers.
+    pub(crate) fn stream_compression(&self) -> StreamCompression {
+        self.stream_compression
+    }
+
     /// Dispatches the given request to the [`RequestHandler`] connected to this transport.
     ///
     /// ## Errors
@@ -125,6 +152,7 @@ impl HttpTransport {
         impl<F: Future> PinnedDrop for ClearOnDrop<F> {
             fn drop(self: Pin<&mut Self>) {
                 self.transport.record_streams.clear();
+                self.transport.stream_resumers.lock().unwrap().clear();
             }
         }
 
@@ -146,31 +174,133 @@ impl HttpTransport {
         }
     }
 
-    /// Connect an inbound stream of MPC record data.
+    /// Connect an inbound stream of MPC record data. `offset` is the position of the first byte
+    /// of `stream` within the records stream. If this stream has been connected before, the new
+    /// connection takes over from the previous one.
     ///
     /// This is called by peer helpers via the HTTP server.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
     pub fn receive_stream(
         self: Arc<Self>,
         query_id: QueryId,
         gate: Gate,
         from: HelperIdentity,
+        offset: u64,
         stream: BodyStream,
     ) {
-        self.record_streams
-            .add_stream((query_id, from, gate), stream);
+        let key = (query_id, from, gate);
+        let mut resumers = self.stream_resumers.lock().unwrap();
+        if let Some(resumer) = resumers.get(&key) {
+            if resumer.resume(offset, stream).is_err() {
+                tracing::warn!("{key:?}: record stream has been closed, ignoring reconnect");
+            }
+        } else {
+            let (records, resumer) = ResumableStream::new(self.stream_resume.grace_period);
+            // receiving end is alive, because it hasn't been handed out yet
+            let _ = resumer.resume(offset, stream);
+            resumers.insert(key.clone(), resumer);
+            self.record_streams.add_stream(key, records);
+        }
+    }
+
+    /// Number of bytes of the records stream from `from` that have been acknowledged. It is
+    /// zero if that stream has not been received yet.
+    ///
+    /// ## Panics
+    /// If mutex is poisoned.
+    pub fn stream_offset(&self, query_id: QueryId, gate: Gate, from: HelperIdentity) -> u64 {
+        self.stream_resumers
+            .lock()
+            .unwrap()
+            .get(&(query_id, from, gate))
+            .map_or(0, StreamResumer::acknowledged)
+    }
+
+    /// Sends a records stream to another helper. If the connection is lost, this asks the peer
+    /// how many bytes it has acknowledged and resumes the stream from there. It gives up if no
+    /// data gets through for the grace period set in [`StreamResumeConfig`], or if the bytes
+    /// the peer is missing are no longer kept for retransmission.
+    async fn send_records<D: Stream<Item = Vec<u8>> + Send + 'static>(
+        &self,
+        dest: HelperIdentity,
+        query_id: QueryId,
+        gate: Gate,
+        data: D,
+    ) -> Result<(), Error> {
+        let sender = ResumableSender::new(data, self.stream_resume.replay_buffer_bytes);
+        let mut offset = 0;
+        let mut lost_since = None;
+        loop {
+            let (body, outcome) =
+                sender
+                    .connect(offset)
+                    .map_err(|retained| Error::StreamNotResumable {
+                        dest: format!("{dest:?}"),
+                        offset,
+                        retained,
+                    })?;
+            let resp_future = self.clients[dest].step(query_id, &gate, offset, body)?;
+            // we don't need to spawn a task here. Gateway's sender interface already does that
+            // so this can just poll this future.
+            let result = resp_future
+                .map_err(Into::into)
+                .and_then(MpcHelperClient::resp_ok)
+                .await;
+            let mut err = match result {
+                // The peer accepted the stream, now wait until it is sent completely
+                Ok(()) if outcome.await => return Ok(()),
+                Ok(()) => Error::ConnectionLost {
+                    dest: format!("{dest:?}"),
+                    offset,
+                },
+                // the peer rejected the stream, there is no point in retrying
+                Err(e @ Error::FailedHttpRequest { .. }) => return Err(e),
+                Err(e) => e,
+            };
+
+            loop {
+                let since = *lost_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= self.stream_resume.grace_period {
+                    return Err(err);
+                }
+                tracing::warn!(
+                    "{dest:?}: lost connection while sending {gate:?}, reconnecting: {err}"
+                );
+                tokio::time::sleep(RECONNECT_DELAY).await;
+
+                match self.clients[dest].step_offset(query_id, &gate).await {
+                    Ok(acknowledged) => {
+                        // only count the time the peer has not received anything
+                        if acknowledged > offset {
+                            lost_since = None;
+                        }
+                        offset = acknowledged;
+                        break;
+                    }
+                    Err(e @ Error::FailedHttpRequest { .. }) => return Err(e),
+                    Err(e) => err = e,
+                }
+            }
+        }
     }
 }
 
 #[async_trait]
 impl Transport for Arc<HttpTransport> {
     type Identity = HelperIdentity;
-    type RecordsStream = ReceiveRecords<HelperIdentity, BodyStream>;
+    type RecordsStream = ReceiveRecords<HelperIdentity, ResumableStream<BodyStream>>;
     type Error = Error;
 
     fn identity(&self) -> HelperIdentity {
         self.identity
     }
 
+    fn bandwidth(&self, dest: HelperIdentity) -> Option<Arc<TokenBucket>> {
+        self.clients[dest].bandwidth().map(Arc::clone)
+    }
+
     async fn send<
         D: Stream<Item = Vec<u8>> + Send + 'static,
         Q: QueryIdBinding,
@@ -194,23 +324,22 @@ impl Transport for Arc<HttpTransport> {
                     .expect("query_id required when sending records");
                 let step =
                     <Option<Gate>>::from(route.gate()).expect("step required when sending records");
-                let resp_future = self.clients[dest].step(query_id, &step, data)?;
-                // we don't need to spawn a task here. Gateway's sender interface already does that
-                // so this can just poll this future.
-                resp_future
-                    .map_err(Into::into)
-                    .and_then(MpcHelperClient::resp_ok)
-                    .await?;
-                Ok(())
+                self.send_records(dest, query_id, step, data).await
             }
             RouteId::PrepareQuery => {
                 let req = serde_json::from_str(route.extra().borrow()).unwrap();
                 self.clients[dest].prepare_query(req).await
             }
+            RouteId::AbortQuery => {
+                let query_id = <Option<QueryId>>::from(route.query_id())
+                    .expect("query_id required when aborting a query");
+                self.clients[dest].abort_query(query_id).await
+            }
             evt @ (RouteId::QueryInput
             | RouteId::ReceiveQuery
             | RouteId::QueryStatus
-            | RouteId::CompleteQuery) => {
+            | RouteId::CompleteQuery
+            | RouteId::PublicKeys) => {
                 unimplemented!(
                     "attempting to send client-specific request {evt:?} to another helper"
                 )
@@ -268,22 +397,26 @@ impl Transport for HttpShardTransport {
 
 #[cfg(all(test, web_test, descriptive_gate))]
 mod tests {
-    use std::{iter::zip, net::TcpListener, task::Poll};
+    use std::{
+        iter::zip,
+        net::{SocketAddr, TcpListener},
+        task::Poll,
+    };
 
     use bytes::Bytes;
     use futures::stream::{poll_immediate, StreamExt};
     use futures_util::future::{join_all, try_join_all};
     use generic_array::GenericArray;
     use once_cell::sync::Lazy;
-    use tokio::sync::mpsc::channel;
+    use tokio::{sync::mpsc::channel, task::JoinHandle};
     use tokio_stream::wrappers::ReceiverStream;
     use typenum::Unsigned;
 
     use super::*;
     use crate::{
-        config::{NetworkConfig, ServerConfig},
+        config::{BandwidthLimit, ClientConfig, NetworkConfig, PeerConfig, ServerConfig},
         ff::{FieldType, Fp31, Serializable},
-        helpers::query::{QueryInput, QueryType::TestMultiply},
+        helpers::query::{PaddingPolicy, QueryInput, QueryType::TestMultiply},
         net::{
             client::ClientIdentity,
             test::{get_test_identity, TestConfig, TestConfigBuilder, TestServer},
@@ -306,7 +439,7 @@ mod tests {
         let body = BodyStream::from_bytes_stream(ReceiverStream::new(rx));
 
         // Register the stream with the transport (normally called by step data HTTP API handler)
-        Arc::clone(&transport).receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, body);
+        Arc::clone(&transport).receive_stream(QueryId, STEP.clone(), HelperIdentity::TWO, 0, body);
 
         // Request step data reception (normally called by protocol)
         let mut stream = Arc::clone(&transport)
@@ -336,6 +469,99 @@ mod tests {
         );
     }
 
+    /// Forwards TCP connections to `target` and can cut all of them at once, as if the network
+    /// between two helpers failed.
+    struct Proxy {
+        addr: SocketAddr,
+        connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    }
+
+    impl Proxy {
+        async fn start(target: SocketAddr) -> Self {
+            let listener = tokio::net::TcpListener::bind("localhost:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let connections = Arc::new(Mutex::new(Vec::new()));
+            tokio::spawn({
+                let connections = Arc::clone(&connections);
+                async move {
+                    loop {
+                        let (mut inbound, _) = listener.accept().await.unwrap();
+                        let mut outbound = tokio::net::TcpStream::connect(target).await.unwrap();
+                        connections.lock().unwrap().push(tokio::spawn(async move {
+                            let _ =
+                                tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                        }));
+                    }
+                }
+            });
+
+            Self { addr, connections }
+        }
+
+        fn cut(&self) {
+            for connection in self.connections.lock().unwrap().drain(..) {
+                connection.abort();
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn resume_after_connection_loss() {
+        let receiver = TestServer::builder().disable_https().build().await;
+        let proxy = Proxy::start(receiver.addr).await;
+        let client = MpcHelperClient::new(
+            &ClientConfig::default(),
+            PeerConfig::new(format!("http://{}", proxy.addr).parse().unwrap(), None),
+            ClientIdentity::Helper(HelperIdentity::ONE),
+        );
+        let sender = HttpTransport::new_internal(
+            HelperIdentity::ONE,
+            [client.clone(), client.clone(), client],
+            None,
+            StreamResumeConfig {
+                grace_period: Duration::from_secs(5),
+                replay_buffer_bytes: 1024,
+            },
+            StreamCompression::None,
+        );
+
+        let (tx, rx) = channel(1);
+        let send = tokio::spawn(async move {
+            sender
+                .send(
+                    HelperIdentity::TWO,
+                    (RouteId::Records, QueryId, STEP.clone()),
+                    ReceiverStream::new(rx),
+                )
+                .await
+        });
+        let mut stream = Arc::clone(&receiver.transport)
+            .receive(HelperIdentity::ONE, (QueryId, STEP.clone()))
+            .into_bytes_stream();
+
+        tx.send(vec![0, 1, 2]).await.unwrap();
+        assert_eq!(Some(vec![0, 1, 2]), stream.next().await);
+
+        // give the sender time to notice the connection is gone, otherwise the data sent next
+        // may still be written into the dead socket
+        proxy.cut();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // the sender reconnects and resumes from the last byte the receiver got
+        tx.send(vec![3, 4, 5]).await.unwrap();
+        tx.send(vec![6, 7]).await.unwrap();
+        drop(tx);
+
+        assert_eq!(vec![3, 4, 5, 6, 7], stream.concat().await);
+        send.await.unwrap().unwrap();
+        assert_eq!(
+            8,
+            receiver
+                .transport
+                .stream_offset(QueryId, STEP.clone(), HelperIdentity::ONE)
+        );
+    }
+
     // TODO(651): write a test for an error while reading the body (after error handling is finalized)
 
     async fn make_helpers(
@@ -352,7 +578,9 @@ mod tests {
                     } else {
                         get_test_identity(id)
                     };
-                    let (setup, handler) = AppSetup::new(AppConfig::default());
+                    let (setup, handler) = AppSetup::new(
+                        AppConfig::default().with_padding_policy(PaddingPolicy::permissive()),
+                    );
                     let clients = MpcHelperClient::from_conf(network_config, &identity);
                     let (transport, server) = HttpTransport::new(
                         id,
@@ -457,4 +685,17 @@ mod tests {
         let conf = TestConfigBuilder::with_open_ports().build();
         test_three_helpers(conf).await;
     }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn three_helpers_bandwidth_limit() {
+        // gateways hold senders back and clients pace the record streams, with a burst too small
+        // to fit all the setup traffic
+        let conf = TestConfigBuilder::with_open_ports()
+            .with_bandwidth_limit(Some(BandwidthLimit {
+                bytes_per_sec: 10_000.try_into().unwrap(),
+                burst_bytes: Some(16.try_into().unwrap()),
+            }))
+            .build();
+        test_three_helpers(conf).await;
+    }
 }
@@ -1,7 +1,8 @@
 use ipa_step::Step;
 
 use crate::protocol::boolean::step::{
-    EightBitStep, SixteenBitStep, ThirtyTwoBitStep, TwoHundredFiftySixBitOpStep,
+    EightBitStep, OneHundredTwentyEightBitStep, SixteenBitStep, ThirtyTwoBitStep,
+    TwoHundredFiftySixBitOpStep,
 };
 
 pub mod and;
@@ -33,6 +34,10 @@ impl NBitStep for ThirtyTwoBitStep {
     const BITS: u32 = 32;
 }
 
+impl NBitStep for OneHundredTwentyEightBitStep {
+    const BITS: u32 = 128;
+}
+
 impl NBitStep for TwoHundredFiftySixBitOpStep {
     const BITS: u32 = 256;
 }
@@ -12,6 +12,10 @@ pub struct SixteenBitStep(usize);
 #[step(count = 32, name = "bit")]
 pub struct ThirtyTwoBitStep(usize);
 
+#[derive(CompactStep)]
+#[step(count = 128, name = "bit")]
+pub struct OneHundredTwentyEightBitStep(usize);
+
 #[derive(CompactStep)]
 #[step(count = 256, name = "bit")]
 pub struct TwoHundredFiftySixBitOpStep(usize);
@@ -9,7 +9,10 @@ use ipa_step::{Step, StepNarrow};
 
 use crate::{
     error::Error,
-    helpers::{Gateway, MpcMessage, MpcReceivingEnd, Role, SendingEnd, TotalRecords},
+    helpers::{
+        Gateway, Message, MpcMessage, MpcReceivingEnd, Role, SendingEnd, ShardReceivingEnd,
+        TotalRecords,
+    },
     protocol::{
         basics::mul::{semi_honest_multiply, step::MaliciousMultiplyStep::RandomnessForValidation},
         context::{
@@ -19,7 +22,7 @@ use crate::{
             step::UpgradeStep,
             upgrade::Upgradable,
             validator::{self, BatchValidator},
-            Base, Context as ContextTrait, InstrumentedSequentialSharedRandomness,
+            Base, Context as ContextTrait, InstrumentedSequentialSharedRandomness, ShardedContext,
             SpecialAccessToUpgradedContext, UpgradableContext, UpgradedContext,
         },
         prss::{Endpoint as PrssEndpoint, FromPrss},
@@ -30,7 +33,7 @@ use crate::{
         semi_honest::AdditiveShare as Replicated,
     },
     seq_join::SeqJoin,
-    sharding::NotSharded,
+    sharding::{NotSharded, ShardBinding, ShardConfiguration, ShardIndex, Sharded},
     sync::Arc,
 };
 
@@ -49,8 +52,8 @@ pub(crate) const TEST_DZKP_STEPS: MaliciousProtocolSteps<
 };
 
 #[derive(Clone)]
-pub struct Context<'a> {
-    inner: Base<'a>,
+pub struct Context<'a, B: ShardBinding = NotSharded> {
+    inner: Base<'a, B>,
 }
 
 impl<'a> Context<'a> {
@@ -59,15 +62,7 @@ impl<'a> Context<'a> {
     }
 
     pub fn new_with_gate(participant: &'a PrssEndpoint, gateway: &'a Gateway, gate: Gate) -> Self {
-        Self {
-            inner: Base::new_complete(
-                participant,
-                gateway,
-                gate,
-                TotalRecords::Unspecified,
-                NotSharded,
-            ),
-        }
+        Self::new_complete(participant, gateway, NotSharded, gate)
     }
 
     pub(crate) fn validator_context(self) -> Base<'a> {
@@ -80,7 +75,51 @@ impl<'a> Context<'a> {
     }
 }
 
-impl<'a> super::Context for Context<'a> {
+impl<'a, B: ShardBinding> Context<'a, B> {
+    pub fn new_complete(
+        participant: &'a PrssEndpoint,
+        gateway: &'a Gateway,
+        shard: B,
+        gate: Gate,
+    ) -> Self {
+        Self {
+            inner: Base::new_complete(participant, gateway, gate, TotalRecords::Unspecified, shard),
+        }
+    }
+}
+
+impl<'a> Context<'a, Sharded> {
+    pub fn new_sharded(
+        participant: &'a PrssEndpoint,
+        gateway: &'a Gateway,
+        shard: Sharded,
+        gate: Gate,
+    ) -> Self {
+        Self::new_complete(participant, gateway, shard, gate)
+    }
+}
+
+impl ShardConfiguration for Context<'_, Sharded> {
+    fn shard_id(&self) -> ShardIndex {
+        self.inner.shard_id()
+    }
+
+    fn shard_count(&self) -> ShardIndex {
+        self.inner.shard_count()
+    }
+}
+
+impl ShardedContext for Context<'_, Sharded> {
+    fn shard_send_channel<M: Message>(&self, dest_shard: ShardIndex) -> SendingEnd<ShardIndex, M> {
+        self.inner.shard_send_channel(dest_shard)
+    }
+
+    fn shard_recv_channel<M: Message>(&self, origin: ShardIndex) -> ShardReceivingEnd<M> {
+        self.inner.shard_recv_channel(origin)
+    }
+}
+
+impl<B: ShardBinding> super::Context for Context<'_, B> {
     fn role(&self) -> Role {
         self.inner.role()
     }
@@ -152,15 +191,17 @@ impl<'a> UpgradableContext for Context<'a> {
     }
 }
 
-impl<'a> SeqJoin for Context<'a> {
+impl<B: ShardBinding> SeqJoin for Context<'_, B> {
     fn active_work(&self) -> NonZeroUsize {
         self.inner.active_work()
     }
 }
 
-impl Debug for Context<'_> {
+impl<B: ShardBinding> Debug for Context<'_, B> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "MaliciousContext")
+        f.debug_struct("MaliciousContext")
+            .field("shard", &self.inner.sharding)
+            .finish()
     }
 }
 
@@ -0,0 +1,297 @@
+//! Privacy accounting.
+//!
+//! A query runs several differentially private mechanisms: the noise added to its output, and
+//! the padding added to its inputs and to the aggregation. [`PrivacyAccountant`] collects them
+//! and composes them into the total privacy loss of the query, which is reported with its
+//! results and charged against the privacy budget.
+
+use serde::{Deserialize, Serialize};
+
+/// Privacy loss of an (ε, δ)-differentially private mechanism.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyLoss {
+    pub epsilon: f64,
+    pub delta: f64,
+}
+
+impl PrivacyLoss {
+    #[must_use]
+    pub fn new(epsilon: f64, delta: f64) -> Self {
+        Self { epsilon, delta }
+    }
+}
+
+/// A differentially private mechanism, as seen by the accountant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mechanism {
+    /// (ε, δ) guarantee of the mechanism.
+    pub loss: PrivacyLoss,
+    /// ρ of the zero-concentrated DP guarantee of the mechanism, if it has a proven one. `loss`
+    /// is then implied by it. Mechanisms without one are composed from their (ε, δ) guarantee
+    /// only.
+    pub rho: Option<f64>,
+}
+
+impl Mechanism {
+    /// Mechanism that only has an (ε, δ) guarantee, like truncated discrete Laplace or binomial
+    /// noise.
+    #[must_use]
+    pub fn approximate(epsilon: f64, delta: f64) -> Self {
+        Self {
+            loss: PrivacyLoss::new(epsilon, delta),
+            rho: None,
+        }
+    }
+
+    /// ρ-zCDP mechanism, that is also (ε, `delta`)-DP for the ε of [`zcdp_epsilon`].
+    #[must_use]
+    pub fn zcdp(rho: f64, delta: f64) -> Self {
+        Self {
+            loss: PrivacyLoss::new(zcdp_epsilon(rho, delta), delta),
+            rho: Some(rho),
+        }
+    }
+
+    /// ε-DP mechanism. It is also ε²/2-zCDP (Bun and Steinke, proposition 1.4).
+    #[must_use]
+    pub fn pure(epsilon: f64) -> Self {
+        Self {
+            loss: PrivacyLoss::new(epsilon, 0.0),
+            rho: Some(epsilon * epsilon / 2.0),
+        }
+    }
+}
+
+/// How the privacy loss of several mechanisms adds up.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Composition {
+    /// Epsilons and deltas add up.
+    #[default]
+    Basic,
+    /// The advanced composition theorem (Dwork, Rothblum and Vadhan), that trades `delta` of
+    /// additional failure probability for a smaller epsilon.
+    Advanced { delta: f64 },
+    /// Mechanisms with a zCDP guarantee add up their ρ, which is converted to (ε, `delta`)-DP.
+    /// Other mechanisms are composed with basic composition.
+    Zcdp { delta: f64 },
+}
+
+/// Collects the mechanisms a query runs, to compute its total privacy loss.
+#[derive(Debug, Default, Clone)]
+pub struct PrivacyAccountant {
+    mechanisms: Vec<Mechanism>,
+}
+
+impl PrivacyAccountant {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with(mut self, mechanism: Mechanism) -> Self {
+        self.add(mechanism);
+        self
+    }
+
+    pub fn add(&mut self, mechanism: Mechanism) {
+        self.mechanisms.push(mechanism);
+    }
+
+    #[must_use]
+    pub fn mechanisms(&self) -> &[Mechanism] {
+        &self.mechanisms
+    }
+
+    /// Total privacy loss of all mechanisms: the tightest of the bounds given by basic, advanced
+    /// and zCDP composition. The last two may spend up to `delta` more failure probability than
+    /// the mechanisms themselves.
+    ///
+    /// Advanced and zCDP composition only improve on basic composition in some regimes, typically
+    /// many mechanisms with small epsilons. Basic composition has the smallest delta, so it wins
+    /// a tie.
+    #[must_use]
+    pub fn total(&self, delta: f64) -> PrivacyLoss {
+        [Composition::Advanced { delta }, Composition::Zcdp { delta }]
+            .into_iter()
+            .map(|composition| self.compose(composition))
+            .fold(self.compose(Composition::Basic), |best, other| {
+                if other.epsilon < best.epsilon {
+                    other
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// Privacy loss of all mechanisms, composed with the given rule.
+    #[must_use]
+    pub fn compose(&self, composition: Composition) -> PrivacyLoss {
+        match composition {
+            Composition::Basic => basic(self.mechanisms.iter().map(|m| m.loss)),
+            Composition::Advanced { delta } => self.advanced(delta),
+            Composition::Zcdp { delta } => self.zcdp(delta),
+        }
+    }
+
+    /// ε' = √(2 ln(1/δ') Σ εᵢ²) + Σ εᵢ (e^εᵢ - 1), δ = δ' + Σ δᵢ
+    fn advanced(&self, delta: f64) -> PrivacyLoss {
+        let sum_squares = self
+            .mechanisms
+            .iter()
+            .map(|m| m.loss.epsilon.powi(2))
+            .sum::<f64>();
+        let drift = self
+            .mechanisms
+            .iter()
+            .map(|m| m.loss.epsilon * m.loss.epsilon.exp_m1())
+            .sum::<f64>();
+        PrivacyLoss {
+            epsilon: (2.0 * (1.0 / delta).ln() * sum_squares).sqrt() + drift,
+            delta: delta + self.mechanisms.iter().map(|m| m.loss.delta).sum::<f64>(),
+        }
+    }
+
+    /// ρ of all zCDP mechanisms add up. The sum is converted to (ε, δ')-DP, and the other
+    /// mechanisms are added with basic composition. The δ of a zCDP mechanism only comes from
+    /// converting its own ρ, so it is not added again.
+    fn zcdp(&self, delta: f64) -> PrivacyLoss {
+        let (concentrated, others): (Vec<&Mechanism>, Vec<&Mechanism>) =
+            self.mechanisms.iter().partition(|m| m.rho.is_some());
+        if concentrated.is_empty() {
+            return basic(others.into_iter().map(|m| m.loss));
+        }
+        let rho = concentrated.iter().filter_map(|m| m.rho).sum::<f64>();
+        let concentrated = PrivacyLoss::new(zcdp_epsilon(rho, delta), delta);
+        basic(std::iter::once(concentrated).chain(others.into_iter().map(|m| m.loss)))
+    }
+}
+
+fn basic<I: IntoIterator<Item = PrivacyLoss>>(losses: I) -> PrivacyLoss {
+    losses
+        .into_iter()
+        .fold(PrivacyLoss::default(), |acc, loss| PrivacyLoss {
+            epsilon: acc.epsilon + loss.epsilon,
+            delta: acc.delta + loss.delta,
+        })
+}
+
+/// Epsilon of the (ε, δ)-DP guarantee implied by ρ-zCDP: ρ + 2√(ρ ln(1/δ)) (Bun and Steinke,
+/// proposition 1.3).
+#[must_use]
+pub fn zcdp_epsilon(rho: f64, delta: f64) -> f64 {
+    if rho <= 0.0 {
+        return 0.0;
+    }
+    rho + 2.0 * (rho * (1.0 / delta).ln()).sqrt()
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{zcdp_epsilon, Composition, Mechanism, PrivacyAccountant, PrivacyLoss};
+
+    fn assert_close(expected: f64, actual: f64) {
+        assert!(
+            (expected - actual).abs() < 1e-9,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    fn repeated(k: usize, mechanism: Mechanism) -> PrivacyAccountant {
+        (0..k).fold(PrivacyAccountant::new(), |acc, _| acc.with(mechanism))
+    }
+
+    #[test]
+    fn basic() {
+        let accountant = PrivacyAccountant::new()
+            .with(Mechanism::approximate(1.0, 1e-6))
+            .with(Mechanism::approximate(2.5, 1e-7))
+            .with(Mechanism::pure(0.5));
+        let total = accountant.compose(Composition::Basic);
+        assert_close(4.0, total.epsilon);
+        assert_close(1.1e-6, total.delta);
+
+        assert_eq!(PrivacyLoss::default(), PrivacyAccountant::new().total(1e-6));
+    }
+
+    #[test]
+    fn advanced_against_basic() {
+        const EPSILON: f64 = 0.1;
+        const DELTA: f64 = 1e-6;
+        for k in [1, 10, 100, 1000, 10_000] {
+            let accountant = repeated(k, Mechanism::approximate(EPSILON, 1e-9));
+            let basic = accountant.compose(Composition::Basic);
+            let advanced = accountant.compose(Composition::Advanced { delta: DELTA });
+
+            #[allow(clippy::cast_precision_loss)]
+            let k = k as f64;
+            assert_close(k * EPSILON, basic.epsilon);
+            assert_close(
+                (2.0 * k * (1.0 / DELTA).ln()).sqrt() * EPSILON + k * EPSILON * EPSILON.exp_m1(),
+                advanced.epsilon,
+            );
+            assert_close(k * 1e-9, basic.delta);
+            assert_close(DELTA + k * 1e-9, advanced.delta);
+
+            // advanced composition grows with √k instead of k, so it only pays off for many
+            // mechanisms: with ε = 0.1 and δ' = 1e-6, from 35 of them
+            assert_eq!(k >= 100.0, advanced.epsilon < basic.epsilon, "k = {k}");
+            assert_eq!(
+                if advanced.epsilon < basic.epsilon {
+                    advanced
+                } else {
+                    basic
+                },
+                accountant.total(DELTA)
+            );
+        }
+
+        // for a few large epsilons, basic composition is tighter
+        let few_large = repeated(2, Mechanism::approximate(5.0, 1e-6));
+        assert_eq!(few_large.compose(Composition::Basic), few_large.total(1e-6));
+    }
+
+    #[test]
+    fn zcdp() {
+        assert_close(0.0, zcdp_epsilon(0.0, 1e-6));
+        assert_close(
+            0.5 + 2.0 * (0.5 * 1e6_f64.ln()).sqrt(),
+            zcdp_epsilon(0.5, 1e-6),
+        );
+
+        let many_pure = repeated(1000, Mechanism::pure(0.01));
+        let total = many_pure.compose(Composition::Zcdp { delta: 1e-6 });
+        assert_close(
+            zcdp_epsilon(1000.0 * 0.01 * 0.01 / 2.0, 1e-6),
+            total.epsilon,
+        );
+        assert_close(1e-6, total.delta);
+        assert!(total.epsilon < many_pure.compose(Composition::Basic).epsilon);
+        assert!(
+            total.epsilon
+                < many_pure
+                    .compose(Composition::Advanced { delta: 1e-6 })
+                    .epsilon
+        );
+        assert_eq!(total, many_pure.total(1e-6));
+
+        // mechanisms without a zCDP guarantee are added with basic composition
+        let mixed = many_pure.with(Mechanism::approximate(1.0, 1e-7));
+        let mixed_total = mixed.compose(Composition::Zcdp { delta: 1e-6 });
+        assert_close(total.epsilon + 1.0, mixed_total.epsilon);
+        assert_close(1e-6 + 1e-7, mixed_total.delta);
+
+        // the delta of zCDP mechanisms comes from converting their rho, which is only done once
+        let concentrated = repeated(16, Mechanism::zcdp(0.01, 1e-9));
+        let concentrated_total = concentrated.compose(Composition::Zcdp { delta: 1e-6 });
+        assert_close(zcdp_epsilon(16.0 * 0.01, 1e-6), concentrated_total.epsilon);
+        assert_close(1e-6, concentrated_total.delta);
+
+        // without any, it is basic composition
+        let approximate = repeated(10, Mechanism::approximate(0.1, 1e-7));
+        assert_eq!(
+            approximate.compose(Composition::Basic),
+            approximate.compose(Composition::Zcdp { delta: 1e-6 })
+        );
+    }
+}
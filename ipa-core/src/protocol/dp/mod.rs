@@ -1,10 +1,13 @@
 // DP in MPC
+pub mod accountant;
 pub mod step;
 
-use std::{convert::Infallible, f64};
+use std::{convert::Infallible, f64, num::NonZeroU32, ops::Not};
 
 use futures_util::{stream, StreamExt};
+use rand::distributions::Distribution;
 use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{
@@ -12,18 +15,27 @@ use crate::{
         LengthError,
     },
     ff::{boolean::Boolean, boolean_array::BooleanArray, U128Conversions},
-    helpers::{query::DpMechanism, Direction, Role, TotalRecords},
+    helpers::{
+        query::{DpMechanism, NoiseMechanism},
+        Direction, Role, TotalRecords,
+    },
     protocol::{
-        boolean::step::ThirtyTwoBitStep,
+        basics::SecureMul,
+        boolean::step::OneHundredTwentyEightBitStep,
         context::{
-            dzkp_validator::DZKPValidator, Context, DZKPUpgraded, MaliciousProtocolSteps,
-            UpgradableContext,
+            dzkp_validator::DZKPValidator, prss::InstrumentedSequentialSharedRandomness, Context,
+            DZKPUpgraded, MaliciousProtocolSteps, UpgradableContext,
+        },
+        dp::{
+            accountant::{zcdp_epsilon, Mechanism},
+            step::{ApplyDpNoise, DPStep, ThresholdStep},
         },
-        dp::step::{ApplyDpNoise, DPStep},
         ipa_prf::{
             aggregation::{aggregate_values, aggregate_values_proof_chunk},
-            boolean_ops::addition_sequential::integer_add,
-            oprf_padding::insecure::OPRFPaddingDp,
+            boolean_ops::{
+                addition_sequential::integer_add, comparison_and_subtraction_sequential::compare_gt,
+            },
+            oprf_padding::{distributions::Poisson, insecure::OPRFPaddingDp},
             step::IpaPrfStep,
         },
         prss::{FromPrss, SharedRandomness},
@@ -75,7 +87,9 @@ impl Default for NoiseParams {
     }
 }
 const MAX_PROBABILITY: f64 = 1.0;
-const MAX_EPSILON: f64 = 20.0;
+/// Largest epsilon of the output noise. Very large epsilons are allowed to make the noise gen
+/// circuit small enough for concurrency testing to be possible.
+pub const MAX_EPSILON: f64 = 20.0;
 
 impl NoiseParams {
     /// # Errors
@@ -204,7 +218,7 @@ where
     let apply_noise_ctx = ctx
         .narrow(&ApplyDpNoise::ApplyNoise)
         .set_total_records(TotalRecords::ONE);
-    let (histogram_noised, _) = integer_add::<_, ThirtyTwoBitStep, B>(
+    let (histogram_noised, _) = integer_add::<_, OneHundredTwentyEightBitStep, B>(
         apply_noise_ctx,
         RecordId::FIRST,
         &noise_vector,
@@ -217,22 +231,84 @@ where
     Ok(Vec::transposed_from(&histogram_noised)?)
 }
 
+/// `apply_skellam_noise` adds Skellam noise to the vector of values: every pair of helpers samples
+/// the difference of two Poisson samples of mean `poisson_mean` for each value from the randomness
+/// they share, and adds it in MPC. No helper knows the sample of the pair that excludes it, which
+/// is the noise that protects the output from that helper. The noise is centered at zero, and
+/// negative samples wrap around modulo `2^OV::BITS`.
+/// # Errors
+/// if `poisson_mean` is out of range, or from transpose
+#[tracing::instrument(name = "apply_skellam_noise", skip_all)]
+pub async fn apply_skellam_noise<C, const B: usize, OV>(
+    ctx: C,
+    histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
+    poisson_mean: u32,
+) -> Result<Vec<Replicated<OV>>, Error>
+where
+    C: Context,
+    Boolean: Vectorizable<B> + FieldSimd<B>,
+    OV: BooleanArray + U128Conversions,
+    Replicated<Boolean, B>: BooleanProtocols<C, B>,
+    Vec<Replicated<OV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    BitDecomposed<AdditiveShare<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [AdditiveShare<OV>; B], Error = Infallible>,
+    AdditiveShare<OV>: ReplicatedSecretSharing<OV>,
+{
+    let skellam = SkellamNoise::new(poisson_mean)?;
+    let mut noised = histogram_bin_values;
+    for (step, excluded_helper) in [
+        (DPStep::SkellamPass1, Role::H1),
+        (DPStep::SkellamPass2, Role::H2),
+        (DPStep::SkellamPass3, Role::H3),
+    ] {
+        noised = apply_pairwise_noise_pass::<_, OV, B, _>(
+            &ctx.narrow(&step),
+            noised,
+            excluded_helper,
+            |rng, direction| skellam.sample_shares(rng, direction),
+        )
+        .await?;
+    }
+
+    Ok(Vec::transposed_from(&noised)?)
+}
+
+/// Shares of the `bits` least significant bits of a value that every helper knows.
+fn public_bits<const B: usize>(value: u128, bits: usize) -> BitDecomposed<Replicated<Boolean, B>>
+where
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: Not<Output = Replicated<Boolean, B>>,
+{
+    BitDecomposed::decompose(bits, |i| {
+        if (value >> i) & 1 == 1 {
+            !Replicated::<Boolean, B>::ZERO
+        } else {
+            Replicated::ZERO
+        }
+    })
+}
+
 // dp_for_aggregation is currently where the DP parameters epsilon, delta
 // are introduced and then from those the parameters of the noise distribution to generate are
 // calculated for use in aggregating histograms.  The DP parameters query_epsilon and
 // per_user_credit_cap come as inputs to the query with per_user_sensitivity_cap = 2^{SS_BITS}
+/// If `threshold` is set, breakdowns whose noisy value is below it are suppressed before the
+/// output is revealed, see [`suppress_below_threshold`].
 /// # Errors
 /// will propogate errors from `apply_dp_noise`
 /// Will return an error epsilon is not in the range (0,`MAX_EPSILON`); we allow very large
 /// epsilons to make the noise gen circuit small enough for concurency testing to be possible.
+/// Will return an error if `threshold` does not fit in the positive half of `OV`, or if it is
+/// set together with binomial noise, which is not centered at zero.
 /// # Panics
 /// may panic from asserts down in  `gen_binomial_noise`
 ///
-#[allow(clippy::too_many_lines)]
 pub async fn dp_for_histogram<C, const B: usize, OV, const SS_BITS: usize>(
     ctx: C,
     histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
     dp_params: DpMechanism,
+    threshold: Option<NonZeroU32>,
 ) -> Result<Vec<Replicated<OV>>, Error>
 where
     C: UpgradableContext,
@@ -245,30 +321,80 @@ where
     BitDecomposed<AdditiveShare<Boolean, B>>:
         for<'a> TransposeFrom<&'a [AdditiveShare<OV>; B], Error = Infallible>,
 {
+    dp_for_histogram_with_steps::<_, B, OV, SS_BITS>(
+        ctx,
+        &HistogramDpSteps::default(),
+        histogram_bin_values,
+        dp_params,
+        threshold,
+    )
+    .await
+}
+
+/// Steps under which [`dp_for_histogram_with_steps`] adds noise and suppresses breakdowns below
+/// the threshold. Queries that add noise to several histograms use different steps for each.
+pub(crate) struct HistogramDpSteps {
+    pub noise: IpaPrfStep,
+    pub noise_validate: IpaPrfStep,
+    pub threshold: IpaPrfStep,
+    pub threshold_validate: IpaPrfStep,
+}
+
+impl Default for HistogramDpSteps {
+    fn default() -> Self {
+        Self {
+            noise: IpaPrfStep::DifferentialPrivacy,
+            noise_validate: IpaPrfStep::DifferentialPrivacyValidate,
+            threshold: IpaPrfStep::DpThreshold,
+            threshold_validate: IpaPrfStep::DpThresholdValidate,
+        }
+    }
+}
+
+/// Same as [`dp_for_histogram`], under the given `steps`.
+///
+/// # Errors
+/// See [`dp_for_histogram`].
+/// # Panics
+/// See [`dp_for_histogram`].
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn dp_for_histogram_with_steps<C, const B: usize, OV, const SS_BITS: usize>(
+    ctx: C,
+    dp_steps: &HistogramDpSteps,
+    histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
+    dp_params: DpMechanism,
+    threshold: Option<NonZeroU32>,
+) -> Result<Vec<Replicated<OV>>, Error>
+where
+    C: UpgradableContext,
+    Boolean: Vectorizable<B> + FieldSimd<B>,
+    BitDecomposed<Replicated<Boolean, B>>: FromPrss<usize>,
+    OV: BooleanArray + U128Conversions,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Vec<Replicated<OV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    BitDecomposed<AdditiveShare<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [AdditiveShare<OV>; B], Error = Infallible>,
+{
+    if threshold.is_some() && matches!(dp_params, DpMechanism::Binomial { .. }) {
+        return Err(Error::InvalidQueryParameter(
+            "dp threshold can't be used with binomial noise, which is not centered at zero".into(),
+        ));
+    }
     let steps = MaliciousProtocolSteps {
-        protocol: &IpaPrfStep::DifferentialPrivacy,
-        validate: &IpaPrfStep::DifferentialPrivacyValidate,
+        protocol: &dp_steps.noise,
+        validate: &dp_steps.noise_validate,
     };
-    match dp_params {
-        DpMechanism::NoDp => Ok(Vec::transposed_from(&histogram_bin_values)?),
-        DpMechanism::Binomial { epsilon } => {
+    let noisy_histogram = match dp_params {
+        DpMechanism::NoDp => Vec::transposed_from(&histogram_bin_values)?,
+        DpMechanism::Binomial { epsilon, delta } => {
             if epsilon <= 0.0 || epsilon > MAX_EPSILON {
                 return Err(EpsilonOutOfBounds);
             }
 
             let per_user_credit_cap = 2_u32.pow(u32::try_from(SS_BITS).unwrap());
-
-            let dimensions = f64::from(u32::try_from(B).unwrap());
-
-            let noise_params = NoiseParams {
-                epsilon,
-                per_user_credit_cap,
-                ell_1_sensitivity: f64::from(per_user_credit_cap),
-                ell_2_sensitivity: f64::from(per_user_credit_cap),
-                ell_infty_sensitivity: f64::from(per_user_credit_cap),
-                dimensions,
-                ..Default::default()
-            };
+            let noise_params = binomial_noise_params(epsilon, delta, per_user_credit_cap, B);
+            let dimensions = noise_params.dimensions;
 
             let num_bernoulli =
                 usize::try_from(find_smallest_num_bernoulli(&noise_params)).unwrap();
@@ -296,7 +422,7 @@ where
                 );
             }
 
-            let dp_validator = ctx.dzkp_validator(steps, num_bernoulli);
+            let dp_validator = ctx.clone().dzkp_validator(steps, num_bernoulli);
 
             let noisy_histogram = apply_dp_noise::<_, B, OV>(
                 dp_validator.context(),
@@ -308,14 +434,44 @@ where
 
             dp_validator.validate().await?;
 
-            Ok(noisy_histogram)
+            noisy_histogram
         }
-        DpMechanism::DiscreteLaplace { epsilon } => {
-            let noise_params = NoiseParams {
-                epsilon,
-                per_user_credit_cap: 2_u32.pow(u32::try_from(SS_BITS).unwrap()),
-                ..Default::default()
-            };
+        DpMechanism::Skellam { epsilon, delta } => {
+            if epsilon <= 0.0 || epsilon > MAX_EPSILON {
+                return Err(EpsilonOutOfBounds);
+            }
+
+            let per_user_credit_cap = 2_u32.pow(u32::try_from(SS_BITS).unwrap());
+            let noise_params = binomial_noise_params(epsilon, delta, per_user_credit_cap, B);
+            let poisson_mean = find_smallest_skellam_poisson_mean(&noise_params);
+            tracing::info!(
+                "In dp_for_histogram with Skellam noise: \
+                epsilon = {epsilon}, \
+                delta = {delta}, \
+                rho = {}, \
+                per_user_credit_cap = {per_user_credit_cap}, \
+                poisson_mean = {poisson_mean}, \
+                OV::BITS = {}",
+                skellam_rho(poisson_mean, &noise_params),
+                OV::BITS,
+            );
+
+            let dp_validator = ctx.clone().dzkp_validator(steps, 1);
+
+            let noisy_histogram = apply_skellam_noise::<_, B, OV>(
+                dp_validator.context(),
+                histogram_bin_values,
+                poisson_mean,
+            )
+            .await?;
+
+            dp_validator.validate().await?;
+
+            noisy_histogram
+        }
+        DpMechanism::DiscreteLaplace { epsilon, delta } => {
+            let noise_params =
+                laplace_noise_params(epsilon, delta, 2_u32.pow(u32::try_from(SS_BITS).unwrap()));
 
             let truncated_discret_laplace = OPRFPaddingDp::new(
                 noise_params.epsilon,
@@ -338,7 +494,7 @@ where
                 OV::BITS,
             );
 
-            let dp_validator = ctx.dzkp_validator(steps, 1);
+            let dp_validator = ctx.clone().dzkp_validator(steps, 1);
 
             let noised_output = apply_laplace_noise_pass::<_, OV, B>(
                 &dp_validator.context().narrow(&DPStep::LaplacePass1),
@@ -366,21 +522,289 @@ where
 
             dp_validator.validate().await?;
 
-            Ok(Vec::transposed_from(&noised_output)?)
+            Vec::transposed_from(&noised_output)?
+        }
+    };
+
+    let Some(threshold) = threshold else {
+        return Ok(noisy_histogram);
+    };
+    // noisy values in the upper half of the range of `OV` are negative
+    if u128::from(threshold.get() - 1) >= 1 << (OV::BITS - 1) {
+        return Err(Error::InvalidQueryParameter(
+            format!(
+                "dp threshold {threshold} does not fit in {} bit output values",
+                OV::BITS
+            )
+            .into(),
+        ));
+    }
+    tracing::info!("In dp_for_histogram: suppressing breakdowns below {threshold}");
+
+    let steps = MaliciousProtocolSteps {
+        protocol: &dp_steps.threshold,
+        validate: &dp_steps.threshold_validate,
+    };
+    let dp_validator = ctx.dzkp_validator(steps, 1);
+    let noisy_histogram: [Replicated<OV>; B] =
+        noisy_histogram
+            .try_into()
+            .map_err(|v: Vec<_>| LengthError {
+                expected: B,
+                actual: v.len(),
+            })?;
+    let thresholded = suppress_below_threshold(
+        dp_validator.context(),
+        &BitDecomposed::transposed_from(&noisy_histogram).unwrap(),
+        threshold,
+    )
+    .await?;
+    dp_validator.validate().await?;
+
+    Ok(Vec::transposed_from(&thresholded)?)
+}
+
+/// Replaces every breakdown of `noisy_histogram` whose value is below `threshold` by zero, without
+/// revealing which breakdowns were suppressed. Noise centered at zero wraps small counts around,
+/// so values in the upper half of the range are treated as negative and suppressed as well.
+///
+/// ## Errors
+/// Propagates errors from the multiplication protocol.
+/// ## Panics
+/// If `threshold - 1` does not fit in the lower half of the range of `noisy_histogram` values.
+pub async fn suppress_below_threshold<C, const B: usize>(
+    ctx: C,
+    noisy_histogram: &BitDecomposed<Replicated<Boolean, B>>,
+    threshold: NonZeroU32,
+) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
+where
+    C: Context,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<C, B>,
+{
+    let bits = noisy_histogram.len();
+    let bound = threshold.get() - 1;
+    assert!(
+        u128::from(bound) < 1 << (bits - 1),
+        "threshold {threshold} does not fit in {bits} bit values"
+    );
+    let ctx = ctx.set_total_records(TotalRecords::ONE);
+
+    // a breakdown is kept if it is greater than `threshold - 1`...
+    let bound = public_bits(u128::from(bound), bits);
+    let above = compare_gt::<_, OneHundredTwentyEightBitStep, B>(
+        ctx.narrow(&ThresholdStep::Compare),
+        RecordId::FIRST,
+        noisy_histogram,
+        &bound,
+    )
+    .await?;
+    // ...and not negative
+    let keep = above
+        .multiply(
+            &!noisy_histogram[bits - 1].clone(),
+            ctx.narrow(&ThresholdStep::DropNegative),
+            RecordId::FIRST,
+        )
+        .await?;
+
+    let suppress_ctx = ctx.narrow(&ThresholdStep::Suppress);
+    BitDecomposed::try_from(
+        suppress_ctx
+            .parallel_join(noisy_histogram.iter().enumerate().map(|(i, bit)| {
+                let ctx = suppress_ctx.narrow(&OneHundredTwentyEightBitStep::from(i));
+                let keep = &keep;
+                async move { bit.multiply(keep, ctx, RecordId::FIRST).await }
+            }))
+            .await?,
+    )
+}
+
+/// Parameters of noise added to a histogram, to which every user contributes at most
+/// `per_user_credit_cap`. Skellam noise uses them too.
+fn binomial_noise_params(
+    epsilon: f64,
+    delta: f64,
+    per_user_credit_cap: u32,
+    dimensions: usize,
+) -> NoiseParams {
+    NoiseParams {
+        epsilon,
+        delta,
+        per_user_credit_cap,
+        ell_1_sensitivity: f64::from(per_user_credit_cap),
+        ell_2_sensitivity: f64::from(per_user_credit_cap),
+        ell_infty_sensitivity: f64::from(per_user_credit_cap),
+        dimensions: f64::from(u32::try_from(dimensions).unwrap()),
+        ..Default::default()
+    }
+}
+
+/// Number of fair coins that binomial noise tosses in MPC for every breakdown of a histogram, or
+/// `None` if `dp_params` does not generate noise from coins.
+pub(crate) fn noise_coins(
+    dp_params: DpMechanism,
+    per_user_credit_cap: u32,
+    dimensions: usize,
+) -> Option<u32> {
+    match dp_params {
+        DpMechanism::NoDp | DpMechanism::DiscreteLaplace { .. } | DpMechanism::Skellam { .. } => {
+            None
         }
+        DpMechanism::Binomial { epsilon, delta } => Some(find_smallest_num_bernoulli(
+            &binomial_noise_params(epsilon, delta, per_user_credit_cap, dimensions),
+        )),
+    }
+}
+
+fn laplace_noise_params(epsilon: f64, delta: f64, per_user_credit_cap: u32) -> NoiseParams {
+    NoiseParams {
+        epsilon,
+        delta,
+        per_user_credit_cap,
+        ..Default::default()
+    }
+}
+
+/// Distribution of the noise that [`dp_for_histogram`] adds to every breakdown of the output.
+/// It is reported with the query results, so the report collector can tell how far off the
+/// noisy values may be from the true ones.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OutputNoise {
+    pub mechanism: NoiseMechanism,
+    pub epsilon: f64,
+    pub delta: f64,
+    /// Expected value of the noise. Binomial noise is not centered, so it biases every breakdown
+    /// by this much.
+    pub mean: f64,
+    pub std: f64,
+}
+
+impl OutputNoise {
+    /// Noise added to a histogram with `dimensions` breakdowns, when every user contributes at
+    /// most `per_user_credit_cap` to it.
+    ///
+    /// ## Errors
+    /// If the DP parameters are out of range.
+    pub fn new(
+        dp_params: DpMechanism,
+        per_user_credit_cap: u32,
+        dimensions: usize,
+    ) -> Result<Self, Error> {
+        match dp_params {
+            DpMechanism::NoDp => Ok(Self {
+                mechanism: NoiseMechanism::None,
+                epsilon: 0.0,
+                delta: 0.0,
+                mean: 0.0,
+                std: 0.0,
+            }),
+            DpMechanism::Binomial { epsilon, delta } => {
+                if epsilon <= 0.0 || epsilon > MAX_EPSILON {
+                    return Err(EpsilonOutOfBounds);
+                }
+                let (mean, std) = binomial_noise_mean_std(&binomial_noise_params(
+                    epsilon,
+                    delta,
+                    per_user_credit_cap,
+                    dimensions,
+                ));
+                Ok(Self {
+                    mechanism: NoiseMechanism::Binomial,
+                    epsilon,
+                    delta,
+                    mean,
+                    std,
+                })
+            }
+            DpMechanism::Skellam { epsilon, delta } => {
+                if epsilon <= 0.0 || epsilon > MAX_EPSILON {
+                    return Err(EpsilonOutOfBounds);
+                }
+                let poisson_mean = find_smallest_skellam_poisson_mean(&binomial_noise_params(
+                    epsilon,
+                    delta,
+                    per_user_credit_cap,
+                    dimensions,
+                ));
+                Ok(Self {
+                    mechanism: NoiseMechanism::Skellam,
+                    epsilon,
+                    delta,
+                    mean: 0.0,
+                    std: skellam_noise_std(poisson_mean),
+                })
+            }
+            DpMechanism::DiscreteLaplace { epsilon, delta } => {
+                let (_, std) =
+                    OPRFPaddingDp::new(epsilon, delta, per_user_credit_cap)?.mean_and_std();
+                // every pair of helpers adds a sample that is shifted to be centered at zero
+                Ok(Self {
+                    mechanism: NoiseMechanism::DiscreteLaplace,
+                    epsilon,
+                    delta,
+                    mean: 0.0,
+                    std: 3.0_f64.sqrt() * std,
+                })
+            }
+        }
+    }
+
+    /// The mechanism as seen by the privacy accountant, or `None` if no noise is added. Only
+    /// Skellam noise has a zCDP guarantee: the support of binomial and truncated Laplace noise is
+    /// finite, so they are only (ε, δ)-DP.
+    #[must_use]
+    pub fn mechanism(
+        dp_params: DpMechanism,
+        per_user_credit_cap: u32,
+        dimensions: usize,
+    ) -> Option<Mechanism> {
+        match dp_params {
+            DpMechanism::NoDp => None,
+            DpMechanism::Binomial { epsilon, delta }
+            | DpMechanism::DiscreteLaplace { epsilon, delta } => {
+                Some(Mechanism::approximate(epsilon, delta))
+            }
+            DpMechanism::Skellam { epsilon, delta } => {
+                let noise_params =
+                    binomial_noise_params(epsilon, delta, per_user_credit_cap, dimensions);
+                let poisson_mean = find_smallest_skellam_poisson_mean(&noise_params);
+                Some(Mechanism::zcdp(
+                    skellam_rho(poisson_mean, &noise_params),
+                    delta,
+                ))
+            }
+        }
+    }
+
+    /// Interval that contains the true value of a breakdown with probability at least
+    /// `confidence`, given its `noisy` value. It is derived from Chebyshev's inequality, so it
+    /// holds for every mechanism, at the cost of being wider than an exact interval.
+    ///
+    /// ## Panics
+    /// If `confidence` is not in `[0, 1)`.
+    #[must_use]
+    pub fn confidence_interval(&self, noisy: f64, confidence: f64) -> (f64, f64) {
+        assert!(
+            (0.0..1.0).contains(&confidence),
+            "confidence must be in [0, 1), got {confidence}"
+        );
+        let estimate = noisy - self.mean;
+        let half_width = self.std / (1.0 - confidence).sqrt();
+        (estimate - half_width, estimate + half_width)
     }
 }
 
+/// Truncated discrete Laplace noise, shifted to be centered at zero. Negative samples are
+/// represented modulo `2^OV::BITS`, so that adding them to a histogram value of type `OV`
+/// subtracts from it.
 struct ShiftedTruncatedDiscreteLaplace {
     truncated_discrete_laplace: OPRFPaddingDp,
     shift: u32,
-    modulus: u32,
 }
 
 impl ShiftedTruncatedDiscreteLaplace {
-    /// # Panics
-    /// if `bit_size > 32`
-    pub fn new(noise_params: &NoiseParams, bit_size: u32) -> Result<Self, Error> {
+    pub fn new(noise_params: &NoiseParams) -> Result<Self, Error> {
         // A truncated Discrete Laplace distribution is the same as a truncated Double Geometric distribution.
         // OPRFPaddingDP is currently just a poorly named wrapper on a Truncated Double Geometric
         let truncated_discrete_laplace = OPRFPaddingDp::new(
@@ -389,17 +813,10 @@ impl ShiftedTruncatedDiscreteLaplace {
             noise_params.per_user_credit_cap,
         )?;
         let shift = truncated_discrete_laplace.get_shift();
-        assert!(bit_size <= 32);
-        let modulus = if bit_size < 32 {
-            2_u32.pow(bit_size)
-        } else {
-            u32::MAX
-        };
 
         Ok(Self {
             truncated_discrete_laplace,
             shift,
-            modulus,
         })
     }
 
@@ -417,22 +834,58 @@ impl ShiftedTruncatedDiscreteLaplace {
         OV: BooleanArray + U128Conversions,
     {
         let sample = self.sample(rng);
-        let symmetric_sample = sample.wrapping_sub(self.shift) % self.modulus;
-        match direction_to_excluded_helper {
-            Direction::Left => {
-                AdditiveShare::new(OV::ZERO, OV::truncate_from(u128::from(symmetric_sample)))
-            }
-            Direction::Right => {
-                AdditiveShare::new(OV::truncate_from(u128::from(symmetric_sample)), OV::ZERO)
-            }
-        }
+        // `truncate_from` reduces modulo `2^OV::BITS`, for any `OV` up to 128 bits
+        let symmetric_sample =
+            OV::truncate_from(u128::from(sample).wrapping_sub(u128::from(self.shift)));
+        shares_hidden_from(symmetric_sample, direction_to_excluded_helper)
+    }
+}
+
+/// Skellam noise: the difference of two Poisson samples of the same mean. Negative samples are
+/// represented modulo `2^OV::BITS`, like [`ShiftedTruncatedDiscreteLaplace`] does.
+struct SkellamNoise {
+    poisson: Poisson,
+}
+
+impl SkellamNoise {
+    pub fn new(poisson_mean: u32) -> Result<Self, Error> {
+        Ok(Self {
+            poisson: Poisson::new(f64::from(poisson_mean))?,
+        })
+    }
+
+    pub fn sample_shares<R, OV>(
+        &self,
+        rng: &mut R,
+        direction_to_excluded_helper: Direction,
+    ) -> AdditiveShare<OV>
+    where
+        R: RngCore + CryptoRng,
+        OV: BooleanArray + U128Conversions,
+    {
+        let positive = self.poisson.sample(rng);
+        let negative = self.poisson.sample(rng);
+        let sample = OV::truncate_from(u128::from(positive).wrapping_sub(u128::from(negative)));
+        shares_hidden_from(sample, direction_to_excluded_helper)
+    }
+}
+
+/// Shares of a value that the helper in `direction_to_excluded_helper` does not know. The two
+/// other helpers hold it in the share they have in common, and the excluded helper holds zeros.
+fn shares_hidden_from<OV: BooleanArray>(
+    value: OV,
+    direction_to_excluded_helper: Direction,
+) -> AdditiveShare<OV> {
+    match direction_to_excluded_helper {
+        Direction::Left => AdditiveShare::new(OV::ZERO, value),
+        Direction::Right => AdditiveShare::new(value, OV::ZERO),
     }
 }
 
 /// # Errors
 /// will propagate errors from constructing a `truncated_discrete_laplace` distribution.
 /// # Panics
-/// if `OV::BITS > 32`
+/// if `OV::BITS > 128`
 pub async fn apply_laplace_noise_pass<C, OV, const B: usize>(
     ctx: &C,
     histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
@@ -447,21 +900,45 @@ where
     BitDecomposed<AdditiveShare<Boolean, B>>:
         for<'a> TransposeFrom<&'a [AdditiveShare<OV>; B], Error = Infallible>,
     AdditiveShare<OV>: ReplicatedSecretSharing<OV>,
+{
+    let shifted_truncated_discrete_laplace = ShiftedTruncatedDiscreteLaplace::new(noise_params)?;
+    apply_pairwise_noise_pass::<_, OV, B, _>(
+        ctx,
+        histogram_bin_values,
+        excluded_helper,
+        |rng, direction| shifted_truncated_discrete_laplace.sample_shares(rng, direction),
+    )
+    .await
+}
+
+/// Adds noise that the helpers other than `excluded_helper` sample with `sample_shares`, from the
+/// randomness they share, to every value of the histogram.
+async fn apply_pairwise_noise_pass<C, OV, const B: usize, F>(
+    ctx: &C,
+    histogram_bin_values: BitDecomposed<Replicated<Boolean, B>>,
+    excluded_helper: Role,
+    mut sample_shares: F,
+) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
+where
+    C: Context,
+    OV: BooleanArray + U128Conversions,
+    Boolean: Vectorizable<B> + FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<C, B>,
+    BitDecomposed<AdditiveShare<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [AdditiveShare<OV>; B], Error = Infallible>,
+    AdditiveShare<OV>: ReplicatedSecretSharing<OV>,
+    F: FnMut(&mut InstrumentedSequentialSharedRandomness<'_>, Direction) -> AdditiveShare<OV>,
 {
     let noise_values_array: [AdditiveShare<OV>; B] =
         if let Some(direction_to_excluded_helper) = ctx.role().direction_to(excluded_helper) {
             // Step 1: Helpers `h_i` and `h_i_plus_one` will get the same rng from PRSS
-            // and use it to sample the same random Laplace noise sample from TruncatedDoubleGeometric.
+            // and use it to sample the same random noise sample.
             let (mut left, mut right) = ctx.prss_rng();
             let rng = match direction_to_excluded_helper {
                 Direction::Left => &mut right,
                 Direction::Right => &mut left,
             };
-            let shifted_truncated_discrete_laplace =
-                ShiftedTruncatedDiscreteLaplace::new(noise_params, OV::BITS)?;
-            std::array::from_fn(|_i| {
-                shifted_truncated_discrete_laplace.sample_shares(rng, direction_to_excluded_helper)
-            })
+            std::array::from_fn(|_i| sample_shares(rng, direction_to_excluded_helper))
         } else {
             //  before we can do integer_add we need the excluded Helper to set its shares to zero
             // for these noise values.
@@ -475,7 +952,7 @@ where
     let apply_noise_ctx = ctx
         .narrow(&ApplyDpNoise::ApplyNoise)
         .set_total_records(TotalRecords::ONE);
-    let (histogram_noised, _) = integer_add::<_, ThirtyTwoBitStep, B>(
+    let (histogram_noised, _) = integer_add::<_, OneHundredTwentyEightBitStep, B>(
         apply_noise_ctx,
         RecordId::FIRST,
         &noise_shares_vectorized,
@@ -566,6 +1043,15 @@ fn error(num_bernoulli: u32, noise_params: &NoiseParams) -> f64 {
 /// will panic if can't find smallest `num_bernoulli` less than 10M.
 #[must_use]
 pub fn find_smallest_num_bernoulli(noise_params: &NoiseParams) -> u32 {
+    find_smallest_satisfying(|num_bernoulli| {
+        delta_constraint(num_bernoulli, noise_params)
+            && noise_params.epsilon >= epsilon_constraint(num_bernoulli, noise_params)
+    })
+}
+
+/// smallest value in `[1, 10M]` for which `constraint` holds, for a constraint that keeps holding as
+/// the value grows
+fn find_smallest_satisfying<F: Fn(u32) -> bool>(constraint: F) -> u32 {
     let mut index = 0; // candidate to be smallest `num_beroulli`
     let mut lower: u32 = 1;
     let mut higher: u32 = 10_000_000;
@@ -574,19 +1060,57 @@ pub fn find_smallest_num_bernoulli(noise_params: &NoiseParams) -> u32 {
     // https://medium.com/@berkkantkoc/a-handy-binary-search-template-that-will-save-you-6b36b7b06b8b
     while lower <= higher {
         let mid: u32 = (higher - lower) / 2 + lower;
-        if delta_constraint(mid, noise_params)
-            && noise_params.epsilon >= epsilon_constraint(mid, noise_params)
-        {
+        if constraint(mid) {
             index = mid;
             higher = mid - 1;
         } else {
             lower = mid + 1;
         }
     }
-    assert!(index > 0, "smallest num_bernoulli not found");
+    assert!(
+        index > 0,
+        "smallest value satisfying the constraint not found"
+    );
     index
 }
 
+// Skellam noise calibration. Every pair of helpers adds the difference of two Poisson samples of
+// mean `poisson_mean`, which has variance `2 * poisson_mean`. The output is protected from each
+// helper by the sample of the pair that excludes it, so the guarantee is that of a single sample.
+// The Skellam mechanism paper https://arxiv.org/pdf/2110.04995 (Agarwal, Kairouz and Liu) bounds
+// the Rényi divergence of integer order alpha of Skellam noise of variance mu by
+//     alpha * Delta_2^2 / (2 mu) + min(((2 alpha - 1) Delta_2^2 + 6 Delta_1) / (4 mu^2), 3 Delta_1 / (2 mu)).
+// Taking the second term of the min, and rounding alpha up to an integer, this is at most
+//     alpha * (2 Delta_2^2 + 3 Delta_1) / (2 mu)
+// for every alpha > 1, which is rho-zCDP with rho = (2 Delta_2^2 + 3 Delta_1) / (2 mu).
+
+/// rho of the zCDP guarantee of Skellam noise made of two Poisson samples of mean `poisson_mean`
+#[must_use]
+pub fn skellam_rho(poisson_mean: u32, noise_params: &NoiseParams) -> f64 {
+    let variance = 2.0 * f64::from(poisson_mean);
+    (2.0 * noise_params.ell_2_sensitivity.powi(2) + 3.0 * noise_params.ell_1_sensitivity)
+        / (2.0 * variance)
+}
+
+/// smallest `poisson_mean` such that Skellam noise made of two Poisson samples of that mean is
+/// zCDP with a rho that converts to (epsilon, delta)-DP
+/// # Panics
+/// will panic if can't find smallest `poisson_mean` less than 10M.
+#[must_use]
+pub fn find_smallest_skellam_poisson_mean(noise_params: &NoiseParams) -> u32 {
+    find_smallest_satisfying(|poisson_mean| {
+        zcdp_epsilon(skellam_rho(poisson_mean, noise_params), noise_params.delta)
+            <= noise_params.epsilon
+    })
+}
+
+/// standard deviation of the Skellam noise that all three pairs of helpers add, each with two
+/// Poisson samples of mean `poisson_mean`
+#[must_use]
+pub fn skellam_noise_std(poisson_mean: u32) -> f64 {
+    (3.0 * 2.0 * f64::from(poisson_mean)).sqrt()
+}
+
 /// for a `NoiseParams` struct will return the mean and standard deviation
 /// of the binomial noise
 #[must_use]
@@ -599,8 +1123,12 @@ pub fn binomial_noise_mean_std(noise_params: &NoiseParams) -> (f64, f64) {
 
 #[cfg(all(test, unit_test))]
 mod test {
+    use std::num::NonZeroU32;
+
+    use rand::{rngs::StdRng, SeedableRng};
 
     use crate::{
+        error::Error,
         ff::{
             boolean::Boolean,
             boolean_array::{
@@ -608,16 +1136,22 @@ mod test {
             },
             U128Conversions,
         },
-        helpers::{query::DpMechanism, Direction},
+        helpers::{
+            query::{DpMechanism, NoiseMechanism},
+            Direction,
+        },
         protocol::{
             dp::{
-                apply_dp_noise, delta_constraint, dp_for_histogram, epsilon_constraint, error,
-                find_smallest_num_bernoulli, gen_binomial_noise, NoiseParams,
+                accountant::{zcdp_epsilon, Mechanism, PrivacyAccountant},
+                apply_dp_noise, apply_skellam_noise, binomial_noise_mean_std,
+                binomial_noise_params, delta_constraint, dp_for_histogram, epsilon_constraint,
+                error, find_smallest_num_bernoulli, find_smallest_skellam_poisson_mean,
+                gen_binomial_noise, skellam_noise_std, skellam_rho, NoiseParams, OutputNoise,
                 ShiftedTruncatedDiscreteLaplace,
             },
             ipa_prf::oprf_padding::insecure::OPRFPaddingDp,
         },
-        rand::thread_rng,
+        rand::{thread_rng, Rng},
         secret_sharing::{
             replicated::{
                 semi_honest::{AdditiveShare as Replicated, AdditiveShare},
@@ -634,7 +1168,7 @@ mod test {
     ) -> BitDecomposed<[Boolean; B]> {
         let values = <&[u32; B]>::try_from(values).unwrap();
         BitDecomposed::decompose(bit_width, |i| {
-            values.map(|v| Boolean::from((v >> i) & 1 == 1))
+            values.map(|v| Boolean::from((u128::from(v) >> i) & 1 == 1))
         })
     }
 
@@ -655,8 +1189,7 @@ mod test {
         };
         let mut rng = thread_rng();
         let shifted_truncated_discrete_laplace =
-            ShiftedTruncatedDiscreteLaplace::new(&noise_params, OV::BITS)
-                .expect("Fail test on Error");
+            ShiftedTruncatedDiscreteLaplace::new(&noise_params).expect("Fail test on Error");
         // there is some chance we add 0 noise, especially in smaller fields
         // (e.g., in BA3, and multiple of 3 will also be 3 noise)
         // we attempt this multiple times to try and make sure some noise is being added
@@ -700,15 +1233,43 @@ mod test {
     }
 
     #[test]
-    #[should_panic(expected = "assertion failed: bit_size <= 32")]
-    fn test_shifted_truncated_discrete_laplace_ba64() {
+    fn test_shifted_truncated_discrete_laplace_wide() {
         build_shifted_truncated_discrete_laplace_test::<BA64>();
+        build_shifted_truncated_discrete_laplace_test::<BA112>();
+    }
+
+    fn check_shifted_truncated_discrete_laplace_modulus<OV>()
+    where
+        OV: BooleanArray + U128Conversions,
+    {
+        let noise_params = NoiseParams {
+            epsilon: 1.0,
+            delta: 1e-6,
+            per_user_credit_cap: 8,
+            ..Default::default()
+        };
+        let distribution = ShiftedTruncatedDiscreteLaplace::new(&noise_params).unwrap();
+        let shift = i128::from(distribution.shift);
+        let seed = thread_rng().gen();
+        let mut sample_rng = StdRng::seed_from_u64(seed);
+        let mut share_rng = StdRng::seed_from_u64(seed);
+        for _ in 0..1000 {
+            // negative noise must wrap around modulo 2^OV::BITS
+            let expected = i128::from(distribution.sample(&mut sample_rng)) - shift;
+            #[allow(clippy::cast_sign_loss)]
+            let expected = OV::truncate_from(expected as u128);
+            let share: AdditiveShare<OV> =
+                distribution.sample_shares(&mut share_rng, Direction::Left);
+            assert_eq!(expected, share.right(), "seed = {seed}");
+        }
     }
 
     #[test]
-    #[should_panic(expected = "assertion failed: bit_size <= 32")]
-    fn test_shifted_truncated_discrete_laplace_ba112() {
-        build_shifted_truncated_discrete_laplace_test::<BA112>();
+    fn shifted_truncated_discrete_laplace_modulus() {
+        check_shifted_truncated_discrete_laplace_modulus::<BA8>();
+        check_shifted_truncated_discrete_laplace_modulus::<BA32>();
+        check_shifted_truncated_discrete_laplace_modulus::<BA64>();
+        check_shifted_truncated_discrete_laplace_modulus::<BA112>();
     }
 
     /// Test for discrete truncated laplace
@@ -723,7 +1284,10 @@ mod test {
         const NUM_BREAKDOWNS: u32 = 16;
         const SS_BITS: usize = 3;
         let epsilon = 2.0;
-        let dp_params = DpMechanism::DiscreteLaplace { epsilon };
+        let dp_params = DpMechanism::DiscreteLaplace {
+            epsilon,
+            delta: 1e-6,
+        };
         let world = TestWorld::default();
         let input_values = [0, 0, 0, 0, 1, 1, 1, 1, 100, 100, 100, 100, 10, 20, 30, 40];
 
@@ -732,7 +1296,7 @@ mod test {
         let result = world
             .semi_honest(input, |ctx, input| async move {
                 dp_for_histogram::<_, { NUM_BREAKDOWNS as usize }, OV, SS_BITS>(
-                    ctx, input, dp_params,
+                    ctx, input, dp_params, None,
                 )
                 .await
                 .unwrap()
@@ -771,6 +1335,436 @@ mod test {
         }
     }
 
+    async fn laplace_noise_wide<OV>()
+    where
+        OV: BooleanArray + U128Conversions,
+        Vec<Replicated<OV>>: for<'a> TransposeFrom<
+            &'a BitDecomposed<Replicated<Boolean, 32>>,
+            Error = crate::error::LengthError,
+        >,
+        BitDecomposed<AdditiveShare<Boolean, 32>>:
+            for<'a> TransposeFrom<&'a [AdditiveShare<OV>; 32], Error = std::convert::Infallible>,
+    {
+        const NUM_BREAKDOWNS: usize = 32;
+        const SS_BITS: usize = 3;
+        let epsilon = 2.0;
+        let dp_params = DpMechanism::DiscreteLaplace {
+            epsilon,
+            delta: 1e-6,
+        };
+        // small values get negative noise that wraps around, and values close to the top of
+        // the `OV` range get positive noise that carries into the highest bits
+        let top =
+            u32::try_from(u128::min(u128::from(u32::MAX), (1 << (OV::BITS - 1)) - 1)).unwrap();
+        let input_values = (0..u32::try_from(NUM_BREAKDOWNS).unwrap())
+            .map(|i| if i % 2 == 0 { i } else { top - i })
+            .collect::<Vec<_>>();
+
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        let result: Vec<OV> = world
+            .semi_honest(input, |ctx, input| async move {
+                dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(ctx, input, dp_params, None)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        let per_user_credit_cap = 2_u32.pow(u32::try_from(SS_BITS).unwrap());
+        let (_, std) = OPRFPaddingDp::new(epsilon, 1e-6, per_user_credit_cap)
+            .unwrap()
+            .mean_and_std();
+        let tolerance = 20.0 * 3.0 * std;
+        let modulus = 1_u128 << OV::BITS;
+        assert_eq!(NUM_BREAKDOWNS, result.len());
+        for (input, output) in input_values.iter().zip(result) {
+            let noise = output.as_u128().wrapping_sub(u128::from(*input)) % modulus;
+            let noise = u128::min(noise, modulus - noise);
+            #[allow(clippy::cast_precision_loss)]
+            let noise = noise as f64;
+            assert!(
+                noise < tolerance,
+                "{input} became {output:?} in {} bits, this will fail with a small chance",
+                OV::BITS
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn laplace_noise_ba32() {
+        laplace_noise_wide::<BA32>().await;
+    }
+
+    #[tokio::test]
+    async fn laplace_noise_ba64() {
+        laplace_noise_wide::<BA64>().await;
+    }
+
+    #[tokio::test]
+    async fn laplace_noise_ba112() {
+        laplace_noise_wide::<BA112>().await;
+    }
+
+    #[tokio::test]
+    async fn dp_for_histogram_with_threshold_ba64() {
+        type OV = BA64;
+        const NUM_BREAKDOWNS: usize = 32;
+        const SS_BITS: usize = 3;
+        let dp_params = DpMechanism::DiscreteLaplace {
+            epsilon: 2.0,
+            delta: 1e-6,
+        };
+        let threshold = NonZeroU32::new(1000);
+        let input_values = (0..32)
+            .map(|i| if i % 2 == 0 { i } else { u32::MAX - i })
+            .collect::<Vec<_>>();
+
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        let result: Vec<OV> = world
+            .semi_honest(input, |ctx, input| async move {
+                dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(ctx, input, dp_params, threshold)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        for (input, output) in input_values.iter().zip(result) {
+            let output = output.as_u128();
+            if *input < 1000 {
+                assert_eq!(0, output, "{input} should have been suppressed");
+            } else {
+                assert!(
+                    output.abs_diff(u128::from(*input)) < 500,
+                    "{input} became {output}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn suppress_below_threshold() {
+        type OV = BA8;
+        const NUM_BREAKDOWNS: usize = 8;
+        // 250 and 200 are negative values that wrapped around
+        let input_values = [0, 4, 5, 6, 100, 127, 250, 200];
+        let threshold = NonZeroU32::new(5).unwrap();
+
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        let result: Vec<OV> = world
+            .upgraded_semi_honest(input, |ctx, input| async move {
+                let suppressed = super::suppress_below_threshold(ctx, &input, threshold)
+                    .await
+                    .unwrap();
+                Vec::<Replicated<OV>>::transposed_from(&suppressed).unwrap()
+            })
+            .await
+            .reconstruct();
+
+        assert_eq!(
+            vec![0, 0, 5, 6, 100, 127, 0, 0],
+            result
+                .iter()
+                .map(U128Conversions::as_u128)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn dp_for_histogram_with_threshold() {
+        type OV = BA16;
+        const NUM_BREAKDOWNS: usize = 32;
+        const SS_BITS: usize = 3;
+        let dp_params = DpMechanism::DiscreteLaplace {
+            epsilon: 2.0,
+            delta: 1e-6,
+        };
+        let threshold = NonZeroU32::new(1000);
+        let input_values = (0..32)
+            .map(|i| if i % 2 == 0 { 20 * i } else { 1000 + 1000 * i })
+            .collect::<Vec<_>>();
+
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        let result: Vec<OV> = world
+            .semi_honest(input, |ctx, input| async move {
+                dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(ctx, input, dp_params, threshold)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        // noise is much smaller than the distance of every input to the threshold
+        for (input, output) in input_values.iter().zip(result) {
+            let output = output.as_u128();
+            if *input < 1000 {
+                assert_eq!(0, output, "{input} should have been suppressed");
+            } else {
+                assert!(
+                    output.abs_diff(u128::from(*input)) < 500,
+                    "{input} became {output}"
+                );
+            }
+        }
+
+        // thresholds that do not fit in the positive half of the output are rejected
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        world
+            .semi_honest(input, |ctx, input| async move {
+                let err = dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(
+                    ctx,
+                    input,
+                    DpMechanism::NoDp,
+                    NonZeroU32::new(1 << 15 | 1),
+                )
+                .await
+                .unwrap_err();
+                assert!(matches!(err, Error::InvalidQueryParameter(_)), "{err:?}");
+            })
+            .await;
+
+        // binomial noise is not centered, so it would keep breakdowns below the threshold
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        world
+            .semi_honest(input, |ctx, input| async move {
+                let err = dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(
+                    ctx,
+                    input,
+                    DpMechanism::Binomial {
+                        epsilon: 2.0,
+                        delta: 1e-6,
+                    },
+                    threshold,
+                )
+                .await
+                .unwrap_err();
+                assert!(matches!(err, Error::InvalidQueryParameter(_)), "{err:?}");
+            })
+            .await;
+    }
+
+    #[test]
+    fn output_noise() {
+        let none = OutputNoise::new(DpMechanism::NoDp, 8, 256).unwrap();
+        assert_eq!(NoiseMechanism::None, none.mechanism);
+        assert_eq!((5.0, 5.0), none.confidence_interval(5.0, 0.99));
+
+        let binomial = OutputNoise::new(
+            DpMechanism::Binomial {
+                epsilon: 1.0,
+                delta: 1e-6,
+            },
+            8,
+            256,
+        )
+        .unwrap();
+        assert_eq!(
+            binomial_noise_mean_std(&binomial_noise_params(1.0, 1e-6, 8, 256)),
+            (binomial.mean, binomial.std)
+        );
+        // binomial noise is not centered, so the interval is too
+        let (low, high) = binomial.confidence_interval(binomial.mean, 0.75);
+        assert!((low + high).abs() < 1e-9);
+        assert!((high - 2.0 * binomial.std).abs() < 1e-9);
+
+        let laplace = OutputNoise::new(
+            DpMechanism::DiscreteLaplace {
+                epsilon: 1.0,
+                delta: 1e-6,
+            },
+            8,
+            256,
+        )
+        .unwrap();
+        let (_, std) = OPRFPaddingDp::new(1.0, 1e-6, 8).unwrap().mean_and_std();
+        assert!((laplace.std - 3.0_f64.sqrt() * std).abs() < 1e-9);
+        assert!(laplace.mean.abs() < f64::EPSILON);
+
+        assert!(OutputNoise::new(
+            DpMechanism::Binomial {
+                epsilon: 0.0,
+                delta: 1e-6
+            },
+            8,
+            256
+        )
+        .is_err());
+
+        let skellam = OutputNoise::new(
+            DpMechanism::Skellam {
+                epsilon: 1.0,
+                delta: 1e-6,
+            },
+            8,
+            256,
+        )
+        .unwrap();
+        let poisson_mean =
+            find_smallest_skellam_poisson_mean(&binomial_noise_params(1.0, 1e-6, 8, 256));
+        assert_eq!(NoiseMechanism::Skellam, skellam.mechanism);
+        assert!((skellam.std - skellam_noise_std(poisson_mean)).abs() < 1e-9);
+        assert!(skellam.mean.abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn skellam_calibration() {
+        let noise_params = binomial_noise_params(1.0, 1e-6, 8, 256);
+        let poisson_mean = find_smallest_skellam_poisson_mean(&noise_params);
+        let rho = skellam_rho(poisson_mean, &noise_params);
+        assert!(zcdp_epsilon(rho, 1e-6) <= 1.0);
+        assert!(zcdp_epsilon(skellam_rho(poisson_mean - 1, &noise_params), 1e-6) > 1.0);
+        // rho = (2 Delta_2^2 + 3 Delta_1) / (2 variance), for the variance of a single sample
+        assert!((rho - (2.0 * 64.0 + 3.0 * 8.0) / (4.0 * f64::from(poisson_mean))).abs() < 1e-12);
+
+        // the accountant sees its zCDP guarantee, that converts to at most the requested epsilon
+        let dp_params = DpMechanism::Skellam {
+            epsilon: 1.0,
+            delta: 1e-6,
+        };
+        let mechanism = OutputNoise::mechanism(dp_params, 8, 256).unwrap();
+        assert_eq!(Mechanism::zcdp(rho, 1e-6), mechanism);
+        assert!(mechanism.loss.epsilon <= 1.0);
+        // binomial noise has a finite support, so it has no zCDP guarantee
+        let binomial = DpMechanism::Binomial {
+            epsilon: 1.0,
+            delta: 1e-6,
+        };
+        assert_eq!(
+            Some(Mechanism::approximate(1.0, 1e-6)),
+            OutputNoise::mechanism(binomial, 8, 256)
+        );
+
+        // several histograms with Skellam noise compose better than with binomial noise
+        let histograms = 16;
+        let skellam_total = (0..histograms)
+            .fold(PrivacyAccountant::new(), |acc, _| acc.with(mechanism))
+            .total(1e-6);
+        let binomial_total = (0..histograms)
+            .fold(PrivacyAccountant::new(), |acc, _| {
+                acc.with(Mechanism::approximate(1.0, 1e-6))
+            })
+            .total(1e-6);
+        assert!(
+            skellam_total.epsilon < binomial_total.epsilon,
+            "{skellam_total:?} vs {binomial_total:?}"
+        );
+
+        // more noise is needed for a smaller epsilon or a larger sensitivity
+        let smaller_epsilon = NoiseParams {
+            epsilon: 0.1,
+            ..binomial_noise_params(1.0, 1e-6, 8, 256)
+        };
+        assert!(find_smallest_skellam_poisson_mean(&smaller_epsilon) > poisson_mean);
+        let larger_sensitivity = binomial_noise_params(1.0, 1e-6, 16, 256);
+        assert!(find_smallest_skellam_poisson_mean(&larger_sensitivity) > poisson_mean);
+    }
+
+    /// Noise that `apply_skellam_noise` adds to every value, in a histogram of 256 16 bit values.
+    async fn skellam_noise(input_values: &[u32], poisson_mean: u32) -> Vec<f64> {
+        const NUM_BREAKDOWNS: usize = 256;
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> = vectorize_input(16, input_values);
+        let result: Vec<BA16> = world
+            .upgraded_semi_honest(input, |ctx, input| async move {
+                apply_skellam_noise::<_, NUM_BREAKDOWNS, BA16>(ctx, input, poisson_mean)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        // negative noise wraps around
+        result
+            .iter()
+            .zip(input_values)
+            .map(|(v, input)| {
+                let v = f64::from(u32::try_from(v.as_u128()).unwrap());
+                let v = if v >= 32768.0 { v - 65536.0 } else { v };
+                v - f64::from(*input)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_apply_skellam_noise() {
+        let poisson_mean = 500;
+        let input_values = (0..256).map(|i| i % 4 * 100).collect::<Vec<u32>>();
+        let noise = skellam_noise(&input_values, poisson_mean).await;
+
+        let standard_deviation = skellam_noise_std(poisson_mean);
+        for sample in &noise {
+            assert!(
+                sample.abs() < 5.0 * standard_deviation,
+                "test failed because noise {sample} is more than 5 standard deviations away from \
+                zero. This will fail with a small chance of failure"
+            );
+        }
+
+        // the noise is centered at zero, and its variance matches the calibration
+        #[allow(clippy::cast_precision_loss)]
+        let n = noise.len() as f64;
+        let mean = noise.iter().sum::<f64>() / n;
+        let variance = noise.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert!(
+            mean.abs() < 5.0 * standard_deviation / n.sqrt(),
+            "mean = {mean}"
+        );
+        let expected_variance = standard_deviation.powi(2);
+        assert!(
+            (variance / expected_variance - 1.0).abs() < 0.5,
+            "variance = {variance}, expected {expected_variance}"
+        );
+    }
+
+    #[tokio::test]
+    async fn dp_for_histogram_with_skellam_noise() {
+        type OV = BA16;
+        const NUM_BREAKDOWNS: usize = 32;
+        const SS_BITS: usize = 3;
+        let dp_params = DpMechanism::Skellam {
+            epsilon: 2.0,
+            delta: 1e-6,
+        };
+        let input_values = (0..32).map(|i| 10 * i).collect::<Vec<u32>>();
+
+        let world = TestWorld::default();
+        let input: BitDecomposed<[Boolean; NUM_BREAKDOWNS]> =
+            vectorize_input(OV::BITS as usize, &input_values);
+        let result: Vec<OV> = world
+            .semi_honest(input, |ctx, input| async move {
+                dp_for_histogram::<_, NUM_BREAKDOWNS, OV, SS_BITS>(ctx, input, dp_params, None)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        let std = OutputNoise::new(dp_params, 8, NUM_BREAKDOWNS).unwrap().std;
+        for (input, output) in input_values.iter().zip(result) {
+            let output = u32::try_from(output.as_u128()).unwrap();
+            // negative noise wraps around
+            let noise = if output >= 1 << 15 {
+                f64::from(output) - 65536.0
+            } else {
+                f64::from(output)
+            } - f64::from(*input);
+            assert!(
+                noise.abs() < 5.0 * std,
+                "{input} became {output}, which is more than 5 standard deviations away"
+            );
+        }
+    }
+
     #[test]
     fn test_epsilon_simple_aggregation_case() {
         let noise_params = NoiseParams {
@@ -970,6 +1964,50 @@ mod test {
         println!("result as u32 {result_u32:?}");
     }
 
+    // Tests for the centered noise of apply_skellam_noise
+    #[tokio::test]
+    async fn skellam_noise_256_breakdowns() {
+        let poisson_mean = 500;
+        let noise = skellam_noise(&[0; 256], poisson_mean).await;
+
+        let standard_deviation = skellam_noise_std(poisson_mean);
+        assert_eq!(256, noise.len());
+        for sample in &noise {
+            assert!(sample.abs() < 5.0 * standard_deviation);
+        }
+
+        // the noise is symmetric around zero: positive and negative samples are about as
+        // frequent, and the third moment vanishes. Bounds are 5 standard deviations of the
+        // statistics, so this fails with a small chance.
+        #[allow(clippy::cast_precision_loss)]
+        let n = noise.len() as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let imbalance = noise.iter().filter(|&&x| x > 0.0).count() as f64
+            - noise.iter().filter(|&&x| x < 0.0).count() as f64;
+        assert!(imbalance.abs() < 5.0 * n.sqrt(), "imbalance = {imbalance}");
+        let skewness = noise
+            .iter()
+            .map(|x| (x / standard_deviation).powi(3))
+            .sum::<f64>()
+            / n;
+        assert!(
+            skewness.abs() < 5.0 * (15.0 / n).sqrt(),
+            "skewness = {skewness}"
+        );
+        // the tails are light: the fraction of samples within one standard deviation is close
+        // to the 68% of a normal distribution
+        #[allow(clippy::cast_precision_loss)]
+        let within_one_std = noise
+            .iter()
+            .filter(|x| x.abs() <= standard_deviation)
+            .count() as f64
+            / n;
+        assert!(
+            (within_one_std - 0.683).abs() < 5.0 * (0.683 * 0.317 / n).sqrt(),
+            "{within_one_std} of samples within one standard deviation"
+        );
+    }
+
     #[tokio::test]
     async fn semi_honest_measure_bandwidth() {
         // uncomment the print statements in this test and
@@ -10,10 +10,25 @@ pub(crate) enum DPStep {
     LaplacePass2,
     #[step(child = ApplyDpNoise)]
     LaplacePass3,
+    #[step(child = ApplyDpNoise)]
+    SkellamPass1,
+    #[step(child = ApplyDpNoise)]
+    SkellamPass2,
+    #[step(child = ApplyDpNoise)]
+    SkellamPass3,
 }
 
 #[derive(CompactStep)]
 pub(crate) enum ApplyDpNoise {
-    #[step(child = crate::protocol::boolean::step::ThirtyTwoBitStep)]
+    #[step(child = crate::protocol::boolean::step::OneHundredTwentyEightBitStep)]
     ApplyNoise,
 }
+
+#[derive(CompactStep)]
+pub(crate) enum ThresholdStep {
+    #[step(child = crate::protocol::boolean::step::OneHundredTwentyEightBitStep)]
+    Compare,
+    DropNegative,
+    #[step(child = crate::protocol::boolean::step::OneHundredTwentyEightBitStep)]
+    Suppress,
+}
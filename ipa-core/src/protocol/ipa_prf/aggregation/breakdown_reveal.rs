@@ -44,13 +44,16 @@ use crate::{
 /// Aggregation. This can be thought as a SQL GROUP BY operation.
 ///
 /// The protocol involves four main steps:
-/// 1. Shuffle the data to protect privacy (see [`shuffle_attributions`]).
-/// 2. Reveal breakdown keys. This is the key difference to the previous
+/// 1. Pad the data with dummy records, as `padding_params` asks for, to hide how many
+///    records each breakdown has once breakdown keys are revealed.
+/// 2. Shuffle the data to protect privacy (see [`shuffle_attributions`]).
+/// 3. Reveal breakdown keys. This is the key difference to the previous
 ///    aggregation (see [`reveal_breakdowns`]).
-/// 3. Add all values for each breakdown.
+/// 4. Add all values for each breakdown.
 pub async fn breakdown_reveal_aggregation<C, BK, TV, HV, const B: usize>(
     ctx: C,
     attributed_values: Vec<SecretSharedAttributionOutputs<BK, TV>>,
+    padding_params: &PaddingParameters,
 ) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
 where
     C: Context,
@@ -62,13 +65,12 @@ where
     BitDecomposed<Replicated<Boolean, B>>:
         for<'a> TransposeFrom<&'a [Replicated<TV>; B], Error = Infallible>,
 {
-    let dp_padding_params = PaddingParameters::default();
     // Apply DP padding for Breakdown Reveal Aggregation
     let attributed_values_padded =
         apply_dp_padding::<_, AttributionOutputs<Replicated<BK>, Replicated<TV>>, B>(
             ctx.narrow(&AggregationStep::PaddingDp),
             attributed_values,
-            dp_padding_params,
+            *padding_params,
         )
         .await?;
 
@@ -203,6 +205,7 @@ pub mod tests {
         },
         protocol::ipa_prf::{
             aggregation::breakdown_reveal::breakdown_reveal_aggregation,
+            oprf_padding::PaddingParameters,
             prf_sharding::{AttributionOutputsTestInput, SecretSharedAttributionOutputs},
         },
         secret_sharing::{
@@ -251,12 +254,16 @@ pub mod tests {
                         })
                         .collect();
                     let r: Vec<Replicated<BA8>> =
-                        breakdown_reveal_aggregation::<_, BA5, BA3, BA8, 32>(ctx, aos)
-                            .map_ok(|d: BitDecomposed<Replicated<Boolean, 32>>| {
-                                Vec::transposed_from(&d).unwrap()
-                            })
-                            .await
-                            .unwrap();
+                        breakdown_reveal_aggregation::<_, BA5, BA3, BA8, 32>(
+                            ctx,
+                            aos,
+                            &PaddingParameters::relaxed(),
+                        )
+                        .map_ok(|d: BitDecomposed<Replicated<Boolean, 32>>| {
+                            Vec::transposed_from(&d).unwrap()
+                        })
+                        .await
+                        .unwrap();
                     r
                 })
                 .await
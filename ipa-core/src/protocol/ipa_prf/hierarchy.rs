@@ -0,0 +1,329 @@
+use std::{convert::Infallible, num::NonZeroU32};
+
+use crate::{
+    error::{Error, LengthError},
+    ff::{boolean::Boolean, boolean_array::BooleanArray, U128Conversions},
+    helpers::{
+        query::{BreakdownHierarchy, DpMechanism},
+        repeat_n, TotalRecords,
+    },
+    protocol::{
+        boolean::{or::bool_or, step::OneHundredTwentyEightBitStep},
+        context::{
+            dzkp_validator::DZKPValidator, Context, DZKPUpgraded, MaliciousProtocolSteps,
+            UpgradableContext,
+        },
+        dp::{dp_for_histogram_with_steps, HistogramDpSteps},
+        ipa_prf::{
+            boolean_ops::addition_sequential::integer_add,
+            step::{HierarchyStep, HierarchySumStep, IpaPrfStep},
+        },
+        prss::FromPrss,
+        BooleanProtocols, RecordId,
+    },
+    secret_sharing::{
+        replicated::semi_honest::AdditiveShare as Replicated, BitDecomposed, FieldSimd,
+        TransposeFrom, Vectorizable,
+    },
+};
+
+/// Checks that a histogram of `breakdowns` breakdowns, keyed by `key_bits` bit breakdown keys,
+/// has enough room for the finest level of `hierarchy`.
+///
+/// ## Errors
+/// If the finest level of `hierarchy` needs more bits or more breakdowns.
+pub fn check_breakdown_hierarchy(
+    hierarchy: &BreakdownHierarchy,
+    key_bits: u32,
+    breakdowns: usize,
+) -> Result<(), Error> {
+    if hierarchy.key_bits() > key_bits || 1 << hierarchy.key_bits() > breakdowns {
+        return Err(Error::InvalidQueryParameter(
+            format!(
+                "breakdown hierarchy {hierarchy} needs {} bit breakdown keys, \
+                 this query supports {key_bits} bit breakdown keys and {breakdowns} breakdowns",
+                hierarchy.key_bits()
+            )
+            .into(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Computes the histogram of every level of `hierarchy` from `histogram`, the histogram of all
+/// breakdowns, and adds DP noise to each of them. Every level gets an equal share of the budget
+/// of `dp_params`, see [`BreakdownHierarchy::level_noise`].
+///
+/// The output has the histograms of all levels, concatenated from the coarsest level to the
+/// finest. Breakdowns whose key does not fit in the bits of the finest level do not contribute
+/// to any level. Like aggregation, summing breakdowns into coarser levels saturates at the
+/// maximum value of `HV`.
+///
+/// ## Errors
+/// If `hierarchy` does not fit in `B` breakdowns, or propagates errors from the noise protocols,
+/// see [`crate::protocol::dp::dp_for_histogram`].
+pub async fn hierarchical_dp_for_histogram<C, const B: usize, HV, const SS_BITS: usize>(
+    ctx: C,
+    histogram: BitDecomposed<Replicated<Boolean, B>>,
+    hierarchy: BreakdownHierarchy,
+    dp_params: DpMechanism,
+    threshold: Option<NonZeroU32>,
+) -> Result<Vec<Replicated<HV>>, Error>
+where
+    C: UpgradableContext,
+    Boolean: Vectorizable<B> + FieldSimd<B>,
+    BitDecomposed<Replicated<Boolean, B>>: FromPrss<usize>,
+    HV: BooleanArray + U128Conversions,
+    Replicated<Boolean, B>: BooleanProtocols<DZKPUpgraded<C>, B>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+{
+    check_breakdown_hierarchy(&hierarchy, BreakdownHierarchy::MAX_BITS, B)?;
+
+    // Every coarser level drops the lowest bits of the breakdown keys of the next finer level,
+    // one bit at a time.
+    let validator = ctx.clone().dzkp_validator(
+        MaliciousProtocolSteps {
+            protocol: &IpaPrfStep::Hierarchy,
+            validate: &IpaPrfStep::HierarchyValidate,
+        },
+        1,
+    );
+    let mut bits = hierarchy.key_bits();
+    let mut dropped_bits = 0;
+    let mut level_histogram = histogram;
+    let mut level_histograms = Vec::with_capacity(hierarchy.levels());
+    for &prefix_bits in hierarchy.prefix_bits().iter().rev().skip(1) {
+        let mut coarser_histogram = level_histogram.clone();
+        while bits > prefix_bits {
+            let ctx = validator
+                .context()
+                .narrow(&HierarchyStep::from(dropped_bits))
+                .set_total_records(TotalRecords::ONE);
+            coarser_histogram =
+                sum_pairs::<_, HV, B>(ctx, &coarser_histogram, 1 << (bits - 1)).await?;
+            bits -= 1;
+            dropped_bits += 1;
+        }
+        level_histograms.push(std::mem::replace(&mut level_histogram, coarser_histogram));
+    }
+    level_histograms.push(level_histogram);
+    validator.validate().await?;
+
+    let level_noise = hierarchy.level_noise(dp_params);
+    let mut output = Vec::with_capacity(hierarchy.output_len());
+    for (level, (level_histogram, size)) in level_histograms
+        .into_iter()
+        .rev()
+        .zip(hierarchy.level_sizes())
+        .enumerate()
+    {
+        tracing::info!("In hierarchical_dp_for_histogram: level {level} has {size} breakdowns");
+        let dp_steps = HistogramDpSteps {
+            noise: IpaPrfStep::LevelDifferentialPrivacy(level),
+            noise_validate: IpaPrfStep::LevelDifferentialPrivacyValidate(level),
+            threshold: IpaPrfStep::LevelDpThreshold(level),
+            threshold_validate: IpaPrfStep::LevelDpThresholdValidate(level),
+        };
+        let mut noisy_histogram = dp_for_histogram_with_steps::<_, B, HV, SS_BITS>(
+            ctx.clone(),
+            &dp_steps,
+            level_histogram,
+            level_noise,
+            threshold,
+        )
+        .await?;
+        noisy_histogram.truncate(size);
+        output.extend(noisy_histogram);
+    }
+
+    Ok(output)
+}
+
+/// Sums breakdowns `2 * i` and `2 * i + 1` of `histogram` into breakdown `i`, for every `i`
+/// below `len`. The other breakdowns of the output are zero.
+async fn sum_pairs<C, HV, const B: usize>(
+    ctx: C,
+    histogram: &BitDecomposed<Replicated<Boolean, B>>,
+    len: usize,
+) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
+where
+    C: Context,
+    HV: BooleanArray,
+    Boolean: FieldSimd<B>,
+    Replicated<Boolean, B>: BooleanProtocols<C, B>,
+    Vec<Replicated<HV>>:
+        for<'a> TransposeFrom<&'a BitDecomposed<Replicated<Boolean, B>>, Error = LengthError>,
+    BitDecomposed<Replicated<Boolean, B>>:
+        for<'a> TransposeFrom<&'a [Replicated<HV>; B], Error = Infallible>,
+{
+    let values = Vec::<Replicated<HV>>::transposed_from(histogram)?;
+    let pick = |offset: usize| -> BitDecomposed<Replicated<Boolean, B>> {
+        let picked: [Replicated<HV>; B] = std::array::from_fn(|i| {
+            if i < len {
+                values[2 * i + offset].clone()
+            } else {
+                Replicated::ZERO
+            }
+        });
+        BitDecomposed::transposed_from(&picked).unwrap()
+    };
+
+    let (sum, carry) = integer_add::<_, OneHundredTwentyEightBitStep, B>(
+        ctx.narrow(&HierarchySumStep::Add),
+        RecordId::FIRST,
+        &pick(0),
+        &pick(1),
+    )
+    .await?;
+    // if carry==1 then {all ones} else {sum}
+    bool_or::<_, OneHundredTwentyEightBitStep, _, B>(
+        ctx.narrow(&HierarchySumStep::Select),
+        RecordId::FIRST,
+        &sum,
+        repeat_n(&carry, sum.len()),
+    )
+    .await
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use crate::{
+        ff::{
+            boolean::Boolean,
+            boolean_array::{BA16, BA64, BA8},
+            U128Conversions,
+        },
+        helpers::query::{BreakdownHierarchy, DpMechanism},
+        protocol::ipa_prf::hierarchy::hierarchical_dp_for_histogram,
+        secret_sharing::BitDecomposed,
+        test_fixture::{Reconstruct, Runner, TestWorld},
+    };
+
+    const B: usize = 32;
+
+    fn input(bits: usize, values: &[u32; B]) -> BitDecomposed<[Boolean; B]> {
+        BitDecomposed::decompose(bits, |i| values.map(|v| Boolean::from((v >> i) & 1 == 1)))
+    }
+
+    async fn run(hierarchy: &str, values: &[u32; B], dp_params: DpMechanism) -> Vec<Vec<u128>> {
+        type HV = BA16;
+        let hierarchy = hierarchy.parse::<BreakdownHierarchy>().unwrap();
+        let result: Vec<HV> = TestWorld::default()
+            .malicious(input(16, values), |ctx, input| async move {
+                hierarchical_dp_for_histogram::<_, B, HV, 3>(ctx, input, hierarchy, dp_params, None)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        hierarchy
+            .split(result.iter().map(U128Conversions::as_u128).collect())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn levels() {
+        let values = std::array::from_fn(|i| u32::try_from(i).unwrap());
+        let levels = run("1,2,2", &values, DpMechanism::NoDp).await;
+        assert_eq!(
+            vec![
+                // 0 + 1 + ... + 15, 16 + 17 + ... + 31
+                vec![120, 376],
+                // sums of 4 consecutive breakdowns
+                vec![6, 22, 38, 54, 70, 86, 102, 118],
+                (0..32).collect(),
+            ],
+            levels
+        );
+
+        // breakdowns beyond the finest level are dropped, a single level is the histogram itself
+        let levels = run("2", &values, DpMechanism::NoDp).await;
+        assert_eq!(vec![vec![0, 1, 2, 3]], levels);
+        let levels = run("1,1", &values, DpMechanism::NoDp).await;
+        assert_eq!(vec![vec![1, 5], vec![0, 1, 2, 3]], levels);
+    }
+
+    #[tokio::test]
+    async fn saturates() {
+        let values = [u32::from(u16::MAX) - 1; B];
+        let levels = run("1,4", &values, DpMechanism::NoDp).await;
+        assert_eq!(vec![u128::from(u16::MAX); 2], levels[0]);
+        assert_eq!(vec![u128::from(u16::MAX) - 1; 32], levels[1]);
+    }
+
+    #[tokio::test]
+    async fn values_wider_than_32_bits() {
+        type HV = BA64;
+        let hierarchy = "1,1".parse::<BreakdownHierarchy>().unwrap();
+        let values = [u64::from(u32::MAX); B];
+        let input =
+            BitDecomposed::decompose(64, |i| values.map(|v| Boolean::from((v >> i) & 1 == 1)));
+        let result: Vec<HV> = TestWorld::default()
+            .malicious(input, |ctx, input| async move {
+                hierarchical_dp_for_histogram::<_, B, HV, 3>(
+                    ctx,
+                    input,
+                    hierarchy,
+                    DpMechanism::NoDp,
+                    None,
+                )
+                .await
+                .unwrap()
+            })
+            .await
+            .reconstruct();
+
+        let levels = hierarchy
+            .split(result.iter().map(U128Conversions::as_u128).collect())
+            .unwrap();
+        assert_eq!(vec![2 * u128::from(u32::MAX); 2], levels[0]);
+        assert_eq!(vec![u128::from(u32::MAX); 4], levels[1]);
+    }
+
+    #[tokio::test]
+    async fn noise_on_every_level() {
+        let values = [1000; B];
+        let levels = run(
+            "2,3",
+            &values,
+            DpMechanism::DiscreteLaplace {
+                epsilon: 2.0,
+                delta: 1e-6,
+            },
+        )
+        .await;
+        for (level, expected) in levels.iter().zip([8000, 1000]) {
+            for &value in level {
+                assert!(
+                    value.abs_diff(expected) < 500,
+                    "{value} is too far from {expected}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_hierarchy_larger_than_histogram() {
+        type HV = BA8;
+        let hierarchy = "3,3".parse::<BreakdownHierarchy>().unwrap();
+        let values = [0; B];
+        TestWorld::default()
+            .semi_honest(input(8, &values), |ctx, input| async move {
+                hierarchical_dp_for_histogram::<_, B, HV, 3>(
+                    ctx,
+                    input,
+                    hierarchy,
+                    DpMechanism::NoDp,
+                    None,
+                )
+                .await
+                .unwrap_err()
+            })
+            .await;
+    }
+}
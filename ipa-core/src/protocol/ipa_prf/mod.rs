@@ -45,6 +45,7 @@ use crate::{
 
 pub(crate) mod aggregation;
 pub mod boolean_ops;
+pub mod hierarchy;
 pub mod oprf_padding;
 pub mod prf_eval;
 pub mod prf_sharding;
@@ -92,11 +93,16 @@ pub const SORT_CHUNK: usize = 256;
 use step::IpaPrfStep as Step;
 
 use crate::{
-    helpers::query::DpMechanism,
+    helpers::query::{BreakdownHierarchy, DpMechanism},
     protocol::{
         context::Validator,
         dp::dp_for_histogram,
-        ipa_prf::{oprf_padding::PaddingParameters, prf_eval::PrfSharing, shuffle::Shuffle},
+        ipa_prf::{
+            hierarchy::{check_breakdown_hierarchy, hierarchical_dp_for_histogram},
+            oprf_padding::PaddingParameters,
+            prf_eval::PrfSharing,
+            shuffle::Shuffle,
+        },
     },
     secret_sharing::replicated::semi_honest::AdditiveShare,
 };
@@ -216,6 +222,9 @@ where
 /// 8. Aggregates the contributions of all users
 /// 9. Adds random noise to the total for each breakdown key (to provide a differential
 ///    privacy guarantee)
+///
+/// If `breakdown_hierarchy` is set, the output has the totals of every level of the hierarchy
+/// instead, see [`hierarchy::hierarchical_dp_for_histogram`].
 /// # Errors
 /// Propagates errors from config issues or while running the protocol
 /// # Panics
@@ -225,7 +234,9 @@ pub async fn oprf_ipa<'ctx, C, BK, TV, HV, TS, const SS_BITS: usize, const B: us
     input_rows: Vec<OPRFIPAInputRow<BK, TV, TS>>,
     attribution_window_seconds: Option<NonZeroU32>,
     dp_params: DpMechanism,
+    dp_threshold: Option<NonZeroU32>,
     dp_padding_params: PaddingParameters,
+    breakdown_hierarchy: Option<BreakdownHierarchy>,
 ) -> Result<Vec<Replicated<HV>>, Error>
 where
     C: UpgradableContext + 'ctx + Shuffle,
@@ -261,8 +272,14 @@ where
     BitDecomposed<AdditiveShare<Boolean, B>>:
         for<'a> TransposeFrom<&'a [AdditiveShare<HV>; B], Error = Infallible>,
 {
+    let output_len = if let Some(hierarchy) = &breakdown_hierarchy {
+        check_breakdown_hierarchy(hierarchy, BK::BITS, B)?;
+        hierarchy.output_len()
+    } else {
+        B
+    };
     if input_rows.is_empty() {
-        return Ok(vec![Replicated::ZERO; B]);
+        return Ok(vec![Replicated::ZERO; output_len]);
     }
 
     // Apply DP padding for OPRF
@@ -281,7 +298,7 @@ where
     let (row_count_histogram, ranges) = histograms_ranges_sortkeys(&mut prfd_inputs);
     if row_count_histogram.len() == 1 {
         // No user has more than one record.
-        return Ok(vec![Replicated::ZERO; B]);
+        return Ok(vec![Replicated::ZERO; output_len]);
     }
     quicksort_ranges_by_key_insecure(
         ctx.narrow(&Step::SortByTimestamp),
@@ -297,11 +314,23 @@ where
         prfd_inputs,
         attribution_window_seconds,
         &row_count_histogram,
+        &dp_padding_params,
     )
     .await?;
 
-    let noisy_output_histogram =
-        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, output_histogram, dp_params).await?;
+    let noisy_output_histogram = if let Some(hierarchy) = breakdown_hierarchy {
+        hierarchical_dp_for_histogram::<_, B, HV, SS_BITS>(
+            ctx,
+            output_histogram,
+            hierarchy,
+            dp_params,
+            dp_threshold,
+        )
+        .await?
+    } else {
+        dp_for_histogram::<_, B, HV, SS_BITS>(ctx, output_histogram, dp_params, dp_threshold)
+            .await?
+    };
     Ok(noisy_output_histogram)
 }
 
@@ -408,7 +437,7 @@ pub mod tests {
             boolean_array::{BA16, BA20, BA3, BA5, BA8},
             U128Conversions,
         },
-        helpers::query::DpMechanism,
+        helpers::query::{BreakdownHierarchy, DpMechanism},
         protocol::{
             dp::NoiseParams,
             ipa_prf::{oprf_ipa, oprf_padding::PaddingParameters},
@@ -458,7 +487,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -498,7 +529,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -513,12 +546,58 @@ pub mod tests {
         });
     }
 
+    #[test]
+    fn malicious_with_breakdown_hierarchy() {
+        // coarse level with the top bit of the breakdown key, then the full breakdown key
+        const EXPECTED: &[&[u128]] = &[&[7, 0], &[0, 2, 5, 0, 0, 0, 0, 0]];
+
+        run(|| async {
+            let world = TestWorld::default();
+
+            let records: Vec<TestRawDataRecord> = vec![
+                test_input(0, 12345, false, 1, 0),
+                test_input(5, 12345, false, 2, 0),
+                test_input(10, 12345, true, 0, 5),
+                test_input(0, 68362, false, 1, 0),
+                test_input(20, 68362, true, 0, 2),
+            ];
+            let hierarchy = "1,2".parse::<BreakdownHierarchy>().unwrap();
+            let padding_params = PaddingParameters::relaxed();
+
+            let result: Vec<BA16> = world
+                .malicious(records.into_iter(), |ctx, input_rows| async move {
+                    oprf_ipa::<_, BA5, BA3, BA16, BA20, 5, 32>(
+                        ctx,
+                        input_rows,
+                        None,
+                        DpMechanism::NoDp,
+                        None,
+                        padding_params,
+                        Some(hierarchy),
+                    )
+                    .await
+                    .unwrap()
+                })
+                .await
+                .reconstruct();
+            assert_eq!(
+                hierarchy
+                    .split(result.iter().map(|&v| v.as_u128()).collect())
+                    .unwrap(),
+                EXPECTED,
+            );
+        });
+    }
+
     #[test]
     fn semi_honest_with_dp() {
         const SS_BITS: usize = 1;
         // setting SS_BITS this small will cause clipping in capping
         // since per_user_credit_cap == 2^SS_BITS
-        semi_honest_with_dp_internal::<SS_BITS>(DpMechanism::DiscreteLaplace { epsilon: 5.0 });
+        semi_honest_with_dp_internal::<SS_BITS>(DpMechanism::DiscreteLaplace {
+            epsilon: 5.0,
+            delta: 1e-6,
+        });
     }
     #[test]
     fn semi_honest_with_dp_slow() {
@@ -526,7 +605,10 @@ pub mod tests {
         if std::env::var("EXEC_SLOW_TESTS").is_err() {
             return;
         }
-        semi_honest_with_dp_internal::<SS_BITS>(DpMechanism::Binomial { epsilon: 10.0 });
+        semi_honest_with_dp_internal::<SS_BITS>(DpMechanism::Binomial {
+            epsilon: 10.0,
+            delta: 1e-6,
+        });
     }
 
     fn semi_honest_with_dp_internal<const SS_BITS: usize>(_dp_mechanism: DpMechanism) {
@@ -536,7 +618,10 @@ pub mod tests {
             const B: usize = 32; // number of histogram bins
             let expected: Vec<u32> = vec![0, 2, 5, 0, 0, 0, 0, 0];
             let epsilon = 10.0;
-            let dp_params = DpMechanism::Binomial { epsilon };
+            let dp_params = DpMechanism::Binomial {
+                epsilon,
+                delta: 1e-6,
+            };
             let per_user_credit_cap = 2_f64.powi(i32::try_from(SS_BITS).unwrap());
             let padding_params = PaddingParameters::relaxed();
             let world = TestWorld::default();
@@ -555,7 +640,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -616,7 +703,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -652,7 +741,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -706,7 +797,9 @@ pub mod tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
@@ -823,7 +916,9 @@ mod compact_gate_tests {
                         input_rows,
                         None,
                         dp_params,
+                        None,
                         padding_params,
+                        None,
                     )
                     .await
                     .unwrap()
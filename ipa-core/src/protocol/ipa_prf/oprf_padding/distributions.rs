@@ -173,6 +173,104 @@ impl Distribution<u32> for TruncatedDoubleGeometric {
     }
 }
 
+/// Largest mean accepted by [`Poisson`]. Samples stay far from the largest integer that `f64`
+/// represents exactly.
+pub const MAX_POISSON_MEAN: f64 = 1e12;
+
+/// Poisson distribution with the given mean.
+///
+/// Small means are sampled by multiplying uniforms (Knuth), larger ones with the transformed
+/// rejection method PTRS of [`Hörmann`], whose running time does not depend on the mean.
+///
+/// [`Hörmann`]: https://doi.org/10.1016/0167-6687(93)90997-4
+#[derive(Debug, PartialEq)]
+pub struct Poisson {
+    mean: f64,
+}
+
+impl Poisson {
+    /// Means from which PTRS is used.
+    const PTRS_MEAN: f64 = 10.0;
+
+    /// Creates a new `Poisson` distribution with the given mean.
+    pub fn new(mean: f64) -> Result<Self, Error> {
+        if !(f64::MIN_POSITIVE..=MAX_POISSON_MEAN).contains(&mean) {
+            return Err(Error::BadPoissonMean(mean));
+        }
+        Ok(Self { mean })
+    }
+
+    fn sample_knuth<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        let limit = (-self.mean).exp();
+        let mut product = rng.gen::<f64>();
+        let mut count = 0;
+        while product > limit {
+            product *= rng.gen::<f64>();
+            count += 1;
+        }
+        count
+    }
+
+    fn sample_ptrs<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        let log_mean = self.mean.ln();
+        let b = 0.931 + 2.53 * self.mean.sqrt();
+        let a = -0.059 + 0.02483 * b;
+        let inv_alpha = 1.1239 + 1.1328 / (b - 3.4);
+        let v_r = 0.9277 - 3.6224 / (b - 2.0);
+        loop {
+            let centered = rng.gen::<f64>() - 0.5;
+            let uniform = rng.gen::<f64>();
+            let u_s = 0.5 - centered.abs();
+            let candidate = ((2.0 * a / u_s + b) * centered + self.mean + 0.43).floor();
+            if u_s >= 0.07 && uniform <= v_r {
+                return as_count(candidate);
+            }
+            if candidate < 0.0 || (u_s < 0.013 && uniform > u_s) {
+                continue;
+            }
+            if uniform.ln() + inv_alpha.ln() - (a / (u_s * u_s) + b).ln()
+                <= -self.mean + candidate * log_mean - ln_factorial(candidate)
+            {
+                return as_count(candidate);
+            }
+        }
+    }
+}
+
+impl Distribution<u64> for Poisson {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        if self.mean < Self::PTRS_MEAN {
+            self.sample_knuth(rng)
+        } else {
+            self.sample_ptrs(rng)
+        }
+    }
+}
+
+/// `k` is a non-negative integer, at most a few standard deviations above [`MAX_POISSON_MEAN`].
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn as_count(k: f64) -> u64 {
+    debug_assert!(k >= 0.0 && k.fract() == 0.0);
+    k as u64
+}
+
+/// ln(k!) for a non-negative integer `k`: exact for small `k`, and from the Stirling series of
+/// ln Γ(k + 1) otherwise, which is accurate to about 1e-10 from there.
+fn ln_factorial(k: f64) -> f64 {
+    const STIRLING_FROM: f64 = 10.0;
+    if k < STIRLING_FROM {
+        let mut ln_factorial = 0.0;
+        let mut i = 2.0;
+        while i <= k {
+            ln_factorial += f64::ln(i);
+            i += 1.0;
+        }
+        return ln_factorial;
+    }
+    let n = k + 1.0;
+    (n - 0.5) * n.ln() - n + 0.5 * (2.0 * PI).ln() + 1.0 / (12.0 * n) - 1.0 / (360.0 * n.powi(3))
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
     use std::{collections::HashMap, f64::consts::E, iter::repeat_with};
@@ -182,7 +280,8 @@ mod tests {
 
     use crate::protocol::ipa_prf::oprf_padding::{
         distributions::{
-            is_close, BoxMuller, DoubleGeometric, Geometric, TruncatedDoubleGeometric,
+            is_close, ln_factorial, BoxMuller, DoubleGeometric, Geometric, Poisson,
+            TruncatedDoubleGeometric, MAX_POISSON_MEAN,
         },
         insecure::Error,
     };
@@ -410,4 +509,90 @@ mod tests {
             );
         }
     }
+
+    /// Tests for Poisson
+    #[test]
+    fn test_poisson_constructor() {
+        for mean in [0.0, -1.0, f64::NAN, 2.0 * MAX_POISSON_MEAN] {
+            assert!(matches!(Poisson::new(mean), Err(Error::BadPoissonMean(_))));
+        }
+        assert!(Poisson::new(MAX_POISSON_MEAN).is_ok());
+    }
+
+    #[test]
+    fn test_ln_factorial() {
+        let mut exact = 0.0;
+        for k in 0..200 {
+            if k > 1 {
+                exact += f64::ln(f64::from(k));
+            }
+            let actual = ln_factorial(f64::from(k));
+            assert!(
+                (actual - exact).abs() < 1e-9 * exact.max(1.0),
+                "ln({k}!) = {exact}, got {actual}"
+            );
+        }
+    }
+
+    /// Compares the observed probability of the values around the mean with the Poisson pmf.
+    fn check_poisson_pmf(mean: f64) {
+        let mut rng = rand::thread_rng();
+        let poisson = Poisson::new(mean).unwrap();
+        let num_samples = 100_000;
+        let mut histogram = HashMap::new();
+        for _ in 0..num_samples {
+            *histogram.entry(poisson.sample(&mut rng)).or_insert(0) += 1;
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let high = (mean + 5.0 * mean.sqrt()) as u32;
+        for x in 0..=high {
+            let observed_probability = histogram
+                .get(&u64::from(x))
+                .map_or(0.0, |count| f64::from(*count) / f64::from(num_samples));
+            let expected_probability =
+                (f64::from(x) * mean.ln() - mean - ln_factorial(f64::from(x))).exp();
+            assert!(
+                (observed_probability - expected_probability).abs() <= 0.005,
+                "mean = {mean}, x = {x}: observed probability {observed_probability}, \
+                expected {expected_probability}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_poisson_sample_dist() {
+        // below and above the mean from which PTRS is used
+        check_poisson_pmf(3.5);
+        check_poisson_pmf(30.0);
+    }
+
+    #[test]
+    fn test_poisson_large_mean() {
+        let mut rng = rand::thread_rng();
+        let mean = 1e6;
+        let poisson = Poisson::new(mean).unwrap();
+        let num_samples = 10_000;
+        #[allow(clippy::cast_precision_loss)]
+        let samples = repeat_with(|| poisson.sample(&mut rng) as f64)
+            .take(num_samples)
+            .collect::<Vec<_>>();
+        #[allow(clippy::cast_precision_loss)]
+        let n = num_samples as f64;
+        let sample_mean = samples.iter().sum::<f64>() / n;
+        let sample_variance = samples
+            .iter()
+            .map(|x| (x - sample_mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+        // the mean and the variance of Poisson are both `mean`. Bounds are about 5 standard
+        // deviations of the estimates.
+        assert!(
+            (sample_mean - mean).abs() < 5.0 * (mean / n).sqrt(),
+            "sample mean {sample_mean}"
+        );
+        assert!(
+            (sample_variance / mean - 1.0).abs() < 5.0 * (2.0 / n).sqrt(),
+            "sample variance {sample_variance}"
+        );
+    }
 }
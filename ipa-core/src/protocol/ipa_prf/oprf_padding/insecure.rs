@@ -6,7 +6,7 @@ use rand::distributions::{BernoulliError, Distribution};
 use rand_core::{CryptoRng, RngCore};
 
 use crate::protocol::ipa_prf::oprf_padding::distributions::{
-    BoxMuller, RoundedBoxMuller, TruncatedDoubleGeometric,
+    BoxMuller, RoundedBoxMuller, TruncatedDoubleGeometric, MAX_POISSON_MEAN,
 };
 
 pub type DpError = Error;
@@ -27,6 +27,11 @@ pub enum Error {
         f64::MIN_POSITIVE
     )]
     BadGeometricProb(f64),
+    #[error(
+        "Valid values for the mean of Poisson are within {:?}, got: {0}",
+        f64::MIN_POSITIVE..=MAX_POISSON_MEAN
+    )]
+    BadPoissonMean(f64),
     #[error(
         "Shift value over 1M -- likely don't need it that large and preventing to avoid any chance of overflow
         in Double Geometric sample",
@@ -240,50 +245,28 @@ impl OPRFPaddingDp {
         self.truncated_double_geometric.shift_doubled / 2
     }
 
-    /// We want to exactly compute the mean and standard deviation of the `OPRFPaddingDp` distribution.
-    /// This the calculation for a Truncated Laplace distribution (TODO this is not discrete):
-    /// <https://stats.stackexchange.com/questions/251300/first-and-second-moments-of-truncated-laplace-distribution>
-    /// where the notation translates as
-    ///    \mu = n
-    ///    \sigma = s
-    ///    2*\sigma = c(s) the normalizing constant discussed in the README
-    /// The link describes how to compute the first and second moments of the Truncated Laplace Distribution
-    /// which are the expected value, E[X], and E[X^2].  From these we can compute the Variance as
-    /// Var[X] = E[X^2] - E[X]^2 and standard deviation as the sqrt of the Variance.
-    /// TODO is the std of a Truncated Laplace distribution an upper bound on the std of a
-    /// Discrete Truncated Laplace distribution with matching parameters?
+    /// Mean and standard deviation of a continuous Laplace distribution with the same scale,
+    /// truncated to the same support. It approximates the discrete distribution for small
+    /// epsilon, see [`Self::mean_and_std`] for the exact values.
+    ///
+    /// The mean is the shift, as the distribution is symmetric around it. A Laplace distribution
+    /// with scale `s` truncated to `[-n, n]` has variance
+    ///    (2s^2 - e^{-n/s} (n^2 + 2sn + 2s^2)) / (1 - e^{-n/s})
+    /// which follows from integrating `x^2 e^{-x/s}` and `e^{-x/s}` over `[0, n]`.
     #[must_use]
     pub fn mean_and_std_truncated_laplace(&self) -> (f64, f64) {
-        let mean = f64::from(self.truncated_double_geometric.shift_doubled) / 2.0;
-        let std = (self.first_moment() + pow_u32(self.second_moment(), 2)).sqrt();
-        (mean, std)
-    }
-
-    /// computes E[X], which is the Out[5] formula from stackexchange answer
-    fn first_moment(&self) -> f64 {
-        let mu = f64::from(self.truncated_double_geometric.shift_doubled) / 2.0;
-        let sigma = 1.0 / self.epsilon;
-        let numerator = 1.0 + 2.0 * f64::exp((1.0 + mu) / sigma) * mu + sigma
-            - f64::exp(2.0 * mu / sigma) * (1.0 + sigma);
-        let denominator = 1.0 + f64::exp(2.0 * mu / sigma) - 2.0 * f64::exp((1.0 + mu) / sigma);
-        -numerator / denominator
-    }
-
-    /// computes E[X^2], which is the Out[6] formula from stackexchange answer
-    fn second_moment(&self) -> f64 {
-        let mu = f64::from(self.truncated_double_geometric.shift_doubled) / 2.0;
-        let sigma = 1.0 / self.epsilon;
-        let third_term_num =
-            2.0 * f64::exp((1.0 + mu) / sigma) * (1.0 - pow_u32(mu, 2) + 2.0 * sigma);
-        let third_term_den = 1.0 + f64::exp(2.0 * mu / sigma) - 2.0 * f64::exp((1.0 + mu) / sigma);
-        1.0 + 2.0 * sigma * (1.0 + sigma) + third_term_num / third_term_den
+        let n = f64::from(self.truncated_double_geometric.shift_doubled) / 2.0;
+        let s = 1.0 / self.epsilon;
+        let tail = f64::exp(-n / s);
+        let variance = (2.0 * s * s - tail * (n * n + 2.0 * s * n + 2.0 * s * s)) / (1.0 - tail);
+        (n, variance.sqrt())
     }
 
     /// Mean and std of a Discrete Truncated Laplace
     ///
     ///
     /// The pdf of a discrete truncated laplace is given in eqs (9) and (10) in <https://arxiv.org/pdf/2110.08177>
-    fn pdf_discrete_truncated_laplace(&self, x: u32) -> f64 {
+    pub(crate) fn pdf_discrete_truncated_laplace(&self, x: u32) -> f64 {
         let r = E.powf(-self.epsilon);
         let n = self.truncated_double_geometric.shift_doubled / 2;
         let a = (1.0 - r) / (1.0 + r - 2.0 * (pow_u32(r, n + 1)));
@@ -557,4 +540,22 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn truncated_laplace_approximation() {
+        // the continuous approximation gets closer to the discrete distribution as epsilon
+        // decreases
+        for (epsilon, tolerance) in [(0.1, 0.001), (0.5, 0.015), (1.0, 0.05)] {
+            for sensitivity in [2, 10] {
+                let padding = OPRFPaddingDp::new(epsilon, 1e-6, sensitivity).unwrap();
+                let (mean, std) = padding.mean_and_std();
+                let (approx_mean, approx_std) = padding.mean_and_std_truncated_laplace();
+                assert!((mean - approx_mean).abs() < 1e-6, "{mean} vs {approx_mean}");
+                assert!(
+                    (approx_std / std - 1.0).abs() < tolerance,
+                    "epsilon = {epsilon}: {std} vs {approx_std}"
+                );
+            }
+        }
+    }
 }
@@ -2,9 +2,12 @@ pub(crate) mod distributions;
 pub mod insecure;
 pub mod step;
 
+use futures::stream;
+use futures_util::{StreamExt, TryStreamExt};
 #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
 pub use insecure::DiscreteDp as InsecureDiscreteDp;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use tokio::try_join;
 
 use crate::{
@@ -12,26 +15,32 @@ use crate::{
     error::Error,
     ff::{
         boolean::Boolean,
-        boolean_array::{BooleanArray, BA32, BA64},
-        U128Conversions,
+        boolean_array::{BooleanArray, BA112, BA32, BA64},
+        ArrayAccess, U128Conversions,
     },
     helpers::{Direction, Role, TotalRecords},
     protocol::{
+        basics::{semi_honest_reveal, ShareKnownValue},
         context::{prss::InstrumentedSequentialSharedRandomness, Context},
+        dp::{accountant::Mechanism, find_smallest_num_bernoulli, NoiseParams},
         ipa_prf::{
+            boolean_ops::{expand_shared_array_in_place, extract_from_shared_array},
             oprf_padding::{
                 insecure::OPRFPaddingDp,
-                step::{PaddingDpStep, SendTotalRows},
+                step::{JointPaddingStep, PaddingDpStep, SendTotalRows},
             },
             prf_sharding::AttributionOutputs,
+            shuffle::base::shuffle_protocol,
             OPRFIPAInputRow,
         },
+        prss::SharedRandomness,
         RecordId,
     },
     secret_sharing::{
         replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
         SharedValue,
     },
+    seq_join::seq_join,
 };
 
 /// Parameter struct for padding parameters.
@@ -39,6 +48,22 @@ use crate::{
 pub struct PaddingParameters {
     pub aggregation_padding: AggregationPadding,
     pub oprf_padding: OPRFPadding,
+    pub sampling: PaddingSampling,
+}
+
+/// How the number of dummies that padding adds is sampled.
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaddingSampling {
+    /// Every pair of helpers samples how many dummies it adds from a truncated discrete Laplace
+    /// distribution, with randomness that the pair shares. Both helpers of the pair know these
+    /// counts, the padding hides them from the third helper.
+    #[default]
+    Pairwise,
+    /// The three helpers toss fair coins from PRSS, and generate a dummy for every coin. Dummies
+    /// are shuffled before coins are revealed, and those whose coin is not set are dropped. The
+    /// counts follow a binomial distribution, and no helper knows them.
+    Joint,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -99,6 +124,7 @@ impl PaddingParameters {
                 matchkey_cardinality_cap: 3,
                 oprf_padding_sensitivity: 2,
             },
+            sampling: PaddingSampling::Pairwise,
         }
     }
 
@@ -107,8 +133,212 @@ impl PaddingParameters {
         PaddingParameters {
             aggregation_padding: AggregationPadding::NoAggPadding,
             oprf_padding: OPRFPadding::NoOPRFPadding,
+            sampling: PaddingSampling::Pairwise,
         }
     }
+
+    /// Both kinds of padding with the same budget. Input padding hides match keys with up to
+    /// `matchkey_cardinality_cap` records, aggregation padding hides up to
+    /// `aggregation_padding_sensitivity` records per breakdown.
+    #[must_use]
+    pub fn new(
+        epsilon: f64,
+        delta: f64,
+        matchkey_cardinality_cap: u32,
+        aggregation_padding_sensitivity: u32,
+    ) -> Self {
+        PaddingParameters {
+            aggregation_padding: AggregationPadding::Parameters {
+                aggregation_epsilon: epsilon,
+                aggregation_delta: delta,
+                aggregation_padding_sensitivity,
+            },
+            oprf_padding: OPRFPadding::Parameters {
+                oprf_epsilon: epsilon,
+                oprf_delta: delta,
+                matchkey_cardinality_cap,
+                oprf_padding_sensitivity: 2,
+            },
+            sampling: PaddingSampling::Pairwise,
+        }
+    }
+
+    /// Both kinds of padding, as seen by the privacy accountant.
+    pub fn mechanisms(&self) -> impl Iterator<Item = Mechanism> {
+        let aggregation = match self.aggregation_padding {
+            AggregationPadding::NoAggPadding => None,
+            AggregationPadding::Parameters {
+                aggregation_epsilon,
+                aggregation_delta,
+                ..
+            } => Some(Mechanism::approximate(
+                aggregation_epsilon,
+                aggregation_delta,
+            )),
+        };
+        let oprf = match self.oprf_padding {
+            OPRFPadding::NoOPRFPadding => None,
+            OPRFPadding::Parameters {
+                oprf_epsilon,
+                oprf_delta,
+                ..
+            } => Some(Mechanism::approximate(oprf_epsilon, oprf_delta)),
+        };
+
+        aggregation.into_iter().chain(oprf)
+    }
+
+    /// Distribution of the number of dummy match keys added for every cardinality, or `None`
+    /// if inputs are not padded.
+    ///
+    /// ## Errors
+    /// If the padding parameters are out of range.
+    pub fn oprf_noise(&self) -> Result<Option<PaddingNoise>, Error> {
+        match self.oprf_padding {
+            OPRFPadding::NoOPRFPadding => Ok(None),
+            OPRFPadding::Parameters {
+                oprf_epsilon,
+                oprf_delta,
+                matchkey_cardinality_cap,
+                oprf_padding_sensitivity,
+            } => PaddingNoise::new(
+                oprf_epsilon,
+                oprf_delta,
+                oprf_padding_sensitivity,
+                self.sampling,
+                matchkey_cardinality_cap,
+            )
+            .map(Some),
+        }
+    }
+
+    /// Distribution of the number of dummy rows added for every one of `num_breakdowns`
+    /// breakdowns, or `None` if the aggregation is not padded.
+    ///
+    /// ## Errors
+    /// If the padding parameters are out of range.
+    ///
+    /// ## Panics
+    /// If `num_breakdowns` does not fit in `u32`.
+    pub fn aggregation_noise(&self, num_breakdowns: usize) -> Result<Option<PaddingNoise>, Error> {
+        match self.aggregation_padding {
+            AggregationPadding::NoAggPadding => Ok(None),
+            AggregationPadding::Parameters {
+                aggregation_epsilon,
+                aggregation_delta,
+                aggregation_padding_sensitivity,
+            } => PaddingNoise::new(
+                aggregation_epsilon,
+                aggregation_delta,
+                aggregation_padding_sensitivity,
+                self.sampling,
+                u32::try_from(num_breakdowns).unwrap(),
+            )
+            .map(Some),
+        }
+    }
+}
+
+/// Distribution of the number of dummies that every pair of helpers pads with, or that the
+/// three helpers pad with together if `sampling` is joint, reported with the query results.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PaddingNoise {
+    pub epsilon: f64,
+    pub delta: f64,
+    pub sensitivity: u32,
+    #[serde(default)]
+    pub sampling: PaddingSampling,
+    pub mean: f64,
+    pub std: f64,
+}
+
+impl PaddingNoise {
+    /// Padding of `buckets` counts, each of which a user changes by at most `sensitivity`.
+    fn new(
+        epsilon: f64,
+        delta: f64,
+        sensitivity: u32,
+        sampling: PaddingSampling,
+        buckets: u32,
+    ) -> Result<Self, Error> {
+        let (mean, std) = match sampling {
+            PaddingSampling::Pairwise => {
+                OPRFPaddingDp::new(epsilon, delta, sensitivity)?.mean_and_std()
+            }
+            PaddingSampling::Joint => {
+                JointPaddingDp::new(epsilon, delta, sensitivity, buckets)?.mean_and_std()
+            }
+        };
+        Ok(Self {
+            epsilon,
+            delta,
+            sensitivity,
+            sampling,
+            mean,
+            std,
+        })
+    }
+}
+
+/// Number of dummies for padding with [`PaddingSampling::Joint`]: the sum of `coins` fair coins
+/// for every bucket.
+///
+/// Dummy counts are binomial noise, calibrated with the same analysis as the binomial noise of
+/// the output. Binomial noise protects all buckets at once, so unlike [`OPRFPaddingDp`] it
+/// depends on the number of buckets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointPaddingDp {
+    coins: u32,
+}
+
+impl JointPaddingDp {
+    /// Padding of `buckets` counts, each of which a user changes by at most `sensitivity`.
+    ///
+    /// ## Errors
+    /// If epsilon, delta or the sensitivity are out of range.
+    ///
+    /// ## Panics
+    /// If the padding needs more than 10M coins per bucket.
+    pub fn new(
+        epsilon: f64,
+        delta: f64,
+        sensitivity: u32,
+        buckets: u32,
+    ) -> Result<Self, insecure::Error> {
+        if epsilon < f64::MIN_POSITIVE {
+            return Err(insecure::Error::BadEpsilon(epsilon));
+        }
+        if !(f64::MIN_POSITIVE..=1.0 - f64::MIN_POSITIVE).contains(&delta) {
+            return Err(insecure::Error::BadDelta(delta));
+        }
+        if sensitivity > 1_000_000 {
+            return Err(insecure::Error::BadSensitivity(sensitivity));
+        }
+        let sensitivity = f64::from(sensitivity);
+        Ok(Self {
+            coins: find_smallest_num_bernoulli(&NoiseParams {
+                epsilon,
+                delta,
+                dimensions: f64::from(buckets.max(1)),
+                ell_1_sensitivity: sensitivity,
+                ell_2_sensitivity: sensitivity,
+                ell_infty_sensitivity: sensitivity,
+                ..Default::default()
+            }),
+        })
+    }
+
+    /// Coins tossed for every bucket, and so the largest number of dummies it may get.
+    #[must_use]
+    pub fn coins(&self) -> u32 {
+        self.coins
+    }
+
+    #[must_use]
+    pub fn mean_and_std(&self) -> (f64, f64) {
+        let coins = f64::from(self.coins);
+        (coins / 2.0, coins.sqrt() / 2.0)
+    }
 }
 
 /// Paddable trait to support generation of padding for both `OPRFIPAInputRow`s and `AttributionOutputs`
@@ -128,6 +358,27 @@ pub trait Paddable {
     fn add_zero_shares<V: Extend<Self>>(padding_input_rows: &mut V, total_number_of_fake_rows: u32)
     where
         Self: Sized;
+
+    /// Buckets whose counts padding with [`PaddingSampling::Joint`] hides, with the number of
+    /// rows of every dummy of the bucket, or `None` if `padding_params` does not pad `Self`.
+    ///
+    /// # Errors
+    /// may propagate errors from `JointPaddingDp` setup
+    fn joint_padding_buckets<const B: usize>(
+        padding_params: &PaddingParameters,
+    ) -> Result<Option<(JointPaddingDp, Vec<JointPaddingBucket>)>, Error>;
+
+    /// Dummy row of `bucket`. `random` is a random value that no helper knows, and that all rows
+    /// of the same dummy share.
+    fn joint_dummy(bucket: &AdditiveShare<BA32>, random: &AdditiveShare<BA64>) -> Self;
+}
+
+/// Bucket that padding with [`PaddingSampling::Joint`] adds dummies to: a cardinality of match
+/// keys, or a breakdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JointPaddingBucket {
+    pub value: u32,
+    pub rows_per_dummy: u32,
 }
 
 impl<BK, TV, TS> Paddable for OPRFIPAInputRow<BK, TV, TS>
@@ -198,6 +449,45 @@ where
             padding_input_rows.extend(std::iter::once(row));
         }
     }
+
+    fn joint_padding_buckets<const B: usize>(
+        padding_params: &PaddingParameters,
+    ) -> Result<Option<(JointPaddingDp, Vec<JointPaddingBucket>)>, Error> {
+        match padding_params.oprf_padding {
+            OPRFPadding::NoOPRFPadding => Ok(None),
+            OPRFPadding::Parameters {
+                oprf_epsilon,
+                oprf_delta,
+                matchkey_cardinality_cap,
+                oprf_padding_sensitivity,
+            } => {
+                let oprf_padding = JointPaddingDp::new(
+                    oprf_epsilon,
+                    oprf_delta,
+                    oprf_padding_sensitivity,
+                    matchkey_cardinality_cap,
+                )?;
+                // a dummy match key of every cardinality has that many rows
+                let buckets = (1..=matchkey_cardinality_cap)
+                    .map(|cardinality| JointPaddingBucket {
+                        value: cardinality,
+                        rows_per_dummy: cardinality,
+                    })
+                    .collect();
+                Ok(Some((oprf_padding, buckets)))
+            }
+        }
+    }
+
+    fn joint_dummy(_bucket: &AdditiveShare<BA32>, random: &AdditiveShare<BA64>) -> Self {
+        OPRFIPAInputRow {
+            match_key: random.clone(),
+            is_trigger: AdditiveShare::new(Boolean::FALSE, Boolean::FALSE),
+            breakdown_key: AdditiveShare::new(BK::ZERO, BK::ZERO),
+            trigger_value: AdditiveShare::new(TV::ZERO, TV::ZERO),
+            timestamp: AdditiveShare::new(TS::ZERO, TS::ZERO),
+        }
+    }
 }
 
 impl<BK, TV> Paddable for AttributionOutputs<AdditiveShare<BK>, AdditiveShare<TV>>
@@ -270,6 +560,41 @@ where
             padding_input_rows.extend(std::iter::once(row));
         }
     }
+
+    fn joint_padding_buckets<const B: usize>(
+        padding_params: &PaddingParameters,
+    ) -> Result<Option<(JointPaddingDp, Vec<JointPaddingBucket>)>, Error> {
+        match padding_params.aggregation_padding {
+            AggregationPadding::NoAggPadding => Ok(None),
+            AggregationPadding::Parameters {
+                aggregation_epsilon,
+                aggregation_delta,
+                aggregation_padding_sensitivity,
+            } => {
+                let num_breakdowns = u32::try_from(B).unwrap();
+                let aggregation_padding = JointPaddingDp::new(
+                    aggregation_epsilon,
+                    aggregation_delta,
+                    aggregation_padding_sensitivity,
+                    num_breakdowns,
+                )?;
+                let buckets = (0..num_breakdowns)
+                    .map(|breakdownkey| JointPaddingBucket {
+                        value: breakdownkey,
+                        rows_per_dummy: 1,
+                    })
+                    .collect();
+                Ok(Some((aggregation_padding, buckets)))
+            }
+        }
+    }
+
+    fn joint_dummy(bucket: &AdditiveShare<BA32>, _random: &AdditiveShare<BA64>) -> Self {
+        AttributionOutputs {
+            attributed_breakdown_key_bits: extract_from_shared_array::<BA32, BK>(bucket, 0),
+            capped_attributed_trigger_value: AdditiveShare::new(TV::ZERO, TV::ZERO),
+        }
+    }
 }
 
 /// # Errors
@@ -285,32 +610,44 @@ where
 {
     let initial_len = input.len();
 
-    // H1 and H2 add padding noise
-    input = apply_dp_padding_pass::<C, T, B>(
-        ctx.narrow(&PaddingDpStep::PaddingDpPass1),
-        input,
-        Role::H3,
-        &padding_params,
-    )
-    .await?;
-
-    // H3 and H1 add padding noise
-    input = apply_dp_padding_pass::<C, T, B>(
-        ctx.narrow(&PaddingDpStep::PaddingDpPass2),
-        input,
-        Role::H2,
-        &padding_params,
-    )
-    .await?;
-
-    // H2 and H3 add padding noise
-    input = apply_dp_padding_pass::<C, T, B>(
-        ctx.narrow(&PaddingDpStep::PaddingDpPass3),
-        input,
-        Role::H1,
-        &padding_params,
-    )
-    .await?;
+    match padding_params.sampling {
+        PaddingSampling::Pairwise => {
+            // H1 and H2 add padding noise
+            input = apply_dp_padding_pass::<C, T, B>(
+                ctx.narrow(&PaddingDpStep::PaddingDpPass1),
+                input,
+                Role::H3,
+                &padding_params,
+            )
+            .await?;
+
+            // H3 and H1 add padding noise
+            input = apply_dp_padding_pass::<C, T, B>(
+                ctx.narrow(&PaddingDpStep::PaddingDpPass2),
+                input,
+                Role::H2,
+                &padding_params,
+            )
+            .await?;
+
+            // H2 and H3 add padding noise
+            input = apply_dp_padding_pass::<C, T, B>(
+                ctx.narrow(&PaddingDpStep::PaddingDpPass3),
+                input,
+                Role::H1,
+                &padding_params,
+            )
+            .await?;
+        }
+        PaddingSampling::Joint => {
+            input = apply_joint_dp_padding::<C, T, B>(
+                ctx.narrow(&PaddingDpStep::JointPadding),
+                input,
+                &padding_params,
+            )
+            .await?;
+        }
+    }
 
     let after_padding_len = input.len();
     tracing::info!(
@@ -402,6 +739,99 @@ where
     Ok(input)
 }
 
+/// Layout of the candidate dummies that [`apply_joint_dp_padding`] shuffles: the random value of
+/// the dummy, its bucket, and its coin.
+const JOINT_RANDOM_OFFSET: usize = 0;
+const JOINT_BUCKET_OFFSET: usize = JOINT_RANDOM_OFFSET + BA64::BITS as usize;
+const JOINT_COIN_OFFSET: usize = JOINT_BUCKET_OFFSET + BA32::BITS as usize;
+
+/// Apply dp padding with the three helpers sampling the number of dummies together.
+/// Steps
+///     1.  For every bucket, the helpers generate as many candidate dummies as `JointPaddingDp`
+///         tosses coins. Every candidate gets a random value and a coin from PRSS, so that no
+///         helper knows them.
+///     2.  Candidates are shuffled.
+///     3.  Coins are revealed, and candidates whose coin is not set are dropped. Because of the
+///         shuffle, this only reveals the total number of dummies, not how many each bucket got,
+///         just like the number of rows that every pass of pairwise padding adds.
+///
+/// # Errors
+/// Will propagate errors from `JointPaddingDp`, the shuffle and the reveal of the coins.
+/// # Panics
+/// If there are more candidate dummies than fit in a `usize`.
+pub async fn apply_joint_dp_padding<C, T, const B: usize>(
+    ctx: C,
+    mut input: Vec<T>,
+    padding_params: &PaddingParameters,
+) -> Result<Vec<T>, Error>
+where
+    C: Context,
+    T: Paddable,
+{
+    let Some((padding, buckets)) = T::joint_padding_buckets::<B>(padding_params)? else {
+        return Ok(input);
+    };
+
+    // Step 1: toss coins and generate candidate dummies. All rows of a dummy share its coin.
+    let coin_ctx = ctx.narrow(&JointPaddingStep::TossCoins);
+    let mut candidates = Vec::new();
+    let mut coins = (0_u32..).map(RecordId::from);
+    for bucket in buckets {
+        let bucket_shares = AdditiveShare::<BA32>::share_known_value(
+            &coin_ctx,
+            BA32::truncate_from(u128::from(bucket.value)),
+        );
+        for record_id in coins
+            .by_ref()
+            .take(usize::try_from(padding.coins()).unwrap())
+        {
+            let mut candidate: AdditiveShare<BA112> = coin_ctx.prss().generate(record_id);
+            expand_shared_array_in_place(&mut candidate, &bucket_shares, JOINT_BUCKET_OFFSET);
+            candidates.extend(
+                std::iter::repeat(candidate).take(usize::try_from(bucket.rows_per_dummy).unwrap()),
+            );
+        }
+    }
+
+    // Step 2: shuffle the candidates, so that revealing their coins does not tell which bucket
+    // they belong to.
+    let num_candidates = candidates.len();
+    let (candidates, _) =
+        shuffle_protocol(ctx.narrow(&JointPaddingStep::Shuffle), candidates).await?;
+
+    // Step 3: reveal the coins and keep the candidates whose coin is set.
+    let reveal_ctx = ctx
+        .narrow(&JointPaddingStep::RevealCoins)
+        .set_total_records(TotalRecords::specified(num_candidates)?);
+    let reveal_work = stream::iter(candidates).enumerate().map(|(i, candidate)| {
+        let reveal_ctx = reveal_ctx.clone();
+        async move {
+            let coin = candidate.get(JOINT_COIN_OFFSET).unwrap();
+            let coin = semi_honest_reveal(reveal_ctx, RecordId::from(i), None, &coin)
+                .await?
+                // Full reveal is used, meaning it is not possible to return None here
+                .unwrap();
+            Ok::<_, Error>(bool::from(Boolean::from_array(&coin)).then_some(candidate))
+        }
+    });
+    let kept: Vec<_> = seq_join(reveal_ctx.active_work(), reveal_work)
+        .try_filter_map(|candidate| async move { Ok(candidate) })
+        .try_collect()
+        .await?;
+    tracing::info!(
+        "Jointly sampled padding kept {} of {num_candidates} candidate rows",
+        kept.len(),
+    );
+
+    input.extend(kept.iter().map(|candidate| {
+        T::joint_dummy(
+            &extract_from_shared_array(candidate, JOINT_BUCKET_OFFSET),
+            &extract_from_shared_array(candidate, JOINT_RANDOM_OFFSET),
+        )
+    }));
+    Ok(input)
+}
+
 #[cfg(all(test, unit_test))]
 mod tests {
     use std::collections::{BTreeMap, HashMap};
@@ -417,8 +847,9 @@ mod tests {
             context::Context,
             ipa_prf::{
                 oprf_padding::{
-                    apply_dp_padding_pass, insecure, insecure::OPRFPaddingDp, AggregationPadding,
-                    OPRFPadding, PaddingParameters,
+                    apply_dp_padding, apply_dp_padding_pass, insecure, insecure::OPRFPaddingDp,
+                    AggregationPadding, JointPaddingDp, OPRFPadding, PaddingParameters,
+                    PaddingSampling,
                 },
                 prf_sharding::{tests::PreAggregationTestOutputInDecimal, AttributionOutputs},
                 OPRFIPAInputRow,
@@ -472,6 +903,7 @@ mod tests {
                         oprf_padding_sensitivity,
                     },
                     aggregation_padding: AggregationPadding::NoAggPadding,
+                    sampling: PaddingSampling::Pairwise,
                 };
                 set_up_apply_dp_padding_pass_for_oprf::<_, BK, TV, TS, B>(ctx, padding_params).await
             })
@@ -562,6 +994,7 @@ mod tests {
                         aggregation_delta,
                         aggregation_padding_sensitivity,
                     },
+                    sampling: PaddingSampling::Pairwise,
                 };
                 set_up_apply_dp_padding_pass_for_agg::<_, BK, TV, B>(ctx, padding_params).await
             })
@@ -609,6 +1042,254 @@ mod tests {
         }
     }
 
+    /// Checks that `samples` follow the distribution of `padding`: they are in its support,
+    /// their mean and standard deviation match the analytic ones, and their histogram passes a
+    /// chi-squared goodness of fit test against its pdf. Tolerances are 6 standard errors wide,
+    /// so this fails randomly about once in a billion runs.
+    fn assert_padding_distribution(samples: &[u32], padding: &OPRFPaddingDp) {
+        let n = f64::from(u32::try_from(samples.len()).unwrap());
+        let max = 2 * padding.get_shift();
+        assert!(samples.iter().all(|&sample| sample <= max));
+
+        let (_, std) = padding.mean_and_std();
+        let (approx_mean, approx_std) = padding.mean_and_std_truncated_laplace();
+        let sample_mean = samples.iter().copied().map(f64::from).sum::<f64>() / n;
+        let sample_std = (samples
+            .iter()
+            .map(|&sample| (f64::from(sample) - sample_mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0))
+            .sqrt();
+        println!(
+            "mean = {sample_mean} (expected {approx_mean}), \
+            std = {sample_std} (expected {std}, approximately {approx_std})"
+        );
+        assert!((sample_mean - approx_mean).abs() < 6.0 * std / n.sqrt());
+        assert!((sample_std / std - 1.0).abs() < 0.15);
+        assert!((sample_std / approx_std - 1.0).abs() < 0.2);
+
+        let mut observed = vec![0_u32; usize::try_from(max).unwrap() + 1];
+        for &sample in samples {
+            observed[usize::try_from(sample).unwrap()] += 1;
+        }
+        // merge bins in the tails until each of them expects at least 5 samples
+        let mut bins = Vec::new();
+        let (mut bin_observed, mut bin_expected) = (0.0, 0.0);
+        for (x, count) in (0..=max).zip(observed) {
+            bin_observed += f64::from(count);
+            bin_expected += n * padding.pdf_discrete_truncated_laplace(x);
+            if bin_expected >= 5.0 {
+                bins.push((bin_observed, bin_expected));
+                (bin_observed, bin_expected) = (0.0, 0.0);
+            }
+        }
+        let last = bins.last_mut().unwrap();
+        last.0 += bin_observed;
+        last.1 += bin_expected;
+
+        let statistic = bins
+            .iter()
+            .map(|(observed, expected)| (observed - expected).powi(2) / expected)
+            .sum::<f64>();
+        // Wilson-Hilferty approximation of the chi-squared quantile
+        let df = f64::from(u32::try_from(bins.len() - 1).unwrap());
+        let critical = df * (1.0 - 2.0 / (9.0 * df) + 6.0 * (2.0 / (9.0 * df)).sqrt()).powi(3);
+        assert!(
+            statistic < critical,
+            "chi-squared statistic {statistic} exceeds {critical} with {df} degrees of freedom"
+        );
+    }
+
+    /// Number of dummy rows that one pair of helpers adds to every breakdown, over `runs`
+    /// queries.
+    async fn aggregation_padding_samples(
+        padding_params: PaddingParameters,
+        runs: usize,
+    ) -> Vec<u32> {
+        type BK = BA8;
+        type TV = BA3;
+        const B: usize = 256;
+
+        let mut samples = Vec::with_capacity(runs * B);
+        for _ in 0..runs {
+            let world = TestWorld::default();
+            let result = world
+                .semi_honest((), |ctx, ()| async move {
+                    set_up_apply_dp_padding_pass_for_agg::<_, BK, TV, B>(ctx, padding_params).await
+                })
+                .await
+                .map(Result::unwrap);
+            assert!(result[0].len() == result[1].len() && result[0].len() == result[2].len());
+
+            let mut sample_per_breakdown = [0; B];
+            let result_reconstructed: Vec<PreAggregationTestOutputInDecimal> = result.reconstruct();
+            for row in result_reconstructed {
+                sample_per_breakdown[usize::try_from(row.attributed_breakdown_key).unwrap()] += 1;
+            }
+            samples.extend(sample_per_breakdown);
+        }
+
+        samples
+    }
+
+    #[tokio::test]
+    async fn padding_distribution() {
+        let (epsilon, delta, sensitivity) = (1.0, 1e-6, 2);
+        let padding = OPRFPaddingDp::new(epsilon, delta, sensitivity).unwrap();
+        let padding_params = PaddingParameters {
+            oprf_padding: OPRFPadding::NoOPRFPadding,
+            aggregation_padding: AggregationPadding::Parameters {
+                aggregation_epsilon: epsilon,
+                aggregation_delta: delta,
+                aggregation_padding_sensitivity: sensitivity,
+            },
+            sampling: PaddingSampling::Pairwise,
+        };
+        let samples = aggregation_padding_samples(padding_params, 8).await;
+        assert_padding_distribution(&samples, &padding);
+    }
+
+    /// Checks that every count of `counts` is a sum of the coins of `padding`, using the same
+    /// 6 standard errors as [`assert_padding_distribution`].
+    fn assert_joint_padding_counts(counts: &[u32], padding: &JointPaddingDp) {
+        let (mean, std) = padding.mean_and_std();
+        let n = f64::from(u32::try_from(counts.len()).unwrap());
+        println!("coins = {}, counts = {counts:?}", padding.coins());
+        for &count in counts {
+            assert!(count <= padding.coins());
+            assert!(
+                (f64::from(count) - mean).abs() < 6.0 * std,
+                "count {count} is too far from the mean {mean}"
+            );
+        }
+        let total = counts.iter().copied().map(f64::from).sum::<f64>();
+        assert!((total - n * mean).abs() < 6.0 * std * n.sqrt());
+    }
+
+    #[tokio::test]
+    async fn joint_aggregation_padding() {
+        type BK = BA8;
+        type TV = BA3;
+        const B: usize = 8;
+        let (epsilon, delta, sensitivity) = (10.0, 1e-4, 3);
+        let padding_params = PaddingParameters {
+            oprf_padding: OPRFPadding::NoOPRFPadding,
+            aggregation_padding: AggregationPadding::Parameters {
+                aggregation_epsilon: epsilon,
+                aggregation_delta: delta,
+                aggregation_padding_sensitivity: sensitivity,
+            },
+            sampling: PaddingSampling::Joint,
+        };
+
+        let result = TestWorld::default()
+            .semi_honest((), |ctx, ()| async move {
+                apply_dp_padding::<_, AttributionOutputs<AdditiveShare<BK>, AdditiveShare<TV>>, B>(
+                    ctx,
+                    Vec::new(),
+                    padding_params,
+                )
+                .await
+            })
+            .await
+            .map(Result::unwrap);
+        assert!(result[0].len() == result[1].len() && result[0].len() == result[2].len());
+
+        let mut count_per_breakdown = [0; B];
+        let result_reconstructed: Vec<PreAggregationTestOutputInDecimal> = result.reconstruct();
+        for row in result_reconstructed {
+            assert_eq!(0, row.capped_attributed_trigger_value);
+            count_per_breakdown[usize::try_from(row.attributed_breakdown_key).unwrap()] += 1;
+        }
+
+        let padding = JointPaddingDp::new(epsilon, delta, sensitivity, 8).unwrap();
+        assert_joint_padding_counts(&count_per_breakdown, &padding);
+    }
+
+    #[tokio::test]
+    async fn joint_oprf_padding() {
+        type BK = BA8;
+        type TV = BA3;
+        type TS = BA20;
+        const B: usize = 256;
+        let (epsilon, delta, matchkey_cardinality_cap) = (10.0, 1e-4, 3);
+        let padding_params = PaddingParameters {
+            oprf_padding: OPRFPadding::Parameters {
+                oprf_epsilon: epsilon,
+                oprf_delta: delta,
+                matchkey_cardinality_cap,
+                oprf_padding_sensitivity: 2,
+            },
+            aggregation_padding: AggregationPadding::NoAggPadding,
+            sampling: PaddingSampling::Joint,
+        };
+
+        let result = TestWorld::default()
+            .semi_honest((), |ctx, ()| async move {
+                apply_dp_padding::<_, OPRFIPAInputRow<BK, TV, TS>, B>(
+                    ctx,
+                    Vec::new(),
+                    padding_params,
+                )
+                .await
+            })
+            .await
+            .map(Result::unwrap);
+        assert!(result[0].len() == result[1].len() && result[0].len() == result[2].len());
+
+        // every dummy match key has as many rows as its cardinality, and nothing else
+        let mut user_id_counts: HashMap<u64, u32> = HashMap::new();
+        for row in result.reconstruct() {
+            assert!(row.timestamp == 0);
+            assert!(row.trigger_value == 0);
+            assert!(!row.is_trigger_report);
+            assert!(row.breakdown_key == 0);
+            *user_id_counts.entry(row.user_id).or_insert(0) += 1;
+        }
+        let mut count_per_cardinality = vec![0; usize::try_from(matchkey_cardinality_cap).unwrap()];
+        for cardinality in user_id_counts.values() {
+            count_per_cardinality[usize::try_from(*cardinality).unwrap() - 1] += 1;
+        }
+
+        let padding = JointPaddingDp::new(epsilon, delta, 2, matchkey_cardinality_cap).unwrap();
+        assert_joint_padding_counts(&count_per_cardinality, &padding);
+    }
+
+    #[test]
+    fn joint_padding_noise() {
+        let padding_params = PaddingParameters {
+            sampling: PaddingSampling::Joint,
+            ..PaddingParameters::relaxed()
+        };
+        let noise = padding_params.oprf_noise().unwrap().unwrap();
+        let padding = JointPaddingDp::new(10.0, 1e-4, 2, 3).unwrap();
+        assert_eq!(PaddingSampling::Joint, noise.sampling);
+        assert_eq!(padding.mean_and_std(), (noise.mean, noise.std));
+        assert!((noise.mean - f64::from(padding.coins()) / 2.0).abs() < 1e-9);
+
+        // binomial noise protects all buckets at once, so more buckets need more coins
+        let few = padding_params.aggregation_noise(8).unwrap().unwrap();
+        let many = padding_params.aggregation_noise(256).unwrap().unwrap();
+        assert!(few.mean < many.mean);
+
+        assert_eq!(
+            PaddingSampling::Pairwise,
+            PaddingParameters::relaxed()
+                .oprf_noise()
+                .unwrap()
+                .unwrap()
+                .sampling
+        );
+        assert!(matches!(
+            JointPaddingDp::new(0.0, 1e-4, 2, 3),
+            Err(insecure::Error::BadEpsilon(_))
+        ));
+        assert!(matches!(
+            JointPaddingDp::new(1.0, 0.0, 2, 3),
+            Err(insecure::Error::BadDelta(_))
+        ));
+    }
+
     /// ////////////////////////////////////////////////////////////////////////////////////
     /// Analysis of Parameters
     ///
@@ -695,6 +1376,7 @@ mod tests {
                                 matchkey_cardinality_cap,
                                 oprf_padding_sensitivity: 2,
                             },
+                            sampling: PaddingSampling::Pairwise,
                         };
                         // Call the function to get expected number of fake rows
                         let (expected_oprf_total_rows, expected_agg_total_rows) =
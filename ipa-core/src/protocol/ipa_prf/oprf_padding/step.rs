@@ -9,6 +9,16 @@ pub(crate) enum PaddingDpStep {
     PaddingDpPass2,
     #[step(child = crate::protocol::ipa_prf::oprf_padding::step::SendTotalRows)]
     PaddingDpPass3,
+    #[step(child = crate::protocol::ipa_prf::oprf_padding::step::JointPaddingStep)]
+    JointPadding,
+}
+
+#[derive(CompactStep)]
+pub(crate) enum JointPaddingStep {
+    TossCoins,
+    #[step(child = crate::protocol::ipa_prf::shuffle::step::OPRFShuffleStep)]
+    Shuffle,
+    RevealCoins,
 }
 
 #[derive(CompactStep)]
@@ -39,6 +39,7 @@ use crate::{
                 comparison_and_subtraction_sequential::{compare_gt, integer_sub},
                 expand_shared_array_in_place,
             },
+            oprf_padding::PaddingParameters,
             prf_sharding::step::{
                 AttributionPerRowStep as PerRowStep, AttributionStep as Step,
                 AttributionWindowStep as WindowStep,
@@ -115,7 +116,7 @@ struct InputsRequiredFromPrevRow<BK: SharedValue, TV: SharedValue, TS: SharedVal
 /// Returns the number of Boolean multiplications per input record, for use in computing the number
 /// of records in each DZKP. These multiplications are in `compute_row_with_previous` and the
 /// functions it calls.
-fn multiplications_per_record<BK: SharedValue, TV: SharedValue, TS: SharedValue>(
+pub(crate) fn multiplications_per_record<BK: SharedValue, TV: SharedValue, TS: SharedValue>(
     attribution_window: Option<NonZeroU32>,
 ) -> usize {
     let mut count =
@@ -448,7 +449,8 @@ where
 /// This circuit expects to receive records from multiple users,
 /// but with all of the records from a given user adjacent to one another, and in time order.
 ///
-/// This circuit will compute attribution, per-user capping and aggregation.
+/// This circuit will compute attribution, per-user capping and aggregation. Aggregation that
+/// reveals breakdown keys pads its input as `padding_params` asks for.
 ///
 /// # Errors
 /// Propagates errors from multiplications
@@ -469,6 +471,7 @@ pub async fn attribute_cap_aggregate<
     input_rows: Vec<PrfShardedIpaInputRow<BK, TV, TS>>,
     attribution_window_seconds: Option<NonZeroU32>,
     histogram: &[usize],
+    padding_params: &PaddingParameters,
 ) -> Result<BitDecomposed<Replicated<Boolean, B>>, Error>
 where
     C: UpgradableContext + 'ctx,
@@ -550,9 +553,12 @@ where
             aggregate_values_proof_chunk(B, usize::try_from(TV::BITS).unwrap()),
         );
         let user_contributions = flattened_user_results.try_collect::<Vec<_>>().await?;
-        let result =
-            breakdown_reveal_aggregation::<_, _, _, HV, B>(validator.context(), user_contributions)
-                .await;
+        let result = breakdown_reveal_aggregation::<_, _, _, HV, B>(
+            validator.context(),
+            user_contributions,
+            padding_params,
+        )
+        .await;
         validator.validate().await?;
         result
     } else {
@@ -900,7 +906,9 @@ pub mod tests {
             Field, U128Conversions,
         },
         helpers::repeat_n,
-        protocol::ipa_prf::prf_sharding::attribute_cap_aggregate,
+        protocol::ipa_prf::{
+            oprf_padding::PaddingParameters, prf_sharding::attribute_cap_aggregate,
+        },
         rand::Rng,
         secret_sharing::{
             replicated::semi_honest::AdditiveShare as Replicated, IntoShares, SharedValue,
@@ -1086,7 +1094,11 @@ pub mod tests {
                 .malicious(records.into_iter(), |ctx, input_rows| async move {
                     Vec::transposed_from(
                         &attribute_cap_aggregate::<_, BA5, BA3, BA16, BA20, 5, 32>(
-                            ctx, input_rows, None, &histogram,
+                            ctx,
+                            input_rows,
+                            None,
+                            &histogram,
+                            &PaddingParameters::relaxed(),
                         )
                         .await
                         .unwrap(),
@@ -1147,6 +1159,7 @@ pub mod tests {
                             input_rows,
                             NonZeroU32::new(ATTRIBUTION_WINDOW_SECONDS),
                             &histogram,
+                            &PaddingParameters::relaxed(),
                         )
                         .await
                         .unwrap(),
@@ -1184,6 +1197,7 @@ pub mod tests {
                         input_rows,
                         None,
                         histogram_ref,
+                        &PaddingParameters::relaxed(),
                     )
                     .await
                     .unwrap()
@@ -1270,7 +1284,13 @@ pub mod tests {
                             BA20,
                             { SaturatingSumType::BITS as usize },
                             256,
-                        >(ctx, input_rows, None, &HISTOGRAM)
+                        >(
+                            ctx,
+                            input_rows,
+                            None,
+                            &HISTOGRAM,
+                            &PaddingParameters::relaxed(),
+                        )
                         .await
                         .unwrap(),
                     )
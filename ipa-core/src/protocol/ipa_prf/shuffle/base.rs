@@ -74,13 +74,13 @@ where
 /// these messages need to be checked for consistency across helpers.
 /// `H1` stores `x1`, `H2` stores `x2` and `H3` stores `y1` and `y2`.
 #[derive(Debug, Clone)]
-pub struct IntermediateShuffleMessages<S: SharedValue> {
-    x1_or_y1: Option<Vec<S>>,
-    x2_or_y2: Option<Vec<S>>,
+pub struct IntermediateShuffleMessages<S> {
+    pub(super) x1_or_y1: Option<Vec<S>>,
+    pub(super) x2_or_y2: Option<Vec<S>>,
 }
 
 #[allow(dead_code)]
-impl<S: SharedValue> IntermediateShuffleMessages<S> {
+impl<S> IntermediateShuffleMessages<S> {
     /// When `IntermediateShuffleMessages` is initialized correctly,
     /// this function returns `x1` when `Role = H1`
     /// and `y1` when `Role = H3`.
@@ -89,7 +89,7 @@ where
 ///
 /// ## Panics
 /// Panics when `S::Bits > B::Bits`.
-fn truncate_tags<S, B>(shares_and_tags: &[AdditiveShare<B>]) -> Vec<AdditiveShare<S>>
+pub(super) fn truncate_tags<S, B>(shares_and_tags: &[AdditiveShare<B>]) -> Vec<AdditiveShare<S>>
 where
     S: BooleanArray,
     B: BooleanArray,
@@ -133,7 +133,7 @@ fn split_row_and_tag<S: BooleanArray, B: BooleanArray>(row_with_tag: B) -> (S, G
 /// ## Errors
 /// Propagates network errors.
 /// Further, returns an error when messages are inconsistent with the MAC tags.
-async fn verify_shuffle<C: Context, S: BooleanArray, B: BooleanArray>(
+pub(super) async fn verify_shuffle<C: Context, S: BooleanArray, B: BooleanArray>(
     ctx: C,
     key_shares: &[AdditiveShare<Gf32Bit>],
     shuffled_shares: &[AdditiveShare<B>],
@@ -330,6 +330,10 @@ async fn h3_verify<C: Context, S: BooleanArray, B: BooleanArray>(
 /// This function computes for each item in the iterator the inner product with `keys`.
 /// It concatenates all inner products and hashes them.
 ///
+/// Sharded shuffle may leave some shards without any rows. Empty input hashes to the same
+/// value as a single row with a zero inner product, so the verification of empty shards
+/// can only be fooled with probability 2^-32, same as for any other row.
+///
 /// ## Panics
 /// Panics when conversion from `BooleanArray` to `Vec<Gf32Bit` fails.
 fn compute_and_hash_tags<S, B, I>(keys: &[Gf32Bit], row_iterator: I) -> Hash
@@ -338,22 +342,28 @@ where
     B: BooleanArray,
     I: IntoIterator<Item = B>,
 {
-    let iterator = row_iterator.into_iter().map(|row_with_tag| {
-        // when split_row_and_tags returns the default value, the verification will fail
-        // except 2^-security_parameter, i.e. 2^-32
-        let (row, tag) = split_row_and_tag(row_with_tag);
-        <S as TryInto<Vec<Gf32Bit>>>::try_into(row)
-            .unwrap()
-            .into_iter()
-            .chain(iter::once(tag))
-    });
-    compute_hash(iterator.map(|row_entry_iterator| {
-        row_entry_iterator
-            .zip(keys)
-            .fold(Gf32Bit::ZERO, |acc, (row_entry, key)| {
-                acc + row_entry * *key
-            })
-    }))
+    let mut iterator = row_iterator
+        .into_iter()
+        .map(|row_with_tag| {
+            // when split_row_and_tags returns the default value, the verification will fail
+            // except 2^-security_parameter, i.e. 2^-32
+            let (row, tag) = split_row_and_tag(row_with_tag);
+            <S as TryInto<Vec<Gf32Bit>>>::try_into(row)
+                .unwrap()
+                .into_iter()
+                .chain(iter::once(tag))
+                .zip(keys)
+                .fold(Gf32Bit::ZERO, |acc, (row_entry, key)| {
+                    acc + row_entry * *key
+                })
+        })
+        .peekable();
+
+    if iterator.peek().is_none() {
+        compute_hash(iter::once(Gf32Bit::ZERO))
+    } else {
+        compute_hash(iterator)
+    }
 }
 
 /// This function reveals the MAC keys,
@@ -399,7 +409,7 @@ async fn reveal_keys<C: Context>(
 /// ## Panics
 /// When conversion fails, when `S::Bits + 32 != B::Bits`
 /// or when `rows` is empty or elements in `rows` have length `0`.
-async fn compute_and_add_tags<C, S, B, I>(
+pub(super) async fn compute_and_add_tags<C, S, B, I>(
     ctx: C,
     keys: &[AdditiveShare<Gf32Bit>],
     rows: I,
@@ -10,16 +10,23 @@
 
 use std::{future::Future, num::NonZeroUsize, ops::Add};
 
-use futures::{future::try_join, stream, StreamExt, TryFutureExt};
+use futures::{future::try_join, stream, StreamExt, TryFutureExt, TryStreamExt};
 use ipa_step::Step;
 use rand::seq::SliceRandom;
 
 use crate::{
-    ff::{boolean_array::BA64, U128Conversions},
+    ff::{
+        boolean_array::{BooleanArray, BA64},
+        Gf32Bit, U128Conversions,
+    },
     helpers::{Direction, Error, Role, TotalRecords},
     protocol::{
         context::{reshard, ShardedContext},
-        prss::{FromRandom, FromRandomU128, SharedRandomness},
+        ipa_prf::shuffle::{
+            base::IntermediateShuffleMessages,
+            malicious::{compute_and_add_tags, truncate_tags, verify_shuffle},
+        },
+        prss::{FromRandom, SharedRandomness},
         RecordId,
     },
     secret_sharing::{
@@ -27,6 +34,7 @@ use crate::{
         Sendable, SharedValue,
     },
     seq_join::{assert_send, seq_join},
+    sharding::ShardIndex,
 };
 
 /// This context is only useful for sharded shuffle modules because it implements common operations
@@ -141,6 +149,37 @@ trait ShuffleContext: ShardedContext {
         }
     }
 
+    /// Generates MAC keys for malicious shuffle. Keys must be the same on all shards, so the
+    /// first shard samples them from PRSS and sends them to all other shards on this helper.
+    fn generate_mac_keys<S: BooleanArray>(
+        &self,
+    ) -> impl Future<Output = Result<Vec<AdditiveShare<Gf32Bit>>, crate::error::Error>> + Send {
+        // compute amount of MAC keys
+        let amount_of_keys: usize = usize::try_from(S::BITS).unwrap().div_ceil(32);
+        let ctx = self.set_total_records(TotalRecords::specified(amount_of_keys).unwrap());
+
+        async move {
+            if ctx.shard_id() == ShardIndex::FIRST {
+                let keys = (0..amount_of_keys)
+                    .map(|i| ctx.prss().generate(RecordId::from(i)))
+                    .collect::<Vec<AdditiveShare<Gf32Bit>>>();
+                for shard in ctx.peer_shards().collect::<Vec<_>>() {
+                    let send_channel = ctx.shard_send_channel::<AdditiveShare<Gf32Bit>>(shard);
+                    for (i, key) in keys.iter().enumerate() {
+                        send_channel.send(RecordId::from(i), key).await?;
+                    }
+                }
+
+                Ok(keys)
+            } else {
+                ctx.shard_recv_channel(ShardIndex::FIRST)
+                    .take(amount_of_keys)
+                    .try_collect()
+                    .await
+            }
+        }
+    }
+
     /// Send all values to the specified helper. It is assumed that this context is narrowed
     /// to the correct step, before receiving.
     ///
@@ -200,6 +239,12 @@ enum ShuffleStep {
     /// Local per-shard shuffle, where each shard redistributes shares locally according to samples
     /// obtained from PRSS. Does not require Shard or MPC communication.
     LocalShuffle,
+    /// Generate MAC keys on the first shard and distribute them to all other shards.
+    MacKeys,
+    /// Compute MAC tags and append them to each row before shuffling.
+    GenerateTags,
+    /// Verify the intermediate shuffle messages against the MAC tags.
+    VerifyShuffle,
 }
 
 impl Step for ShuffleStep {}
@@ -216,6 +261,9 @@ impl AsRef<str> for ShuffleStep {
             ShuffleStep::C => "C",
             ShuffleStep::Mask => "Mask",
             ShuffleStep::LocalShuffle => "LocalShuffle",
+            ShuffleStep::MacKeys => "MacKeys",
+            ShuffleStep::GenerateTags => "GenerateTags",
+            ShuffleStep::VerifyShuffle => "VerifyShuffle",
         }
     }
 }
@@ -246,7 +294,7 @@ pub trait Shuffleable: Send + 'static {
     fn new(l: Self::Share, r: Self::Share) -> Self;
 }
 
-impl<V: SharedValue + FromRandomU128> Shuffleable for AdditiveShare<V> {
+impl<V: SharedValue + FromRandom> Shuffleable for AdditiveShare<V> {
     type Share = V;
 
     fn left(&self) -> Self::Share {
@@ -263,7 +311,10 @@ impl<V: SharedValue + FromRandomU128> Shuffleable for AdditiveShare<V> {
 }
 
 /// Sharded shuffle as performed by shards on H1.
-async fn h1_shuffle_for_shard<I, S, C>(ctx: C, shares: I) -> Result<Vec<S>, crate::error::Error>
+async fn h1_shuffle_for_shard<I, S, C>(
+    ctx: C,
+    shares: I,
+) -> Result<(Vec<S>, IntermediateShuffleMessages<S::Share>), crate::error::Error>
 where
     I: IntoIterator<Item = S>,
     I::IntoIter: Send + ExactSizeIterator,
@@ -281,9 +332,10 @@ where
 
     // Generate X_2 = perm_31(x_1 ⊕ z_31) and reshard it using the randomness
     // shared with the left helper.
+    // x_1 must be kept for malicious verification, cloning it causes allocation.
     let x2 = ctx
         .narrow(&ShuffleStep::Permute31)
-        .mask_and_shuffle(Direction::Left, x1)
+        .mask_and_shuffle(Direction::Left, x1.clone())
         .await?;
 
     // X2 is masked now and cannot reveal anything to the helper on the right.
@@ -302,7 +354,7 @@ where
 
     // set our shares
     let ctx = ctx.narrow(&ShuffleStep::PseudoRandomTable);
-    Ok((0..sz)
+    let res = (0..sz)
         .map(|i| {
             // This may be confusing as paper specifies Ã and B̃ as independent tables, but
             // there is really no reason to generate them using unique PRSS keys.
@@ -310,11 +362,22 @@ where
 
             S::new(a, b)
         })
-        .collect())
+        .collect();
+
+    Ok((
+        res,
+        IntermediateShuffleMessages {
+            x1_or_y1: Some(x1),
+            x2_or_y2: None,
+        },
+    ))
 }
 
 /// Sharded shuffle as performed by shards on H2.
-async fn h2_shuffle_for_shard<I, S, C>(ctx: C, shares: I) -> Result<Vec<S>, crate::error::Error>
+async fn h2_shuffle_for_shard<I, S, C>(
+    ctx: C,
+    shares: I,
+) -> Result<(Vec<S>, IntermediateShuffleMessages<S::Share>), crate::error::Error>
 where
     I: IntoIterator<Item = S>,
     I::IntoIter: Send + ExactSizeIterator,
@@ -342,10 +405,15 @@ where
         .await?;
 
     // generate X_3 = perm_23(x_2 ⊕ z_23)
+    // x_2 must be kept for malicious verification, cloning it causes allocation.
     let x3 = ctx
         .narrow(&ShuffleStep::Permute23)
-        .mask_and_shuffle(Direction::Right, x2)
+        .mask_and_shuffle(Direction::Right, x2.clone())
         .await?;
+    let messages = IntermediateShuffleMessages {
+        x1_or_y1: None,
+        x2_or_y2: Some(x2),
+    };
 
     // at this moment we know the cardinality of C, and we let H1 know it, so it can start
     // setting up its own shares.
@@ -354,7 +422,7 @@ where
         .await?;
 
     let Some(x3_len) = NonZeroUsize::new(x3.len()) else {
-        return Ok(Vec::new());
+        return Ok((Vec::new(), messages));
     };
 
     // Generate c_1 = x_3 ⊕ b, stream it to H3 and receive c_2 from it at the same time.
@@ -368,7 +436,7 @@ where
         .narrow(&ShuffleStep::C)
         .recv_channel(ctx.role().peer(Direction::Right));
 
-    Ok(ctx
+    let res = ctx
         .try_join(x3.into_iter().enumerate().map(|(i, x3)| {
             let record_id = RecordId::from(i);
             // FIXME(1029): update PRSS trait to compute only left or right part
@@ -383,12 +451,18 @@ where
             )
             .map_ok(|((), c2)| S::new(b, c1 + c2))
         }))
-        .await?)
+        .await?;
+
+    Ok((res, messages))
 }
 
-/// Sharded shuffle as performed by shards on H3. Note that in semi-honest setting, H3 does not
-/// use its input. Adding support for active security will change that.
-async fn h3_shuffle_for_shard<I, S, C>(ctx: C, _: I) -> Result<Vec<S>, crate::error::Error>
+/// Sharded shuffle as performed by shards on H3. Note that H3 does not use its input, in
+/// malicious setting it keeps `y_1` and `y_2` to verify them against the messages seen by
+/// H1 and H2.
+async fn h3_shuffle_for_shard<I, S, C>(
+    ctx: C,
+    _: I,
+) -> Result<(Vec<S>, IntermediateShuffleMessages<S::Share>), crate::error::Error>
 where
     I: IntoIterator<Item = S>,
     I::IntoIter: Send + ExactSizeIterator,
@@ -402,19 +476,24 @@ where
         .await?;
 
     // Generate y2 = perm_31(y_1 ⊕ z_31)
+    // y_1 and y_2 must be kept for malicious verification, cloning them causes allocation.
     let y2 = ctx
         .narrow(&ShuffleStep::Permute31)
-        .mask_and_shuffle(Direction::Right, y1)
+        .mask_and_shuffle(Direction::Right, y1.clone())
         .await?;
 
     // Generate y3 = perm_23(y_2 ⊕ z_23)
     let y3 = ctx
         .narrow(&ShuffleStep::Permute23)
-        .mask_and_shuffle(Direction::Left, y2)
+        .mask_and_shuffle(Direction::Left, y2.clone())
         .await?;
+    let messages = IntermediateShuffleMessages {
+        x1_or_y1: Some(y1),
+        x2_or_y2: Some(y2),
+    };
 
     let Some(y3_len) = NonZeroUsize::new(y3.len()) else {
-        return Ok(Vec::new());
+        return Ok((Vec::new(), messages));
     };
 
     // Generate c_2 = y_3 ⊕ a, stream it to H2 and receive c_1 from it at the same time.
@@ -426,7 +505,7 @@ where
     let recv_channel = ctx
         .narrow(&ShuffleStep::C)
         .recv_channel::<S::Share>(ctx.role().peer(Direction::Left));
-    Ok(ctx
+    let res = ctx
         .try_join(y3.into_iter().enumerate().map(|(i, y3)| {
             let record_id = RecordId::from(i);
             // FIXME(1029): update PRSS trait to compute only left or right part
@@ -441,7 +520,9 @@ where
             )
             .map_ok(|((), c1)| S::new(c1 + c2, a))
         }))
-        .await?)
+        .await?;
+
+    Ok((res, messages))
 }
 
 /// Entry point to execute sharded shuffle.
@@ -449,6 +530,25 @@ where
 /// Failure to communicate over the network, either to other MPC helpers, and/or to other shards
 /// will generate a shuffle error.
 pub async fn shuffle<I, S, C>(ctx: C, shares: I) -> Result<Vec<S>, crate::error::Error>
+where
+    I: IntoIterator<Item = S>,
+    I::IntoIter: Send + ExactSizeIterator,
+    C: ShardedContext,
+    S: Shuffleable,
+{
+    Ok(shuffle_protocol(ctx, shares).await?.0)
+}
+
+/// Executes sharded shuffle and returns the messages each helper needs to keep to verify it.
+/// See [`IntermediateShuffleMessages`].
+///
+/// ## Errors
+/// Failure to communicate over the network, either to other MPC helpers, and/or to other shards
+/// will generate a shuffle error.
+async fn shuffle_protocol<I, S, C>(
+    ctx: C,
+    shares: I,
+) -> Result<(Vec<S>, IntermediateShuffleMessages<S::Share>), crate::error::Error>
 where
     I: IntoIterator<Item = S>,
     I::IntoIter: Send + ExactSizeIterator,
@@ -462,11 +562,90 @@ where
     }
 }
 
+/// Maliciously secure version of sharded shuffle. It follows the same approach as
+/// [`malicious_shuffle`] does for non-sharded MPC: each row is extended with a MAC tag, then
+/// shuffled and, once shuffle is complete, helpers check that the messages they have seen are
+/// consistent with each other.
+///
+/// MAC keys must be the same across all shards, because rows move between shards during
+/// resharding. The first shard generates them and shares them with its peers on the same helper.
+/// Each shard then verifies the messages it received, because resharding is deterministic
+/// across helpers and the rows at each position are the same on all of them. Any deviation
+/// in resharding rounds or in the shuffle itself makes this check fail.
+///
+/// [`malicious_shuffle`]: crate::protocol::ipa_prf::shuffle::malicious::malicious_shuffle
+///
+/// ## Errors
+/// Failure to communicate over the network, either to other MPC helpers, and/or to other shards.
+/// If verification fails, [`ShuffleValidationFailed`] error is returned, naming the helper and
+/// the shard that detected the inconsistency.
+///
+/// [`ShuffleValidationFailed`]: crate::error::Error::ShuffleValidationFailed
+///
+/// ## Panics
+/// Panics when `S::Bits + 32 != B::Bits` or type conversions fail.
+pub async fn malicious_shuffle<I, S, B, C>(
+    ctx: C,
+    shares: I,
+) -> Result<Vec<AdditiveShare<S>>, crate::error::Error>
+where
+    I: IntoIterator<Item = AdditiveShare<S>>,
+    I::IntoIter: Send + ExactSizeIterator,
+    C: ShardedContext,
+    S: BooleanArray,
+    B: BooleanArray + FromRandom,
+{
+    assert_eq!(S::BITS + 32, B::BITS);
+    let shares = shares.into_iter();
+
+    let keys = ctx
+        .narrow(&ShuffleStep::MacKeys)
+        .generate_mac_keys::<S>()
+        .await?;
+
+    // compute_and_add_tags needs at least one record to run multiplications
+    let shares_and_tags: Vec<AdditiveShare<B>> = if shares.len() > 0 {
+        compute_and_add_tags(ctx.narrow(&ShuffleStep::GenerateTags), &keys, shares).await?
+    } else {
+        Vec::new()
+    };
+
+    let (shuffled_shares, messages) = shuffle_protocol(ctx.clone(), shares_and_tags).await?;
+
+    verify_shuffle::<_, S, B>(
+        ctx.narrow(&ShuffleStep::VerifyShuffle),
+        &keys,
+        &shuffled_shares,
+        messages,
+    )
+    .await
+    .map_err(|e| match e {
+        crate::error::Error::ShuffleValidationFailed(msg) => {
+            crate::error::Error::ShuffleValidationFailed(format!(
+                "{role:?} on shard {shard} detected malicious behavior: {msg}",
+                role = ctx.role(),
+                shard = ctx.shard_id(),
+            ))
+        }
+        e => e,
+    })?;
+
+    Ok(truncate_tags(&shuffled_shares))
+}
+
 #[cfg(all(test, any(unit_test, feature = "shuttle")))]
 mod tests {
+    use std::sync::Arc;
+
     use crate::{
-        ff::{boolean_array::BA8, U128Conversions},
-        protocol::ipa_prf::shuffle::sharded::shuffle,
+        error::Error,
+        ff::{
+            boolean_array::{BA32, BA64, BA8},
+            U128Conversions,
+        },
+        helpers::{in_memory_config::InspectContext, HelperIdentity},
+        protocol::ipa_prf::shuffle::sharded::{malicious_shuffle, shuffle},
+        secret_sharing::replicated::semi_honest::AdditiveShare,
         test_executor::run,
         test_fixture::{
             Distribute, RandomInputDistribution, Reconstruct, RoundRobinInputDistribution, Runner,
@@ -539,4 +718,144 @@ mod tests {
             assert!(result.is_empty());
         });
     }
+
+    async fn malicious_sharded_shuffle<const SHARDS: usize, D: Distribute>(
+        input: Vec<BA32>,
+    ) -> Vec<BA32> {
+        let world: TestWorld<WithShards<SHARDS, D>> =
+            TestWorld::with_shards(TestWorldConfig::default());
+        world
+            .malicious(input.into_iter(), |ctx, input| async move {
+                malicious_shuffle::<_, BA32, BA64, _>(ctx, input)
+                    .await
+                    .unwrap()
+            })
+            .await
+            .into_iter()
+            .flat_map(|v| v.reconstruct())
+            .collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn malicious_non_empty_input() {
+        async fn shuffle_using<const SHARDS: usize, D: Distribute>() {
+            let inputs = (1_u32..=12).map(BA32::truncate_from).collect::<Vec<_>>();
+            let mut result = malicious_sharded_shuffle::<SHARDS, D>(inputs.clone()).await;
+
+            assert_ne!(inputs, result);
+            result.sort_by_key(U128Conversions::as_u128);
+
+            assert_eq!(inputs, result);
+        }
+
+        run(|| async move {
+            shuffle_using::<1, RoundRobinInputDistribution>().await;
+            shuffle_using::<3, RoundRobinInputDistribution>().await;
+            shuffle_using::<5, RandomInputDistribution>().await;
+            shuffle_using::<8, RandomInputDistribution<123>>().await;
+        });
+    }
+
+    #[test]
+    fn malicious_empty() {
+        run(|| async move {
+            let result =
+                malicious_sharded_shuffle::<1, RoundRobinInputDistribution>(Vec::new()).await;
+            assert!(result.is_empty());
+            let result = malicious_sharded_shuffle::<3, RandomInputDistribution>(Vec::new()).await;
+            assert!(result.is_empty());
+        });
+    }
+
+    /// Runs malicious sharded shuffle with an interceptor that flips the first bit of every
+    /// chunk sent by `identity` on the step that ends with `step`, and returns the results
+    /// obtained by helper `H2` on every shard.
+    async fn attack_and_run<const SHARDS: usize>(
+        identity: HelperIdentity,
+        step: &'static str,
+    ) -> Vec<Result<Vec<AdditiveShare<BA32>>, Error>> {
+        let config = TestWorldConfig {
+            stream_interceptor: Arc::new(move |ctx: &InspectContext, data: &mut Vec<u8>| {
+                if ctx.identity == identity && ctx.gate.as_ref().ends_with(step) && !data.is_empty()
+                {
+                    data[0] ^= 1u8;
+                }
+            }),
+            ..Default::default()
+        };
+        let world: TestWorld<WithShards<SHARDS, RoundRobinInputDistribution>> =
+            TestWorld::with_shards(config);
+        let records = (0..24_u32).map(BA32::truncate_from).collect::<Vec<_>>();
+        world
+            .malicious(records.into_iter(), |ctx, shares| async move {
+                malicious_shuffle::<_, BA32, BA64, _>(ctx, shares).await
+            })
+            .await
+            .into_iter()
+            .map(|[_, h2, _]| h2)
+            .collect()
+    }
+
+    /// H1 changes `x2` before sending it to H2, the shard on H2 that received it must detect it.
+    #[test]
+    #[should_panic(expected = "H2 on shard")]
+    fn fail_under_bit_flip_attack_on_x2() {
+        run(|| async move {
+            for r in attack_and_run::<3>(HelperIdentity::ONE, "LeftToRight").await {
+                let _ = r.unwrap();
+            }
+        });
+    }
+
+    /// H1 tampers with the rows it reshards across its own shards. This makes `x2`
+    /// inconsistent with `y2` that H3 derives from the honest `y1`.
+    #[test]
+    #[should_panic(expected = "X2 is inconsistent")]
+    fn fail_under_resharding_attack() {
+        run(|| async move {
+            for r in attack_and_run::<3>(HelperIdentity::ONE, "Permute12").await {
+                let _ = r.unwrap();
+            }
+        });
+    }
+
+    /// H3 changes `c_2` before sending it to H2. H2 does not detect it, but H1 does.
+    #[test]
+    fn fail_under_bit_flip_attack_on_c() {
+        run(|| async move {
+            let config = TestWorldConfig {
+                stream_interceptor: Arc::new(move |ctx: &InspectContext, data: &mut Vec<u8>| {
+                    if ctx.identity == HelperIdentity::THREE
+                        && ctx.gate.as_ref().ends_with("/C")
+                        && !data.is_empty()
+                    {
+                        data[0] ^= 1u8;
+                    }
+                }),
+                ..Default::default()
+            };
+            let world: TestWorld<WithShards<2, RoundRobinInputDistribution>> =
+                TestWorld::with_shards(config);
+            let records = (0..24_u32).map(BA32::truncate_from).collect::<Vec<_>>();
+            let results = world
+                .malicious(records.into_iter(), |ctx, shares| async move {
+                    malicious_shuffle::<_, BA32, BA64, _>(ctx, shares).await
+                })
+                .await;
+
+            let h1_errors = results
+                .into_iter()
+                .filter_map(|[h1, h2, _]| {
+                    assert!(h2.is_ok());
+                    h1.err()
+                })
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>();
+            assert!(!h1_errors.is_empty());
+            for e in h1_errors {
+                assert!(e.contains("H1 on shard"), "{e}");
+                assert!(e.contains("C from H2 is inconsistent"), "{e}");
+            }
+        });
+    }
 }
@@ -2,6 +2,14 @@ use ipa_step_derive::CompactStep;
 
 #[derive(CompactStep)]
 pub(crate) enum IpaPrfStep {
+    /// Helpers exchange the input reports that they could not decrypt or accept.
+    UndecryptableReports,
+    /// Helpers exchange the input reports that they have seen before.
+    DuplicateReports,
+    /// Helpers generate random match keys for the dummy rows that replace excluded reports.
+    DummyMatchKeys,
+    /// Helpers check that all of them recorded the reports used by the query.
+    RecordReports,
     #[step(child = crate::protocol::ipa_prf::oprf_padding::step::PaddingDpStep, name="padding_dp")]
     PaddingDp,
     #[step(child = crate::protocol::ipa_prf::shuffle::step::OPRFShuffleStep)]
@@ -21,6 +29,39 @@ pub(crate) enum IpaPrfStep {
     DifferentialPrivacy,
     #[step(child = crate::protocol::context::step::DzkpSingleBatchStep)]
     DifferentialPrivacyValidate,
+    #[step(child = crate::protocol::dp::step::ThresholdStep, name = "dp_threshold")]
+    DpThreshold,
+    #[step(child = crate::protocol::context::step::DzkpSingleBatchStep)]
+    DpThresholdValidate,
+    #[step(child = HierarchyStep)]
+    Hierarchy,
+    #[step(child = crate::protocol::context::step::DzkpSingleBatchStep)]
+    HierarchyValidate,
+    /// The number of levels must be kept in sync with `BreakdownHierarchy::MAX_LEVELS`.
+    #[step(count = 4, child = crate::protocol::dp::step::DPStep, name = "level_dp")]
+    LevelDifferentialPrivacy(usize),
+    #[step(count = 4, child = crate::protocol::context::step::DzkpSingleBatchStep)]
+    LevelDifferentialPrivacyValidate(usize),
+    #[step(count = 4, child = crate::protocol::dp::step::ThresholdStep, name = "level_dp_threshold")]
+    LevelDpThreshold(usize),
+    #[step(count = 4, child = crate::protocol::context::step::DzkpSingleBatchStep)]
+    LevelDpThresholdValidate(usize),
+}
+
+/// Sums pairs of breakdowns, once for every breakdown key bit that a coarser level of a
+/// breakdown hierarchy drops. The count must be kept in sync with `BreakdownHierarchy::MAX_BITS`.
+#[derive(CompactStep)]
+#[step(count = 8, child = HierarchySumStep, name = "drop_bit")]
+pub(crate) struct HierarchyStep(usize);
+
+/// Saturated addition of pairs of breakdowns. Unlike `SaturatedAdditionStep`, it has room for
+/// output values of up to 128 bits.
+#[derive(CompactStep)]
+pub(crate) enum HierarchySumStep {
+    #[step(child = crate::protocol::boolean::step::OneHundredTwentyEightBitStep)]
+    Add,
+    #[step(child = crate::protocol::boolean::step::OneHundredTwentyEightBitStep)]
+    Select,
 }
 
 #[derive(CompactStep)]
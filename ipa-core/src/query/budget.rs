@@ -0,0 +1,513 @@
+//! Privacy budget accounting.
+//!
+//! An IPA query spends privacy budget of the site whose reports it processes, on every epoch it
+//! covers: the (ε, δ) that [`PrivacyAccountant`] computes by composing the DP noise added to its
+//! output with the padding added to its inputs and to the aggregation. Helpers keep a ledger of
+//! budget spent per site and epoch and refuse queries that would take any of them over the cap.
+//!
+//! [`PrivacyAccountant`]: crate::protocol::dp::accountant::PrivacyAccountant
+//!
+//! Budget is spent in two phases. Every helper first reserves the budget a query needs: the
+//! coordinator before it asks followers to prepare the query, and followers when they accept it.
+//! Reserved budget counts against the cap, but is not written to the ledger file. The coordinator
+//! spends its reservation once both followers have accepted the query, and releases it if any of
+//! them rejects it. Followers spend theirs when the query receives its inputs, which the report
+//! collector only sends after the coordinator has confirmed the query. Reservations of queries
+//! that never start are released when helper restarts. Budget spent on a query is not returned
+//! if the query fails later.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    helpers::query::{IpaQueryConfig, QueryType},
+    protocol::dp::accountant::PrivacyLoss,
+    query::runner::ipa_privacy_loss,
+    report::{Epoch, EpochRange},
+    sync::{Arc, Mutex},
+};
+
+/// Slack for rounding errors when adding up epsilons and deltas, so that a sequence of queries
+/// that spends exactly the cap is accepted.
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BudgetError {
+    #[error(
+        "query must set site_domain and epochs, because this helper enforces a privacy budget"
+    )]
+    Undeclared,
+    #[error("query privacy loss must be non-negative, got epsilon {epsilon} and delta {delta}")]
+    InvalidLoss { epsilon: f64, delta: f64 },
+    #[error(
+        "query needs {requested} of privacy budget of {site_domain} in epoch {epoch}, \
+         but only {remaining} is left"
+    )]
+    Exceeded {
+        site_domain: String,
+        epoch: Epoch,
+        requested: f64,
+        remaining: f64,
+    },
+    #[error(
+        "query needs delta {requested} of privacy budget of {site_domain} in epoch {epoch}, \
+         but only {remaining} is left"
+    )]
+    DeltaExceeded {
+        site_domain: String,
+        epoch: Epoch,
+        requested: f64,
+        remaining: f64,
+    },
+    #[error("failed to persist privacy budget ledger: {0}")]
+    Io(#[from] io::Error),
+}
+
+type Ledger = HashMap<(String, Epoch), PrivacyLoss>;
+
+/// Privacy budget spent by a single query.
+struct Charge {
+    site_domain: String,
+    epochs: EpochRange,
+    loss: PrivacyLoss,
+}
+
+impl Charge {
+    fn add_to(&self, ledger: &mut Ledger) {
+        for epoch in self.epochs.first()..=self.epochs.last() {
+            let spent = ledger.entry((self.site_domain.clone(), epoch)).or_default();
+            spent.epsilon += self.loss.epsilon;
+            spent.delta += self.loss.delta;
+        }
+    }
+
+    fn remove_from(&self, ledger: &mut Ledger) {
+        for epoch in self.epochs.first()..=self.epochs.last() {
+            if let Some(spent) = ledger.get_mut(&(self.site_domain.clone(), epoch)) {
+                spent.epsilon -= self.loss.epsilon;
+                spent.delta -= self.loss.delta;
+            }
+        }
+    }
+}
+
+/// Ledger of privacy budget spent per site and epoch.
+pub struct PrivacyBudget {
+    cap: f64,
+    delta_cap: f64,
+    state: Mutex<State>,
+}
+
+struct State {
+    spent: Ledger,
+    /// Budget of queries that were accepted, but have not started yet.
+    reserved: Ledger,
+    path: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LedgerEntry {
+    site_domain: String,
+    epoch: Epoch,
+    spent: f64,
+    spent_delta: f64,
+}
+
+impl PrivacyBudget {
+    /// Creates a ledger that accepts any query. Queries that declare their site and epochs are
+    /// still recorded.
+    #[must_use]
+    pub fn unlimited() -> Self {
+        Self::in_memory(f64::INFINITY)
+    }
+
+    /// Creates a ledger that forgets everything when helper restarts.
+    #[must_use]
+    pub fn in_memory(cap: f64) -> Self {
+        Self {
+            cap,
+            delta_cap: f64::INFINITY,
+            state: Mutex::new(State {
+                spent: HashMap::new(),
+                reserved: HashMap::new(),
+                path: None,
+            }),
+        }
+    }
+
+    /// Creates a ledger backed by the file at `path`, loading the budget already spent from it.
+    /// The file is created on first charge if it does not exist.
+    ///
+    /// ## Errors
+    /// If the file cannot be read or is malformed.
+    pub fn open(path: &Path, cap: f64) -> io::Result<Self> {
+        let entries: Vec<LedgerEntry> = match fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            cap,
+            delta_cap: f64::INFINITY,
+            state: Mutex::new(State {
+                spent: entries
+                    .into_iter()
+                    .map(|e| {
+                        (
+                            (e.site_domain, e.epoch),
+                            PrivacyLoss::new(e.spent, e.spent_delta),
+                        )
+                    })
+                    .collect(),
+                reserved: HashMap::new(),
+                path: Some(path.to_path_buf()),
+            }),
+        })
+    }
+
+    /// Caps delta spent per site and epoch, in addition to epsilon. Delta is not capped by
+    /// default.
+    #[must_use]
+    pub fn with_delta_cap(mut self, delta_cap: f64) -> Self {
+        self.delta_cap = delta_cap;
+        self
+    }
+
+    /// Budget of `site_domain` already spent in `epoch`.
+    ///
+    /// ## Panics
+    /// If the ledger mutex is poisoned.
+    #[must_use]
+    pub fn spent(&self, site_domain: &str, epoch: Epoch) -> PrivacyLoss {
+        let state = self.state.lock().unwrap();
+        state
+            .spent
+            .get(&(site_domain.to_owned(), epoch))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Reserves the budget the query needs. It is spent by [`BudgetReservation::commit`] and
+    /// released if the reservation is dropped before that.
+    ///
+    /// ## Errors
+    /// If the query does not fit into the remaining budget, or does not say whose budget it
+    /// spends.
+    ///
+    /// ## Panics
+    /// If the ledger mutex is poisoned.
+    pub fn reserve(self: &Arc<Self>, query: &QueryType) -> Result<BudgetReservation, BudgetError> {
+        let charge = self.charge_for(query)?;
+        if let Some(charge) = &charge {
+            let mut state = self.state.lock().unwrap();
+            self.check_charge(&state, charge)?;
+            charge.add_to(&mut state.reserved);
+        }
+
+        Ok(BudgetReservation {
+            budget: Arc::clone(self),
+            charge,
+        })
+    }
+
+    fn charge_for(&self, query: &QueryType) -> Result<Option<Charge>, BudgetError> {
+        match query {
+            QueryType::SemiHonestOprfIpa(config) | QueryType::MaliciousOprfIpa(config) => {
+                self.ipa_charge(config)
+            }
+            // other queries don't process reports
+            #[cfg(any(test, feature = "test-fixture", feature = "cli"))]
+            QueryType::TestMultiply
+            | QueryType::TestAddInPrimeField
+            | QueryType::TestShardedShuffle => Ok(None),
+            QueryType::SemiHonestHybrid(_) => Ok(None),
+        }
+    }
+
+    fn ipa_charge(&self, config: &IpaQueryConfig) -> Result<Option<Charge>, BudgetError> {
+        let loss = ipa_privacy_loss(config);
+        if loss.epsilon.is_nan() || loss.epsilon < 0.0 || loss.delta.is_nan() || loss.delta < 0.0 {
+            return Err(BudgetError::InvalidLoss {
+                epsilon: loss.epsilon,
+                delta: loss.delta,
+            });
+        }
+        match (&config.site_domain, config.epochs) {
+            (Some(site_domain), Some(epochs)) => Ok(Some(Charge {
+                site_domain: site_domain.clone(),
+                epochs,
+                loss,
+            })),
+            _ if self.cap.is_finite() || self.delta_cap.is_finite() => Err(BudgetError::Undeclared),
+            _ => Ok(None),
+        }
+    }
+
+    fn check_charge(&self, state: &State, charge: &Charge) -> Result<(), BudgetError> {
+        for epoch in charge.epochs.first()..=charge.epochs.last() {
+            let key = (charge.site_domain.clone(), epoch);
+            let [spent, reserved] = [&state.spent, &state.reserved]
+                .map(|ledger| ledger.get(&key).copied().unwrap_or_default());
+            let spent = PrivacyLoss::new(
+                spent.epsilon + reserved.epsilon,
+                spent.delta + reserved.delta,
+            );
+            if spent.epsilon + charge.loss.epsilon > self.cap + TOLERANCE {
+                return Err(BudgetError::Exceeded {
+                    site_domain: charge.site_domain.clone(),
+                    epoch,
+                    requested: charge.loss.epsilon,
+                    remaining: (self.cap - spent.epsilon).max(0.0),
+                });
+            }
+            if spent.delta + charge.loss.delta > self.delta_cap + TOLERANCE * self.delta_cap {
+                return Err(BudgetError::DeltaExceeded {
+                    site_domain: charge.site_domain.clone(),
+                    epoch,
+                    requested: charge.loss.delta,
+                    remaining: (self.delta_cap - spent.delta).max(0.0),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Budget reserved by a query that has not started yet. It counts against the cap, but is only
+/// spent once [`Self::commit`] is called.
+pub struct BudgetReservation {
+    budget: Arc<PrivacyBudget>,
+    /// Not set for queries that do not spend budget.
+    charge: Option<Charge>,
+}
+
+impl BudgetReservation {
+    /// Spends the reserved budget.
+    ///
+    /// ## Errors
+    /// If the ledger is backed by a file and writing to it fails. The budget is released in this
+    /// case.
+    ///
+    /// ## Panics
+    /// If the ledger mutex is poisoned.
+    pub fn commit(mut self) -> Result<(), BudgetError> {
+        let Some(charge) = self.charge.take() else {
+            return Ok(());
+        };
+        let mut state = self.budget.state.lock().unwrap();
+        charge.remove_from(&mut state.reserved);
+        // the ledger is only updated in memory once it is written to the file
+        let mut spent = state.spent.clone();
+        charge.add_to(&mut spent);
+        state.persist(&spent)?;
+        state.spent = spent;
+
+        Ok(())
+    }
+}
+
+impl Drop for BudgetReservation {
+    fn drop(&mut self) {
+        if let Some(charge) = &self.charge {
+            charge.remove_from(&mut self.budget.state.lock().unwrap().reserved);
+        }
+    }
+}
+
+impl State {
+    /// Replaces the ledger file with `spent`.
+    fn persist(&self, spent: &Ledger) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let entries = spent
+            .iter()
+            .map(|((site_domain, epoch), spent)| LedgerEntry {
+                site_domain: site_domain.clone(),
+                epoch: *epoch,
+                spent: spent.epsilon,
+                spent_delta: spent.delta,
+            })
+            .collect::<Vec<_>>();
+
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&serde_json::to_vec(&entries)?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use super::{BudgetError, PrivacyBudget, TOLERANCE};
+    use crate::{
+        helpers::query::{DpConfig, IpaQueryConfig, QueryType},
+        query::runner::ipa_privacy_loss,
+        report::{Epoch, EpochRange},
+        sync::Arc,
+    };
+
+    fn charge(budget: &Arc<PrivacyBudget>, query: &QueryType) -> Result<(), BudgetError> {
+        budget.reserve(query)?.commit()
+    }
+
+    fn ipa(site_domain: &str, epochs: EpochRange, epsilon: f64) -> QueryType {
+        QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+            dp: DpConfig::discrete_laplace(epsilon),
+            site_domain: Some(site_domain.to_owned()),
+            epochs: Some(epochs),
+            ..Default::default()
+        })
+    }
+
+    fn assert_spent(budget: &PrivacyBudget, site_domain: &str, epoch: Epoch, expected: f64) {
+        let spent = budget.spent(site_domain, epoch).epsilon;
+        assert!(
+            (spent - expected).abs() < TOLERANCE,
+            "{site_domain} spent {spent} in epoch {epoch}, expected {expected}"
+        );
+    }
+
+    fn budget_delta(query: &QueryType) -> f64 {
+        let (QueryType::SemiHonestOprfIpa(config) | QueryType::MaliciousOprfIpa(config)) = query
+        else {
+            unreachable!()
+        };
+        ipa_privacy_loss(config).delta
+    }
+
+    fn cost(query: &QueryType) -> f64 {
+        let (QueryType::SemiHonestOprfIpa(config) | QueryType::MaliciousOprfIpa(config)) = query
+        else {
+            unreachable!()
+        };
+        ipa_privacy_loss(config).epsilon
+    }
+
+    #[test]
+    fn charges_every_epoch() {
+        let query = ipa("example.com", EpochRange::new(1, 2).unwrap(), 1.0);
+        let budget = Arc::new(PrivacyBudget::in_memory(cost(&query) * 2.0));
+        charge(&budget, &query).unwrap();
+        charge(&budget, &query).unwrap();
+
+        assert_spent(&budget, "example.com", 1, 2.0 * cost(&query));
+        assert_spent(&budget, "example.com", 2, 2.0 * cost(&query));
+        assert_spent(&budget, "example.com", 3, 0.0);
+        assert_spent(&budget, "example.org", 1, 0.0);
+    }
+
+    #[test]
+    fn refuses_over_cap() {
+        let query = ipa("example.com", EpochRange::single(1), 1.0);
+        let budget = Arc::new(PrivacyBudget::in_memory(cost(&query) * 2.0));
+        charge(&budget, &query).unwrap();
+        charge(&budget, &query).unwrap();
+
+        let overlapping = ipa("example.com", EpochRange::new(0, 1).unwrap(), 1.0);
+        assert!(matches!(
+            charge(&budget, &overlapping),
+            Err(BudgetError::Exceeded { epoch: 1, .. })
+        ));
+        // rejected query does not spend anything
+        assert_spent(&budget, "example.com", 0, 0.0);
+
+        // other sites have their own budget
+        charge(&budget, &ipa("example.org", EpochRange::single(1), 1.0)).unwrap();
+    }
+
+    #[test]
+    fn released_unless_committed() {
+        let query = ipa("example.com", EpochRange::single(1), 1.0);
+        let budget = Arc::new(PrivacyBudget::in_memory(cost(&query) * 1.5));
+        let reservation = budget.reserve(&query).unwrap();
+        assert_spent(&budget, "example.com", 1, 0.0);
+        // reserved budget is not available to other queries
+        assert!(matches!(
+            budget.reserve(&query),
+            Err(BudgetError::Exceeded { epoch: 1, .. })
+        ));
+
+        // query rejected by another helper
+        drop(reservation);
+        assert_spent(&budget, "example.com", 1, 0.0);
+        charge(&budget, &query).unwrap();
+        assert_spent(&budget, "example.com", 1, cost(&query));
+    }
+
+    #[test]
+    fn requires_site_and_epochs() {
+        let query = QueryType::SemiHonestOprfIpa(IpaQueryConfig::default());
+        assert!(matches!(
+            charge(&Arc::new(PrivacyBudget::in_memory(100.0)), &query),
+            Err(BudgetError::Undeclared)
+        ));
+        charge(&Arc::new(PrivacyBudget::unlimited()), &query).unwrap();
+        charge(
+            &Arc::new(PrivacyBudget::in_memory(100.0)),
+            &QueryType::TestMultiply,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn invalid_epsilon() {
+        let query = ipa("example.com", EpochRange::single(1), f64::NAN);
+        assert!(matches!(
+            charge(&Arc::new(PrivacyBudget::unlimited()), &query),
+            Err(BudgetError::InvalidLoss { .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_over_delta_cap() {
+        let query = ipa("example.com", EpochRange::single(1), 1.0);
+        let delta = budget_delta(&query);
+        assert!(delta > 0.0);
+        let budget = Arc::new(PrivacyBudget::unlimited().with_delta_cap(delta * 1.5));
+        charge(&budget, &query).unwrap();
+        assert!(
+            (budget.spent("example.com", 1).delta - delta).abs() <= TOLERANCE * delta,
+            "spent delta {}, expected {delta}",
+            budget.spent("example.com", 1).delta
+        );
+        assert!(matches!(
+            charge(&budget, &query),
+            Err(BudgetError::DeltaExceeded { epoch: 1, .. })
+        ));
+
+        // a finite delta cap requires queries to declare whose budget they spend
+        assert!(matches!(
+            charge(
+                &Arc::new(PrivacyBudget::unlimited().with_delta_cap(1.0)),
+                &QueryType::SemiHonestOprfIpa(IpaQueryConfig::default())
+            ),
+            Err(BudgetError::Undeclared)
+        ));
+    }
+
+    #[test]
+    fn persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("budget");
+        let query = ipa("example.com", EpochRange::single(1), 1.0);
+        let cap = cost(&query) * 1.5;
+        charge(&Arc::new(PrivacyBudget::open(&path, cap).unwrap()), &query).unwrap();
+
+        let budget = Arc::new(PrivacyBudget::open(&path, cap).unwrap());
+        assert_spent(&budget, "example.com", 1, cost(&query));
+        assert!(matches!(
+            charge(&budget, &query),
+            Err(BudgetError::Exceeded { .. })
+        ));
+    }
+}
@@ -15,6 +15,7 @@ use generic_array::GenericArray;
 use ipa_step::StepNarrow;
 use rand::rngs::StdRng;
 use rand_core::SeedableRng;
+use serde::{Deserialize, Serialize};
 #[cfg(all(feature = "shuttle", test))]
 use shuttle::future as tokio;
 use typenum::Unsigned;
@@ -40,18 +41,70 @@ use crate::{
     hpke::PrivateKeyRegistry,
     protocol::{
         context::{MaliciousContext, SemiHonestContext},
+        dp::{accountant::PrivacyLoss, OutputNoise},
+        ipa_prf::oprf_padding::PaddingNoise,
         prss::Endpoint as PrssEndpoint,
         Gate,
     },
     query::{
         runner::{HybridQuery, OprfIpaQuery, QueryResult},
         state::RunningQuery,
+        SeenReports,
     },
     sync::Arc,
 };
 
 pub trait Result: Send + Debug {
     fn to_bytes(&self) -> Vec<u8>;
+
+    /// Information about query execution that is returned alongside the result.
+    fn metadata(&self) -> QueryMetadata {
+        QueryMetadata::default()
+    }
+}
+
+/// Information about query execution, reported by each helper separately.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QueryMetadata {
+    /// Number of input reports that some helper could not decrypt, or that are outside of the
+    /// epochs or site covered by the query, and were replaced with dummy rows.
+    pub dropped_reports: u64,
+    /// Number of input reports that were already submitted before and were replaced with dummy
+    /// rows.
+    pub duplicate_reports: u64,
+    /// Noise added to the output and to the inputs of queries that are differentially private.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub noise: Option<NoiseMetadata>,
+}
+
+/// Noise added to the output of a query, and the dummy rows it was padded with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoiseMetadata {
+    /// Noise added to every breakdown of the output.
+    pub output: OutputNoise,
+    /// Dummy match keys added for every cardinality up to the match key cardinality cap.
+    pub oprf_padding: Option<PaddingNoise>,
+    /// Dummy rows added to every breakdown before aggregation.
+    pub aggregation_padding: Option<PaddingNoise>,
+    /// Privacy budget the query spends, composing the output noise and both kinds of padding.
+    pub total: PrivacyLoss,
+}
+
+/// Query result together with [`QueryMetadata`] collected while computing it.
+#[derive(Debug)]
+pub struct WithMetadata<T> {
+    pub result: T,
+    pub metadata: QueryMetadata,
+}
+
+impl<T: Result> Result for WithMetadata<T> {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.result.to_bytes()
+    }
+
+    fn metadata(&self) -> QueryMetadata {
+        self.metadata
+    }
 }
 
 impl<T> Result for Vec<T>
@@ -76,10 +129,11 @@ where
 pub fn execute<R: PrivateKeyRegistry>(
     config: QueryConfig,
     key_registry: Arc<R>,
+    seen_reports: Arc<SeenReports>,
     gateway: Gateway,
     input: BodyStream,
 ) -> RunningQuery {
-    match (config.query_type, config.field_type) {
+    match (config.query_type.clone(), config.field_type) {
         #[cfg(any(test, feature = "weak-field"))]
         (QueryType::TestMultiply, FieldType::Fp31) => {
             do_query(config, gateway, input, |prss, gateway, _config, input| {
@@ -127,6 +181,7 @@ pub fn execute<R: PrivateKeyRegistry>(
                 let ctx = SemiHonestContext::new(prss, gateway);
                 Box::pin(
                     OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
+                        .with_seen_reports(seen_reports)
                         .execute(ctx, config.size, input)
                         .then(|res| ready(res.map(|out| Box::new(out) as Box<dyn Result>))),
                 )
@@ -140,6 +195,7 @@ pub fn execute<R: PrivateKeyRegistry>(
                 let ctx = MaliciousContext::new(prss, gateway);
                 Box::pin(
                     OprfIpaQuery::<_, BA32, R>::new(ipa_config, key_registry)
+                        .with_seen_reports(seen_reports)
                         .execute(ctx, config.size, input)
                         .then(|res| ready(res.map(|out| Box::new(out) as Box<dyn Result>))),
                 )
@@ -1,14 +1,20 @@
+mod budget;
 mod completion;
 mod executor;
 mod processor;
+mod replay;
 mod runner;
 mod state;
 
+pub use budget::{BudgetError, BudgetReservation, PrivacyBudget};
 use completion::Handle as CompletionHandle;
-pub use executor::Result as ProtocolResult;
+pub use executor::{NoiseMetadata, QueryMetadata, Result as ProtocolResult, WithMetadata};
 pub use processor::{
-    NewQueryError, PrepareQueryError, Processor as QueryProcessor, QueryCompletionError,
-    QueryInputError, QueryStatusError,
+    NewQueryError, PrepareQueryError, Processor as QueryProcessor, QueryAbortError,
+    QueryCompletionError, QueryInputError, QueryStatusError,
 };
+pub use replay::SeenReports;
 pub use runner::OprfIpaQuery;
+#[cfg(any(test, feature = "cli", feature = "test-fixture"))]
+pub use runner::{estimate_ipa, IpaEstimate, MpcCost, PaddingRows};
 pub use state::QueryStatus;
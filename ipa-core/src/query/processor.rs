@@ -1,26 +1,29 @@
 use std::{
-    collections::hash_map::Entry,
+    collections::{hash_map::Entry, HashMap},
     fmt::{Debug, Formatter},
     num::NonZeroUsize,
+    time::{Duration, SystemTime},
 };
 
-use futures::{future::try_join, stream};
+use futures::{future::join, stream};
 
 use crate::{
     error::Error as ProtocolError,
     helpers::{
-        query::{PrepareQuery, QueryConfig, QueryInput},
+        query::{DpConfigError, PaddingPolicy, PrepareQuery, QueryConfig, QueryInput},
+        routing::RouteId,
         Gateway, GatewayConfig, MpcTransportError, MpcTransportImpl, Role, RoleAssignment,
         ShardTransportImpl, Transport,
     },
-    hpke::{KeyRegistry, PrivateKeyOnly},
+    hpke::{KeyRegistry, PrivateKeyOnly, PublicKeySet, ReloadableKeyRegistry},
     protocol::QueryId,
     query::{
         executor,
         state::{QueryState, QueryStatus, RemoveQuery, RunningQueries, StateError},
-        CompletionHandle, ProtocolResult,
+        BudgetError, BudgetReservation, CompletionHandle, PrivacyBudget, ProtocolResult,
+        SeenReports,
     },
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 
 /// `Processor` accepts and tracks requests to initiate new queries on this helper party
@@ -42,7 +45,12 @@ use crate::{
 /// [`AdditiveShare`]: crate::secret_sharing::replicated::semi_honest::AdditiveShare
 pub struct Processor {
     queries: RunningQueries,
-    key_registry: Arc<KeyRegistry<PrivateKeyOnly>>,
+    key_registry: Arc<ReloadableKeyRegistry<PrivateKeyOnly>>,
+    seen_reports: Arc<SeenReports>,
+    privacy_budget: Arc<PrivacyBudget>,
+    /// Budget reserved by queries this helper accepted as a follower, spent when they start.
+    budget_reservations: Mutex<HashMap<QueryId, BudgetReservation>>,
+    padding_policy: PaddingPolicy,
     active_work: Option<NonZeroUsize>,
 }
 
@@ -50,7 +58,13 @@ impl Default for Processor {
     fn default() -> Self {
         Self {
             queries: RunningQueries::default(),
-            key_registry: Arc::new(KeyRegistry::<PrivateKeyOnly>::empty()),
+            key_registry: Arc::new(ReloadableKeyRegistry::new(
+                KeyRegistry::<PrivateKeyOnly>::empty(),
+            )),
+            seen_reports: Arc::new(SeenReports::in_memory(Duration::ZERO)),
+            privacy_budget: Arc::new(PrivacyBudget::unlimited()),
+            budget_reservations: Mutex::default(),
+            padding_policy: PaddingPolicy::permissive(),
             active_work: None,
         }
     }
@@ -62,6 +76,10 @@ pub enum NewQueryError {
     State(#[from] StateError),
     #[error(transparent)]
     MpcTransport(#[from] MpcTransportError),
+    #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
+    InvalidDp(#[from] DpConfigError),
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -71,17 +89,31 @@ pub enum PrepareQueryError {
     #[error("Query is already running")]
     AlreadyRunning,
     #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
+    InvalidDp(#[from] DpConfigError),
+    #[error(transparent)]
     StateError {
         #[from]
         source: StateError,
     },
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum QueryAbortError {
+    #[error("The query with id {0:?} does not exist")]
+    NoSuchQuery(QueryId),
+    #[error("The query in state {0:?} cannot be aborted")]
+    InvalidState(QueryStatus),
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum QueryInputError {
     #[error("The query with id {0:?} does not exist")]
     NoSuchQuery(QueryId),
     #[error(transparent)]
+    Budget(#[from] BudgetError),
+    #[error(transparent)]
     StateError {
         #[from]
         source: StateError,
@@ -116,12 +148,19 @@ impl Debug for Processor {
 impl Processor {
     #[must_use]
     pub fn new(
-        key_registry: KeyRegistry<PrivateKeyOnly>,
+        key_registry: Arc<ReloadableKeyRegistry<PrivateKeyOnly>>,
+        seen_reports: Arc<SeenReports>,
+        privacy_budget: Arc<PrivacyBudget>,
+        padding_policy: PaddingPolicy,
         active_work: Option<NonZeroUsize>,
     ) -> Self {
         Self {
             queries: RunningQueries::default(),
-            key_registry: Arc::new(key_registry),
+            key_registry,
+            seen_reports,
+            privacy_budget,
+            budget_reservations: Mutex::default(),
+            padding_policy,
             active_work,
         }
     }
@@ -133,24 +172,32 @@ impl Processor {
     ///     The coordinator is in theory free to choose helpers for `Role::H2` and `Role::H3`
     ///         arbitrarily (aka followers), however, this is not currently exercised.
     /// * Requests Infra and Network layer to create resources for this query
+    /// * checks that the query pads its inputs at least as much as the padding policy requires
+    /// * reserves the privacy budget of the query
     /// * sends `prepare` request that describes the query configuration
     ///     (query id, query type, field type, roles -> endpoints or reverse)
     ///         to followers and waits for the confirmation
+    /// * spends the reserved privacy budget, or releases it if any follower rejects the query. In
+    ///     that case, the query is aborted on the follower that accepted it, so that it releases
+    ///     the budget it reserved too
     /// * records newly created query id internally and sets query state to awaiting data
     /// * returns query configuration
     ///
     /// ## Errors
-    /// When other peers failed to acknowledge this query
+    /// When the DP parameters of the query are invalid or weaker than the padding policy, the
+    /// query does not fit into the privacy budget or other peers failed to acknowledge this query
     #[allow(clippy::missing_panics_doc)]
     pub async fn new_query(
         &self,
         transport: MpcTransportImpl,
         req: QueryConfig,
     ) -> Result<PrepareQuery, NewQueryError> {
+        req.query_type.validate_dp(&self.padding_policy)?;
         let query_id = QueryId;
         let handle = self.queries.handle(query_id);
-        handle.set_state(QueryState::Preparing(req))?;
+        handle.set_state(QueryState::Preparing(req.clone()))?;
         let guard = handle.remove_query_on_drop();
+        let reservation = self.privacy_budget.reserve(&req.query_type)?;
 
         let id = transport.identity();
         let [right, left] = id.others();
@@ -160,17 +207,30 @@ impl Processor {
 
         let prepare_request = PrepareQuery {
             query_id,
-            config: req,
+            config: req.clone(),
             roles: roles.clone(),
         };
 
-        // Inform other parties about new query. If any of them rejects it, this join will fail
-        try_join(
+        // Inform other parties about new query
+        let (left_prepared, right_prepared) = join(
             transport.send(left, prepare_request.clone(), stream::empty()),
             transport.send(right, prepare_request.clone(), stream::empty()),
         )
-        .await
-        .map_err(NewQueryError::MpcTransport)?;
+        .await;
+        if left_prepared.is_err() || right_prepared.is_err() {
+            for (follower, prepared) in [(left, &left_prepared), (right, &right_prepared)] {
+                if prepared.is_ok() {
+                    let abort = (RouteId::AbortQuery, query_id);
+                    if let Err(e) = transport.send(follower, abort, stream::empty()).await {
+                        tracing::warn!("failed to abort {query_id:?} on {follower:?}: {e}");
+                    }
+                }
+            }
+            return Err(NewQueryError::MpcTransport(
+                left_prepared.and(right_prepared).unwrap_err(),
+            ));
+        }
+        reservation.commit()?;
 
         handle.set_state(QueryState::AwaitingInputs(query_id, req, roles))?;
 
@@ -182,10 +242,16 @@ impl Processor {
     /// * ensures that it is not the leader on this query
     /// * query is not registered yet
     /// * creates gateway and network
+    /// * checks the query against the padding policy of this helper
+    /// * reserves the privacy budget of the query, spent once the query receives its inputs
     /// * registers query
     ///
     /// ## Errors
-    /// if query is already running or this helper cannot be a follower in it
+    /// if query is already running, has invalid DP parameters, pads less than the padding policy
+    /// requires, does not fit into the privacy budget or this helper cannot be a follower in it
+    ///
+    /// ## Panics
+    /// If the budget reservations mutex is poisoned.
     pub fn prepare(
         &self,
         transport: &MpcTransportImpl,
@@ -200,20 +266,57 @@ impl Processor {
         if handle.status().is_some() {
             return Err(PrepareQueryError::AlreadyRunning);
         }
+        req.config.query_type.validate_dp(&self.padding_policy)?;
+        // the query is not registered, so a reservation left for it is stale and must not count
+        // against the budget of this one
+        self.budget_reservations
+            .lock()
+            .unwrap()
+            .remove(&req.query_id);
+        let reservation = self.privacy_budget.reserve(&req.config.query_type)?;
 
         handle.set_state(QueryState::AwaitingInputs(
             req.query_id,
             req.config,
             req.roles,
         ))?;
+        self.budget_reservations
+            .lock()
+            .unwrap()
+            .insert(req.query_id, reservation);
 
         Ok(())
     }
 
+    /// Aborts a query this helper accepted as a follower, when the coordinator gives up on it
+    /// because the other follower rejected it. The privacy budget reserved for the query is
+    /// released.
+    ///
+    /// ## Errors
+    /// If query is not registered on this helper, or has already received its inputs.
+    ///
+    /// ## Panics
+    /// If the query collection or the budget reservations mutex is poisoned.
+    pub fn abort(&self, query_id: QueryId) -> Result<(), QueryAbortError> {
+        let mut queries = self.queries.inner.lock().unwrap();
+        match queries.remove(&query_id) {
+            Some(QueryState::AwaitingInputs(..)) => {
+                self.budget_reservations.lock().unwrap().remove(&query_id);
+                Ok(())
+            }
+            Some(state) => {
+                let status = QueryStatus::from(&state);
+                queries.insert(query_id, state);
+                Err(QueryAbortError::InvalidState(status))
+            }
+            None => Err(QueryAbortError::NoSuchQuery(query_id)),
+        }
+    }
+
     /// Receive inputs for the specified query. That triggers query processing
     ///
     /// ## Errors
-    /// if query is not registered on this helper.
+    /// if query is not registered on this helper, or its privacy budget cannot be spent.
     ///
     /// ## Panics
     /// If failed to obtain exclusive access to the query collection.
@@ -232,6 +335,12 @@ impl Processor {
                         input.query_id, query_id,
                         "received inputs for a different query"
                     );
+                    // inputs only arrive once the coordinator confirmed the query, so followers
+                    // can spend the budget they reserved
+                    let reservation = self.budget_reservations.lock().unwrap().remove(&query_id);
+                    if let Some(reservation) = reservation {
+                        reservation.commit()?;
+                    }
                     let mut gateway_config = GatewayConfig::default();
                     if let Some(active_work) = self.active_work {
                         gateway_config.active = active_work;
@@ -249,7 +358,10 @@ impl Processor {
                         input.query_id,
                         QueryState::Running(executor::execute(
                             config,
-                            Arc::clone(&self.key_registry),
+                            // keys can be rotated while the query is running, it keeps
+                            // using the ones that were current when it started
+                            self.key_registry.current(),
+                            Arc::clone(&self.seen_reports),
                             gateway,
                             input.input_stream,
                         )),
@@ -292,6 +404,12 @@ impl Processor {
         Ok(status)
     }
 
+    /// Public keys matching the private keys this helper currently uses to decrypt reports.
+    #[must_use]
+    pub fn public_keys(&self) -> PublicKeySet {
+        PublicKeySet::from_registry(&self.key_registry.current(), SystemTime::now())
+    }
+
     /// Awaits the query completion
     ///
     /// ## Errors
@@ -332,7 +450,15 @@ impl Processor {
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::{array, future::Future, sync::Arc};
+    use std::{
+        array,
+        future::{ready, Future},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
 
     use futures::pin_mut;
     use futures_util::future::poll_immediate;
@@ -342,14 +468,21 @@ mod tests {
         ff::FieldType,
         helpers::{
             make_owned_handler,
-            query::{PrepareQuery, QueryConfig, QueryType::TestMultiply},
+            query::{
+                DpConfig, DpConfigError, IpaQueryConfig, PaddingMode, PaddingPolicy, PrepareQuery,
+                QueryConfig, QueryType, QueryType::TestMultiply,
+            },
+            routing::RouteId,
             ApiError, HandlerBox, HelperIdentity, HelperResponse, InMemoryMpcNetwork,
-            RequestHandler, RoleAssignment, Transport,
+            InMemoryTransport, RequestHandler, RoleAssignment, Transport,
         },
+        hpke::{KeyRegistry, ReloadableKeyRegistry},
         protocol::QueryId,
         query::{
-            processor::Processor, state::StateError, NewQueryError, PrepareQueryError, QueryStatus,
+            processor::Processor, runner::ipa_privacy_loss, state::StateError, BudgetError,
+            NewQueryError, PrepareQueryError, PrivacyBudget, QueryStatus, SeenReports,
         },
+        report::EpochRange,
     };
 
     fn prepare_query_handler<F, Fut>(cb: F) -> Arc<dyn RequestHandler<Identity = HelperIdentity>>
@@ -363,6 +496,25 @@ mod tests {
         })
     }
 
+    /// Handler that passes prepare and abort requests on to `processor`.
+    fn processor_handler(
+        processor: Arc<Processor>,
+        transport: InMemoryTransport<HelperIdentity>,
+    ) -> Arc<dyn RequestHandler<Identity = HelperIdentity>> {
+        make_owned_handler(move |req, _| {
+            let result: Result<(), ApiError> = match req.route {
+                RouteId::PrepareQuery => processor
+                    .prepare(&transport, req.into().unwrap())
+                    .map_err(Into::into),
+                RouteId::AbortQuery => processor
+                    .abort(req.query_id.unwrap())
+                    .map_err(Into::into),
+                route => panic!("unexpected request {route:?}"),
+            };
+            ready(result.map(HelperResponse::from))
+        })
+    }
+
     fn respond_ok() -> Arc<dyn RequestHandler<Identity = HelperIdentity>> {
         prepare_query_handler(move |_| async move { Ok(HelperResponse::ok()) })
     }
@@ -371,6 +523,30 @@ mod tests {
         QueryConfig::new(TestMultiply, FieldType::Fp31, 1).unwrap()
     }
 
+    fn ipa_config() -> QueryConfig {
+        QueryConfig::new(
+            QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+                site_domain: Some("example.com".to_string()),
+                epochs: Some(EpochRange::single(1)),
+                ..Default::default()
+            }),
+            FieldType::Fp32BitPrime,
+            1,
+        )
+        .unwrap()
+    }
+
+    /// Processor that refuses any query spending privacy budget.
+    fn no_budget_processor() -> Processor {
+        Processor::new(
+            Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())),
+            Arc::new(SeenReports::in_memory(Duration::ZERO)),
+            Arc::new(PrivacyBudget::in_memory(0.0)),
+            PaddingPolicy::permissive(),
+            None,
+        )
+    }
+
     #[tokio::test]
     async fn new_query() {
         let barrier = Arc::new(Barrier::new(3));
@@ -399,7 +575,7 @@ mod tests {
         let p0 = Processor::default();
         let request = test_multiply_config();
 
-        let qc_future = p0.new_query(t0, request);
+        let qc_future = p0.new_query(t0, request.clone());
         pin_mut!(qc_future);
 
         // poll future once to trigger query status change
@@ -437,7 +613,7 @@ mod tests {
         let request = test_multiply_config();
 
         let _qc = p0
-            .new_query(Transport::clone_ref(&t0), request)
+            .new_query(Transport::clone_ref(&t0), request.clone())
             .await
             .unwrap();
         assert!(matches!(
@@ -481,7 +657,9 @@ mod tests {
         let [t0, _, _] = network.transports();
         let p0 = Processor::default();
         let request = test_multiply_config();
-        p0.new_query(t0.clone_ref(), request).await.unwrap_err();
+        p0.new_query(t0.clone_ref(), request.clone())
+            .await
+            .unwrap_err();
 
         assert!(matches!(
             p0.new_query(t0, request).await.unwrap_err(),
@@ -489,9 +667,199 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn rejects_over_budget() {
+        let h2 = respond_ok();
+        let h3 = respond_ok();
+        let network = InMemoryMpcNetwork::new([
+            None,
+            Some(HandlerBox::owning_ref(&h2)),
+            Some(HandlerBox::owning_ref(&h3)),
+        ]);
+        let [t0, _, _] = network.transports();
+        let p0 = no_budget_processor();
+
+        assert!(matches!(
+            p0.new_query(t0.clone_ref(), ipa_config())
+                .await
+                .unwrap_err(),
+            NewQueryError::Budget(BudgetError::Exceeded { epoch: 1, .. })
+        ));
+        // queries that don't spend budget are still accepted
+        p0.new_query(t0, test_multiply_config()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn releases_budget_if_rejected() {
+        let h2 = respond_ok();
+        let h3 = prepare_query_handler(|_| async move {
+            Err(ApiError::QueryPrepare(PrepareQueryError::Budget(
+                BudgetError::Undeclared,
+            )))
+        });
+        let network = InMemoryMpcNetwork::new([
+            None,
+            Some(HandlerBox::owning_ref(&h2)),
+            Some(HandlerBox::owning_ref(&h3)),
+        ]);
+        let [t0, _, _] = network.transports();
+        let QueryType::SemiHonestOprfIpa(config) = ipa_config().query_type else {
+            unreachable!()
+        };
+        // enough for one query only
+        let budget = Arc::new(PrivacyBudget::in_memory(
+            ipa_privacy_loss(&config).epsilon * 1.5,
+        ));
+        let p0 = Processor::new(
+            Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())),
+            Arc::new(SeenReports::in_memory(Duration::ZERO)),
+            Arc::clone(&budget),
+            PaddingPolicy::permissive(),
+            None,
+        );
+
+        assert!(matches!(
+            p0.new_query(t0, ipa_config()).await.unwrap_err(),
+            NewQueryError::MpcTransport(_)
+        ));
+        assert!(budget.spent("example.com", 1).epsilon <= 0.0);
+        budget.reserve(&ipa_config().query_type).unwrap();
+    }
+
+    #[tokio::test]
+    async fn aborts_on_follower_if_rejected() {
+        let QueryType::SemiHonestOprfIpa(config) = ipa_config().query_type else {
+            unreachable!()
+        };
+        // enough for one query only
+        let budget = Arc::new(PrivacyBudget::in_memory(
+            ipa_privacy_loss(&config).epsilon * 1.5,
+        ));
+        let p1 = Arc::new(Processor::new(
+            Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())),
+            Arc::new(SeenReports::in_memory(Duration::ZERO)),
+            Arc::clone(&budget),
+            PaddingPolicy::permissive(),
+            None,
+        ));
+        // the other follower rejects the first attempt only
+        let rejected = Arc::new(AtomicBool::new(false));
+        let h3 = prepare_query_handler(move |_| {
+            let reject = !rejected.swap(true, Ordering::Relaxed);
+            async move {
+                if reject {
+                    Err(ApiError::QueryPrepare(PrepareQueryError::AlreadyRunning))
+                } else {
+                    Ok(HelperResponse::ok())
+                }
+            }
+        });
+        let h2 = HandlerBox::empty();
+        let network = InMemoryMpcNetwork::new([
+            None,
+            Some(h2.clone()),
+            Some(HandlerBox::owning_ref(&h3)),
+        ]);
+        let [t0, t1, _] = network.transports();
+        let h2_handler = processor_handler(Arc::clone(&p1), t1);
+        h2.set_handler(Arc::downgrade(&h2_handler));
+        let p0 = Processor::default();
+
+        assert!(matches!(
+            p0.new_query(t0.clone_ref(), ipa_config())
+                .await
+                .unwrap_err(),
+            NewQueryError::MpcTransport(_)
+        ));
+        // the query is gone from the follower that accepted it, and its budget is released
+        assert!(p1.query_status(QueryId).is_err());
+        assert!(budget.spent("example.com", 1).epsilon <= 0.0);
+
+        p0.new_query(t0, ipa_config()).await.unwrap();
+        assert_eq!(QueryStatus::AwaitingInputs, p1.query_status(QueryId).unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_dp() {
+        let network = InMemoryMpcNetwork::default();
+        let [t0, _, _] = network.transports();
+        let p0 = Processor::default();
+        let request = QueryConfig::new(
+            QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+                dp: DpConfig::discrete_laplace(0.0),
+                ..Default::default()
+            }),
+            FieldType::Fp32BitPrime,
+            1,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            p0.new_query(t0, request).await.unwrap_err(),
+            NewQueryError::InvalidDp(DpConfigError::Epsilon(_))
+        ));
+        assert!(p0.query_status(QueryId).is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_weak_padding() {
+        let network = InMemoryMpcNetwork::default();
+        let [t0, _, _] = network.transports();
+        let p0 = Processor::new(
+            Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())),
+            Arc::new(SeenReports::in_memory(Duration::ZERO)),
+            Arc::new(PrivacyBudget::unlimited()),
+            PaddingPolicy {
+                max_epsilon: 5.0,
+                max_delta: 1e-6,
+                min_matchkey_cardinality_cap: 10,
+                min_aggregation_padding_sensitivity: 10,
+                allow_no_padding: false,
+            },
+            None,
+        );
+        let request = |dp| {
+            QueryConfig::new(
+                QueryType::SemiHonestOprfIpa(IpaQueryConfig {
+                    dp,
+                    ..Default::default()
+                }),
+                FieldType::Fp32BitPrime,
+                1,
+            )
+            .unwrap()
+        };
+
+        assert!(matches!(
+            p0.new_query(
+                t0.clone_ref(),
+                request(DpConfig {
+                    matchkey_cardinality_cap: 3.try_into().unwrap(),
+                    ..DpConfig::default()
+                })
+            )
+            .await
+            .unwrap_err(),
+            NewQueryError::InvalidDp(DpConfigError::PolicyCardinalityCap(_, 10))
+        ));
+        assert!(matches!(
+            p0.new_query(
+                t0,
+                request(DpConfig {
+                    padding: PaddingMode::None,
+                    ..DpConfig::default()
+                })
+            )
+            .await
+            .unwrap_err(),
+            NewQueryError::InvalidDp(DpConfigError::PolicyNoPadding)
+        ));
+        assert!(p0.query_status(QueryId).is_err());
+    }
+
     mod prepare {
         use super::*;
-        use crate::query::QueryStatusError;
+        use crate::query::{QueryAbortError, QueryStatusError};
 
         fn prepare_query(identities: [HelperIdentity; 3]) -> PrepareQuery {
             PrepareQuery {
@@ -534,6 +902,64 @@ mod tests {
             ));
         }
 
+        #[tokio::test]
+        async fn rejects_over_budget() {
+            let network = InMemoryMpcNetwork::default();
+            let identities = HelperIdentity::make_three();
+            let req = PrepareQuery {
+                config: ipa_config(),
+                ..prepare_query(identities)
+            };
+            let transport = network.transport(identities[1]);
+            let processor = no_budget_processor();
+
+            assert!(matches!(
+                processor.prepare(&transport, req),
+                Err(PrepareQueryError::Budget(BudgetError::Exceeded { .. }))
+            ));
+            assert!(matches!(
+                processor.query_status(QueryId).unwrap_err(),
+                QueryStatusError::NoSuchQuery(_)
+            ));
+        }
+
+        #[tokio::test]
+        async fn abort() {
+            let network = InMemoryMpcNetwork::default();
+            let identities = HelperIdentity::make_three();
+            let req = PrepareQuery {
+                config: ipa_config(),
+                ..prepare_query(identities)
+            };
+            let transport = network.transport(identities[1]);
+            // enough for one query only
+            let QueryType::SemiHonestOprfIpa(config) = ipa_config().query_type else {
+                unreachable!()
+            };
+            let processor = Processor::new(
+                Arc::new(ReloadableKeyRegistry::new(KeyRegistry::empty())),
+                Arc::new(SeenReports::in_memory(Duration::ZERO)),
+                Arc::new(PrivacyBudget::in_memory(
+                    ipa_privacy_loss(&config).epsilon * 1.5,
+                )),
+                PaddingPolicy::permissive(),
+                None,
+            );
+
+            assert!(matches!(
+                processor.abort(QueryId),
+                Err(QueryAbortError::NoSuchQuery(_))
+            ));
+            processor.prepare(&transport, req.clone()).unwrap();
+            processor.abort(QueryId).unwrap();
+            assert!(matches!(
+                processor.query_status(QueryId).unwrap_err(),
+                QueryStatusError::NoSuchQuery(_)
+            ));
+            // the budget reserved for the aborted query is available again
+            processor.prepare(&transport, req).unwrap();
+        }
+
         #[tokio::test]
         async fn rejects_if_query_exists() {
             let network = InMemoryMpcNetwork::default();
@@ -561,7 +987,9 @@ mod tests {
                 boolean_array::{BA20, BA3, BA8},
                 Fp31, U128Conversions,
             },
-            helpers::query::{IpaQueryConfig, QueryType},
+            helpers::query::{
+                DpConfig, DuplicateReportPolicy, InvalidReportPolicy, IpaQueryConfig, QueryType,
+            },
             protocol::ipa_prf::OPRFIPAInputRow,
             secret_sharing::replicated::semi_honest,
             test_fixture::{ipa::TestRawDataRecord, Reconstruct, TestApp},
@@ -681,9 +1109,13 @@ mod tests {
                             per_user_credit_cap: 8,
                             max_breakdown_key: 3,
                             attribution_window_seconds: None,
-                            with_dp: 0,
-                            epsilon: 5.0,
+                            dp: DpConfig::no_noise().with_relaxed_padding(),
                             plaintext_match_keys: true,
+                            epochs: None,
+                            site_domain: None,
+                            invalid_reports: InvalidReportPolicy::Fail,
+                            duplicate_reports: DuplicateReportPolicy::Fail,
+                            breakdown_hierarchy: None,
                         }),
                     },
                 )
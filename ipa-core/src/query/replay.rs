@@ -0,0 +1,466 @@
+//! Replay protection for input reports.
+//!
+//! Helpers remember reports they have already processed and refuse to count them again, within a
+//! query and across queries submitted during a configurable window. Reports are identified by the
+//! SHA-256 hash of the encapsulated key of their match key ciphertext. It is different for every
+//! encryption, and because HPKE binds it to the ciphertext, it cannot be changed without making
+//! the report undecryptable.
+//!
+//! Reports used by a running query are reserved, so concurrent queries cannot use them either.
+//! Once the query completes, they are written to the file backing the store, if there is one, and
+//! helpers check that all of them succeeded before recording the reports as seen. Reports of
+//! queries that fail on any helper are released, also from the file, and can be submitted again.
+//! File writes and the removal of expired reports run on blocking threads, off the executor that
+//! runs queries.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    panic,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use sha2::{Digest, Sha256};
+use tokio::runtime::Handle;
+
+use crate::sync::{Arc, Mutex};
+
+pub type ReportFingerprint = [u8; 32];
+
+/// Each log entry is the time report was recorded, in seconds since the Unix epoch, as `u64` LE,
+/// followed by its fingerprint.
+const ENTRY_LEN: usize = size_of::<u64>() + size_of::<ReportFingerprint>();
+
+/// Time of log entries that release reports written by a query that failed afterwards.
+const RELEASED: u64 = 0;
+
+#[must_use]
+pub fn fingerprint(encap_key: &[u8]) -> ReportFingerprint {
+    Sha256::digest(encap_key).into()
+}
+
+fn to_unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Runs `f` on a thread where blocking is allowed, so that file writes do not stall the executor.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(v) => v,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Reports this helper has processed recently.
+pub struct SeenReports {
+    window: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    /// Reports used by completed queries, with the time they were recorded.
+    seen: HashMap<ReportFingerprint, u64>,
+    /// Reports used by queries that are still running.
+    pending: HashSet<ReportFingerprint>,
+    log: Option<Log>,
+}
+
+/// Append-only file with reports recorded in [`State::seen`]. Expired entries are removed from
+/// it when it gets much larger than the set of reports it needs to keep.
+struct Log {
+    path: PathBuf,
+    file: File,
+    entries: usize,
+}
+
+impl Log {
+    fn open(path: PathBuf, seen: &HashMap<ReportFingerprint, u64>) -> io::Result<Self> {
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&encode(seen.iter().map(|(fp, ts)| (fp, *ts))))?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(&path)?,
+            path,
+            entries: seen.len(),
+        })
+    }
+
+    fn append(&mut self, entries: &[ReportFingerprint], ts: u64) -> io::Result<()> {
+        let res = self
+            .file
+            .write_all(&encode(entries.iter().map(|fp| (fp, ts))))
+            .and_then(|()| self.file.sync_data());
+        if res.is_err() {
+            // Remove what was written, so that a partial entry does not shift the entries
+            // appended later. If this fails too, they are lost when the log is loaded.
+            let _ = self
+                .file
+                .set_len(u64::try_from(self.entries * ENTRY_LEN).unwrap());
+            return res;
+        }
+        self.entries += entries.len();
+        Ok(())
+    }
+}
+
+fn encode<'a, I: Iterator<Item = (&'a ReportFingerprint, u64)>>(entries: I) -> Vec<u8> {
+    entries
+        .flat_map(|(fp, ts)| ts.to_le_bytes().into_iter().chain(fp.iter().copied()))
+        .collect()
+}
+
+impl SeenReports {
+    /// Creates a store that forgets everything when helper restarts.
+    #[must_use]
+    pub fn in_memory(window: Duration) -> Self {
+        Self {
+            window,
+            state: Mutex::new(State {
+                seen: HashMap::new(),
+                pending: HashSet::new(),
+                log: None,
+            }),
+        }
+    }
+
+    /// Creates a store backed by the file at `path`, loading reports recorded there that are
+    /// still within the window. The file is created if it does not exist.
+    ///
+    /// ## Errors
+    /// If the file cannot be read or written.
+    ///
+    /// ## Panics
+    /// If the store mutex is poisoned.
+    pub fn open(path: &Path, window: Duration) -> io::Result<Self> {
+        let mut data = Vec::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                file.read_to_end(&mut data)?;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        let now = to_unix_secs(SystemTime::now());
+        let this = Self::in_memory(window);
+        {
+            let mut state = this.state.lock().unwrap();
+            // incomplete entry at the end is left by a crash in the middle of a write, and is
+            // ignored.
+            for entry in data.chunks_exact(ENTRY_LEN) {
+                let (ts, fp) = entry.split_at(size_of::<u64>());
+                let fp = fp.try_into().unwrap();
+                match u64::from_le_bytes(ts.try_into().unwrap()) {
+                    RELEASED => {
+                        state.seen.remove(&fp);
+                    }
+                    ts if this.is_live(ts, now) => {
+                        state.seen.insert(fp, ts);
+                    }
+                    _ => {}
+                }
+            }
+            state.log = Some(Log::open(path.to_path_buf(), &state.seen)?);
+        }
+
+        Ok(this)
+    }
+
+    fn is_live(&self, recorded: u64, now: u64) -> bool {
+        now.saturating_sub(recorded) < self.window.as_secs()
+    }
+
+    /// Forgets reports recorded before the window that ends at `now`, and compacts the log if it
+    /// got much larger than the set of reports it needs to keep.
+    fn expire(&self, now: u64) {
+        let mut state = self.state.lock().unwrap();
+        let State { seen, log, .. } = &mut *state;
+        seen.retain(|_, ts| self.is_live(*ts, now));
+
+        if let Some(log) = log {
+            if log.entries > 2 * seen.len() + 1024 {
+                // reports are already in the log, so it is only compacted if possible
+                match Log::open(log.path.clone(), seen) {
+                    Ok(compacted) => *log = compacted,
+                    Err(e) => tracing::warn!("failed to compact {}: {e}", log.path.display()),
+                }
+            }
+        }
+    }
+
+    /// Releases reports reserved by a query that did not complete. If they were written to the
+    /// log, they are released there first, so a query that reserves them afterwards writes its
+    /// entries after the release.
+    fn release(&self, fingerprints: &[ReportFingerprint], written: bool) {
+        let mut state = self.state.lock().unwrap();
+        if let (true, Some(log)) = (written, &mut state.log) {
+            if let Err(e) = log.append(fingerprints, RELEASED) {
+                tracing::error!("failed to release reports in {}: {e}", log.path.display());
+            }
+        }
+        for fp in fingerprints {
+            state.pending.remove(fp);
+        }
+    }
+
+    /// Starts reserving reports for a query that runs at `now`.
+    #[must_use]
+    pub fn reserve(self: &Arc<Self>, now: SystemTime) -> Reservation {
+        Reservation {
+            store: Arc::clone(self),
+            now: to_unix_secs(now),
+            fingerprints: Vec::new(),
+            written: false,
+            prepared: false,
+        }
+    }
+}
+
+/// Reports reserved by one query. They are released when this is dropped, unless
+/// [`Self::commit`] is called first.
+pub struct Reservation {
+    store: Arc<SeenReports>,
+    now: u64,
+    fingerprints: Vec<ReportFingerprint>,
+    /// Whether [`Self::prepare`] started writing the reports to the log. They are released from
+    /// the log on drop even if the write failed or was interrupted.
+    written: bool,
+    /// Whether the reports were written to the log by [`Self::prepare`].
+    prepared: bool,
+}
+
+impl Reservation {
+    /// Reserves the report, returning `false` if it was already seen, or reserved by this or
+    /// another query.
+    ///
+    /// ## Panics
+    /// If the store mutex is poisoned.
+    pub fn insert(&mut self, fingerprint: ReportFingerprint) -> bool {
+        let mut state = self.store.state.lock().unwrap();
+        if let Some(&ts) = state.seen.get(&fingerprint) {
+            if self.store.is_live(ts, self.now) {
+                return false;
+            }
+        }
+        if !state.pending.insert(fingerprint) {
+            return false;
+        }
+        self.fingerprints.push(fingerprint);
+
+        true
+    }
+
+    /// Writes the reserved reports to the file backing the store, if there is one, without
+    /// recording them as seen. If the reservation is dropped afterwards, they are released from
+    /// the file as well.
+    ///
+    /// ## Errors
+    /// If writing to the file fails.
+    ///
+    /// ## Panics
+    /// If the store mutex is poisoned.
+    pub async fn prepare(&mut self) -> io::Result<()> {
+        if self.prepared || self.fingerprints.is_empty() {
+            return Ok(());
+        }
+        self.written = true;
+        let (store, fingerprints, now) = (
+            Arc::clone(&self.store),
+            self.fingerprints.clone(),
+            self.now,
+        );
+        blocking(move || {
+            if let Some(log) = &mut store.state.lock().unwrap().log {
+                log.append(&fingerprints, now)?;
+            }
+            Ok::<_, io::Error>(())
+        })
+        .await?;
+        self.prepared = true;
+
+        Ok(())
+    }
+
+    /// Records all reserved reports as seen, writing them to the file backing the store first
+    /// unless [`Self::prepare`] already did.
+    ///
+    /// ## Errors
+    /// If writing to the file fails. Reports are released in this case.
+    ///
+    /// ## Panics
+    /// If the store mutex is poisoned.
+    pub async fn commit(mut self) -> io::Result<()> {
+        self.prepare().await?;
+        let fingerprints = std::mem::take(&mut self.fingerprints);
+        if fingerprints.is_empty() {
+            return Ok(());
+        }
+        {
+            let mut state = self.store.state.lock().unwrap();
+            for fp in &fingerprints {
+                state.pending.remove(fp);
+                state.seen.insert(*fp, self.now);
+            }
+        }
+        let (store, now) = (Arc::clone(&self.store), self.now);
+        blocking(move || store.expire(now)).await;
+
+        Ok(())
+    }
+
+    /// Releases the reserved reports, also from the file backing the store if [`Self::prepare`]
+    /// wrote them there. Dropping the reservation does the same, but the file may be updated
+    /// after the drop returns.
+    ///
+    /// ## Panics
+    /// If the store mutex is poisoned.
+    pub async fn release(mut self) {
+        if self.fingerprints.is_empty() {
+            return;
+        }
+        let written = self.written;
+        let release = self.take_release();
+        if written {
+            blocking(release).await;
+        } else {
+            release();
+        }
+    }
+
+    fn take_release(&mut self) -> impl FnOnce() + Send + 'static {
+        let (store, fingerprints, written) = (
+            Arc::clone(&self.store),
+            std::mem::take(&mut self.fingerprints),
+            self.written,
+        );
+        move || store.release(&fingerprints, written)
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if self.fingerprints.is_empty() {
+            return;
+        }
+        let written = self.written;
+        let release = self.take_release();
+        match Handle::try_current() {
+            Ok(handle) if written => drop(handle.spawn_blocking(release)),
+            _ => release(),
+        }
+    }
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{fingerprint, SeenReports};
+    use crate::sync::Arc;
+
+    const DAY: Duration = Duration::from_secs(86_400);
+
+    #[test]
+    fn duplicates_within_query() {
+        let store = Arc::new(SeenReports::in_memory(DAY));
+        let mut reservation = store.reserve(SystemTime::now());
+        assert!(reservation.insert(fingerprint(b"a")));
+        assert!(reservation.insert(fingerprint(b"b")));
+        assert!(!reservation.insert(fingerprint(b"a")));
+    }
+
+    #[test]
+    fn concurrent_queries() {
+        let store = Arc::new(SeenReports::in_memory(DAY));
+        let mut first = store.reserve(SystemTime::now());
+        assert!(first.insert(fingerprint(b"a")));
+        let mut second = store.reserve(SystemTime::now());
+        assert!(!second.insert(fingerprint(b"a")));
+
+        // failed query releases its reports
+        drop(first);
+        assert!(second.insert(fingerprint(b"a")));
+    }
+
+    #[tokio::test]
+    async fn window() {
+        let store = Arc::new(SeenReports::in_memory(DAY));
+        let now = SystemTime::now();
+        let mut reservation = store.reserve(now);
+        assert!(reservation.insert(fingerprint(b"a")));
+        reservation.commit().await.unwrap();
+
+        assert!(!store.reserve(now + DAY / 2).insert(fingerprint(b"a")));
+        assert!(store.reserve(now + DAY).insert(fingerprint(b"a")));
+    }
+
+    #[tokio::test]
+    async fn zero_window_only_checks_within_query() {
+        let store = Arc::new(SeenReports::in_memory(Duration::ZERO));
+        let now = SystemTime::now();
+        let mut reservation = store.reserve(now);
+        assert!(reservation.insert(fingerprint(b"a")));
+        assert!(!reservation.insert(fingerprint(b"a")));
+        reservation.commit().await.unwrap();
+
+        assert!(store.reserve(now).insert(fingerprint(b"a")));
+    }
+
+    #[tokio::test]
+    async fn persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seen");
+        {
+            let store = Arc::new(SeenReports::open(&path, DAY).unwrap());
+            let mut reservation = store.reserve(SystemTime::now());
+            assert!(reservation.insert(fingerprint(b"a")));
+            reservation.commit().await.unwrap();
+            let mut reservation = store.reserve(SystemTime::now());
+            assert!(reservation.insert(fingerprint(b"b")));
+            // not committed
+        }
+
+        let store = Arc::new(SeenReports::open(&path, DAY).unwrap());
+        let mut reservation = store.reserve(SystemTime::now());
+        assert!(!reservation.insert(fingerprint(b"a")));
+        assert!(reservation.insert(fingerprint(b"b")));
+
+        // entries recorded too long ago are not loaded
+        let mut reservation = store.reserve(SystemTime::now() - 2 * DAY);
+        assert!(reservation.insert(fingerprint(b"c")));
+        reservation.commit().await.unwrap();
+        let store = Arc::new(SeenReports::open(&path, DAY).unwrap());
+        assert!(store.reserve(SystemTime::now()).insert(fingerprint(b"c")));
+    }
+
+    #[tokio::test]
+    async fn prepared_and_released() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seen");
+        {
+            let store = Arc::new(SeenReports::open(&path, DAY).unwrap());
+            let mut reservation = store.reserve(SystemTime::now());
+            assert!(reservation.insert(fingerprint(b"a")));
+            reservation.prepare().await.unwrap();
+            reservation.commit().await.unwrap();
+
+            // query failed on another helper after reports were written
+            let mut reservation = store.reserve(SystemTime::now());
+            assert!(reservation.insert(fingerprint(b"b")));
+            reservation.prepare().await.unwrap();
+            reservation.release().await;
+            assert!(store.reserve(SystemTime::now()).insert(fingerprint(b"b")));
+        }
+
+        let store = Arc::new(SeenReports::open(&path, DAY).unwrap());
+        let mut reservation = store.reserve(SystemTime::now());
+        assert!(!reservation.insert(fingerprint(b"a")));
+        assert!(reservation.insert(fingerprint(b"b")));
+    }
+}
@@ -0,0 +1,358 @@
+use serde::Serialize;
+
+use crate::{
+    error::Error,
+    ff::boolean_array::{BA20, BA3, BA32, BA8},
+    helpers::query::{DpMechanism, IpaQueryConfig},
+    protocol::{
+        dp::noise_coins,
+        ipa_prf::{
+            oprf_padding::{PaddingNoise, PaddingSampling},
+            prf_sharding::multiplications_per_record,
+        },
+    },
+    query::{
+        runner::oprf_ipa::{noise_metadata, NUM_BREAKDOWNS},
+        NoiseMetadata,
+    },
+    secret_sharing::SharedValue,
+};
+
+// These must be kept in sync with the types that `OprfIpaQuery` runs IPA with.
+type BK = BA8;
+type TV = BA3;
+type TS = BA20;
+type HV = BA32;
+
+/// Gates to convert a match key to the prime field of curve 25519, see `compute_prf_for_inputs`.
+const CONVERSION_MULTIPLICATIONS: f64 = 512.0;
+
+/// Every row of padding is added by one of the three pairs of helpers, unless the three helpers
+/// sample it together.
+const PAIRS: f64 = 3.0;
+
+/// Expected number of dummy rows a query is padded with, summed over all pairs of helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PaddingRows {
+    pub mean: f64,
+    pub std: f64,
+}
+
+impl PaddingRows {
+    const NONE: Self = Self {
+        mean: 0.0,
+        std: 0.0,
+    };
+
+    /// Every pair of helpers, or the three of them for joint sampling, adds `count` independent
+    /// samples of `noise`, each repeated `weight` times, for every `(count, weight)` in `samples`.
+    fn new(noise: Option<PaddingNoise>, samples: impl Iterator<Item = (f64, f64)>) -> Self {
+        let Some(noise) = noise else {
+            return Self::NONE;
+        };
+        let passes = match noise.sampling {
+            PaddingSampling::Pairwise => PAIRS,
+            PaddingSampling::Joint => 1.0,
+        };
+        let (mean, variance) = samples.fold((0.0, 0.0), |(mean, variance), (count, weight)| {
+            (
+                mean + count * weight * noise.mean,
+                variance + count * weight * weight * noise.std * noise.std,
+            )
+        });
+        Self {
+            mean: passes * mean,
+            std: (passes * variance).sqrt(),
+        }
+    }
+}
+
+/// Approximate work the helpers do to run a query, in expectation.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct MpcCost {
+    /// Rows that the helpers evaluate the PRF on, including OPRF padding.
+    pub records: f64,
+    /// Multiplications of boolean shares, which dominate the cost of IPA.
+    pub boolean_multiplications: f64,
+    /// Multiplications in the prime field of curve 25519, used to evaluate the PRF.
+    pub prime_field_multiplications: f64,
+}
+
+/// What an IPA query with a given config is expected to cost and how noisy its output is
+/// expected to be, computed without running it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct IpaEstimate {
+    /// Records submitted by the report collector.
+    pub records: usize,
+    /// Noise the query adds to its output and padding, as reported with its results.
+    pub noise: NoiseMetadata,
+    pub oprf_padding_rows: PaddingRows,
+    pub aggregation_padding_rows: PaddingRows,
+    pub cost: MpcCost,
+}
+
+/// Estimates an IPA query with `config` on `records` records.
+///
+/// The cost of sorting and attribution depends on how many events every user has, which the
+/// helpers do not know before running the query. It is estimated as if every user had as many
+/// events as the match key cardinality cap of `config`, which gives an upper bound for inputs
+/// that respect the cap.
+///
+/// Aggregation is estimated for the protocol this build runs: moving every value to its breakdown
+/// by default, or revealing breakdown keys with the `reveal-aggregation` feature, which assumes
+/// values are spread evenly over breakdowns.
+///
+/// ## Errors
+/// If the DP parameters of `config` are out of range.
+pub fn estimate_ipa(config: &IpaQueryConfig, records: usize) -> Result<IpaEstimate, Error> {
+    let noise = noise_metadata(config)?;
+    let cardinality_cap = config.dp.matchkey_cardinality_cap.get();
+
+    // every pair of helpers adds dummy match keys for every cardinality up to the cap, and
+    // dummy rows for every breakdown
+    let oprf_padding_rows = PaddingRows::new(
+        noise.oprf_padding,
+        (1..=cardinality_cap).map(|cardinality| (1.0, f64::from(cardinality))),
+    );
+    let aggregation_padding_rows = PaddingRows::new(
+        noise.aggregation_padding,
+        std::iter::once((as_f64(NUM_BREAKDOWNS), 1.0)),
+    );
+
+    #[allow(clippy::cast_precision_loss)] // record counts are far below 2^52
+    let rows = records as f64 + oprf_padding_rows.mean;
+    let events_per_user = f64::from(cardinality_cap);
+    let users = rows / events_per_user;
+    // every row but the first one of its user is attributed
+    let attributed_rows = rows - users;
+
+    let conversion = CONVERSION_MULTIPLICATIONS * rows;
+    let sort = users * quicksort_comparisons(cardinality_cap) * f64::from(TS::BITS);
+    let attribution = attributed_rows
+        * as_f64(multiplications_per_record::<BK, TV, TS>(
+            config.attribution_window_seconds,
+        ));
+    let aggregation = aggregation_multiplications(attributed_rows, aggregation_padding_rows.mean);
+    let dp = histograms(config) * dp_multiplications(config);
+
+    Ok(IpaEstimate {
+        records,
+        noise,
+        oprf_padding_rows,
+        aggregation_padding_rows,
+        cost: MpcCost {
+            records: rows,
+            boolean_multiplications: conversion + sort + attribution + aggregation + dp,
+            prime_field_multiplications: rows,
+        },
+    })
+}
+
+/// Multiplications to aggregate the trigger values of `attributed_rows` rows into the histogram of
+/// all breakdowns.
+fn aggregation_multiplications(attributed_rows: f64, padding_rows: f64) -> f64 {
+    let tv_bits = f64::from(TV::BITS);
+    if cfg!(feature = "reveal-aggregation") {
+        // breakdown keys of the rows and of the padding are revealed, so every value is only
+        // added to the other values of its breakdown
+        (attributed_rows + padding_rows) * (tv_bits + 1.0)
+    } else {
+        // every value is moved to its breakdown, multiplying it with a bit of its breakdown key
+        // for every breakdown, then added to every breakdown
+        attributed_rows * as_f64(NUM_BREAKDOWNS) * (2.0 * tv_bits + 1.0)
+    }
+}
+
+/// Histograms the query adds noise to, one for every level of its breakdown hierarchy.
+fn histograms(config: &IpaQueryConfig) -> f64 {
+    as_f64(
+        config
+            .breakdown_hierarchy
+            .map_or(1, |hierarchy| hierarchy.levels()),
+    )
+}
+
+/// Multiplications to add noise to a histogram of all breakdowns and threshold it.
+fn dp_multiplications(config: &IpaQueryConfig) -> f64 {
+    let dp_params = match config.breakdown_hierarchy {
+        Some(hierarchy) => hierarchy.level_noise(config.dp.noise()),
+        None => config.dp.noise(),
+    };
+    let hv_bits = f64::from(HV::BITS);
+    let coins = noise_coins(dp_params, config.per_user_credit_cap, NUM_BREAKDOWNS);
+    let per_breakdown = match (dp_params, coins) {
+        // summing 1 bit values costs about 2 multiplications per value, followed by an addition
+        (_, Some(coins)) => 2.0 * f64::from(coins) + hv_bits,
+        // Laplace and Skellam noise is sampled by every pair of helpers and added to the histogram
+        (DpMechanism::DiscreteLaplace { .. } | DpMechanism::Skellam { .. }, None) => {
+            PAIRS * hv_bits
+        }
+        _ => 0.0,
+    };
+    let threshold = if config.dp.threshold.is_some() {
+        hv_bits
+    } else {
+        0.0
+    };
+
+    as_f64(NUM_BREAKDOWNS) * (per_breakdown + threshold)
+}
+
+/// Expected number of comparisons to quicksort `n` distinct values with random pivots.
+fn quicksort_comparisons(n: u32) -> f64 {
+    let harmonic = (1..=n).map(|i| 1.0 / f64::from(i)).sum::<f64>();
+    let n = f64::from(n);
+    2.0 * (n + 1.0) * harmonic - 4.0 * n
+}
+
+fn as_f64(v: usize) -> f64 {
+    f64::from(u32::try_from(v).unwrap())
+}
+
+#[cfg(all(test, unit_test))]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use super::{estimate_ipa, quicksort_comparisons, MpcCost, PaddingRows, BK, TS, TV};
+    use crate::{
+        helpers::query::{DpConfig, IpaQueryConfig, NoiseMechanism, PaddingMode},
+        protocol::ipa_prf::{
+            oprf_padding::PaddingSampling, prf_sharding::multiplications_per_record,
+        },
+        secret_sharing::SharedValue,
+    };
+
+    #[test]
+    fn quicksort() {
+        assert!((quicksort_comparisons(1) - 0.0).abs() < 1e-9);
+        assert!((quicksort_comparisons(2) - 1.0).abs() < 1e-9);
+        // 2 comparisons with the median as the first pivot, 3 otherwise
+        assert!((quicksort_comparisons(3) - 8.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_dp() {
+        let config = IpaQueryConfig {
+            dp: DpConfig {
+                mechanism: NoiseMechanism::None,
+                padding: PaddingMode::None,
+                matchkey_cardinality_cap: NonZeroU32::new(1).unwrap(),
+                ..DpConfig::default()
+            },
+            ..IpaQueryConfig::default()
+        };
+        let estimate = estimate_ipa(&config, 1000).unwrap();
+        assert_eq!(NoiseMechanism::None, estimate.noise.output.mechanism);
+        assert_eq!(PaddingRows::NONE, estimate.oprf_padding_rows);
+        assert_eq!(PaddingRows::NONE, estimate.aggregation_padding_rows);
+        // users with a single event are not sorted or attributed, only converted for the PRF
+        assert_eq!(
+            MpcCost {
+                records: 1000.0,
+                boolean_multiplications: 512_000.0,
+                prime_field_multiplications: 1000.0,
+            },
+            estimate.cost
+        );
+    }
+
+    #[test]
+    fn aggregation() {
+        let config = IpaQueryConfig {
+            dp: DpConfig {
+                mechanism: NoiseMechanism::None,
+                padding: PaddingMode::None,
+                matchkey_cardinality_cap: NonZeroU32::new(2).unwrap(),
+                ..DpConfig::default()
+            },
+            ..IpaQueryConfig::default()
+        };
+        let estimate = estimate_ipa(&config, 1000).unwrap();
+        // 500 users with 2 events each, one of which is attributed
+        let conversion = 512.0 * 1000.0;
+        let sort = 500.0 * f64::from(TS::BITS);
+        let attribution = 500.0
+            * f64::from(
+                u32::try_from(multiplications_per_record::<BK, TV, TS>(
+                    config.attribution_window_seconds,
+                ))
+                .unwrap(),
+            );
+        let aggregation = estimate.cost.boolean_multiplications - conversion - sort - attribution;
+        let tv_bits = f64::from(TV::BITS);
+        let expected = if cfg!(feature = "reveal-aggregation") {
+            500.0 * (tv_bits + 1.0)
+        } else {
+            // moving every value to one of 256 breakdowns dominates
+            500.0 * 256.0 * (2.0 * tv_bits + 1.0)
+        };
+        assert!(
+            (aggregation - expected).abs() < 1e-6,
+            "aggregation {aggregation}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn padding_and_noise() {
+        let mut config = IpaQueryConfig::default();
+        config.dp.mechanism = NoiseMechanism::Binomial;
+        let estimate = estimate_ipa(&config, 1_000_000).unwrap();
+        let oprf = estimate.noise.oprf_padding.unwrap();
+        let cap = f64::from(config.dp.matchkey_cardinality_cap.get());
+        // three pairs of helpers add dummy match keys for every cardinality
+        assert!(
+            (estimate.oprf_padding_rows.mean - 3.0 * oprf.mean * cap * (cap + 1.0) / 2.0).abs()
+                < 1e-6
+        );
+        // only the aggregation that reveals breakdown keys is padded
+        match estimate.noise.aggregation_padding {
+            Some(aggregation) => assert!(
+                (estimate.aggregation_padding_rows.mean - 3.0 * 256.0 * aggregation.mean).abs()
+                    < 1e-6
+            ),
+            None => assert_eq!(PaddingRows::NONE, estimate.aggregation_padding_rows),
+        }
+        assert!(estimate.oprf_padding_rows.std > 0.0);
+        assert!(estimate.noise.output.std > 0.0);
+        assert!(
+            (estimate.cost.records - (1_000_000.0 + estimate.oprf_padding_rows.mean)).abs() < 1e-6
+        );
+
+        // more noise needs more coins, more budget needs less
+        let mut cheaper = config.clone();
+        cheaper.dp.epsilon *= 2.0;
+        let cheaper = estimate_ipa(&cheaper, 1_000_000).unwrap();
+        assert!(cheaper.noise.output.std < estimate.noise.output.std);
+        assert!(cheaper.cost.boolean_multiplications < estimate.cost.boolean_multiplications);
+    }
+
+    #[test]
+    fn joint_padding() {
+        let mut config = IpaQueryConfig::default();
+        config.dp.padding = PaddingMode::JointDp;
+        let estimate = estimate_ipa(&config, 1_000_000).unwrap();
+        let oprf = estimate.noise.oprf_padding.unwrap();
+        assert_eq!(PaddingSampling::Joint, oprf.sampling);
+        // the three helpers add dummy match keys for every cardinality together, half as many as
+        // they toss coins for
+        let cap = f64::from(config.dp.matchkey_cardinality_cap.get());
+        assert!(
+            (estimate.oprf_padding_rows.mean - oprf.mean * cap * (cap + 1.0) / 2.0).abs() < 1e-6
+        );
+        assert!((oprf.std - (oprf.mean / 2.0).sqrt()).abs() < 1e-6);
+
+        let pairwise = estimate_ipa(&IpaQueryConfig::default(), 1_000_000).unwrap();
+        assert_eq!(
+            PaddingSampling::Pairwise,
+            pairwise.noise.oprf_padding.unwrap().sampling
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dp() {
+        let mut config = IpaQueryConfig::default();
+        config.dp.mechanism = NoiseMechanism::Binomial;
+        config.dp.epsilon = -1.0;
+        assert!(estimate_ipa(&config, 10).is_err());
+    }
+}
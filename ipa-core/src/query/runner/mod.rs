@@ -1,5 +1,7 @@
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 mod add_in_prime_field;
+#[cfg(any(test, feature = "cli", feature = "test-fixture"))]
+mod estimate;
 mod hybrid;
 mod oprf_ipa;
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
@@ -7,10 +9,13 @@ mod test_multiply;
 
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 pub(super) use add_in_prime_field::execute as test_add_in_prime_field;
+#[cfg(any(test, feature = "cli", feature = "test-fixture"))]
+pub use estimate::{estimate_ipa, IpaEstimate, MpcCost, PaddingRows};
 pub use hybrid::Query as HybridQuery;
 #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
 pub(super) use test_multiply::execute_test_multiply;
 
+pub(super) use self::oprf_ipa::privacy_loss as ipa_privacy_loss;
 pub use self::oprf_ipa::OprfIpaQuery;
 use crate::{error::Error, query::ProtocolResult};
 
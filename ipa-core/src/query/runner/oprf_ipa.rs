@@ -1,34 +1,55 @@
-use std::{convert::Infallible, marker::PhantomData};
+use std::{
+    convert::Infallible,
+    iter::{repeat, zip},
+    marker::PhantomData,
+    num::NonZeroUsize,
+    time::{Duration, SystemTime},
+};
 
-use futures::{stream::iter, StreamExt, TryStreamExt};
-use futures_util::stream::repeat;
+use futures::{
+    future::{try_join, try_join3},
+    stream::iter,
+    StreamExt, TryStreamExt,
+};
 
 use crate::{
     error::{Error, LengthError},
     ff::{
         boolean::Boolean,
-        boolean_array::{BooleanArray, BA20, BA3, BA8},
+        boolean_array::{BooleanArray, BA20, BA256, BA3, BA8},
         curve_points::RP25519,
         ec_prime_field::Fp25519,
-        Field, Serializable, U128Conversions,
+        ArrayAccess, Field, Serializable, U128Conversions,
     },
     helpers::{
-        query::{DpMechanism, IpaQueryConfig, QuerySize},
-        BodyStream, LengthDelimitedStream, RecordsStream,
+        query::{
+            DpConfig, DpMechanism, DuplicateReportPolicy, InvalidReportPolicy, IpaQueryConfig,
+            PaddingMode, QuerySize,
+        },
+        BodyStream, Direction, LengthDelimitedStream, RecordsStream,
     },
     hpke::PrivateKeyRegistry,
     protocol::{
         basics::{BooleanArrayMul, Reveal, ShareKnownValue},
-        context::{DZKPUpgraded, MacUpgraded, UpgradableContext},
+        context::{Context, DZKPUpgraded, MacUpgraded, UpgradableContext},
+        dp::{
+            accountant::{PrivacyAccountant, PrivacyLoss},
+            OutputNoise,
+        },
         ipa_prf::{
-            oprf_ipa, oprf_padding::PaddingParameters, prf_eval::PrfSharing, shuffle::Shuffle,
+            oprf_ipa,
+            oprf_padding::{AggregationPadding, PaddingParameters, PaddingSampling},
+            prf_eval::PrfSharing,
+            shuffle::Shuffle,
+            step::IpaPrfStep,
             OPRFIPAInputRow, AGG_CHUNK, CONV_CHUNK, PRF_CHUNK, SORT_CHUNK,
         },
-        prss::FromPrss,
+        prss::{FromPrss, SharedRandomness},
         step::ProtocolStep::IpaPrf,
-        BooleanProtocols,
+        BooleanProtocols, RecordId,
     },
-    report::{EncryptedOprfReport, EventType},
+    query::{replay::fingerprint, NoiseMetadata, QueryMetadata, SeenReports, WithMetadata},
+    report::{EncryptedOprfReport, EventType, InvalidReportError},
     secret_sharing::{
         replicated::semi_honest::{AdditiveShare as Replicated, AdditiveShare},
         BitDecomposed, SharedValue, TransposeFrom, Vectorizable,
@@ -36,20 +57,169 @@ use crate::{
     sync::Arc,
 };
 
+/// Number of breakdowns in the output histogram, regardless of the max breakdown key of the query.
+pub(super) const NUM_BREAKDOWNS: usize = 256;
+
+/// Failure probability that advanced and zCDP composition may add to the delta of a query, when
+/// they give a smaller epsilon than basic composition.
+const COMPOSITION_DELTA: f64 = 1e-9;
+
 pub struct OprfIpaQuery<C, HV, R: PrivateKeyRegistry> {
     config: IpaQueryConfig,
     key_registry: Arc<R>,
+    seen_reports: Arc<SeenReports>,
     phantom_data: PhantomData<(C, HV)>,
 }
 
 impl<C, HV, R: PrivateKeyRegistry> OprfIpaQuery<C, HV, R> {
+    /// Creates a query that only detects duplicate reports within its own input. Use
+    /// [`Self::with_seen_reports`] to detect reports submitted in earlier queries.
     pub fn new(config: IpaQueryConfig, key_registry: Arc<R>) -> Self {
         Self {
             config,
             key_registry,
+            seen_reports: Arc::new(SeenReports::in_memory(Duration::ZERO)),
             phantom_data: PhantomData,
         }
     }
+
+    #[must_use]
+    pub fn with_seen_reports(mut self, seen_reports: Arc<SeenReports>) -> Self {
+        self.seen_reports = seen_reports;
+        self
+    }
+}
+
+/// Padding requested by the query. Helpers have already checked it against their padding policy.
+///
+/// Only the aggregation that reveals breakdown keys is padded, so the default aggregation has no
+/// aggregation padding to spend budget on or to report.
+fn padding_parameters(dp: &DpConfig) -> PaddingParameters {
+    match dp.padding {
+        PaddingMode::None => PaddingParameters::no_padding(),
+        PaddingMode::Dp | PaddingMode::JointDp => {
+            let mut padding = PaddingParameters::new(
+                dp.padding_epsilon,
+                dp.padding_delta,
+                dp.matchkey_cardinality_cap.get(),
+                dp.aggregation_padding_sensitivity.get(),
+            );
+            if !cfg!(feature = "reveal-aggregation") {
+                padding.aggregation_padding = AggregationPadding::NoAggPadding;
+            }
+            if dp.padding == PaddingMode::JointDp {
+                padding.sampling = PaddingSampling::Joint;
+            }
+            padding
+        }
+    }
+}
+
+/// Noise added to every output histogram. A query with a breakdown hierarchy outputs one
+/// histogram per level, that split the budget.
+fn histogram_noise(config: &IpaQueryConfig) -> DpMechanism {
+    match config.breakdown_hierarchy {
+        Some(hierarchy) => hierarchy.level_noise(config.dp.noise()),
+        None => config.dp.noise(),
+    }
+}
+
+/// Noise that an IPA query adds to its output and inputs, reported with its results.
+pub(super) fn noise_metadata(config: &IpaQueryConfig) -> Result<NoiseMetadata, Error> {
+    let padding = padding_parameters(&config.dp);
+    Ok(NoiseMetadata {
+        output: OutputNoise::new(
+            histogram_noise(config),
+            config.per_user_credit_cap,
+            NUM_BREAKDOWNS,
+        )?,
+        oprf_padding: padding.oprf_noise()?,
+        aggregation_padding: padding.aggregation_noise(NUM_BREAKDOWNS)?,
+        total: privacy_loss(config),
+    })
+}
+
+/// Mechanisms that an IPA query runs: the DP noise added to its output histograms and the padding
+/// added to its inputs and to the aggregation.
+fn accountant(config: &IpaQueryConfig) -> PrivacyAccountant {
+    let mut accountant = PrivacyAccountant::new();
+    if let Some(noise) = OutputNoise::mechanism(
+        histogram_noise(config),
+        config.per_user_credit_cap,
+        NUM_BREAKDOWNS,
+    ) {
+        let histograms = config
+            .breakdown_hierarchy
+            .map_or(1, |hierarchy| hierarchy.levels());
+        for _ in 0..histograms {
+            accountant.add(noise);
+        }
+    }
+    for padding in padding_parameters(&config.dp).mechanisms() {
+        accountant.add(padding);
+    }
+
+    accountant
+}
+
+/// Exchanges `bits` of this helper with the other helpers and returns whether each of them is set
+/// by any helper.
+async fn set_by_any_helper<C: Context>(ctx: C, bits: &[bool]) -> Result<Vec<bool>, Error> {
+    const BITS: usize = BA256::BITS as usize;
+    let chunks = bits
+        .chunks(BITS)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|&bit| Boolean::from(bit))
+                .chain(repeat(Boolean::FALSE))
+                .collect::<BA256>()
+        })
+        .collect::<Vec<_>>();
+    let Some(total_records) = NonZeroUsize::new(chunks.len()) else {
+        return Ok(Vec::new());
+    };
+
+    let ctx = ctx.set_total_records(total_records);
+    let peers = [Direction::Left, Direction::Right].map(|direction| ctx.role().peer(direction));
+    let received = ctx
+        .try_join(chunks.into_iter().enumerate().map(|(i, chunk)| {
+            let ctx = ctx.clone();
+            async move {
+                let record_id = RecordId::from(i);
+                let [left_sender, right_sender] = peers.map(|peer| ctx.send_channel::<BA256>(peer));
+                let [left_receiver, right_receiver] =
+                    peers.map(|peer| ctx.recv_channel::<BA256>(peer));
+                let (_, from_left, from_right) = try_join3(
+                    try_join(
+                        left_sender.send(record_id, chunk),
+                        right_sender.send(record_id, chunk),
+                    ),
+                    left_receiver.receive(record_id),
+                    right_receiver.receive(record_id),
+                )
+                .await?;
+                Ok::<_, Error>([from_left, from_right])
+            }
+        }))
+        .await?;
+
+    Ok(bits
+        .iter()
+        .enumerate()
+        .map(|(i, &bit)| {
+            bit || received[i / BITS]
+                .iter()
+                .any(|chunk| chunk.get(i % BITS) == Some(Boolean::TRUE))
+        })
+        .collect())
+}
+
+/// Privacy budget spent by an IPA query on every epoch it covers: the tightest bound on the
+/// privacy loss of all the mechanisms the query runs.
+#[must_use]
+pub fn privacy_loss(config: &IpaQueryConfig) -> PrivacyLoss {
+    accountant(config).total(COMPOSITION_DELTA)
 }
 
 #[allow(clippy::too_many_lines)]
@@ -82,16 +252,20 @@ where
         ctx: C,
         query_size: QuerySize,
         input_stream: BodyStream,
-    ) -> Result<Vec<Replicated<HV>>, Error> {
+    ) -> Result<WithMetadata<Vec<Replicated<HV>>>, Error> {
         let Self {
             config,
             key_registry,
+            seen_reports,
             phantom_data: _,
         } = self;
         tracing::info!("New query: {config:?}");
         let ctx = ctx.narrow(&IpaPrf);
         let sz = usize::from(query_size);
 
+        let mut dropped_reports = 0;
+        let mut duplicate_reports = 0;
+        let mut reservation = seen_reports.reserve(SystemTime::now());
         let input = if config.plaintext_match_keys {
             let mut v = RecordsStream::<OPRFIPAInputRow<BA8, BA3, BA20>, _>::new(input_stream)
                 .try_concat()
@@ -99,20 +273,83 @@ where
             v.truncate(sz);
             v
         } else {
-            LengthDelimitedStream::<EncryptedOprfReport<BA8, BA3, BA20, _>, _>::new(input_stream)
-                .map_err(Into::<Error>::into)
-                .map_ok(|enc_reports| {
-                    iter(enc_reports.into_iter().map(|enc_report| {
-                        enc_report
-                            .decrypt(key_registry.as_ref())
-                            .map_err(Into::<Error>::into)
-                    }))
+            let reports = LengthDelimitedStream::<EncryptedOprfReport<BA8, BA3, BA20, _>, _>::new(
+                input_stream,
+            )
+            .map_err(Into::<Error>::into)
+            .map_ok(|enc_reports| {
+                iter(enc_reports.into_iter().map(|enc_report| {
+                    let epoch = enc_report.epoch();
+                    let report = match (config.epochs, &config.site_domain) {
+                        (Some(range), _) if !range.contains(epoch) => {
+                            Err(InvalidReportError::EpochOutOfRange { epoch, range })
+                        }
+                        (_, Some(site_domain)) if enc_report.site_domain() != site_domain => {
+                            Err(InvalidReportError::SiteDomainMismatch {
+                                site_domain: enc_report.site_domain().to_owned(),
+                                expected: site_domain.clone(),
+                            })
+                        }
+                        _ => enc_report.decrypt(key_registry.as_ref()),
+                    };
+                    match report {
+                        Ok(report) => Ok(Some((report, fingerprint(enc_report.encap_key_mk())))),
+                        Err(e) if config.invalid_reports == InvalidReportPolicy::Drop => {
+                            tracing::debug!("dropping report: {e}");
+                            Ok(None)
+                        }
+                        Err(e) => Err(Error::from(e)),
+                    }
+                }))
+            })
+            .try_flatten()
+            .take(sz)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+            // Helpers may fail to decrypt or accept different reports. Each of them drops every
+            // report that any helper could not use, so that they all replace the same rows.
+            let invalid = if config.invalid_reports == InvalidReportPolicy::Drop {
+                set_by_any_helper(
+                    ctx.narrow(&IpaPrfStep::UndecryptableReports),
+                    &reports.iter().map(Option::is_none).collect::<Vec<_>>(),
+                )
+                .await?
+            } else {
+                vec![false; reports.len()]
+            };
+
+            // Each helper checks reports against its own store, and helpers may disagree, for
+            // example if one of them restarted with an empty store. Reports seen by any helper are
+            // duplicates for all of them. The rest stays reserved by every helper, so all of them
+            // record it once the query completes.
+            let duplicate = zip(&reports, &invalid)
+                .map(|(report, &invalid)| match report {
+                    Some((_, fingerprint)) if !invalid => !reservation.insert(*fingerprint),
+                    _ => false,
                 })
-                .try_flatten()
-                .take(sz)
-                .zip(repeat(ctx.clone()))
-                .map(|(res, ctx)| {
-                    res.map(|report| {
+                .collect::<Vec<_>>();
+            let duplicate =
+                set_by_any_helper(ctx.narrow(&IpaPrfStep::DuplicateReports), &duplicate).await?;
+            if config.duplicate_reports == DuplicateReportPolicy::Fail && duplicate.contains(&true)
+            {
+                return Err(Error::from(InvalidReportError::Duplicate));
+            }
+
+            // Source events never contribute to the output on their own. Every dummy row gets a
+            // random match key, so they do not add up to one user with a long history.
+            let dummy_ctx = ctx.narrow(&IpaPrfStep::DummyMatchKeys);
+            let dummy_row = |i: usize| OPRFIPAInputRow {
+                timestamp: Replicated::ZERO,
+                match_key: dummy_ctx.prss().generate(RecordId::from(i)),
+                is_trigger: Replicated::share_known_value(&ctx, Boolean::ZERO),
+                breakdown_key: Replicated::ZERO,
+                trigger_value: Replicated::ZERO,
+            };
+            zip(reports, zip(invalid, duplicate))
+                .enumerate()
+                .map(|(i, (report, excluded))| match (report, excluded) {
+                    (Some((report, _)), (false, false)) => {
                         let is_trigger = Replicated::<Boolean>::share_known_value(
                             &ctx,
                             match report.event_type {
@@ -128,61 +365,115 @@ where
                             breakdown_key: report.breakdown_key,
                             trigger_value: report.trigger_value,
                         }
-                    })
+                    }
+                    (_, (false, true)) => {
+                        duplicate_reports += 1;
+                        dummy_row(i)
+                    }
+                    _ => {
+                        dropped_reports += 1;
+                        dummy_row(i)
+                    }
                 })
-                .try_collect::<Vec<_>>()
-                .await?
+                .collect()
         };
+        if dropped_reports > 0 {
+            tracing::warn!("{dropped_reports} reports were invalid and were dropped");
+        }
+        if duplicate_reports > 0 {
+            tracing::warn!("{duplicate_reports} reports were already submitted and were dropped");
+        }
 
+        let noise = noise_metadata(&config)?;
         let aws = config.attribution_window_seconds;
-        let dp_params: DpMechanism = match config.with_dp {
-            0 => DpMechanism::NoDp,
-            _ => DpMechanism::DiscreteLaplace {
-                epsilon: config.epsilon,
-            },
-        };
-
-        #[cfg(any(test, feature = "cli", feature = "test-fixture"))]
-        let padding_params = PaddingParameters::relaxed();
-        #[cfg(not(any(test, feature = "cli", feature = "test-fixture")))]
-        let padding_params = PaddingParameters::default();
-        match config.per_user_credit_cap {
-            8 => oprf_ipa::<_, BA8, BA3, HV, BA20, 3, 256>(ctx, input, aws, dp_params, padding_params).await,
-            16 => oprf_ipa::<_, BA8, BA3, HV, BA20, 4, 256>(ctx, input, aws, dp_params, padding_params).await,
-            32 => oprf_ipa::<_, BA8, BA3, HV, BA20, 5, 256>(ctx, input, aws, dp_params, padding_params).await,
-            64 => oprf_ipa::<_, BA8, BA3, HV, BA20, 6, 256>(ctx, input, aws, dp_params, padding_params).await,
-            128 => oprf_ipa::<_, BA8, BA3, HV, BA20, 7, 256>(ctx, input, aws, dp_params, padding_params).await,
+        let dp_params = config.dp.noise();
+        let dp_threshold = config.dp.threshold;
+        let padding_params = padding_parameters(&config.dp);
+        let breakdown_hierarchy = config.breakdown_hierarchy;
+        let commit_ctx = ctx.narrow(&IpaPrfStep::RecordReports);
+        let result = match config.per_user_credit_cap {
+            8 => oprf_ipa::<_, BA8, BA3, HV, BA20, 3, NUM_BREAKDOWNS>(ctx, input, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy).await,
+            16 => oprf_ipa::<_, BA8, BA3, HV, BA20, 4, NUM_BREAKDOWNS>(ctx, input, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy).await,
+            32 => oprf_ipa::<_, BA8, BA3, HV, BA20, 5, NUM_BREAKDOWNS>(ctx, input, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy).await,
+            64 => oprf_ipa::<_, BA8, BA3, HV, BA20, 6, NUM_BREAKDOWNS>(ctx, input, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy).await,
+            128 => oprf_ipa::<_, BA8, BA3, HV, BA20, 7, NUM_BREAKDOWNS>(ctx, input, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy).await,
             _ => panic!(
                 "Invalid value specified for per-user cap: {:?}. Must be one of 8, 16, 32, 64, or 128.",
                 config.per_user_credit_cap
             ),
+        }?;
+        // Reports are only recorded once the query succeeds on every helper, so they can be
+        // submitted again if it fails anywhere.
+        let prepared = reservation.prepare().await;
+        if let Err(e) = &prepared {
+            tracing::error!("failed to record reports: {e}");
         }
+        if set_by_any_helper(commit_ctx, &[prepared.is_err()]).await?[0] {
+            reservation.release().await;
+            return Err(prepared
+                .err()
+                .map_or(Error::ReportsNotRecorded, Error::from));
+        }
+        reservation.commit().await?;
+
+        Ok(WithMetadata {
+            result,
+            metadata: QueryMetadata {
+                dropped_reports,
+                duplicate_reports,
+                noise: Some(noise),
+            },
+        })
     }
 }
 
 #[cfg(all(test, unit_test))]
 mod tests {
-    use std::{iter::zip, sync::Arc};
+    use std::{array, iter::zip, sync::Arc, time::Duration};
+
+    use futures::future::join3;
 
     use rand::rngs::StdRng;
     use rand_core::SeedableRng;
 
     use crate::{
+        error::Error,
         ff::{
             boolean_array::{BA16, BA20, BA3, BA8},
             U128Conversions,
         },
         helpers::{
-            query::{IpaQueryConfig, QuerySize},
+            query::{
+                DpConfig, DuplicateReportPolicy, InvalidReportPolicy, IpaQueryConfig,
+                NoiseMechanism, QuerySize,
+            },
             BodyStream,
         },
         hpke::{KeyPair, KeyRegistry},
-        query::runner::OprfIpaQuery,
-        report::{OprfReport, DEFAULT_KEY_ID},
-        secret_sharing::IntoShares,
+        query::{runner::OprfIpaQuery, QueryMetadata, SeenReports, WithMetadata},
+        report::{EpochRange, InvalidReportError, OprfReport, DEFAULT_KEY_ID},
+        secret_sharing::{replicated::semi_honest::AdditiveShare, IntoShares},
         test_fixture::{ipa::TestRawDataRecord, join3v, Reconstruct, TestWorld},
     };
 
+    fn encrypt(records: Vec<TestRawDataRecord>) -> (Arc<KeyRegistry<KeyPair>>, [Vec<u8>; 3]) {
+        let mut rng = StdRng::seed_from_u64(42);
+        let key_registry = Arc::new(KeyRegistry::<KeyPair>::random(1, &mut rng));
+
+        let mut buffers: [_; 3] = std::array::from_fn(|_| Vec::new());
+
+        let shares: [Vec<OprfReport<BA8, BA3, BA20>>; 3] = records.into_iter().share();
+        for (buf, shares) in zip(&mut buffers, shares) {
+            for share in shares {
+                share
+                    .delimited_encrypt_to(DEFAULT_KEY_ID, key_registry.as_ref(), &mut rng, buf)
+                    .unwrap();
+            }
+        }
+
+        (key_registry, buffers)
+    }
+
     #[tokio::test]
     async fn encrypted_reports() {
         const EXPECTED: &[u128] = &[0, 8, 5];
@@ -234,20 +525,7 @@ mod tests {
 
         let query_size = QuerySize::try_from(records.len()).unwrap();
 
-        let mut rng = StdRng::seed_from_u64(42);
-        let key_id = DEFAULT_KEY_ID;
-        let key_registry = Arc::new(KeyRegistry::<KeyPair>::random(1, &mut rng));
-
-        let mut buffers: [_; 3] = std::array::from_fn(|_| Vec::new());
-
-        let shares: [Vec<OprfReport<BA8, BA3, BA20>>; 3] = records.into_iter().share();
-        for (buf, shares) in zip(&mut buffers, shares) {
-            for share in shares {
-                share
-                    .delimited_encrypt_to(key_id, key_registry.as_ref(), &mut rng, buf)
-                    .unwrap();
-            }
-        }
+        let (key_registry, buffers) = encrypt(records);
 
         let world = TestWorld::default();
         let contexts = world.contexts();
@@ -257,9 +535,9 @@ mod tests {
                 per_user_credit_cap: 8,
                 attribution_window_seconds: None,
                 max_breakdown_key: 3,
-                with_dp: 0,
-                epsilon: 5.0,
+                dp: DpConfig::no_noise().with_relaxed_padding(),
                 plaintext_match_keys: false,
+                ..Default::default()
             };
             let input = BodyStream::from(buffer);
 
@@ -272,11 +550,488 @@ mod tests {
         .await;
 
         assert_eq!(
-            results.reconstruct()[0..3]
+            results.map(|r| r.result).reconstruct()[0..3]
                 .iter()
                 .map(U128Conversions::as_u128)
                 .collect::<Vec<u128>>(),
             EXPECTED
         );
     }
+
+    #[tokio::test]
+    async fn reports_outside_of_query_epochs() {
+        let records = vec![TestRawDataRecord {
+            timestamp: 0,
+            user_id: 12345,
+            is_trigger_report: false,
+            breakdown_key: 1,
+            trigger_value: 0,
+        }];
+        let query_size = QuerySize::try_from(records.len()).unwrap();
+        let (key_registry, buffers) = encrypt(records);
+
+        let world = TestWorld::default();
+        #[allow(clippy::large_futures)]
+        let results = join3v(
+            buffers
+                .into_iter()
+                .zip(world.contexts())
+                .map(|(buffer, ctx)| {
+                    let query_config = IpaQueryConfig {
+                        max_breakdown_key: 3,
+                        dp: DpConfig::no_noise().with_relaxed_padding(),
+                        epochs: Some(EpochRange::new(2, 3).unwrap()),
+                        ..Default::default()
+                    };
+                    let query = OprfIpaQuery::<_, BA16, KeyRegistry<KeyPair>>::new(
+                        query_config,
+                        Arc::clone(&key_registry),
+                    );
+                    async move {
+                        let err = query
+                            .execute(ctx, query_size, BodyStream::from(buffer))
+                            .await
+                            .unwrap_err();
+                        Ok::<_, Error>(err)
+                    }
+                }),
+        )
+        .await;
+
+        for err in results {
+            assert!(
+                matches!(
+                    err,
+                    Error::InvalidReport(InvalidReportError::EpochOutOfRange { epoch: 1, .. })
+                ),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_from_other_sites() {
+        let (key_registry, inputs) = encrypt(vec![TestRawDataRecord {
+            timestamp: 0,
+            user_id: 12345,
+            is_trigger_report: false,
+            breakdown_key: 1,
+            trigger_value: 0,
+        }]);
+        let seen_reports = array::from_fn(|_| Arc::new(SeenReports::in_memory(Duration::ZERO)));
+        let config = IpaQueryConfig {
+            max_breakdown_key: 3,
+            dp: DpConfig::no_noise().with_relaxed_padding(),
+            site_domain: Some("other.example".to_string()),
+            ..Default::default()
+        };
+
+        #[allow(clippy::large_futures)]
+        let results = run_query(inputs, 1, config, &key_registry, &seen_reports).await;
+        for r in results {
+            assert!(
+                matches!(
+                    r,
+                    Err(Error::InvalidReport(
+                        InvalidReportError::SiteDomainMismatch { ref expected, .. }
+                    )) if expected == "other.example"
+                ),
+                "{:?}",
+                r.map(|_| ())
+            );
+        }
+    }
+
+    #[tokio::test]
+    #[allow(clippy::large_futures)]
+    async fn drop_reports_outside_of_query_scope() {
+        let (key_registry, inputs) = encrypt(vec![
+            TestRawDataRecord {
+                timestamp: 0,
+                user_id: 12345,
+                is_trigger_report: false,
+                breakdown_key: 1,
+                trigger_value: 0,
+            },
+            TestRawDataRecord {
+                timestamp: 5,
+                user_id: 12345,
+                is_trigger_report: true,
+                breakdown_key: 0,
+                trigger_value: 3,
+            },
+        ]);
+        let seen_reports = array::from_fn(|_| Arc::new(SeenReports::in_memory(Duration::ZERO)));
+        let config = IpaQueryConfig {
+            max_breakdown_key: 3,
+            dp: DpConfig::no_noise().with_relaxed_padding(),
+            invalid_reports: InvalidReportPolicy::Drop,
+            ..Default::default()
+        };
+
+        for config in [
+            IpaQueryConfig {
+                epochs: Some(EpochRange::new(2, 3).unwrap()),
+                ..config.clone()
+            },
+            IpaQueryConfig {
+                site_domain: Some("other.example".to_string()),
+                ..config
+            },
+        ] {
+            let results = run_query(inputs.clone(), 2, config, &key_registry, &seen_reports)
+                .await
+                .map(Result::unwrap);
+            for r in &results {
+                assert_eq!(2, r.metadata.dropped_reports);
+            }
+            assert!(results
+                .map(|r| r.result)
+                .reconstruct()
+                .iter()
+                .all(|v| v.as_u128() == 0));
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_undecryptable_reports() {
+        let records = vec![
+            TestRawDataRecord {
+                timestamp: 0,
+                user_id: 12345,
+                is_trigger_report: false,
+                breakdown_key: 1,
+                trigger_value: 0,
+            },
+            TestRawDataRecord {
+                timestamp: 5,
+                user_id: 12345,
+                is_trigger_report: true,
+                breakdown_key: 0,
+                trigger_value: 3,
+            },
+        ];
+        let (key_registry, mut buffers) = encrypt(records);
+
+        // this report is encrypted with a key helpers do not have
+        let mut rng = StdRng::seed_from_u64(1);
+        let stale_keys = KeyRegistry::<KeyPair>::random(1, &mut rng);
+        let shares: [Vec<OprfReport<BA8, BA3, BA20>>; 3] = vec![TestRawDataRecord {
+            timestamp: 6,
+            user_id: 12345,
+            is_trigger_report: true,
+            breakdown_key: 0,
+            trigger_value: 5,
+        }]
+        .into_iter()
+        .share();
+        for (buf, shares) in zip(&mut buffers, shares) {
+            shares[0]
+                .delimited_encrypt_to(DEFAULT_KEY_ID, &stale_keys, &mut rng, buf)
+                .unwrap();
+        }
+
+        // only the first helper cannot decrypt this report, the others must drop it as well
+        let shares: [Vec<OprfReport<BA8, BA3, BA20>>; 3] = vec![TestRawDataRecord {
+            timestamp: 7,
+            user_id: 12345,
+            is_trigger_report: true,
+            breakdown_key: 0,
+            trigger_value: 4,
+        }]
+        .into_iter()
+        .share();
+        for (i, (buf, shares)) in zip(&mut buffers, shares).enumerate() {
+            if i == 0 {
+                shares[0].delimited_encrypt_to(DEFAULT_KEY_ID, &stale_keys, &mut rng, buf)
+            } else {
+                shares[0].delimited_encrypt_to(DEFAULT_KEY_ID, key_registry.as_ref(), &mut rng, buf)
+            }
+            .unwrap();
+        }
+
+        let world = TestWorld::default();
+        #[allow(clippy::large_futures)]
+        let results = join3v(
+            buffers
+                .into_iter()
+                .zip(world.contexts())
+                .map(|(buffer, ctx)| {
+                    let query_config = IpaQueryConfig {
+                        max_breakdown_key: 3,
+                        dp: DpConfig::no_noise().with_relaxed_padding(),
+                        invalid_reports: InvalidReportPolicy::Drop,
+                        ..Default::default()
+                    };
+                    OprfIpaQuery::<_, BA16, KeyRegistry<KeyPair>>::new(
+                        query_config,
+                        Arc::clone(&key_registry),
+                    )
+                    .execute(
+                        ctx,
+                        QuerySize::try_from(4).unwrap(),
+                        BodyStream::from(buffer),
+                    )
+                }),
+        )
+        .await;
+
+        for r in &results {
+            let QueryMetadata {
+                dropped_reports,
+                duplicate_reports,
+                noise: Some(noise),
+            } = r.metadata
+            else {
+                panic!("noise metadata is missing");
+            };
+            assert_eq!((2, 0), (dropped_reports, duplicate_reports));
+            assert_eq!(NoiseMechanism::None, noise.output.mechanism);
+            assert!(noise.oprf_padding.is_some());
+            assert_eq!(
+                cfg!(feature = "reveal-aggregation"),
+                noise.aggregation_padding.is_some()
+            );
+        }
+        assert_eq!(
+            vec![0, 3, 0],
+            results.map(|r| r.result).reconstruct()[0..3]
+                .iter()
+                .map(U128Conversions::as_u128)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::large_futures)]
+    async fn drop_many_undecryptable_reports() {
+        // more dropped reports than a single user may have rows
+        const DROPPED: usize = 70;
+        let (key_registry, mut inputs) = encrypt(vec![
+            TestRawDataRecord {
+                timestamp: 0,
+                user_id: 12345,
+                is_trigger_report: false,
+                breakdown_key: 1,
+                trigger_value: 0,
+            },
+            TestRawDataRecord {
+                timestamp: 5,
+                user_id: 12345,
+                is_trigger_report: true,
+                breakdown_key: 0,
+                trigger_value: 3,
+            },
+        ]);
+        // these reports are encrypted with a key helpers do not have
+        let mut rng = StdRng::seed_from_u64(1);
+        let stale_keys = KeyRegistry::<KeyPair>::random(1, &mut rng);
+        let shares: [Vec<OprfReport<BA8, BA3, BA20>>; 3] = (0..DROPPED)
+            .map(|i| TestRawDataRecord {
+                timestamp: 10,
+                user_id: 68362,
+                is_trigger_report: i % 2 == 1,
+                breakdown_key: 2,
+                trigger_value: 1,
+            })
+            .share();
+        for (buf, shares) in zip(&mut inputs, shares) {
+            for share in shares {
+                share
+                    .delimited_encrypt_to(DEFAULT_KEY_ID, &stale_keys, &mut rng, buf)
+                    .unwrap();
+            }
+        }
+        let seen_reports = array::from_fn(|_| Arc::new(SeenReports::in_memory(Duration::ZERO)));
+        let config = IpaQueryConfig {
+            max_breakdown_key: 3,
+            dp: DpConfig::no_noise().with_relaxed_padding(),
+            invalid_reports: InvalidReportPolicy::Drop,
+            ..Default::default()
+        };
+
+        let results = run_query(inputs, DROPPED + 2, config, &key_registry, &seen_reports)
+            .await
+            .map(Result::unwrap);
+        for r in &results {
+            assert_eq!(DROPPED as u64, r.metadata.dropped_reports);
+        }
+        assert_eq!(
+            vec![0, 3, 0],
+            results.map(|r| r.result).reconstruct()[0..3]
+                .iter()
+                .map(U128Conversions::as_u128)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    async fn run_query(
+        inputs: [Vec<u8>; 3],
+        query_size: usize,
+        config: IpaQueryConfig,
+        key_registry: &Arc<KeyRegistry<KeyPair>>,
+        seen_reports: &[Arc<SeenReports>; 3],
+    ) -> [Result<WithMetadata<Vec<AdditiveShare<BA16>>>, Error>; 3] {
+        let world = TestWorld::default();
+        let [a, b, c] = world.contexts();
+        let [ia, ib, ic] = inputs;
+        let query_size = QuerySize::try_from(query_size).unwrap();
+        let run = |ctx, input, seen_reports: &Arc<SeenReports>| {
+            OprfIpaQuery::<_, BA16, _>::new(config.clone(), Arc::clone(key_registry))
+                .with_seen_reports(Arc::clone(seen_reports))
+                .execute(ctx, query_size, BodyStream::from(input))
+        };
+        #[allow(clippy::large_futures)]
+        let (a, b, c) = join3(
+            run(a, ia, &seen_reports[0]),
+            run(b, ib, &seen_reports[1]),
+            run(c, ic, &seen_reports[2]),
+        )
+        .await;
+
+        [a, b, c]
+    }
+
+    #[tokio::test]
+    #[allow(clippy::large_futures)]
+    async fn replay_many_reports() {
+        // more replayed reports than a single user may have rows
+        const REPORTS: usize = 70;
+        let (key_registry, inputs) = encrypt(
+            (0..REPORTS)
+                .map(|i| TestRawDataRecord {
+                    timestamp: 0,
+                    user_id: 12345 + u64::try_from(i).unwrap(),
+                    is_trigger_report: false,
+                    breakdown_key: 1,
+                    trigger_value: 0,
+                })
+                .collect(),
+        );
+        let replayed = inputs.each_ref().map(|input| input.repeat(2));
+        let seen_reports = array::from_fn(|_| Arc::new(SeenReports::in_memory(Duration::ZERO)));
+        let config = IpaQueryConfig {
+            max_breakdown_key: 3,
+            dp: DpConfig::no_noise().with_relaxed_padding(),
+            duplicate_reports: DuplicateReportPolicy::Drop,
+            ..Default::default()
+        };
+
+        let results = run_query(replayed, 2 * REPORTS, config, &key_registry, &seen_reports)
+            .await
+            .map(Result::unwrap);
+        for r in &results {
+            assert_eq!(REPORTS as u64, r.metadata.duplicate_reports);
+        }
+        assert!(results
+            .map(|r| r.result)
+            .reconstruct()
+            .iter()
+            .all(|v| v.as_u128() == 0));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::large_futures)]
+    async fn duplicate_reports() {
+        const DAY: Duration = Duration::from_secs(86_400);
+        let (key_registry, inputs) = encrypt(vec![
+            TestRawDataRecord {
+                timestamp: 0,
+                user_id: 12345,
+                is_trigger_report: false,
+                breakdown_key: 1,
+                trigger_value: 0,
+            },
+            TestRawDataRecord {
+                timestamp: 5,
+                user_id: 12345,
+                is_trigger_report: true,
+                breakdown_key: 0,
+                trigger_value: 3,
+            },
+        ]);
+        // every report is submitted twice
+        let replayed = inputs.each_ref().map(|input| input.repeat(2));
+        let seen_reports = array::from_fn(|_| Arc::new(SeenReports::in_memory(DAY)));
+        let config = IpaQueryConfig {
+            max_breakdown_key: 3,
+            dp: DpConfig::no_noise().with_relaxed_padding(),
+            ..Default::default()
+        };
+        let is_duplicate = |r: &Result<_, Error>| {
+            matches!(r, Err(Error::InvalidReport(InvalidReportError::Duplicate)))
+        };
+
+        let results = run_query(
+            replayed.clone(),
+            4,
+            config.clone(),
+            &key_registry,
+            &seen_reports,
+        )
+        .await;
+        assert!(results.iter().all(is_duplicate));
+
+        let results = run_query(
+            replayed,
+            4,
+            IpaQueryConfig {
+                duplicate_reports: DuplicateReportPolicy::Drop,
+                ..config.clone()
+            },
+            &key_registry,
+            &seen_reports,
+        )
+        .await
+        .map(Result::unwrap);
+        for r in &results {
+            assert_eq!(2, r.metadata.duplicate_reports);
+        }
+        assert_eq!(
+            vec![0, 3, 0],
+            results.map(|r| r.result).reconstruct()[0..3]
+                .iter()
+                .map(U128Conversions::as_u128)
+                .collect::<Vec<_>>()
+        );
+
+        // reports used by the successful query can't be submitted again
+        let results = run_query(
+            inputs.clone(),
+            2,
+            config.clone(),
+            &key_registry,
+            &seen_reports,
+        )
+        .await;
+        assert!(results.iter().all(is_duplicate));
+
+        // helpers that restarted with an empty store drop the reports that the first helper
+        // has seen
+        let restarted = [
+            Arc::clone(&seen_reports[0]),
+            Arc::new(SeenReports::in_memory(DAY)),
+            Arc::new(SeenReports::in_memory(DAY)),
+        ];
+        let results = run_query(
+            inputs,
+            2,
+            IpaQueryConfig {
+                duplicate_reports: DuplicateReportPolicy::Drop,
+                ..config
+            },
+            &key_registry,
+            &restarted,
+        )
+        .await
+        .map(Result::unwrap);
+        for r in &results {
+            assert_eq!(2, r.metadata.duplicate_reports);
+        }
+        assert!(results
+            .map(|r| r.result)
+            .reconstruct()
+            .iter()
+            .all(|v| v.as_u128() == 0));
+    }
 }
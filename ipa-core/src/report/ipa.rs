@@ -23,18 +23,21 @@
 //! (via `Oprf.delmited_encrypt_to`) → `helpers::BodyStream`
 
 use std::{
+    borrow::Cow,
     fmt::{Display, Formatter},
     fs::File,
     io::{BufRead, BufReader},
     marker::PhantomData,
     ops::{Add, Deref},
     path::PathBuf,
+    str::FromStr,
 };
 
 use bytes::{BufMut, Bytes};
 use generic_array::{ArrayLength, GenericArray};
 use hpke::Serializable as _;
 use rand_core::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
 use typenum::{Sum, Unsigned, U1, U16};
 
 use crate::{
@@ -42,8 +45,8 @@ use crate::{
     ff::{boolean_array::BA64, Serializable},
     helpers::BodyStream,
     hpke::{
-        open_in_place, seal_in_place, CryptError, EncapsulationSize, Info, PrivateKeyRegistry,
-        PublicKeyRegistry, TagSize,
+        open_in_place, seal_in_place, CipherSuite, CryptError, EncapsulationSize, Info,
+        PrivateKeyRegistry, PublicKeyRegistry, TagSize,
     },
     secret_sharing::{replicated::semi_honest::AdditiveShare as Replicated, SharedValue},
 };
@@ -64,6 +67,89 @@ pub type Timestamp = u32;
 /// [`ipa-spec`]: https://github.com/patcg-individual-drafts/ipa/blob/main/IPA-End-to-End.md#other-key-terms
 pub type Epoch = u16;
 
+/// Inclusive range of epochs. In text form, it is written as `first-last`, or as a single epoch
+/// if the range covers only one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct EpochRange {
+    first: Epoch,
+    last: Epoch,
+}
+
+impl EpochRange {
+    /// ## Errors
+    /// If `first` is greater than `last`.
+    pub fn new(first: Epoch, last: Epoch) -> Result<Self, String> {
+        if first > last {
+            return Err(format!("epoch range {first}-{last} is empty"));
+        }
+        Ok(Self { first, last })
+    }
+
+    #[must_use]
+    pub fn single(epoch: Epoch) -> Self {
+        Self {
+            first: epoch,
+            last: epoch,
+        }
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Epoch {
+        self.first
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Epoch {
+        self.last
+    }
+
+    #[must_use]
+    pub fn contains(&self, epoch: Epoch) -> bool {
+        (self.first..=self.last).contains(&epoch)
+    }
+}
+
+impl Display for EpochRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+impl FromStr for EpochRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<Epoch>()
+                .map_err(|e| format!("invalid epoch {v}: {e}"))
+        };
+        match s.split_once('-') {
+            Some((first, last)) => Self::new(parse(first)?, parse(last)?),
+            None => Ok(Self::single(parse(s)?)),
+        }
+    }
+}
+
+impl TryFrom<String> for EpochRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<EpochRange> for String {
+    fn from(value: EpochRange) -> Self {
+        value.to_string()
+    }
+}
+
 /// Event type as described [`ipa-issue`]
 /// Initially we will just support trigger vs source event types but could extend to others in
 /// the future.
@@ -185,6 +271,17 @@ pub enum InvalidReportError {
     DeserializationError(&'static str, #[source] BoxError),
     #[error("report is too short: {0}, expected length at least: {1}")]
     Length(usize, usize),
+    #[error("report epoch {epoch} is outside of the epochs {range} covered by the query")]
+    EpochOutOfRange { epoch: Epoch, range: EpochRange },
+    #[error("key {key_id} is not used for reports from epoch {epoch}")]
+    KeyEpochMismatch { key_id: KeyIdentifier, epoch: Epoch },
+    #[error("report has already been submitted")]
+    Duplicate,
+    #[error("report is from site {site_domain}, but the query covers {expected}")]
+    SiteDomainMismatch {
+        site_domain: String,
+        expected: String,
+    },
 }
 
 /// A struct intended for the Report Collector to hold the streams of underlying
@@ -262,7 +359,12 @@ where
 //  * d: `event_type`
 //  * d+1: `key_id`
 //  * d+2..d+4: `epoch`
-//  * d+4..: `site_domain`
+//  * d+4..e: SEC1 tags of `encap_key_1` and `encap_key_2`, only if `key_id` is a P-256 key
+//  * e..: `site_domain`
+//
+// X25519 encapsulated keys take 32 bytes. P-256 ones are compressed to 33 bytes: the x coordinate
+// takes the place of an X25519 key and the tag goes after the epoch, so that all fields up to
+// the epoch are at the same offsets for both cipher suites.
 
 // btt ciphertext structure
 // * 0..a `timestamp`
@@ -306,7 +408,7 @@ where
         + <Replicated<TS> as Serializable>::Size::USIZE);
     const KEY_IDENTIFIER_OFFSET: usize = Self::EVENT_TYPE_OFFSET + 1;
     const EPOCH_OFFSET: usize = Self::KEY_IDENTIFIER_OFFSET + 1;
-    const SITE_DOMAIN_OFFSET: usize = Self::EPOCH_OFFSET + 2;
+    const ENCAP_TAGS_OFFSET: usize = Self::EPOCH_OFFSET + 2;
 
     // offsets within Ciphertext_BTT
     const TS_OFFSET: usize = 0;
@@ -315,6 +417,15 @@ where
     const TV_OFFSET: usize = Self::BK_OFFSET + <Replicated<BK> as Serializable>::Size::USIZE;
     const TV_END: usize = Self::TV_OFFSET + <Replicated<TV> as Serializable>::Size::USIZE;
 
+    fn site_domain_offset(key_id: KeyIdentifier) -> usize {
+        match CipherSuite::of_key(key_id) {
+            CipherSuite::X25519 => Self::ENCAP_TAGS_OFFSET,
+            CipherSuite::P256 => Self::ENCAP_TAGS_OFFSET + 2,
+        }
+    }
+
+    /// Encapsulated key of the match key ciphertext. For P-256 reports, this is just its x
+    /// coordinate.
     pub fn encap_key_mk(&self) -> &[u8] {
         &self.data[Self::ENCAP_KEY_MK_OFFSET..Self::CIPHERTEXT_MK_OFFSET]
     }
@@ -323,6 +434,8 @@ where
         &self.data[Self::CIPHERTEXT_MK_OFFSET..Self::ENCAP_KEY_BTT_OFFSET]
     }
 
+    /// Encapsulated key of the breakdown key, trigger value and timestamp ciphertext. For P-256
+    /// reports, this is just its x coordinate.
     pub fn encap_key_btt(&self) -> &[u8] {
         &self.data[Self::ENCAP_KEY_BTT_OFFSET..Self::CIPHERTEXT_BTT_OFFSET]
     }
@@ -345,7 +458,7 @@ where
     /// Never.
     pub fn epoch(&self) -> Epoch {
         u16::from_le_bytes(
-            self.data[Self::EPOCH_OFFSET..Self::SITE_DOMAIN_OFFSET]
+            self.data[Self::EPOCH_OFFSET..Self::ENCAP_TAGS_OFFSET]
                 .try_into()
                 .unwrap(), // infallible slice-to-array conversion
         )
@@ -354,20 +467,36 @@ where
     /// ## Panics
     /// Only if a `Report` constructor failed to validate the contents properly, which would be a bug.
     pub fn site_domain(&self) -> &str {
-        std::str::from_utf8(&self.data[Self::SITE_DOMAIN_OFFSET..]).unwrap() // validated on construction
+        std::str::from_utf8(&self.data[Self::site_domain_offset(self.key_id())..]).unwrap()
+        // validated on construction
+    }
+
+    /// Encapsulated key in the form [`open_in_place`] expects. For P-256 reports, `index`-th tag
+    /// is prepended to the x coordinate stored in `key`.
+    fn full_encap_key<'a>(&'a self, key: &'a [u8], index: usize) -> Cow<'a, [u8]> {
+        match CipherSuite::of_key(self.key_id()) {
+            CipherSuite::X25519 => Cow::Borrowed(key),
+            CipherSuite::P256 => {
+                Cow::Owned([&[self.data[Self::ENCAP_TAGS_OFFSET + index]], key].concat())
+            }
+        }
     }
 
     /// ## Errors
     /// If the report contents are invalid.
     pub fn from_bytes(bytes: B) -> Result<Self, InvalidReportError> {
-        if bytes.len() <= Self::SITE_DOMAIN_OFFSET {
+        if bytes.len() <= Self::ENCAP_TAGS_OFFSET {
             return Err(InvalidReportError::Length(
                 bytes.len(),
-                Self::SITE_DOMAIN_OFFSET,
+                Self::ENCAP_TAGS_OFFSET,
             ));
         }
+        let site_domain_offset = Self::site_domain_offset(bytes[Self::KEY_IDENTIFIER_OFFSET]);
+        if bytes.len() <= site_domain_offset {
+            return Err(InvalidReportError::Length(bytes.len(), site_domain_offset));
+        }
         EventType::try_from(bytes[Self::EVENT_TYPE_OFFSET])?;
-        let site_domain = &bytes[Self::SITE_DOMAIN_OFFSET..];
+        let site_domain = &bytes[site_domain_offset..];
         if !site_domain.is_ascii() {
             return Err(NonAsciiStringError::from(site_domain).into());
         }
@@ -379,7 +508,8 @@ where
 
     /// ## Errors
     /// If the match key shares in the report cannot be decrypted (e.g. due to a
-    /// failure of the authenticated encryption).
+    /// failure of the authenticated encryption), or if the key the report is encrypted
+    /// under is not used for the epoch of the report.
     /// ## Panics
     /// Should not panic. Only panics if a `Report` constructor failed to validate the
     /// contents properly, which would be a bug.
@@ -396,6 +526,13 @@ where
             TagSize,
         >;
 
+        if !key_registry.accepts_epoch(self.key_id(), self.epoch()) {
+            return Err(InvalidReportError::KeyEpochMismatch {
+                key_id: self.key_id(),
+                epoch: self.epoch(),
+            });
+        }
+
         let info = Info::new(
             self.key_id(),
             self.epoch(),
@@ -407,11 +544,21 @@ where
 
         let mut ct_mk: GenericArray<u8, CTMKLength> =
             *GenericArray::from_slice(self.mk_ciphertext());
-        let plaintext_mk = open_in_place(key_registry, self.encap_key_mk(), &mut ct_mk, &info)?;
+        let plaintext_mk = open_in_place(
+            key_registry,
+            &self.full_encap_key(self.encap_key_mk(), 0),
+            &mut ct_mk,
+            &info,
+        )?;
         let mut ct_btt: GenericArray<u8, CTBTTLength<BK, TV, TS>> =
             GenericArray::from_slice(self.btt_ciphertext()).clone();
 
-        let plaintext_btt = open_in_place(key_registry, self.encap_key_btt(), &mut ct_btt, &info)?;
+        let plaintext_btt = open_in_place(
+            key_registry,
+            &self.full_encap_key(self.encap_key_btt(), 1),
+            &mut ct_btt,
+            &info,
+        )?;
 
         Ok(OprfReport::<BK, TV, TS> {
             timestamp: Replicated::<TS>::deserialize(GenericArray::from_slice(
@@ -510,10 +657,12 @@ where
     const TV_OFFSET: usize = Self::BK_OFFSET + <Replicated<BK> as Serializable>::Size::USIZE;
     const BTT_END: usize = Self::TV_OFFSET + <Replicated<TV> as Serializable>::Size::USIZE;
 
+    /// Length of the report encrypted under the key with the given identifier.
+    ///
     /// # Panics
     /// If report length does not fit in `u16`.
-    pub fn encrypted_len(&self) -> u16 {
-        let len = EncryptedOprfReport::<BK, TV, TS, &[u8]>::SITE_DOMAIN_OFFSET
+    pub fn encrypted_len(&self, key_id: KeyIdentifier) -> u16 {
+        let len = EncryptedOprfReport::<BK, TV, TS, &[u8]>::site_domain_offset(key_id)
             + self.site_domain.as_bytes().len();
         len.try_into().unwrap()
     }
@@ -527,7 +676,7 @@ where
         rng: &mut R,
         out: &mut B,
     ) -> Result<(), InvalidReportError> {
-        out.put_u16_le(self.encrypted_len());
+        out.put_u16_le(self.encrypted_len(key_id));
         self.encrypt_to(key_id, key_registry, rng, out)
     }
 
@@ -539,9 +688,9 @@ where
         key_registry: &impl PublicKeyRegistry,
         rng: &mut R,
     ) -> Result<Vec<u8>, InvalidReportError> {
-        let mut out = Vec::with_capacity(usize::from(self.encrypted_len()));
+        let mut out = Vec::with_capacity(usize::from(self.encrypted_len(key_id)));
         self.encrypt_to(key_id, key_registry, rng, &mut out)?;
-        debug_assert_eq!(out.len(), usize::from(self.encrypted_len()));
+        debug_assert_eq!(out.len(), usize::from(self.encrypted_len(key_id)));
         Ok(out)
     }
 
@@ -583,15 +732,23 @@ where
         let (encap_key_btt, ciphertext_btt, tag_btt) =
             seal_in_place(key_registry, plaintext_btt.as_mut(), &info, rng)?;
 
-        out.put_slice(&encap_key_mk.to_bytes());
+        // compressed P-256 keys are one byte longer than X25519 keys, that byte is their SEC1 tag
+        let (sec1_tag_mk, encap_key_mk) =
+            encap_key_mk.split_at(encap_key_mk.len() - EncapsulationSize::USIZE);
+        let (sec1_tag_btt, encap_key_btt) =
+            encap_key_btt.split_at(encap_key_btt.len() - EncapsulationSize::USIZE);
+
+        out.put_slice(encap_key_mk);
         out.put_slice(ciphertext_mk);
         out.put_slice(&tag_mk.to_bytes());
-        out.put_slice(&encap_key_btt.to_bytes());
+        out.put_slice(encap_key_btt);
         out.put_slice(ciphertext_btt);
         out.put_slice(&tag_btt.to_bytes());
         out.put_slice(&[u8::from(&self.event_type)]);
         out.put_slice(&[key_id]);
         out.put_slice(&self.epoch.to_le_bytes());
+        out.put_slice(sec1_tag_mk);
+        out.put_slice(sec1_tag_btt);
         out.put_slice(self.site_domain.as_bytes());
 
         Ok(())
@@ -605,7 +762,7 @@ mod test {
     use super::*;
     use crate::{
         ff::boolean_array::{BA20, BA3, BA8},
-        hpke::{Deserializable, IpaPrivateKey, IpaPublicKey, KeyPair, KeyRegistry},
+        hpke::{CipherSuite, IpaPrivateKey, IpaPublicKey, KeyPair, KeyRegistry, KeyValidity},
         report,
         report::EventType::{Source, Trigger},
         secret_sharing::replicated::{semi_honest::AdditiveShare, ReplicatedSecretSharing},
@@ -642,6 +799,101 @@ mod test {
         assert_eq!(dec_report, report);
     }
 
+    #[test]
+    fn enc_dec_roundtrip_both_suites() {
+        let mut rng = thread_rng();
+        let key_registry = KeyRegistry::from_keys_with_ids([
+            (1, KeyPair::gen_in(CipherSuite::X25519, &mut rng)),
+            (129, KeyPair::gen_in(CipherSuite::P256, &mut rng)),
+        ]);
+        let report = OprfReport::<BA8, BA3, BA20> {
+            match_key: AdditiveShare::new(rng.gen(), rng.gen()),
+            timestamp: AdditiveShare::new(rng.gen(), rng.gen()),
+            breakdown_key: AdditiveShare::new(rng.gen(), rng.gen()),
+            trigger_value: AdditiveShare::new(rng.gen(), rng.gen()),
+            event_type: Trigger,
+            epoch: 7,
+            site_domain: "example.com".to_owned(),
+        };
+
+        let x25519 = report.encrypt(1, &key_registry, &mut rng).unwrap();
+        let p256 = report.encrypt(129, &key_registry, &mut rng).unwrap();
+        assert_eq!(x25519.len() + 2, p256.len());
+        assert_eq!(usize::from(report.encrypted_len(129)), p256.len());
+
+        for (key_id, bytes) in [(1, &x25519), (129, &p256)] {
+            let enc_report =
+                EncryptedOprfReport::<BA8, BA3, BA20, _>::from_bytes(bytes.as_slice()).unwrap();
+            assert_eq!(key_id, enc_report.key_id());
+            assert_eq!(7, enc_report.epoch());
+            assert_eq!("example.com", enc_report.site_domain());
+            assert_eq!(report, enc_report.decrypt(&key_registry).unwrap());
+        }
+
+        // corrupting the tag of a compressed key makes the report undecryptable
+        let mut corrupted = p256.clone();
+        corrupted[EncryptedOprfReport::<BA8, BA3, BA20, &[u8]>::ENCAP_TAGS_OFFSET] ^= 1;
+        let enc_report =
+            EncryptedOprfReport::<BA8, BA3, BA20, _>::from_bytes(corrupted.as_slice()).unwrap();
+        assert!(enc_report.decrypt(&key_registry).is_err());
+    }
+
+    #[test]
+    fn key_bound_to_epochs() {
+        let mut rng = thread_rng();
+        let key_registry = KeyRegistry::from_keys_with_validity([(
+            3,
+            KeyPair::gen(&mut rng),
+            KeyValidity {
+                epochs: Some(EpochRange::new(4, 5).unwrap()),
+                ..KeyValidity::default()
+            },
+        )]);
+
+        for (epoch, accepted) in [(3, false), (4, true), (5, true), (6, false)] {
+            let report = OprfReport::<BA8, BA3, BA20> {
+                match_key: AdditiveShare::new(rng.gen(), rng.gen()),
+                timestamp: AdditiveShare::new(rng.gen(), rng.gen()),
+                breakdown_key: AdditiveShare::new(rng.gen(), rng.gen()),
+                trigger_value: AdditiveShare::new(rng.gen(), rng.gen()),
+                event_type: Source,
+                epoch,
+                site_domain: "example.com".to_owned(),
+            };
+            let enc_report_bytes = report.encrypt(3, &key_registry, &mut rng).unwrap();
+            let enc_report =
+                EncryptedOprfReport::<BA8, BA3, BA20, _>::from_bytes(enc_report_bytes.as_slice())
+                    .unwrap();
+            match enc_report.decrypt(&key_registry) {
+                Ok(dec_report) => {
+                    assert!(accepted, "epoch {epoch}");
+                    assert_eq!(report, dec_report);
+                }
+                Err(e) => {
+                    assert!(!accepted, "epoch {epoch}");
+                    assert!(matches!(
+                        e,
+                        InvalidReportError::KeyEpochMismatch { key_id: 3, epoch: e } if e == epoch
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn epoch_range_from_str() {
+        assert_eq!(EpochRange::new(2, 7).unwrap(), "2-7".parse().unwrap());
+        assert_eq!(EpochRange::single(4), "4".parse().unwrap());
+        assert_eq!("4", EpochRange::single(4).to_string());
+        assert_eq!("2-7", EpochRange::new(2, 7).unwrap().to_string());
+        assert!("7-2".parse::<EpochRange>().is_err());
+        assert!("a-2".parse::<EpochRange>().is_err());
+
+        let range = EpochRange::new(2, 7).unwrap();
+        assert!(range.contains(2) && range.contains(7));
+        assert!(!range.contains(1) && !range.contains(8));
+    }
+
     #[test]
     fn test_decryption_fails() {
         let mut rng = thread_rng();
@@ -727,8 +979,8 @@ mod test {
         expected: &RawReport,
     ) -> OprfReport<BA8, BA3, BA20> {
         let key_registry1 = KeyRegistry::<KeyPair>::from_keys([KeyPair::from((
-            IpaPrivateKey::from_bytes(sk).unwrap(),
-            IpaPublicKey::from_bytes(pk).unwrap(),
+            IpaPrivateKey::from_bytes(CipherSuite::X25519, sk).unwrap(),
+            IpaPublicKey::from_bytes(CipherSuite::X25519, pk).unwrap(),
         ))]);
 
         let enc_report = EncryptedOprfReport::from_bytes(encrypted_report_bytes).unwrap();
@@ -43,7 +43,7 @@ use crate::{
     error::{LengthError, UnwrapInfallible},
     ff::{
         boolean::Boolean,
-        boolean_array::{BA16, BA256, BA3, BA32, BA5, BA64, BA8},
+        boolean_array::{BA112, BA16, BA256, BA3, BA32, BA5, BA64, BA8},
         ec_prime_field::Fp25519,
     },
     protocol::ipa_prf::{CONV_CHUNK, MK_BITS},
@@ -624,6 +624,10 @@ impl_transpose_shares_bool_to_ba!(BA16, 16, 16, test_transpose_shares_bool_to_ba
 // Usage: ?
 impl_transpose_shares_bool_to_ba_small!(BA8, 8, 16, test_transpose_shares_bool_to_ba_8x16);
 
+// Usage: Laplace noise mechanism with output values wider than 32 bits.
+impl_transpose_shares_bool_to_ba!(BA64, 64, 32, test_transpose_shares_bool_to_ba_64x32);
+impl_transpose_shares_bool_to_ba!(BA112, 112, 32, test_transpose_shares_bool_to_ba_112x32);
+
 /// Implement a transpose of a MxN matrix of secret-shared bits represented as
 /// `[AdditiveShare<BA<N>>; M]` into a NxM bit matrix represented as `[AdditiveShare<Boolean, M>; N]`.
 ///
@@ -795,6 +799,8 @@ impl_transpose_shares_ba_to_bool!(BA32, 32, 32, test_transpose_shares_ba_to_bool
 impl_transpose_shares_ba_to_bool!(BA16, 256, 16, test_transpose_shares_ba_to_bool_256x16);
 impl_transpose_shares_ba_to_bool!(BA16, 32, 16, test_transpose_shares_ba_to_bool_32x16);
 impl_transpose_shares_ba_to_bool_small!(BA8, 16, 8, test_transpose_shares_ba_to_bool_16x8);
+impl_transpose_shares_ba_to_bool!(BA64, 32, 64, test_transpose_shares_ba_to_bool_32x64);
+impl_transpose_shares_ba_to_bool!(BA112, 32, 112, test_transpose_shares_ba_to_bool_32x112);
 
 // Special transpose used for "aggregation intermediate". See [`aggregate_contributions`] for
 // additional details.
@@ -14,6 +14,8 @@ pub mod metrics {
     pub const REQUESTS_RECEIVED: &str = "requests.received";
     pub const RECORDS_SENT: &str = "records.sent";
     pub const BYTES_SENT: &str = "bytes.sent";
+    pub const STREAM_BYTES_UNCOMPRESSED: &str = "stream.bytes.uncompressed";
+    pub const STREAM_BYTES_COMPRESSED: &str = "stream.bytes.compressed";
     pub const INDEXED_PRSS_GENERATED: &str = "i.prss.gen";
     pub const SEQUENTIAL_PRSS_GENERATED: &str = "s.prss.gen";
     pub use ::ipa_step::descriptive::labels::STEP_NARROWED;
@@ -89,6 +91,18 @@ pub mod metrics {
             "Bytes sent from the infrastructure layer to the network"
         );
 
+        describe_counter!(
+            STREAM_BYTES_UNCOMPRESSED,
+            Unit::Bytes,
+            "Bytes of helper-to-helper record streams before compression"
+        );
+
+        describe_counter!(
+            STREAM_BYTES_COMPRESSED,
+            Unit::Bytes,
+            "Bytes of helper-to-helper record streams after compression"
+        );
+
         describe_counter!(
             INDEXED_PRSS_GENERATED,
             Unit::Count,
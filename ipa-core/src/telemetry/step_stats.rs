@@ -11,6 +11,7 @@ use crate::telemetry::{
     labels,
     metrics::{
         BYTES_SENT, INDEXED_PRSS_GENERATED, RECORDS_SENT, SEQUENTIAL_PRSS_GENERATED, STEP_NARROWED,
+        STREAM_BYTES_COMPRESSED, STREAM_BYTES_UNCOMPRESSED,
     },
     stats::Metrics,
 };
@@ -40,15 +41,18 @@ impl CsvExporter for Metrics {
         // because it does not allow such breakdown atm.
         writeln!(
             w,
-            "Step,Records sent,Bytes sent,Indexed PRSS,Sequential PRSS,Step narrowed"
+            "Step,Records sent,Bytes sent,Bytes before compression,Bytes after compression,\
+             Indexed PRSS,Sequential PRSS,Step narrowed"
         )?;
         for (step, stats) in steps_stats.all_steps() {
             writeln!(
                 w,
-                "{},{},{},{},{},{}",
+                "{},{},{},{},{},{},{},{}",
                 step,
                 stats.get(RECORDS_SENT),
                 stats.get(BYTES_SENT),
+                stats.get(STREAM_BYTES_UNCOMPRESSED),
+                stats.get(STREAM_BYTES_COMPRESSED),
                 stats.get(INDEXED_PRSS_GENERATED),
                 stats.get(SEQUENTIAL_PRSS_GENERATED),
                 stats.get(STEP_NARROWED),
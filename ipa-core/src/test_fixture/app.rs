@@ -7,7 +7,7 @@ use crate::{
     app::AppConfig,
     ff::Serializable,
     helpers::{
-        query::{QueryConfig, QueryInput},
+        query::{PaddingPolicy, QueryConfig, QueryInput},
         ApiError, InMemoryMpcNetwork, InMemoryShardNetwork, Transport,
     },
     protocol::QueryId,
@@ -62,8 +62,9 @@ fn unzip_tuple_array<T, U>(input: [(T, U); 3]) -> ([T; 3], [U; 3]) {
 
 impl Default for TestApp {
     fn default() -> Self {
-        let (setup, handlers) =
-            unzip_tuple_array(array::from_fn(|_| AppSetup::new(AppConfig::default())));
+        let (setup, handlers) = unzip_tuple_array(array::from_fn(|_| {
+            AppSetup::new(AppConfig::default().with_padding_policy(PaddingPolicy::permissive()))
+        }));
 
         let mpc_network = InMemoryMpcNetwork::new(handlers.map(Some));
         let shard_network = InMemoryShardNetwork::with_shards(1);
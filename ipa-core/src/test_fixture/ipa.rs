@@ -202,20 +202,17 @@ pub async fn test_oprf_ipa<F>(
     };
 
     let aws = config.attribution_window_seconds;
-    let dp_params: DpMechanism = match config.with_dp {
-        0 => DpMechanism::NoDp,
-        _ => DpMechanism::DiscreteLaplace {
-            epsilon: config.epsilon,
-        },
-    };
+    let dp_params = config.dp.noise();
+    let dp_threshold = config.dp.threshold;
     let padding_params = PaddingParameters::default();
+    let breakdown_hierarchy = config.breakdown_hierarchy;
     let result: Vec<_> = if config.per_user_credit_cap == 256 {
         // Note that many parameters are different in this case, not just the credit cap.
         // This config is needed for collect_steps coverage.
         world.semi_honest(
             records.into_iter(),
             |ctx, input_rows: Vec<OPRFIPAInputRow<BA5, BA8, BA20>>| async move {
-                oprf_ipa::<_, BA5, BA8, BA32, BA20, 8, 32>(ctx, input_rows, aws, dp_params, padding_params)
+                oprf_ipa::<_, BA5, BA8, BA32, BA20, 8, 32>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap()
             },
@@ -227,19 +224,19 @@ pub async fn test_oprf_ipa<F>(
             |ctx, input_rows: Vec<OPRFIPAInputRow<BA8, BA3, BA20>>| async move {
 
                 match config.per_user_credit_cap {
-                    8 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 3, 256>(ctx, input_rows, aws, dp_params, padding_params)
+                    8 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 3, 256>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap(),
-                    16 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 4, 256>(ctx, input_rows, aws, dp_params, padding_params)
+                    16 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 4, 256>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap(),
-                    32 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 5, 256>(ctx, input_rows, aws, dp_params, padding_params)
+                    32 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 5, 256>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap(),
-                    64 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 6, 256>(ctx, input_rows, aws, dp_params, padding_params)
+                    64 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 6, 256>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap(),
-                    128 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 7, 256>(ctx, input_rows, aws, dp_params, padding_params)
+                    128 => oprf_ipa::<_, BA8, BA3, BA32, BA20, 7, 256>(ctx, input_rows, aws, dp_params, dp_threshold, padding_params, breakdown_hierarchy)
                     .await
                     .unwrap(),
                     _ =>
@@ -266,10 +263,10 @@ pub async fn test_oprf_ipa<F>(
         DpMechanism::NoDp => {
             assert_eq!(result, expected_results);
         }
-        DpMechanism::Binomial { epsilon } => {
+        DpMechanism::Binomial { epsilon, delta } => {
             let noise_params = NoiseParams {
                 epsilon,
-                delta: 1e-6,
+                delta,
                 per_user_credit_cap: config.per_user_credit_cap,
                 ell_1_sensitivity: f64::from(config.per_user_credit_cap),
                 ell_2_sensitivity: f64::from(config.per_user_credit_cap),
@@ -288,9 +285,9 @@ pub async fn test_oprf_ipa<F>(
                 );
             }
         }
-        DpMechanism::DiscreteLaplace { epsilon } => {
+        DpMechanism::DiscreteLaplace { epsilon, delta } => {
             let truncated_discrete_laplace =
-                OPRFPaddingDp::new(epsilon, 1e-6, config.per_user_credit_cap).unwrap();
+                OPRFPaddingDp::new(epsilon, delta, config.per_user_credit_cap).unwrap();
 
             let (_, std) = truncated_discrete_laplace.mean_and_std();
             let tolerance_factor = 12.0;
@@ -314,6 +311,37 @@ pub async fn test_oprf_ipa<F>(
                 );
             }
         }
+        DpMechanism::Skellam { epsilon, delta } => {
+            let noise_params = NoiseParams {
+                epsilon,
+                delta,
+                per_user_credit_cap: config.per_user_credit_cap,
+                ell_1_sensitivity: f64::from(config.per_user_credit_cap),
+                ell_2_sensitivity: f64::from(config.per_user_credit_cap),
+                ell_infty_sensitivity: f64::from(config.per_user_credit_cap),
+                dimensions: 256.0, // matches hard coded dimension in oprf_ipa.rs/execute
+                ..Default::default()
+            };
+            let std = crate::protocol::dp::skellam_noise_std(
+                crate::protocol::dp::find_smallest_skellam_poisson_mean(&noise_params),
+            );
+
+            assert_eq!(result.len(), expected_results.len());
+
+            for (&sample, &expected) in std::iter::zip(result.iter(), expected_results.iter()) {
+                // Skellam noise is centered at zero, so negative noise wraps around like
+                // Laplace noise does.
+                let sample_shifted = if f64::from(sample) > 2.0_f64.powf(31.0) {
+                    f64::from(sample) - 2.0_f64.powf(32.0)
+                } else {
+                    f64::from(sample)
+                };
+                assert!(
+                    (sample_shifted - f64::from(expected)).abs() < 5.0 * std,
+                    "DP result was not within 5 standard deviations from what was expected"
+                );
+            }
+        }
     }
 }
 
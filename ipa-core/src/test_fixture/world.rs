@@ -15,7 +15,7 @@ use tracing::{Instrument, Level, Span};
 
 use crate::{
     helpers::{
-        in_memory_config::{passthrough, DynStreamInterceptor},
+        in_memory_config::{passthrough, DynStreamInterceptor, FaultConfig},
         Gateway, GatewayConfig, HelperIdentity, InMemoryMpcNetwork, InMemoryShardNetwork,
         InMemoryTransport, Role, RoleAssignment, TotalRecords, Transport,
     },
@@ -23,8 +23,8 @@ use crate::{
         context::{
             dzkp_validator::DZKPValidator, upgrade::Upgradable, Context,
             DZKPUpgradedMaliciousContext, MaliciousContext, SemiHonestContext,
-            ShardedSemiHonestContext, UpgradableContext, UpgradedContext, UpgradedMaliciousContext,
-            UpgradedSemiHonestContext, Validator, TEST_DZKP_STEPS,
+            ShardedMaliciousContext, ShardedSemiHonestContext, UpgradableContext, UpgradedContext,
+            UpgradedMaliciousContext, UpgradedSemiHonestContext, Validator, TEST_DZKP_STEPS,
         },
         prss::Endpoint as PrssEndpoint,
         Gate, QueryId, RecordId,
@@ -143,6 +143,13 @@ pub struct TestWorldConfig {
     /// [`MaliciousHelper`]: crate::helpers::in_memory_config::MaliciousHelper
     /// [`passthrough`]: crate::helpers::in_memory_config::passthrough
     pub stream_interceptor: DynStreamInterceptor,
+
+    /// Faults to inject into streams exchanged between helpers and shards. Tests can use it
+    /// to make sure protocols fail cleanly and do not hang when streams are delayed, dropped,
+    /// truncated or duplicated. By default, no faults are injected.
+    ///
+    /// See [`FaultConfig`] for details.
+    pub faults: FaultConfig,
 }
 
 impl ShardingScheme for NotSharded {
@@ -269,8 +276,11 @@ impl<S: ShardingScheme> TestWorld<S> {
         println!("TestWorld random seed {seed}", seed = config.seed);
 
         let shard_count = ShardIndex::try_from(S::SHARDS).unwrap();
-        let shard_network =
-            InMemoryShardNetwork::with_stream_interceptor(shard_count, &config.stream_interceptor);
+        let shard_network = InMemoryShardNetwork::with_config(
+            shard_count,
+            &config.stream_interceptor,
+            &config.faults,
+        );
 
         let shards = shard_count
             .iter()
@@ -320,6 +330,7 @@ impl Default for TestWorldConfig {
             seed: thread_rng().next_u64(),
             initial_gate: None,
             stream_interceptor: passthrough(),
+            faults: FaultConfig::default(),
         }
     }
 }
@@ -369,6 +380,8 @@ where
 pub trait Runner<S: ShardingScheme> {
     /// This could be also derived from [`S`], but maybe that's too much for that trait.
     type SemiHonestContext<'ctx>: Context;
+    /// The type of context used to run protocols that are secure against active adversaries.
+    type MaliciousContext<'ctx>: Context;
     /// Run with a context that can be upgraded, but is only good for semi-honest.
     async fn semi_honest<'a, I, A, O, H, R>(
         &'a self,
@@ -396,12 +409,12 @@ pub trait Runner<S: ShardingScheme> {
         R: Future<Output = O> + Send;
 
     /// Run with a context that can be upgraded to malicious.
-    async fn malicious<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> [O; 3]
+    async fn malicious<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> S::Container<[O; 3]>
     where
-        I: IntoShares<A> + Send + 'static,
+        I: RunnerInput<S, A>,
         A: Send,
         O: Send + Debug,
-        H: Fn(MaliciousContext<'a>, A) -> R + Send + Sync,
+        H: Fn(Self::MaliciousContext<'a>, S::Container<A>) -> R + Send + Sync,
         R: Future<Output = O> + Send;
 
     /// Run with a context that has already been upgraded to malicious.
@@ -444,6 +457,7 @@ impl<const SHARDS: usize, D: Distribute> Runner<WithShards<SHARDS, D>>
     for TestWorld<WithShards<SHARDS, D>>
 {
     type SemiHonestContext<'ctx> = ShardedSemiHonestContext<'ctx>;
+    type MaliciousContext<'ctx> = ShardedMaliciousContext<'ctx>;
     async fn semi_honest<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> Vec<[O; 3]>
     where
         I: RunnerInput<WithShards<SHARDS, D>, A>,
@@ -494,15 +508,38 @@ impl<const SHARDS: usize, D: Distribute> Runner<WithShards<SHARDS, D>>
         unimplemented!()
     }
 
-    async fn malicious<'a, I, A, O, H, R>(&'a self, _input: I, _helper_fn: H) -> [O; 3]
+    async fn malicious<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> Vec<[O; 3]>
     where
-        I: IntoShares<A> + Send + 'static,
+        I: RunnerInput<WithShards<SHARDS, D>, A>,
         A: Send,
         O: Send + Debug,
-        H: Fn(MaliciousContext<'a>, A) -> R + Send + Sync,
+        H: Fn(
+                Self::MaliciousContext<'a>,
+                <WithShards<SHARDS> as ShardingScheme>::Container<A>,
+            ) -> R
+            + Send
+            + Sync,
         R: Future<Output = O> + Send,
     {
-        unimplemented!()
+        let shards = self.shards();
+        let [h1, h2, h3]: [[Vec<A>; SHARDS]; 3] = input.share().map(D::distribute);
+        let gate = self.next_gate();
+
+        // No clippy, you're wrong, it is not redundant, it allows shard_fn to be `Copy`
+        #[allow(clippy::redundant_closure)]
+        let shard_fn = |ctx, input| helper_fn(ctx, input);
+        zip(shards, zip(zip(h1, h2), h3))
+            .map(|(shard, ((h1, h2), h3))| {
+                ShardWorld::<Sharded>::run_either(
+                    shard.malicious_contexts(&gate),
+                    self.metrics_handle.span(),
+                    [h1, h2, h3],
+                    shard_fn,
+                )
+            })
+            .collect::<FuturesOrdered<_>>()
+            .collect::<Vec<_>>()
+            .await
     }
 
     async fn upgraded_malicious<'a, F, I, A, M, O, H, R, P>(
@@ -541,6 +578,7 @@ impl<const SHARDS: usize, D: Distribute> Runner<WithShards<SHARDS, D>>
 #[async_trait]
 impl Runner<NotSharded> for TestWorld<NotSharded> {
     type SemiHonestContext<'ctx> = SemiHonestContext<'ctx>;
+    type MaliciousContext<'ctx> = MaliciousContext<'ctx>;
 
     async fn semi_honest<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> [O; 3]
     where
@@ -583,10 +621,10 @@ impl Runner<NotSharded> for TestWorld<NotSharded> {
 
     async fn malicious<'a, I, A, O, H, R>(&'a self, input: I, helper_fn: H) -> [O; 3]
     where
-        I: IntoShares<A> + Send + 'static,
+        I: RunnerInput<NotSharded, A>,
         A: Send,
         O: Send + Debug,
-        H: Fn(MaliciousContext<'a>, A) -> R + Send + Sync,
+        H: Fn(Self::MaliciousContext<'a>, A) -> R + Send + Sync,
         R: Future<Output = O> + Send,
     {
         ShardWorld::<NotSharded>::run_either(
@@ -704,9 +742,10 @@ impl<B: ShardBinding> ShardWorld<B> {
         transports: [InMemoryTransport<ShardIndex>; 3],
     ) -> Self {
         let participants = make_participants(&mut StdRng::seed_from_u64(config.seed + shard_seed));
-        let network = InMemoryMpcNetwork::with_stream_interceptor(
+        let network = InMemoryMpcNetwork::with_config(
             InMemoryMpcNetwork::noop_handlers(),
             &config.stream_interceptor,
+            &config.faults,
         );
 
         let mut gateways = zip3_ref(&network.transports(), &transports).map(|(mpc, shard)| {
@@ -741,7 +780,7 @@ impl<B: ShardBinding> ShardWorld<B> {
         helper_fn: H,
     ) -> [O; 3]
     where
-        C: UpgradableContext,
+        C: Context,
         A: Send,
         O: Send + Debug,
         H: Fn(C, A) -> R + Send + Sync,
@@ -778,9 +817,14 @@ impl<B: ShardBinding> ShardWorld<B> {
     /// # Panics
     /// Panics if world has more or less than 3 gateways/participants
     #[must_use]
-    pub fn malicious_contexts(&self, gate: &Gate) -> [MaliciousContext<'_>; 3] {
+    pub fn malicious_contexts(&self, gate: &Gate) -> [MaliciousContext<'_, B>; 3] {
         zip3_ref(&self.participants, &self.gateways).map(|(participant, gateway)| {
-            MaliciousContext::new_with_gate(participant, gateway, gate.clone())
+            MaliciousContext::new_complete(
+                participant,
+                gateway,
+                self.shard_info.clone(),
+                gate.clone(),
+            )
         })
     }
 }
@@ -821,15 +865,19 @@ mod tests {
     use std::{
         collections::{HashMap, HashSet},
         sync::{Arc, Mutex},
+        time::Duration,
     };
 
     use futures_util::future::try_join4;
 
     use crate::{
+        error::Error,
         ff::{boolean_array::BA3, Field, Fp31, U128Conversions},
         helpers::{
-            in_memory_config::{MaliciousHelper, MaliciousHelperContext},
-            Direction, Role,
+            in_memory_config::{
+                Fault, FaultConfig, FaultRule, MaliciousHelper, MaliciousHelperContext,
+            },
+            Direction, Error as HelperError, HelperIdentity, Role, RoleAssignment,
         },
         protocol::{context::Context, prss::SharedRandomness, RecordId},
         secret_sharing::{
@@ -961,4 +1009,149 @@ mod tests {
             assert_eq!(shares[1].right(), shares[2].left());
         });
     }
+
+    /// Each helper sends a share to both of its peers and receives shares from them.
+    async fn exchange_shares(
+        config: TestWorldConfig,
+        step: &'static str,
+    ) -> [Result<AdditiveShare<Fp31>, Error>; 3] {
+        let world = TestWorld::new_with(config);
+        world
+            .semi_honest((), |ctx, ()| async move {
+                let ctx = ctx.narrow(step).set_total_records(1);
+                let (l, r): (Fp31, Fp31) = ctx.prss().generate(RecordId::FIRST);
+
+                let ((), (), r, l) = try_join4(
+                    ctx.send_channel(ctx.role().peer(Direction::Right))
+                        .send(RecordId::FIRST, r),
+                    ctx.send_channel(ctx.role().peer(Direction::Left))
+                        .send(RecordId::FIRST, l),
+                    ctx.recv_channel::<Fp31>(ctx.role().peer(Direction::Right))
+                        .receive(RecordId::FIRST),
+                    ctx.recv_channel::<Fp31>(ctx.role().peer(Direction::Left))
+                        .receive(RecordId::FIRST),
+                )
+                .await?;
+
+                Ok(AdditiveShare::new(l, r))
+            })
+            .await
+    }
+
+    fn faulty_config(fault: Fault, step: &str) -> TestWorldConfig {
+        TestWorldConfig {
+            // fix role assignment, so H1 is always the first helper
+            role_assignment: Some(
+                RoleAssignment::try_from([Role::H1, Role::H2, Role::H3]).unwrap(),
+            ),
+            faults: FaultConfig::new(42).with_rule(
+                FaultRule::new(fault)
+                    .on_gate(step)
+                    .from_helper(HelperIdentity::ONE),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn dropped_stream_fails_cleanly() {
+        const STEP: &str = "drop";
+        run(|| async move {
+            let [h1, h2, h3] = exchange_shares(faulty_config(Fault::Drop, STEP), STEP).await;
+
+            // H1 receives everything it needs, but its peers don't
+            h1.unwrap();
+            assert!(
+                matches!(
+                    h2,
+                    Err(Error::MpcInfraError(HelperError::EndOfStream { .. }))
+                ),
+                "{h2:?}"
+            );
+            assert!(
+                matches!(
+                    h3,
+                    Err(Error::MpcInfraError(HelperError::EndOfStream { .. }))
+                ),
+                "{h3:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn truncated_stream_fails_cleanly() {
+        const STEP: &str = "truncate";
+        run(|| async move {
+            let [h1, h2, h3] = exchange_shares(
+                faulty_config(Fault::Truncate { after_chunks: 0 }, STEP),
+                STEP,
+            )
+            .await;
+
+            h1.unwrap();
+            assert!(
+                matches!(
+                    h2,
+                    Err(Error::MpcInfraError(HelperError::EndOfStream { .. }))
+                ),
+                "{h2:?}"
+            );
+            assert!(
+                matches!(
+                    h3,
+                    Err(Error::MpcInfraError(HelperError::EndOfStream { .. }))
+                ),
+                "{h3:?}"
+            );
+        });
+    }
+
+    #[test]
+    fn delayed_stream_succeeds() {
+        const STEP: &str = "delay";
+        run(|| async move {
+            let shares = exchange_shares(
+                faulty_config(Fault::Delay(Duration::from_millis(10)), STEP),
+                STEP,
+            )
+            .await
+            .map(Result::unwrap);
+
+            assert_eq!(shares[0].right(), shares[1].left());
+            assert_eq!(shares[1].right(), shares[2].left());
+            assert_eq!(shares[2].right(), shares[0].left());
+        });
+    }
+
+    #[test]
+    fn faults_are_deterministic() {
+        const STEP: &str = "coin_flip";
+        fn config(seed: u64) -> TestWorldConfig {
+            let mut faults = FaultConfig::new(seed);
+            for helper in HelperIdentity::make_three() {
+                faults = faults.with_rule(
+                    FaultRule::new(Fault::Drop)
+                        .on_gate(STEP)
+                        .from_helper(helper)
+                        .with_probability(0.5),
+                );
+            }
+
+            TestWorldConfig {
+                role_assignment: Some(
+                    RoleAssignment::try_from([Role::H1, Role::H2, Role::H3]).unwrap(),
+                ),
+                faults,
+                ..Default::default()
+            }
+        }
+
+        run(|| async move {
+            for seed in 0..4 {
+                let first = exchange_shares(config(seed), STEP).await.map(|r| r.is_ok());
+                let second = exchange_shares(config(seed), STEP).await.map(|r| r.is_ok());
+                assert_eq!(first, second, "seed {seed}");
+            }
+        });
+    }
 }
@@ -12,7 +12,9 @@ use std::{
 
 use command_fds::CommandFdExt;
 use ipa_core::{
-    cli::IpaQueryResult, helpers::query::IpaQueryConfig, test_fixture::ipa::IpaSecurityModel,
+    cli::IpaQueryResult,
+    helpers::query::{DpConfig, IpaQueryConfig},
+    test_fixture::ipa::IpaSecurityModel,
 };
 use rand::thread_rng;
 use rand_core::RngCore;
@@ -138,6 +140,9 @@ pub fn spawn_helpers(
             command
                 .args(["-i", &id.to_string()])
                 .args(["--network".into(), config_path.join("network.toml")])
+                // tests run queries with relaxed padding
+                .args(["--min-matchkey-cardinality-cap", "3"])
+                .args(["--min-aggregation-padding-sensitivity", "3"])
                 .silent();
 
             if https {
@@ -222,6 +227,7 @@ pub fn test_ipa(mode: IpaSecurityModel, https: bool, encrypted_inputs: bool) {
         mode,
         https,
         IpaQueryConfig {
+            dp: DpConfig::default().with_relaxed_padding(),
             ..Default::default()
         },
         encrypted_inputs,
@@ -311,16 +317,18 @@ pub fn test_ipa_with_config(
             &config.per_user_credit_cap.to_string(),
         ]);
 
-    match config.with_dp {
-        0 => {
-            command.args(["--with-dp", &config.with_dp.to_string()]);
-        }
-        _ => {
-            command
-                .args(["--with-dp", &config.with_dp.to_string()])
-                .args(["--epsilon", &config.epsilon.to_string()]);
-        }
-    }
+    command
+        .args(["--dp-mechanism", &config.dp.mechanism.to_string()])
+        .args(["--dp-epsilon", &config.dp.epsilon.to_string()])
+        .args(["--dp-delta", &config.dp.delta.to_string()])
+        .args([
+            "--matchkey-cardinality-cap",
+            &config.dp.matchkey_cardinality_cap.to_string(),
+        ])
+        .args([
+            "--aggregation-padding-sensitivity",
+            &config.dp.aggregation_padding_sensitivity.to_string(),
+        ]);
     command.stdin(Stdio::piped());
 
     if config.attribution_window_seconds.is_some() {
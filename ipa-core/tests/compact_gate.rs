@@ -6,7 +6,10 @@ mod common;
 use std::num::NonZeroU32;
 
 use common::test_ipa_with_config;
-use ipa_core::{helpers::query::IpaQueryConfig, test_fixture::ipa::IpaSecurityModel};
+use ipa_core::{
+    helpers::query::{DpConfig, IpaQueryConfig},
+    test_fixture::ipa::IpaSecurityModel,
+};
 
 fn test_compact_gate<I: TryInto<NonZeroU32>>(
     mode: IpaSecurityModel,
@@ -17,7 +20,7 @@ fn test_compact_gate<I: TryInto<NonZeroU32>>(
     let config = IpaQueryConfig {
         per_user_credit_cap,
         attribution_window_seconds: attribution_window_seconds.try_into().ok(),
-        with_dp: 0,
+        dp: DpConfig::no_noise().with_relaxed_padding(),
         ..Default::default()
     };
 